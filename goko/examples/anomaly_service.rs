@@ -0,0 +1,224 @@
+/*
+* Licensed to Elasticsearch B.V. under one or more contributor
+* license agreements. See the NOTICE file distributed with
+* this work for additional information regarding copyright
+* ownership. Elasticsearch B.V. licenses this file to you under
+* the Apache License, Version 2.0 (the "License"); you may
+* not use this file except in compliance with the License.
+* You may obtain a copy of the License at
+*
+*  http://www.apache.org/licenses/LICENSE-2.0
+*
+* Unless required by applicable law or agreed to in writing,
+* software distributed under the License is distributed on an
+* "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+* KIND, either express or implied.  See the License for the
+* specific language governing permissions and limitations
+* under the License.
+*/
+
+//! A template anomaly detection service. Fork it and swap in your own loader, plugins and scores.
+//!
+//! It loads the points and the tree parameters from one yaml file, builds the tree, attaches the gaussian and
+//! dirichlet plugins, and serves scores over HTTP. Run it with
+//! `cargo run --release --features anomaly-service --example anomaly_service -- data/service.yml`.
+//! The config is a data config with the tree parameters and these optional service fields.
+//! ```yaml
+//! ---
+//! data_path: DATAMEMMAP
+//! data_dim: 784
+//! leaf_cutoff: 10
+//! min_res_index: -10
+//! address: 127.0.0.1:8080
+//! prior_weight: 1.0
+//! observation_weight: 1.3
+//! window_size: 1000
+//! ```
+//! The endpoints take points as comma separated values, one point to a line.
+//! * `POST /score` scores the points without changing any state.
+//! * `POST /track` adds the points to the tracked sequence and returns the drift stats of the sequence.
+//! * `GET /stats` returns the drift stats of the tracked sequence.
+
+extern crate goko;
+extern crate pointcloud;
+extern crate yaml_rust;
+
+use goko::plugins::distributions::*;
+use goko::*;
+use pointcloud::loaders::*;
+use pointcloud::*;
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use yaml_rust::YamlLoader;
+
+type Tree = CoverTreeWriter<DefaultCloud<L2>>;
+
+struct ServiceConfig {
+    address: String,
+    prior_weight: f64,
+    observation_weight: f64,
+    window_size: usize,
+}
+
+impl ServiceConfig {
+    fn from_yaml(path: &str) -> ServiceConfig {
+        let config = fs::read_to_string(path).expect("Unable to read config file");
+        let params = &YamlLoader::load_from_str(&config).unwrap()[0];
+        ServiceConfig {
+            address: params["address"]
+                .as_str()
+                .unwrap_or("127.0.0.1:8080")
+                .to_string(),
+            prior_weight: params["prior_weight"].as_f64().unwrap_or(1.0),
+            observation_weight: params["observation_weight"].as_f64().unwrap_or(1.3),
+            window_size: params["window_size"].as_i64().unwrap_or(1000) as usize,
+        }
+    }
+}
+
+fn build_tree(path: &str) -> Tree {
+    let point_cloud = ram_from_yaml::<_, L2>(path).unwrap();
+    let builder = CoverTreeBuilder::from_yaml(path);
+    let mut tree = builder.build(Arc::new(point_cloud)).unwrap();
+    tree.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive())
+        .unwrap();
+    tree.add_plugin::<GokoDirichlet>(DirichletTree {}).unwrap();
+    tree.refresh();
+    tree
+}
+
+fn parse_points(body: &str) -> Result<Vec<Vec<f32>>, String> {
+    body.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            l.split(',')
+                .map(|v| v.trim().parse::<f32>().map_err(|e| format!("{}: {}", v, e)))
+                .collect()
+        })
+        .collect()
+}
+
+/// The distance to the nearest neighbor, the mahalanobis distance to the gaussian of the deepest node the point
+/// reaches and how deep that node is.
+fn score(reader: &CoverTreeReader<DefaultCloud<L2>>, point: &[f32]) -> GokoResult<String> {
+    let nearest = reader.knn(point, 1)?;
+    let path = reader.path(point)?;
+    let (_, deepest) = path.last().unwrap();
+    let mahalanobis = reader
+        .get_node_plugin_and::<DiagGaussian, _, _>(*deepest, |g| g.mahalanobis(&point.into()))?
+        .flatten();
+    Ok(format!(
+        "{{\"knn_distance\":{},\"mahalanobis\":{},\"depth\":{}}}",
+        nearest[0].0,
+        mahalanobis.map_or("null".to_string(), |m| m.to_string()),
+        path.len()
+    ))
+}
+
+fn stats_json(stats: &KLDivergenceStats) -> String {
+    format!(
+        "{{\"max\":{},\"min\":{},\"nz_count\":{},\"moment1_nz\":{},\"moment2_nz\":{},\"sequence_len\":{}}}",
+        stats.max, stats.min, stats.nz_count, stats.moment1_nz, stats.moment2_nz, stats.sequence_len
+    )
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Reads the request line and the body, this only understands requests with a `Content-Length`.
+fn read_request(stream: &TcpStream) -> std::io::Result<(String, String, String)> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("").to_string();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let mut fields = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok((method, target, String::from_utf8_lossy(&body).to_string()))
+}
+
+fn handle(
+    stream: &mut TcpStream,
+    tracker: &mut BayesCategoricalTracker<DefaultCloud<L2>>,
+) -> std::io::Result<()> {
+    let (method, target, body) = read_request(stream)?;
+    let reader = tracker.tree_reader().clone();
+    match (method.as_str(), target.as_str()) {
+        ("GET", "/stats") => respond(stream, "200 OK", &stats_json(&tracker.kl_div_stats())),
+        ("POST", "/score") | ("POST", "/track") => {
+            let points = match parse_points(&body) {
+                Ok(points) => points,
+                Err(e) => return respond(stream, "400 Bad Request", &format!("{:?}", e)),
+            };
+            let scored: GokoResult<Vec<String>> =
+                points.iter().map(|p| score(&reader, p)).collect();
+            let scores = match scored {
+                Ok(scores) => scores,
+                Err(e) => return respond(stream, "400 Bad Request", &format!("\"{}\"", e)),
+            };
+            if target == "/score" {
+                return respond(stream, "200 OK", &format!("[{}]", scores.join(",")));
+            }
+            for p in &points {
+                // The points were just scored, so their paths exist
+                tracker.add_path(reader.path(p).unwrap());
+            }
+            respond(stream, "200 OK", &stats_json(&tracker.kl_div_stats()))
+        }
+        _ => respond(stream, "404 Not Found", "\"not found\""),
+    }
+}
+
+fn main() {
+    let config_path = env::args()
+        .nth(1)
+        .expect("Pass the path of the service's yaml config");
+    let config = ServiceConfig::from_yaml(&config_path);
+    let tree = build_tree(&config_path);
+    println!("Tree has {} nodes", tree.reader().node_count());
+
+    let mut tracker = BayesCategoricalTracker::new(
+        config.prior_weight,
+        config.observation_weight,
+        config.window_size,
+        tree.reader(),
+    );
+    let listener = TcpListener::bind(&config.address).unwrap();
+    println!("Serving on {}", config.address);
+    // One connection at a time keeps the tracker's sequence in the order the points arrive
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if let Err(e) = handle(&mut stream, &mut tracker) {
+                    eprintln!("Request failed: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Connection failed: {}", e),
+        }
+    }
+}
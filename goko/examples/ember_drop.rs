@@ -47,7 +47,7 @@ fn build_tree() -> CoverTreeWriter<SimpleLabeledCloud<DataRam<L2>, SmallIntLabel
 
 fn main() {
     let mut ct = build_tree();
-    ct.generate_summaries();
+    ct.generate_summaries().unwrap();
     ct.refresh();
     let ct_reader = ct.reader();
     println!("Tree has {} nodes", ct_reader.node_count());
@@ -60,7 +60,7 @@ fn main() {
         if let Ok(path) = path {
             let mut homogenity_depth = path.len();
             for (i, (_d, a)) in path.iter().enumerate() {
-                let summ = reader.get_node_label_summary(*a).unwrap();
+                let summ = reader.get_node_label_summary(*a).unwrap().unwrap();
                 if summ.summary.items.len() == 1 {
                     homogenity_depth = i;
                     break;
@@ -51,7 +51,7 @@ fn build_test_set() -> SimpleLabeledCloud<DataRam<L2>, VecLabels> {
 
 fn main() {
     let mut ct = build_tree();
-    ct.add_plugin::<GokoDirichlet>(DirichletTree {});
+    ct.add_plugin::<GokoDirichlet>(DirichletTree {}).unwrap();
     let test_set = build_test_set();
     //ct.cluster().unwrap();
     ct.refresh();
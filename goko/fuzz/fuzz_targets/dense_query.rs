@@ -0,0 +1,28 @@
+#![no_main]
+use goko::*;
+use libfuzzer_sys::fuzz_target;
+use pointcloud::*;
+use std::sync::Arc;
+
+thread_local! {
+    static TREE: CoverTreeWriter<DefaultCloud<L2>> = {
+        let data: Vec<f32> = (0..256).map(|i| ((i * 37) % 101) as f32 / 101.0).collect();
+        let cloud = DefaultCloud::<L2>::new(data, 4).unwrap();
+        CoverTreeBuilder::new().build(Arc::new(cloud)).unwrap()
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    let k = data.first().map(|k| *k as usize % 16).unwrap_or(1);
+    let point: Vec<f32> = data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    TREE.with(|tree| {
+        let reader = tree.reader();
+        let _ = reader.knn(&point[..], k);
+        let _ = reader.routing_knn(&point[..], k);
+        let _ = reader.beam_knn(&point[..], k, 2);
+        let _ = reader.path(&point[..]);
+    });
+});
@@ -0,0 +1,25 @@
+#![no_main]
+use goko::utils::load_tree_from_bytes;
+use libfuzzer_sys::fuzz_target;
+use pointcloud::*;
+use std::sync::Arc;
+
+thread_local! {
+    static CLOUD: Arc<DefaultCloud<L2>> = {
+        let data: Vec<f32> = (0..256).map(|i| ((i * 37) % 101) as f32 / 101.0).collect();
+        Arc::new(DefaultCloud::<L2>::new(data, 4).unwrap())
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    CLOUD.with(|cloud| {
+        if let Ok(tree) = load_tree_from_bytes(data, Arc::clone(cloud)) {
+            let reader = tree.reader();
+            let _ = reader.knn(&[0.5f32, 0.5, 0.5, 0.5][..], 5);
+            let _ = reader.path(&[0.5f32, 0.5, 0.5, 0.5][..]);
+            for i in 0..cloud.len() {
+                let _ = reader.known_path(i);
+            }
+        }
+    });
+});
@@ -0,0 +1,37 @@
+#![no_main]
+use goko::*;
+use libfuzzer_sys::fuzz_target;
+use pointcloud::*;
+use std::sync::Arc;
+
+thread_local! {
+    static TREE: CoverTreeWriter<DefaultCloud<L2>> = {
+        let data: Vec<f32> = (0..256).map(|i| ((i * 37) % 101) as f32 / 101.0).collect();
+        let cloud = DefaultCloud::<L2>::new(data, 4).unwrap();
+        CoverTreeBuilder::new().build(Arc::new(cloud)).unwrap()
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte splits the rest into indexes and values, so the two can disagree in length.
+    let split = data
+        .first()
+        .map(|s| *s as usize)
+        .unwrap_or(0)
+        .min(data.len());
+    let (index_bytes, value_bytes) = data.split_at(split);
+    let indexes: Vec<u32> = index_bytes.iter().map(|i| *i as u32).collect();
+    let values: Vec<f32> = value_bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let point = PointRef::Sparse(&values[..], &indexes[..]);
+    TREE.with(|tree| {
+        let reader = tree.reader();
+        let _ = reader.knn(point, 3);
+        let _ = reader.path(point);
+    });
+    let _ = L2::dist(point, point);
+    let _ = L1::dist(point, point);
+    let _ = Linfty::dist(point, point);
+});
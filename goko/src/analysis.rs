@@ -0,0 +1,289 @@
+//! # Analysis
+//!
+//! Reports on how well a built tree fits the assumptions behind the cover tree's guarantees. The bounds are the ones of
+//! Beygelzimer, Kakade and Langford, "Cover Trees for Nearest Neighbor", stated in terms of the expansion constant `c`
+//! of the data: the depth is `O(c^2 log n)`, each node has `O(c^4)` children, and a nearest neighbor query evaluates
+//! `O(c^12 log n)` distances. The constants hidden by the `O` are dropped. The expansion constant is estimated from
+//! the ratio of the sizes of balls of radius `r` and `2r` around the sampled points, at each of the tree's scales.
+//!
+//! The empirical half finds the `k`th nearest neighbor of each sampled point, then walks the tree pruning against that
+//! known distance. It records the pruning slack, how far past the `k`th nearest neighbor the triangle inequality bound
+//! put each pruned node, relative to that neighbor's distance. A lot of slack near zero means the bounds are barely
+//! pruning, and queries will cost close to the worst case.
+
+use crate::*;
+use std::cmp::Ordering;
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter};
+
+/// Quantiles of the relative pruning slack over all pruned nodes of all the sampled queries.
+#[derive(Debug, Clone, Default)]
+pub struct SlackSummary {
+    /// The number of pruned nodes
+    pub count: usize,
+    /// The smallest slack
+    pub min: f32,
+    /// The 10th percentile
+    pub p10: f32,
+    /// The median
+    pub median: f32,
+    /// The 90th percentile
+    pub p90: f32,
+    /// The fraction of pruned nodes with less than 5% slack
+    pub tight_fraction: f32,
+}
+
+impl SlackSummary {
+    fn from_slacks(mut slacks: Vec<f32>) -> SlackSummary {
+        if slacks.is_empty() {
+            return SlackSummary::default();
+        }
+        slacks.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let quantile = |q: f32| slacks[((slacks.len() - 1) as f32 * q).round() as usize];
+        SlackSummary {
+            count: slacks.len(),
+            min: slacks[0],
+            p10: quantile(0.1),
+            median: quantile(0.5),
+            p90: quantile(0.9),
+            tight_fraction: slacks.iter().filter(|s| **s < 0.05).count() as f32
+                / slacks.len() as f32,
+        }
+    }
+
+    fn to_yaml(&self) -> Yaml {
+        let mut hash = Hash::new();
+        insert_int(&mut hash, "count", self.count);
+        insert_real(&mut hash, "min", self.min as f64);
+        insert_real(&mut hash, "p10", self.p10 as f64);
+        insert_real(&mut hash, "median", self.median as f64);
+        insert_real(&mut hash, "p90", self.p90 as f64);
+        insert_real(&mut hash, "tight_fraction", self.tight_fraction as f64);
+        Yaml::Hash(hash)
+    }
+}
+
+/// The theoretical bounds for the tree and the empirical behavior of the sampled queries.
+#[derive(Debug, Clone)]
+pub struct GuaranteeReport {
+    /// The number of points in the tree
+    pub point_count: usize,
+    /// The number of nodes in the tree
+    pub node_count: usize,
+    /// The number of layers between the root and the deepest node
+    pub depth: usize,
+    /// The most children any node has, counting the nested child
+    pub max_children: usize,
+    /// The largest ball growth ratio seen, the estimate of the expansion constant
+    pub expansion_constant: f32,
+    /// The median ball growth ratio, a less pessimistic estimate
+    pub median_expansion: f32,
+    /// `c^2 log2(n)`
+    pub depth_bound: f64,
+    /// `c^4`
+    pub children_bound: f64,
+    /// `c^12 log2(n)`
+    pub query_cost_bound: f64,
+    /// The number of sampled queries
+    pub query_count: usize,
+    /// The number of neighbors each query asked for
+    pub k: usize,
+    /// The mean number of distance evaluations per query, for a traversal that prunes against the known `k`th
+    /// neighbor distance
+    pub mean_distance_evaluations: f32,
+    /// The most distance evaluations any query needed, counted the same way
+    pub max_distance_evaluations: usize,
+    /// The pruning slack of the sampled queries
+    pub pruning_slack: SlackSummary,
+}
+
+fn insert_int(hash: &mut Hash, key: &str, value: usize) {
+    hash.insert(Yaml::String(key.to_string()), Yaml::Integer(value as i64));
+}
+
+fn insert_real(hash: &mut Hash, key: &str, value: f64) {
+    hash.insert(Yaml::String(key.to_string()), Yaml::Real(value.to_string()));
+}
+
+impl GuaranteeReport {
+    /// The report as a yaml document.
+    pub fn to_yaml(&self) -> String {
+        let mut hash = Hash::new();
+        insert_int(&mut hash, "point_count", self.point_count);
+        insert_int(&mut hash, "node_count", self.node_count);
+        insert_int(&mut hash, "depth", self.depth);
+        insert_int(&mut hash, "max_children", self.max_children);
+        insert_real(
+            &mut hash,
+            "expansion_constant",
+            self.expansion_constant as f64,
+        );
+        insert_real(&mut hash, "median_expansion", self.median_expansion as f64);
+        insert_real(&mut hash, "depth_bound", self.depth_bound);
+        insert_real(&mut hash, "children_bound", self.children_bound);
+        insert_real(&mut hash, "query_cost_bound", self.query_cost_bound);
+        insert_int(&mut hash, "query_count", self.query_count);
+        insert_int(&mut hash, "k", self.k);
+        insert_real(
+            &mut hash,
+            "mean_distance_evaluations",
+            self.mean_distance_evaluations as f64,
+        );
+        insert_int(
+            &mut hash,
+            "max_distance_evaluations",
+            self.max_distance_evaluations,
+        );
+        hash.insert(
+            Yaml::String("pruning_slack".to_string()),
+            self.pruning_slack.to_yaml(),
+        );
+        let mut out = String::new();
+        YamlEmitter::new(&mut out)
+            .dump(&Yaml::Hash(hash))
+            .expect("Writing to a string can't fail");
+        out
+    }
+}
+
+/// The ratios `|B(p, 2r)| / |B(p, r)|` around the point, for `r` at each scale of the tree where the smaller ball
+/// isn't already the whole dataset.
+fn ball_growth<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    point_index: PointIndex,
+) -> GokoResult<Vec<f32>> {
+    let point = reader.point_cloud().point(point_index)?;
+    let total = reader.point_cloud().len();
+    let mut ratios = Vec::new();
+    for scale_index in reader.scale_range() {
+        let radius = reader.scale(scale_index);
+        let inner = reader.range_query(point, radius)?.len();
+        if inner >= total {
+            continue;
+        }
+        let outer = reader.range_query(point, 2.0 * radius)?.len();
+        ratios.push(outer as f32 / inner.max(1) as f32);
+    }
+    Ok(ratios)
+}
+
+/// Walks the tree from the root, pruning a node when the triangle inequality puts it past `kth_dist`, the true `k`th
+/// neighbor distance, and visiting everything else. This is the traversal of a query that knew its answer up front,
+/// not the one `knn` does. Returns the number of distances that traversal needs, counting each visited node's children
+/// and singletons without computing the singletons' distances, and the slack of each pruned node relative to
+/// `kth_dist` (absolute when `kth_dist` is 0).
+fn pruning_trace<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    point: PointRef,
+    kth_dist: f32,
+) -> GokoResult<(usize, Vec<f32>)> {
+    let point_cloud = reader.point_cloud();
+    let root = reader.root_address();
+    let mut to_visit = vec![(D::Metric::dist(point_cloud.point(root.1)?, point)?, root)];
+    let mut evaluations = 1;
+    let mut slacks = Vec::new();
+    let scale = if kth_dist > 0.0 { kth_dist } else { 1.0 };
+    while let Some((dist, address)) = to_visit.pop() {
+        reader.get_node_and(address, |n| -> GokoResult<()> {
+            let lower_bound = dist - n.radius().max(0.0);
+            if lower_bound > kth_dist {
+                slacks.push((lower_bound - kth_dist) / scale);
+                return Ok(());
+            }
+            evaluations += n.singletons_len();
+            if let Some((nested_scale, children)) = n.children() {
+                to_visit.push((dist, (nested_scale, address.1)));
+                let child_indexes: Vec<PointIndex> = children.iter().map(|(_, pi)| *pi).collect();
+                let child_dists = point_cloud.distances_to_point(point, &child_indexes)?;
+                evaluations += child_dists.len();
+                to_visit.extend(child_dists.into_iter().zip(children.iter().cloned()));
+            }
+            Ok(())
+        })??;
+    }
+    Ok((evaluations, slacks))
+}
+
+/// Builds the report, using the given points as the sample for both the expansion constant and the queries. The
+/// queries exclude the point itself, like `knn_by_index`.
+pub fn guarantee_report<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    sample_indexes: &[PointIndex],
+    k: usize,
+) -> GokoResult<GuaranteeReport> {
+    let mut node_count = 0;
+    let mut max_children = 0;
+    let mut occupied_layers = 0;
+    for (_, layer) in reader.layers() {
+        if !layer.is_empty() {
+            occupied_layers += 1;
+        }
+        layer.for_each_node(|_, n| {
+            node_count += 1;
+            max_children = max_children.max(n.children_len());
+        });
+    }
+
+    let mut ratios = Vec::new();
+    let mut evaluations = Vec::with_capacity(sample_indexes.len());
+    let mut slacks = Vec::new();
+    for pi in sample_indexes {
+        ratios.extend(ball_growth(reader, *pi)?);
+        let knn = reader.knn_by_index(*pi, k)?;
+        let kth_dist = knn.last().map(|(d, _)| *d).unwrap_or(0.0);
+        let point = reader.point_cloud().point(*pi)?;
+        let (evals, query_slacks) = pruning_trace(reader, point, kth_dist)?;
+        evaluations.push(evals);
+        slacks.extend(query_slacks);
+    }
+    ratios.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let expansion_constant = ratios.last().cloned().unwrap_or(1.0);
+    let median_expansion = ratios.get(ratios.len() / 2).cloned().unwrap_or(1.0);
+
+    let n = reader.point_cloud().len();
+    let log_n = (n.max(2) as f64).log2();
+    let c = expansion_constant as f64;
+    Ok(GuaranteeReport {
+        point_count: n,
+        node_count,
+        depth: occupied_layers,
+        max_children,
+        expansion_constant,
+        median_expansion,
+        depth_bound: c.powi(2) * log_n,
+        children_bound: c.powi(4),
+        query_cost_bound: c.powi(12) * log_n,
+        query_count: sample_indexes.len(),
+        k,
+        mean_distance_evaluations: evaluations.iter().sum::<usize>() as f32
+            / evaluations.len().max(1) as f32,
+        max_distance_evaluations: evaluations.iter().cloned().max().unwrap_or(0),
+        pruning_slack: SlackSummary::from_slacks(slacks),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn report_sanity() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        let sample: Vec<PointIndex> = (0..reader.point_cloud().len()).collect();
+        let report = guarantee_report(&reader, &sample, 1).unwrap();
+        assert_eq!(report.node_count, reader.node_count());
+        assert_eq!(report.query_count, sample.len());
+        assert!(report.expansion_constant >= report.median_expansion);
+        assert!(report.median_expansion >= 1.0);
+        assert!(report.mean_distance_evaluations >= 1.0);
+        assert!(report.pruning_slack.min >= 0.0);
+        let yaml = yaml_rust::YamlLoader::load_from_str(&report.to_yaml()).unwrap();
+        assert_eq!(
+            yaml[0]["node_count"].as_i64(),
+            Some(report.node_count as i64)
+        );
+        assert!(yaml[0]["pruning_slack"]["median"].as_f64().is_some());
+    }
+}
@@ -0,0 +1,516 @@
+//! # Clustering
+//!
+//! The tree is a hierarchical clustering of the data, each node is a cluster of the points it covers and its children
+//! split it into smaller clusters. These cut the tree to get a flat clustering.
+//!
+//! A cut at a scale keeps the nodes whose scale is at most the cut, the leaves above it, and makes every singleton of a
+//! node above it a cluster of its own. Singletons are points that were too far from the node's children to join them,
+//! so they are outliers at that scale. If the `GokoCoverageIndexes` plugin is on the tree, without a cap, the clusters
+//! are read from it rather than walking the subtree of each node.
+//!
+//! `CoverTreeReader::condensed_clusters` picks clusters at different scales, like HDBSCAN. It condenses the tree into
+//! the clusters of at least a minimum size, with the inverse of the scale as HDBSCAN's `lambda`, and picks the most
+//! stable ones with the excess of mass rule.
+
+use crate::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A cluster of a cut, a node or a singleton of a node above the cut.
+enum Cut {
+    Node(NodeAddress),
+    Singleton(PointIndex),
+}
+
+impl<D: PointCloud> CoverTreeReader<D> {
+    /// The nodes with scale index at most `scale_index`, or leaves above it, and the singletons of the nodes above.
+    fn cut(&self, scale_index: i32) -> GokoResult<Vec<Cut>> {
+        let mut cut = Vec::new();
+        let mut to_visit = vec![self.root_address()];
+        while let Some(address) = to_visit.pop() {
+            if address.0 <= scale_index {
+                cut.push(Cut::Node(address));
+                continue;
+            }
+            self.get_node_and(address, |n| match n.children() {
+                Some((nested_scale, children)) => {
+                    cut.extend(n.singletons().iter().map(|pi| Cut::Singleton(*pi)));
+                    to_visit.push((nested_scale, address.1));
+                    to_visit.extend(children);
+                }
+                None => cut.push(Cut::Node(address)),
+            })?;
+        }
+        Ok(cut)
+    }
+
+    fn cut_memberships(&self, cut: Vec<Cut>) -> GokoResult<Vec<Vec<PointIndex>>> {
+        cut.into_iter()
+            .map(|c| match c {
+                Cut::Node(address) => {
+                    let mut points = self.covered_indexes(address)?;
+                    points.sort_unstable();
+                    Ok(points)
+                }
+                Cut::Singleton(pi) => Ok(vec![pi]),
+            })
+            .collect()
+    }
+
+    /// Cuts the tree at the scale and returns the points of each cluster, see the module docs. Every point is in
+    /// exactly one cluster. A cut above the root's scale gives one cluster of all the points.
+    pub fn partition_at_scale(&self, scale: f32) -> GokoResult<Vec<Vec<PointIndex>>> {
+        let scale_index = scale.log(self.parameters().scale_base).floor();
+        let scale_index = scale_index.max(i32::MIN as f32).min(i32::MAX as f32) as i32;
+        let cut = self.cut(scale_index)?;
+        self.cut_memberships(cut)
+    }
+
+    /// Cuts the tree at the scale that gives the number of clusters closest to `k`, and returns the points of each
+    /// cluster. The cuts only happen at the tree's scales, so there may not be a cut with exactly `k` clusters.
+    pub fn partition_into(&self, k: usize) -> GokoResult<Vec<Vec<PointIndex>>> {
+        let mut best = self.cut(self.root_address().0)?;
+        for scale_index in self.scale_range().rev() {
+            if best.len() >= k {
+                break;
+            }
+            let cut = self.cut(scale_index)?;
+            if (cut.len() as i64 - k as i64).abs() <= (best.len() as i64 - k as i64).abs() {
+                best = cut;
+            } else {
+                break;
+            }
+        }
+        self.cut_memberships(best)
+    }
+
+    /// Returns `min(k, len)` well spread points, for seeding k-means or as a coreset. This is a farthest first
+    /// traversal read off the tree. The node with the largest radius is opened and its children's centers and its
+    /// singletons are added, farthest from its center first, until there are `k` points. The first point is the
+    /// root's center.
+    pub fn k_centers(&self, k: usize) -> GokoResult<Vec<PointIndex>> {
+        let mut centers = Vec::with_capacity(k);
+        if k == 0 {
+            return Ok(centers);
+        }
+        let root = self.root_address();
+        centers.push(root.1);
+        // Non negative floats order the same as their bits
+        let radius_key =
+            |address: NodeAddress| self.get_node_and(address, |n| n.radius().max(0.0).to_bits());
+        let mut to_open: BinaryHeap<(u32, NodeAddress)> = BinaryHeap::new();
+        to_open.push((radius_key(root)?, root));
+        while let Some((_, address)) = to_open.pop() {
+            if centers.len() >= k {
+                break;
+            }
+            let (children, singletons) = self.get_node_and(address, |n| {
+                let children = n
+                    .children()
+                    .map(|(nested_scale, children)| (nested_scale, children.to_vec()));
+                (children, n.singletons().to_vec())
+            })?;
+            let mut candidates = singletons;
+            if let Some((nested_scale, children)) = children {
+                let nested = (nested_scale, address.1);
+                to_open.push((radius_key(nested)?, nested));
+                for child in children {
+                    to_open.push((radius_key(child)?, child));
+                    candidates.push(child.1);
+                }
+            }
+            let dists = self
+                .point_cloud()
+                .distances_to_point_index(address.1, &candidates)?;
+            let mut candidates: Vec<(f32, PointIndex)> =
+                dists.into_iter().zip(candidates).collect();
+            candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+            let room = k - centers.len();
+            centers.extend(candidates.into_iter().take(room).map(|(_, pi)| pi));
+        }
+        Ok(centers)
+    }
+}
+
+/// The flat clustering picked by `CoverTreeReader::condensed_clusters`.
+#[derive(Debug, Clone)]
+pub struct CondensedClusters {
+    /// The cluster of each point, indexed by point index, `None` for noise
+    pub labels: Vec<Option<usize>>,
+    /// How strongly each point belongs to its cluster, from 0 to 1. This is the `lambda` at which the point left the
+    /// tree's clusters, over the largest such `lambda` in its cluster. Noise has probability 0.
+    pub probabilities: Vec<f32>,
+    /// The node each cluster was born at
+    pub addresses: Vec<NodeAddress>,
+    /// The stability of each cluster, the excess of mass that picked it
+    pub stabilities: Vec<f32>,
+}
+
+impl CondensedClusters {
+    /// The number of clusters.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// If every point is noise.
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+/// A cluster of the condensed tree.
+struct CondensedNode {
+    parent: Option<usize>,
+    address: NodeAddress,
+    birth: f32,
+    stability: f32,
+    children: Vec<usize>,
+}
+
+impl<D: PointCloud> CoverTreeReader<D> {
+    /// Condenses the tree into the clusters that cover at least `min_cluster_size` points and picks the most stable
+    /// of them, see the module docs. The tree is walked down one scale at a time, with each cluster held as the nodes
+    /// it has at the current scale. The nodes of a cluster are grouped by overlap, two nodes are connected if the gap
+    /// between their balls is less than the scale. The groups that cover fewer than `min_cluster_size` points, and
+    /// the singletons, fall out of the cluster as noise. If one group is large enough the cluster carries on as that
+    /// group, and if several are they're born as new clusters. The `lambda` of a scale is its inverse.
+    ///
+    /// A split of a node isn't a split of its cluster, its children overlap unless there's a gap in the data between
+    /// them. Unless `allow_single_cluster`, the root's cluster can't be picked, as in HDBSCAN. This compares every
+    /// pair of nodes of a cluster at each scale, so on large trees set a `leaf_cutoff` that keeps the bottom coarse.
+    pub fn condensed_clusters(
+        &self,
+        min_cluster_size: usize,
+        allow_single_cluster: bool,
+    ) -> GokoResult<CondensedClusters> {
+        let min_cluster_size = min_cluster_size.max(1);
+        let lambda = |scale_index: i32| 1.0 / self.scale(scale_index);
+        let frontier_node = |address: NodeAddress| {
+            self.get_node_and(address, |n| (address, n.radius(), n.coverage_count()))
+        };
+        let mut clusters = vec![CondensedNode {
+            parent: None,
+            address: self.root_address(),
+            birth: 0.0,
+            stability: 0.0,
+            children: Vec::new(),
+        }];
+        // The cluster each point fell out of, and the lambda it fell out at
+        let mut fell_out: Vec<(usize, PointIndex, f32)> =
+            Vec::with_capacity(self.point_cloud().len());
+        // The live clusters, with their nodes at the current scale
+        let mut live = vec![(0, vec![frontier_node(self.root_address())?])];
+        let mut scale_index = self.root_address().0;
+        while !live.is_empty() && scale_index >= self.scale_range().start {
+            scale_index -= 1;
+            let l = lambda(scale_index);
+            let mut next_live = Vec::new();
+            for (cluster, nodes) in live.drain(..) {
+                let mut expanded = Vec::with_capacity(nodes.len());
+                for node in nodes {
+                    let address = node.0;
+                    if address.0 <= scale_index {
+                        expanded.push(node);
+                        continue;
+                    }
+                    let (children, singletons) = self.get_node_and(address, |n| {
+                        let children = n.children().map(|(nested_scale, children)| {
+                            let mut c = vec![(nested_scale, address.1)];
+                            c.extend(children);
+                            c
+                        });
+                        (children, n.singletons().to_vec())
+                    })?;
+                    match children {
+                        Some(children) => {
+                            fell_out.extend(singletons.into_iter().map(|pi| (cluster, pi, l)));
+                            for child in children {
+                                expanded.push(frontier_node(child)?);
+                            }
+                        }
+                        None => {
+                            let leaf_lambda = lambda(address.0);
+                            fell_out.push((cluster, address.1, leaf_lambda));
+                            fell_out.extend(
+                                singletons.into_iter().map(|pi| (cluster, pi, leaf_lambda)),
+                            );
+                        }
+                    }
+                }
+                let mut groups = self.overlapping_groups(&expanded, self.scale(scale_index))?;
+                let mut large = Vec::new();
+                for group in groups.drain(..) {
+                    let coverage: usize = group.iter().map(|(_, _, c)| c).sum();
+                    if coverage >= min_cluster_size {
+                        large.push((group, coverage));
+                    } else {
+                        for (address, _, _) in group {
+                            let points = self.covered_indexes(address)?;
+                            fell_out.extend(points.into_iter().map(|pi| (cluster, pi, l)));
+                        }
+                    }
+                }
+                if large.len() == 1 {
+                    next_live.push((cluster, large.pop().unwrap().0));
+                    continue;
+                }
+                for (group, coverage) in large {
+                    let birth = clusters[cluster].birth;
+                    clusters[cluster].stability += (l - birth) * coverage as f32;
+                    let id = clusters.len();
+                    // The cluster is named after its largest node
+                    let address = group.iter().max_by_key(|(_, _, c)| *c).unwrap().0;
+                    clusters.push(CondensedNode {
+                        parent: Some(cluster),
+                        address,
+                        birth: l,
+                        stability: 0.0,
+                        children: Vec::new(),
+                    });
+                    clusters[cluster].children.push(id);
+                    next_live.push((id, group));
+                }
+            }
+            live = next_live;
+        }
+        for (cluster, nodes) in live {
+            for (address, _, _) in nodes {
+                let points = self.covered_indexes(address)?;
+                let l = lambda(address.0);
+                fell_out.extend(points.into_iter().map(|pi| (cluster, pi, l)));
+            }
+        }
+        for (cluster, _, l) in &fell_out {
+            let birth = clusters[*cluster].birth;
+            clusters[*cluster].stability += l - birth;
+        }
+
+        // Children are always after their parents, so going backwards sees every child before its parent
+        let mut selected = vec![false; clusters.len()];
+        let mut subtree_stability = vec![0.0f32; clusters.len()];
+        for c in (0..clusters.len()).rev() {
+            let children_stability: f32 = clusters[c]
+                .children
+                .iter()
+                .map(|child| subtree_stability[*child])
+                .sum();
+            let can_pick = c != 0 || allow_single_cluster || clusters[c].children.is_empty();
+            if can_pick
+                && (clusters[c].children.is_empty() || clusters[c].stability >= children_stability)
+            {
+                selected[c] = true;
+                subtree_stability[c] = clusters[c].stability;
+            } else {
+                subtree_stability[c] = children_stability;
+            }
+        }
+        if !allow_single_cluster && clusters.len() == 1 {
+            selected[0] = false;
+        }
+        // The picked cluster each cluster is part of, the highest selected one above it
+        let mut label_of: Vec<Option<usize>> = vec![None; clusters.len()];
+        let mut addresses = Vec::new();
+        let mut stabilities = Vec::new();
+        for c in 0..clusters.len() {
+            label_of[c] = match clusters[c].parent.and_then(|p| label_of[p]) {
+                Some(label) => Some(label),
+                None if selected[c] => {
+                    addresses.push(clusters[c].address);
+                    stabilities.push(clusters[c].stability);
+                    Some(addresses.len() - 1)
+                }
+                None => None,
+            };
+        }
+
+        let len = fell_out
+            .iter()
+            .map(|(_, pi, _)| pi + 1)
+            .max()
+            .unwrap_or(0)
+            .max(self.point_cloud().len());
+        let mut labels = vec![None; len];
+        let mut probabilities = vec![0.0; len];
+        let mut max_lambda = vec![0.0f32; addresses.len()];
+        for (cluster, pi, l) in &fell_out {
+            if let Some(label) = label_of[*cluster] {
+                labels[*pi] = Some(label);
+                probabilities[*pi] = *l;
+                max_lambda[label] = max_lambda[label].max(*l);
+            }
+        }
+        for (label, probability) in labels.iter().zip(probabilities.iter_mut()) {
+            if let Some(label) = label {
+                *probability /= max_lambda[*label];
+            }
+        }
+        Ok(CondensedClusters {
+            labels,
+            probabilities,
+            addresses,
+            stabilities,
+        })
+    }
+
+    /// Groups the nodes into the connected components of the graph where two nodes are joined if the gap between
+    /// their balls, the distance between the centers less both radii, is at most `scale`.
+    fn overlapping_groups(
+        &self,
+        nodes: &[(NodeAddress, f32, usize)],
+        scale: f32,
+    ) -> GokoResult<Vec<Vec<(NodeAddress, f32, usize)>>> {
+        let mut roots: Vec<usize> = (0..nodes.len()).collect();
+        fn find(roots: &mut [usize], mut i: usize) -> usize {
+            while roots[i] != i {
+                roots[i] = roots[roots[i]];
+                i = roots[i];
+            }
+            i
+        }
+        let centers: Vec<PointIndex> = nodes.iter().map(|(a, _, _)| a.1).collect();
+        for i in 1..nodes.len() {
+            let dists = self
+                .point_cloud()
+                .distances_to_point_index(centers[i], &centers[..i])?;
+            for (j, d) in dists.iter().enumerate() {
+                if d - nodes[i].1 - nodes[j].1 <= scale {
+                    let (a, b) = (find(&mut roots, i), find(&mut roots, j));
+                    roots[a] = b;
+                }
+            }
+        }
+        let mut groups: Vec<Vec<(NodeAddress, f32, usize)>> = Vec::new();
+        let mut group_of = vec![usize::MAX; nodes.len()];
+        for (i, node) in nodes.iter().enumerate() {
+            let root = find(&mut roots, i);
+            if group_of[root] == usize::MAX {
+                group_of[root] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[group_of[root]].push(*node);
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use crate::plugins::utils::GokoCoverageIndexes;
+    use std::sync::Arc;
+
+    #[test]
+    fn partitions_cover_every_point_once() {
+        let mut tree = build_basic_tree();
+        let reader = tree.reader();
+        let all_points: Vec<PointIndex> = (0..reader.point_cloud().len()).collect();
+        let flatten = |clusters: &[Vec<PointIndex>]| {
+            let mut points: Vec<PointIndex> = clusters.iter().flatten().copied().collect();
+            points.sort_unstable();
+            points
+        };
+
+        let top = reader.partition_at_scale(1000.0).unwrap();
+        assert_eq!(top, vec![all_points.clone()]);
+        let bottom = reader.partition_at_scale(1e-9).unwrap();
+        assert_eq!(flatten(&bottom), all_points);
+        // The first three points are within 0.02 of each other, the other two are far from everything
+        let mut halves = reader.partition_at_scale(0.25).unwrap();
+        halves.sort();
+        assert_eq!(halves, vec![vec![0, 1, 2], vec![3], vec![4]]);
+
+        for k in 1..=all_points.len() {
+            let clusters = reader.partition_into(k).unwrap();
+            assert_eq!(flatten(&clusters), all_points);
+        }
+        assert_eq!(reader.partition_into(1).unwrap().len(), 1);
+        assert_eq!(reader.partition_into(3).unwrap().len(), 3);
+        assert_eq!(reader.partition_into(100).unwrap().len(), bottom.len());
+
+        tree.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::new())
+            .unwrap();
+        let mut from_plugin = tree.reader().partition_at_scale(0.25).unwrap();
+        from_plugin.sort();
+        assert_eq!(from_plugin, halves);
+    }
+
+    #[test]
+    fn condensed_clusters_find_the_blobs() {
+        let mixture = pointcloud::synthetic::GaussianMixture {
+            count: 900,
+            dim: 2,
+            clusters: 3,
+            cluster_std: 0.3,
+            spread: 20.0,
+            seed: 3,
+        };
+        let (data, truth) = mixture.data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 2).unwrap());
+        let builder = CoverTreeBuilder {
+            leaf_cutoff: 5,
+            ..CoverTreeBuilder::new()
+        };
+        let tree = builder.build_deterministic(point_cloud, 0).unwrap();
+        let reader = tree.reader();
+
+        let clusters = reader.condensed_clusters(50, false).unwrap();
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters.labels.len(), 900);
+        // Each cluster is one of the blobs
+        let mut blob_of = vec![None; 3];
+        let mut noise = 0;
+        for (label, blob) in clusters.labels.iter().zip(&truth) {
+            match label {
+                Some(label) => {
+                    let blob_of = blob_of[*label].get_or_insert(*blob);
+                    assert_eq!(blob_of, blob);
+                }
+                None => noise += 1,
+            }
+        }
+        assert!(noise < 90, "{} of the points are noise", noise);
+        assert!(clusters
+            .probabilities
+            .iter()
+            .all(|p| (0.0..=1.0).contains(p)));
+        assert!(clusters.probabilities.iter().any(|p| *p == 1.0));
+
+        let single = reader.condensed_clusters(1000, true).unwrap();
+        assert_eq!(single.len(), 1);
+        assert!(single.labels.iter().all(|l| *l == Some(0)));
+        assert!(reader.condensed_clusters(1000, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn k_centers_are_spread_out() {
+        let (data, truth) = pointcloud::synthetic::GaussianMixture {
+            count: 800,
+            dim: 2,
+            clusters: 4,
+            cluster_std: 0.2,
+            spread: 20.0,
+            seed: 1,
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 2).unwrap());
+        let tree = CoverTreeBuilder::new()
+            .build_deterministic(point_cloud, 0)
+            .unwrap();
+        let reader = tree.reader();
+
+        let centers = reader.k_centers(4).unwrap();
+        assert_eq!(centers[0], reader.root_address().1);
+        let mut blobs: Vec<i64> = centers.iter().map(|pi| truth[*pi]).collect();
+        blobs.sort_unstable();
+        assert_eq!(blobs, vec![0, 1, 2, 3]);
+
+        assert!(reader.k_centers(0).unwrap().is_empty());
+        // Every point is picked once
+        let mut all = reader.k_centers(10_000).unwrap();
+        assert_eq!(all.len(), 800);
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), 800);
+    }
+}
@@ -22,24 +22,65 @@ use super::layer::*;
 use super::node::*;
 use super::*;
 use crate::plugins::TreePluginSet;
+use crate::runtime::GokoRuntime;
 use crate::*;
 use pbr::ProgressBar;
+use pointcloud::data_sources::NormalizedCloud;
+use pointcloud::metric_audit::audit_metric;
+use rand::rngs::StdRng;
+use rand::{thread_rng, SeedableRng};
 use std::cmp::{max, min};
-use std::fs::read_to_string;
-use std::path::Path;
+use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+use std::io::Stdout;
+use std::path::{Path, PathBuf};
 use std::sync::{atomic, Arc, RwLock};
 use yaml_rust::YamlLoader;
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use errors::GokoResult;
+use errors::{GokoError, GokoResult};
 
 use std::time::Instant;
 
+/// What a stopping criterion sees of a node before it's split.
 #[derive(Debug)]
+pub struct NodeStats<'a> {
+    /// The address the node will have
+    pub address: NodeAddress,
+    /// The address of the node's parent, `None` for the root
+    pub parent_address: Option<NodeAddress>,
+    /// The distance from the center to the farthest covered point
+    pub radius: f32,
+    /// The covered points, not counting the center
+    pub covered: &'a [PointIndex],
+}
+
+impl<'a> NodeStats<'a> {
+    /// The number of points the node covers, counting the center.
+    pub fn coverage_count(&self) -> usize {
+        self.covered.len() + 1
+    }
+}
+
+type StopCriterion = Arc<dyn Fn(&NodeStats) -> bool + Send + Sync>;
+
 struct BuilderNode {
     parent_address: Option<NodeAddress>,
     scale_index: i32,
     covered: CoveredData,
+    should_stop: Option<StopCriterion>,
+    seed: Option<u64>,
+    /// Split this node even if it would otherwise be a leaf, the children don't inherit this
+    force_split: bool,
+}
+
+impl std::fmt::Debug for BuilderNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BuilderNode")
+            .field("parent_address", &self.parent_address)
+            .field("scale_index", &self.scale_index)
+            .field("covered", &self.covered)
+            .finish()
+    }
 }
 
 type NodeSplitResult<D> = GokoResult<(i32, PointIndex, CoverNode<D>)>;
@@ -48,20 +89,25 @@ impl BuilderNode {
     fn new<D: PointCloud>(
         parameters: &CoverTreeParameters<D>,
         partition_type: PartitionType,
+        parallel: bool,
     ) -> GokoResult<BuilderNode> {
         let covered = match partition_type {
             PartitionType::Nearest => CoveredData::NearestCoveredData(
-                NearestCoveredData::new::<D>(&parameters.point_cloud)?,
+                NearestCoveredData::new::<D>(&parameters.point_cloud, parallel)?,
             ),
-            PartitionType::First => {
-                CoveredData::FirstCoveredData(FirstCoveredData::new::<D>(&parameters.point_cloud)?)
-            }
+            PartitionType::First => CoveredData::FirstCoveredData(FirstCoveredData::new::<D>(
+                &parameters.point_cloud,
+                parallel,
+            )?),
         };
         let scale_index = (covered.max_distance()).log(parameters.scale_base).ceil() as i32;
         Ok(BuilderNode {
             parent_address: None,
             scale_index,
             covered,
+            should_stop: None,
+            seed: None,
+            force_split: false,
         })
     }
 
@@ -70,6 +116,22 @@ impl BuilderNode {
         (self.scale_index, self.covered.center_index())
     }
 
+    /// The rng used to pick the centers of this node's children. With a seed it only depends on the seed and the
+    /// node's address, so it's the same no matter which thread splits the node or when.
+    fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => {
+                let (scale_index, center_index) = self.address();
+                let mut x = seed ^ (scale_index as u64).rotate_left(32) ^ (center_index as u64);
+                // splitmix64 finalizer, so that nearby addresses get unrelated streams
+                x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                StdRng::seed_from_u64(x ^ (x >> 31))
+            }
+            None => StdRng::from_rng(thread_rng()).unwrap(),
+        }
+    }
+
     fn split_parallel<D: PointCloud>(
         self,
         parameters: &Arc<CoverTreeParameters<D>>,
@@ -91,6 +153,27 @@ impl BuilderNode {
         });
     }
 
+    /// Splits this node, then builds the subtrees of its children concurrently on rayon's pool. Each child only
+    /// covers its own part of the points, so the subtrees are independent. They're merged after this node in the
+    /// order of the children, so the result doesn't depend on how the pool schedules them.
+    fn build_subtree<D: PointCloud>(
+        self,
+        parameters: &Arc<CoverTreeParameters<D>>,
+    ) -> GokoResult<Vec<(i32, PointIndex, CoverNode<D>)>> {
+        let (si, pi) = self.address();
+        let (new_node, new_nodes) = self.split(parameters)?;
+        let subtrees = new_nodes
+            .into_par_iter()
+            .map(|node| node.build_subtree(parameters))
+            .collect::<GokoResult<Vec<_>>>()?;
+        let mut built = Vec::with_capacity(1 + subtrees.iter().map(|s| s.len()).sum::<usize>());
+        built.push((si, pi, new_node));
+        for subtree in subtrees {
+            built.extend(subtree);
+        }
+        Ok(built)
+    }
+
     fn split<D: PointCloud>(
         self,
         parameters: &Arc<CoverTreeParameters<D>>,
@@ -100,11 +183,27 @@ impl BuilderNode {
         let mut node = CoverNode::new(self.parent_address, current_address);
         let radius = self.covered.max_distance();
         node.set_radius(radius);
+        let should_stop = self.should_stop.clone();
+        let seed = self.seed;
+        let mut rng = self.rng();
+        let stopped = should_stop
+            .as_ref()
+            .map(|should_stop| {
+                should_stop(&NodeStats {
+                    address: current_address,
+                    parent_address: self.parent_address,
+                    radius: radius.max(0.0),
+                    covered: self.covered.indexes(),
+                })
+            })
+            .unwrap_or(false);
         /* Occasionally there's a small cluster split off of at a low min_res_index.
         This brings the scale-index down/min_res_index up quickly, locally.
         */
-        let mut new_nodes = if self.covered.len() <= parameters.leaf_cutoff
-            || scale_index < parameters.min_res_index
+        let mut new_nodes = if !self.force_split
+            && (self.covered.len() <= parameters.leaf_cutoff
+                || scale_index < parameters.min_res_index
+                || stopped)
         {
             //println!("== This is getting cut down by parameters ==");
             node.insert_singletons(self.covered.into_indexes());
@@ -124,6 +223,7 @@ impl BuilderNode {
                     covered,
                     next_scale_index,
                     parameters,
+                    &mut rng,
                 )?,
                 CoveredData::NearestCoveredData(covered) => BuilderNode::split_nearest(
                     &mut node,
@@ -131,6 +231,7 @@ impl BuilderNode {
                     covered,
                     next_scale_index,
                     parameters,
+                    &mut rng,
                 )?,
             }
         };
@@ -143,6 +244,11 @@ impl BuilderNode {
             node.insert_singletons(new_nodes.pop().unwrap().covered.into_indexes());
         }
 
+        for new_node in new_nodes.iter_mut() {
+            new_node.should_stop = should_stop.clone();
+            new_node.seed = seed;
+        }
+
         // This node is done, send it in
         //println!("=====================");
         Ok((node, new_nodes))
@@ -154,16 +260,19 @@ impl BuilderNode {
         covered: NearestCoveredData,
         split_scale_index: i32,
         parameters: &Arc<CoverTreeParameters<D>>,
+        rng: &mut StdRng,
     ) -> GokoResult<Vec<BuilderNode>> {
         let next_scale = parameters.scale_base.powi(split_scale_index);
-        let (nested_potential, mut splits) = covered.split(next_scale, &parameters.point_cloud)?;
+        let (nested_potential, mut splits) =
+            covered.split(next_scale, &parameters.point_cloud, rng)?;
         let mut new_nodes = Vec::new();
 
         let mut inserts = Vec::new();
 
         for potential in splits.drain(0..) {
-            if potential.len() == 1 && parameters.use_singletons {
+            if potential.len() <= parameters.singleton_threshold {
                 parent_node.insert_singleton(potential.center_index);
+                parent_node.insert_singletons(potential.into_indexes());
             } else {
                 inserts.push(((split_scale_index, potential.center_index), potential.len()));
 
@@ -171,6 +280,9 @@ impl BuilderNode {
                     parent_address: Some(parent_address),
                     scale_index: split_scale_index,
                     covered: CoveredData::NearestCoveredData(potential),
+                    should_stop: None,
+                    seed: None,
+                    force_split: false,
                 };
                 new_nodes.push(new_node);
                 parameters
@@ -178,13 +290,20 @@ impl BuilderNode {
                     .fetch_add(1, atomic::Ordering::SeqCst);
             }
         }
-        if !inserts.is_empty() || !(nested_potential.len() == 1 && parameters.use_singletons) {
+        if inserts.is_empty() && nested_potential.len() <= parameters.singleton_threshold {
+            // The nested child would be as small as a singleton, so its points other than the parent's center are
+            // stored as singletons of the parent
+            parent_node.insert_singletons(nested_potential.into_indexes());
+        } else {
             parent_node.insert_nested_child(split_scale_index, nested_potential.len())?;
 
             let new_node = BuilderNode {
                 parent_address: Some(parent_address),
                 scale_index: split_scale_index,
                 covered: CoveredData::NearestCoveredData(nested_potential),
+                should_stop: None,
+                seed: None,
+                force_split: false,
             };
             new_nodes.push(new_node);
             parameters
@@ -206,6 +325,7 @@ impl BuilderNode {
         covered: FirstCoveredData,
         split_scale_index: i32,
         parameters: &Arc<CoverTreeParameters<D>>,
+        rng: &mut StdRng,
     ) -> GokoResult<Vec<BuilderNode>> {
         let mut new_nodes = Vec::new();
 
@@ -226,6 +346,9 @@ impl BuilderNode {
             parent_address: Some(parent_address),
             scale_index: split_scale_index,
             covered: CoveredData::FirstCoveredData(close),
+            should_stop: None,
+            seed: None,
+            force_split: false,
         };
         new_nodes.push(new_node);
         parameters
@@ -246,9 +369,9 @@ impl BuilderNode {
         */
 
         while fars.len() > 0 {
-            let new_close = fars.pick_center(next_scale, &parameters.point_cloud)?;
+            let new_close = fars.pick_center(next_scale, &parameters.point_cloud, rng)?;
             //println!("\t\t [{}] New Covered: {:?}",split_count, new_close);
-            if new_close.len() == 1 && parameters.use_singletons {
+            if new_close.len() <= parameters.singleton_threshold {
                 /*
                 We have a vast quantity of internal ourliers. These are singleton points that are
                 at least next_scale away from each other. These could be fully fledged leaf nodes,
@@ -258,6 +381,7 @@ impl BuilderNode {
                 ram savings.
                 */
                 parent_node.insert_singleton(new_close.center_index);
+                parent_node.insert_singletons(new_close.into_indexes());
             } else {
                 parent_node
                     .insert_child((split_scale_index, new_close.center_index), new_close.len())?;
@@ -265,6 +389,9 @@ impl BuilderNode {
                     parent_address: Some(parent_address),
                     scale_index: split_scale_index,
                     covered: CoveredData::FirstCoveredData(new_close),
+                    should_stop: None,
+                    seed: None,
+                    force_split: false,
                 };
                 new_nodes.push(new_node);
                 parameters
@@ -277,8 +404,49 @@ impl BuilderNode {
     }
 }
 
+/// Builds the subtree of a node over the points it should cover, not counting its center, on the current thread. The
+/// node is split at least once even if the builder would have made it a leaf, and the centers are picked with an rng
+/// seeded from the node's address, so the same call always makes the same nodes. The root of the returned nodes has
+/// the passed in address, and the tree's node count is updated for the new descendants.
+pub(crate) fn rebuild_subtree<D: PointCloud>(
+    parameters: &Arc<CoverTreeParameters<D>>,
+    address: NodeAddress,
+    parent_address: Option<NodeAddress>,
+    covered: Vec<PointIndex>,
+) -> GokoResult<Vec<CoverNode<D>>> {
+    let covered = match parameters.partition_type {
+        PartitionType::Nearest => CoveredData::NearestCoveredData(NearestCoveredData::with_center(
+            &parameters.point_cloud,
+            address.1,
+            covered,
+            false,
+        )?),
+        PartitionType::First => CoveredData::FirstCoveredData(FirstCoveredData::with_center(
+            &parameters.point_cloud,
+            address.1,
+            covered,
+            false,
+        )?),
+    };
+    let mut unsplit = vec![BuilderNode {
+        parent_address,
+        scale_index: address.0,
+        covered,
+        should_stop: None,
+        seed: Some(0),
+        force_split: true,
+    }];
+    let mut nodes = Vec::new();
+    while let Some(builder_node) = unsplit.pop() {
+        let (node, children) = builder_node.split(parameters)?;
+        nodes.push(node);
+        unsplit.extend(children);
+    }
+    Ok(nodes)
+}
+
 /// A construction object for a covertree.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CoverTreeBuilder {
     /// See paper or main description, governs the number of children of each node. Higher is more.
     pub scale_base: f32,
@@ -369,19 +537,273 @@ impl CoverTreeBuilder {
     /// Pass a point cloud object when ready.
     /// To do, make this point cloud an Arc
     pub fn build<D: PointCloud>(&self, point_cloud: Arc<D>) -> GokoResult<CoverTreeWriter<D>> {
+        self.build_on(point_cloud, None, None, None, false, false)
+    }
+
+    /// Same as `build`, but a node is made a leaf instead of being split whenever `should_stop` returns true for it.
+    /// Use this to stop subdividing parts of the tree that are already good enough for your task, like label pure
+    /// regions for a classifier.
+    pub fn build_with_stop<D, F>(
+        &self,
+        point_cloud: Arc<D>,
+        should_stop: F,
+    ) -> GokoResult<CoverTreeWriter<D>>
+    where
+        D: PointCloud,
+        F: Fn(&NodeStats) -> bool + Send + Sync + 'static,
+    {
+        self.build_on(
+            point_cloud,
+            None,
+            Some(Arc::new(should_stop)),
+            None,
+            false,
+            false,
+        )
+    }
+
+    /// Same as `build`, but the node splitting is done on the runtime's pool rather than rayon's global pool. If the
+    /// runtime has a memory budget, the build keeps to it as described in `CoverTreeParams::memory_budget`, and the
+    /// nodes are then split one at a time, see `GokoRuntime::set_memory_budget`.
+    pub fn build_with_runtime<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        runtime: &GokoRuntime,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        self.build_on(point_cloud, Some(runtime), None, None, false, false)
+    }
+
+    /// Same as `build`, but every point is L2 normalized at ingest, see `NormalizedCloud`. The tree remembers this,
+    /// normalizes every query it's given the same way, and refuses to load onto a point cloud that isn't normalized.
+    /// Use this with `L2` for cosine similarity search.
+    pub fn build_normalized<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+    ) -> GokoResult<CoverTreeWriter<NormalizedCloud<D>>> {
+        let point_cloud = Arc::new(NormalizedCloud::new(point_cloud)?);
+        self.build_on(point_cloud, None, None, None, true, false)
+    }
+
+    /// Same as `build`, but bitwise reproducible. The centers are picked with rngs seeded from `seed` and each node's
+    /// address, and the layers are written out in a fixed order, so the same data, parameters and seed give the same
+    /// saved tree regardless of the number of threads or how the work is scheduled across them.
+    pub fn build_deterministic<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        seed: u64,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        self.build_on(point_cloud, None, None, Some(seed), false, false)
+    }
+
+    /// Same as `build`, but the tree is built by partitioning the points and building the subtrees concurrently.
+    /// Each split hands its covered points to the children, whose subtrees are then built on rayon's pool
+    /// independently of each other and merged into the layers in a fixed order once they're all done.
+    ///
+    /// With a seed the build is bitwise reproducible, and gives the same tree as `build_deterministic` with that
+    /// seed, the merge order doesn't depend on the pool's size or scheduling.
+    pub fn build_parallel<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        seed: Option<u64>,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        self.build_on(point_cloud, None, None, seed, false, true)
+    }
+
+    /// Same as `build`, but the point cloud's metric is first checked on `triples` sampled triples of points with
+    /// `audit_metric`, and the build fails with `GokoError::NotAMetric` if any check fails. Use this with a
+    /// `UserMetric` or any other metric that hasn't been checked yet, a tree built on a distance that breaks the
+    /// triangle inequality silently misses neighbors.
+    pub fn build_audited<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        triples: usize,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        let audit = audit_metric(point_cloud.as_ref(), triples, 1e-5, &mut thread_rng())?;
+        if !audit.is_metric() {
+            return Err(GokoError::NotAMetric(audit));
+        }
+        self.build(point_cloud)
+    }
+
+    fn build_on<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        runtime: Option<&GokoRuntime>,
+        should_stop: Option<StopCriterion>,
+        seed: Option<u64>,
+        normalized: bool,
+        parallel: bool,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        let mut params = CoverTreeParams::from(self);
+        params.seed = seed;
+        params.build_on(point_cloud, runtime, should_stop, normalized, parallel)
+    }
+}
+
+/// All the parameters of a tree's construction in one object, passed to `CoverTreeWriter::with_params`. Start from
+/// the defaults and set what you need, the fields are the same as `CoverTreeBuilder`'s with a few more knobs.
+///
+/// The scale base governs the depth/fanout tradeoff. A child's scale is the parent's divided by the base, so a base
+/// close to 1, like 1.3, makes deep trees with few children per node, and a large base makes shallow, wide trees.
+#[derive(Debug, Clone)]
+pub struct CoverTreeParams {
+    /// The ratio between the scales of a node and its children, has to be more than 1
+    pub scale_base: f32,
+    /// If a node covers less than or equal to this number of points, it becomes a leaf.
+    pub leaf_cutoff: usize,
+    /// If a node has scale index less than or equal to this, it becomes a leaf
+    pub min_res_index: i32,
+    /// Caps the scale index of the root. By default the root's scale covers the whole point cloud, with a cap the
+    /// root has more children instead, and the tree is shallower.
+    pub max_res_index: Option<i32>,
+    /// A potential child that covers this many points or fewer is stored as singletons of its parent. 1 is
+    /// `CoverTreeBuilder::use_singletons`, and 0 makes every point a node or an element of a leaf.
+    pub singleton_threshold: usize,
+    /// Seeds the rngs that pick the centers, see `CoverTreeBuilder::build_deterministic`. Unseeded builds use the
+    /// thread rng.
+    pub seed: Option<u64>,
+    /// Partition type of the tree
+    pub partition_type: PartitionType,
+    /// Printing verbosity, 2 gives a progress bar
+    pub verbosity: u32,
+    /// Caps the bytes held by the point lists of the nodes waiting to be split, about 12 per point. With a budget the
+    /// tree is built depth first on the current thread, and when the waiting nodes go over it the lists that will be
+    /// split last are written to `spill_dir` until the rest fit. The node being split is always in ram, and the first
+    /// splits cover most of the data, so pair this with a memmapped point cloud. `None` falls back to the budget of the
+    /// runtime passed to `CoverTreeBuilder::build_with_runtime`, if any, and otherwise keeps everything in ram.
+    pub memory_budget: Option<usize>,
+    /// Where a build with a `memory_budget` spills, the system's temp dir if `None`. The build makes its own
+    /// directory in here and removes it when it's done.
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for CoverTreeParams {
+    fn default() -> CoverTreeParams {
+        CoverTreeParams::from(&CoverTreeBuilder::default())
+    }
+}
+
+impl From<&CoverTreeBuilder> for CoverTreeParams {
+    fn from(builder: &CoverTreeBuilder) -> CoverTreeParams {
+        CoverTreeParams {
+            scale_base: builder.scale_base,
+            leaf_cutoff: builder.leaf_cutoff,
+            min_res_index: builder.min_res_index,
+            max_res_index: None,
+            singleton_threshold: builder.use_singletons as usize,
+            seed: None,
+            partition_type: builder.partition_type,
+            verbosity: builder.verbosity,
+            memory_budget: None,
+            spill_dir: None,
+        }
+    }
+}
+
+impl CoverTreeParams {
+    /// The same defaults as `CoverTreeBuilder::new`.
+    pub fn new() -> CoverTreeParams {
+        CoverTreeParams::default()
+    }
+
+    /// Sets the ratio between the scales of a node and its children.
+    pub fn set_scale_base(&mut self, x: f32) -> &mut Self {
+        self.scale_base = x;
+        self
+    }
+    /// Sets the number of points a node has to cover to be split.
+    pub fn set_leaf_cutoff(&mut self, x: usize) -> &mut Self {
+        self.leaf_cutoff = x;
+        self
+    }
+    /// Sets the scale index below which nodes aren't split.
+    pub fn set_min_res_index(&mut self, x: i32) -> &mut Self {
+        self.min_res_index = x;
+        self
+    }
+    /// Caps the scale index of the root.
+    pub fn set_max_res_index(&mut self, x: i32) -> &mut Self {
+        self.max_res_index = Some(x);
+        self
+    }
+    /// Sets the size of the potential children that are stored as singletons.
+    pub fn set_singleton_threshold(&mut self, x: usize) -> &mut Self {
+        self.singleton_threshold = x;
+        self
+    }
+    /// Seeds the build, so that it's bitwise reproducible.
+    pub fn set_seed(&mut self, x: u64) -> &mut Self {
+        self.seed = Some(x);
+        self
+    }
+    /// Sets how the points a node covers are split between its children.
+    pub fn set_partition_type(&mut self, x: PartitionType) -> &mut Self {
+        self.partition_type = x;
+        self
+    }
+    /// Sets the printing verbosity.
+    pub fn set_verbosity(&mut self, x: u32) -> &mut Self {
+        self.verbosity = x;
+        self
+    }
+    /// Caps the bytes the nodes waiting to be split hold in ram, spilling the rest to disk.
+    pub fn set_memory_budget(&mut self, x: usize) -> &mut Self {
+        self.memory_budget = Some(x);
+        self
+    }
+    /// Sets where a build with a memory budget spills.
+    pub fn set_spill_dir<P: AsRef<Path>>(&mut self, x: P) -> &mut Self {
+        self.spill_dir = Some(x.as_ref().to_path_buf());
+        self
+    }
+
+    /// Checks that a tree can be built with these parameters.
+    pub fn validate(&self) -> GokoResult<()> {
+        if !self.scale_base.is_finite() || self.scale_base <= 1.0 {
+            return Err(GokoError::InvalidParameters(
+                "the scale base has to be a finite number more than 1",
+            ));
+        }
+        if let Some(max_res_index) = self.max_res_index {
+            if max_res_index < self.min_res_index {
+                return Err(GokoError::InvalidParameters(
+                    "the max resolution index is below the min resolution index",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn build_on<D: PointCloud>(
+        &self,
+        point_cloud: Arc<D>,
+        runtime: Option<&GokoRuntime>,
+        should_stop: Option<StopCriterion>,
+        normalized: bool,
+        parallel: bool,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        self.validate()?;
         let parameters = CoverTreeParameters {
             total_nodes: atomic::AtomicUsize::new(1),
             scale_base: self.scale_base,
             leaf_cutoff: self.leaf_cutoff,
             min_res_index: self.min_res_index,
-            use_singletons: self.use_singletons,
+            use_singletons: self.singleton_threshold > 0,
+            singleton_threshold: self.singleton_threshold,
             partition_type: self.partition_type,
             point_cloud,
             verbosity: self.verbosity,
             plugins: RwLock::new(TreePluginSet::new()),
+            generation: atomic::AtomicUsize::new(0),
+            normalized,
         };
 
-        let root = BuilderNode::new(&parameters, self.partition_type)?;
+        let mut root = BuilderNode::new(&parameters, self.partition_type, parallel)?;
+        root.should_stop = should_stop;
+        root.seed = self.seed;
+        if let Some(max_res_index) = self.max_res_index {
+            root.scale_index = min(root.scale_index, max_res_index);
+        }
         let root_address = root.address();
         let scale_range = root_address.0 - parameters.min_res_index;
         let mut layers = Vec::with_capacity(scale_range as usize);
@@ -390,14 +812,7 @@ impl CoverTreeBuilder {
             layers.push(CoverLayerWriter::new(parameters.min_res_index + i as i32));
         }
 
-        let (node_sender, node_receiver): (
-            Sender<NodeSplitResult<D>>,
-            Receiver<NodeSplitResult<D>>,
-        ) = unbounded();
-
-        let node_sender = Arc::new(node_sender);
         let parameters = Arc::new(parameters);
-        root.split_parallel(&parameters, &node_sender);
         let mut pb = ProgressBar::new(1u64);
         if parameters.verbosity > 1 {
             pb.format("╢▌▌░╟");
@@ -410,37 +825,82 @@ impl CoverTreeBuilder {
             layers,
             root_address,
             final_addresses,
+            journal: None,
         };
 
-        let mut inserted_nodes: usize = 0;
         let now = Instant::now();
-        loop {
-            if let Ok(res) = node_receiver.recv() {
-                let (scale_index, point_index, new_node) = res.unwrap();
-                for singleton in new_node.singletons() {
-                    cover_tree
-                        .final_addresses
-                        .insert(*singleton, (scale_index, point_index));
-                }
-                if new_node.is_leaf() {
-                    cover_tree
-                        .final_addresses
-                        .insert(point_index, (scale_index, point_index));
+        let memory_budget = self
+            .memory_budget
+            .or_else(|| runtime.and_then(|r| r.memory_budget()));
+        let inserted_nodes = match memory_budget {
+            Some(budget) => {
+                let spill_dir = self.spill_dir.clone().unwrap_or_else(std::env::temp_dir);
+                let spill = || {
+                    build_spilling(
+                        root,
+                        &parameters,
+                        budget,
+                        &spill_dir,
+                        &mut cover_tree,
+                        &mut pb,
+                    )
+                };
+                match runtime {
+                    Some(runtime) => runtime.install(spill)?,
+                    None => spill()?,
                 }
-                unsafe {
-                    cover_tree.insert_raw(scale_index, point_index, new_node);
+            }
+            None if parallel => {
+                let build = || root.build_subtree(&parameters);
+                let built = match runtime {
+                    Some(runtime) => runtime.install(build)?,
+                    None => build()?,
+                };
+                let inserted_nodes = built.len();
+                for (scale_index, point_index, new_node) in built {
+                    cover_tree.insert_built(scale_index, point_index, new_node);
                 }
-                inserted_nodes += 1;
                 if parameters.verbosity > 1 {
-                    pb.total = parameters.total_nodes.load(atomic::Ordering::SeqCst) as u64;
-                    pb.inc();
+                    pb.total = inserted_nodes as u64;
+                    pb.set(inserted_nodes as u64);
                 }
+                inserted_nodes
             }
-            // Stop if there are enough done, and there are no more outstanding parameter references
-            if inserted_nodes == parameters.total_nodes.load(atomic::Ordering::SeqCst) {
-                break;
+            None => {
+                let (node_sender, node_receiver): (
+                    Sender<NodeSplitResult<D>>,
+                    Receiver<NodeSplitResult<D>>,
+                ) = unbounded();
+
+                let node_sender = Arc::new(node_sender);
+                match runtime {
+                    Some(runtime) => {
+                        let parameters = Arc::clone(&parameters);
+                        let node_sender = Arc::clone(&node_sender);
+                        runtime.spawn(move || root.split_parallel(&parameters, &node_sender));
+                    }
+                    None => root.split_parallel(&parameters, &node_sender),
+                }
+
+                let mut inserted_nodes: usize = 0;
+                loop {
+                    if let Ok(res) = node_receiver.recv() {
+                        let (scale_index, point_index, new_node) = res.unwrap();
+                        cover_tree.insert_built(scale_index, point_index, new_node);
+                        inserted_nodes += 1;
+                        if parameters.verbosity > 1 {
+                            pb.total = parameters.total_nodes.load(atomic::Ordering::SeqCst) as u64;
+                            pb.inc();
+                        }
+                    }
+                    // Stop if there are enough done, and there are no more outstanding parameter references
+                    if inserted_nodes == parameters.total_nodes.load(atomic::Ordering::SeqCst) {
+                        break;
+                    }
+                }
+                inserted_nodes
             }
-        }
+        };
         if parameters.verbosity > 1 {
             println!("\nWriting layers...");
         }
@@ -458,10 +918,137 @@ impl CoverTreeBuilder {
     }
 }
 
+/// A directory for a build's spill files, removed with whatever is left in it when the build ends.
+struct SpillDir(PathBuf);
+
+static SPILL_DIRS: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+impl SpillDir {
+    fn new(parent: &Path) -> GokoResult<SpillDir> {
+        let path = parent.join(format!(
+            "goko-spill-{}-{}",
+            std::process::id(),
+            SPILL_DIRS.fetch_add(1, atomic::Ordering::SeqCst)
+        ));
+        create_dir_all(&path)?;
+        Ok(SpillDir(path))
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
+
+/// A node waiting to be split by `build_spilling`.
+enum PendingNode {
+    Ram(BuilderNode),
+    Spilled {
+        node: BuilderNode,
+        covered: SpilledCoverage,
+    },
+}
+
+/// Builds the tree depth first on the current thread, inserting each node as it's made. Whenever the nodes waiting to
+/// be split hold more than `budget` bytes of coverage, the ones at the bottom of the stack, which are split last, are
+/// written to a directory in `spill_dir` and read back when it's their turn. Returns the number of nodes made.
+fn build_spilling<D: PointCloud>(
+    root: BuilderNode,
+    parameters: &Arc<CoverTreeParameters<D>>,
+    budget: usize,
+    spill_dir: &Path,
+    cover_tree: &mut CoverTreeWriter<D>,
+    pb: &mut ProgressBar<Stdout>,
+) -> GokoResult<usize> {
+    let spill_dir = SpillDir::new(spill_dir)?;
+    let mut in_ram = root.covered.len() * COVERED_POINT_BYTES;
+    let mut pending = vec![PendingNode::Ram(root)];
+    // Every pending node below this is spilled, the ones above it are in ram
+    let mut spilled_below = 0;
+    let mut spill_files = 0;
+    let mut inserted_nodes = 0;
+    while let Some(node) = pending.pop() {
+        spilled_below = min(spilled_below, pending.len());
+        let node = match node {
+            PendingNode::Ram(node) => {
+                in_ram -= node.covered.len() * COVERED_POINT_BYTES;
+                node
+            }
+            PendingNode::Spilled { mut node, covered } => {
+                node.covered = covered.load()?;
+                node
+            }
+        };
+        let (scale_index, point_index) = node.address();
+        let (new_node, children) = node.split(parameters)?;
+        cover_tree.insert_built(scale_index, point_index, new_node);
+        inserted_nodes += 1;
+        if parameters.verbosity > 1 {
+            pb.total = parameters.total_nodes.load(atomic::Ordering::SeqCst) as u64;
+            pb.inc();
+        }
+
+        for child in children {
+            in_ram += child.covered.len() * COVERED_POINT_BYTES;
+            pending.push(PendingNode::Ram(child));
+        }
+        if in_ram > budget {
+            for node in pending.split_off(spilled_below) {
+                match node {
+                    PendingNode::Ram(mut node) if in_ram > budget => {
+                        in_ram -= node.covered.len() * COVERED_POINT_BYTES;
+                        spill_files += 1;
+                        let path = spill_dir.0.join(format!("{}.cov", spill_files));
+                        // The node keeps an empty placeholder until its coverage is read back
+                        let covered = std::mem::replace(
+                            &mut node.covered,
+                            CoveredData::FirstCoveredData(FirstCoveredData::empty()),
+                        )
+                        .spill(path)?;
+                        pending.push(PendingNode::Spilled { node, covered });
+                        spilled_below = pending.len();
+                    }
+                    node => pending.push(node),
+                }
+            }
+        }
+    }
+    Ok(inserted_nodes)
+}
+
+impl<D: PointCloud> CoverTreeWriter<D> {
+    /// Inserts a node made by the builder, and records the final addresses of the points it holds.
+    fn insert_built(&mut self, scale_index: i32, point_index: PointIndex, new_node: CoverNode<D>) {
+        for singleton in new_node.singletons() {
+            self.final_addresses
+                .insert(*singleton, (scale_index, point_index));
+        }
+        if new_node.is_leaf() {
+            self.final_addresses
+                .insert(point_index, (scale_index, point_index));
+        }
+        unsafe {
+            self.insert_raw(scale_index, point_index, new_node);
+        }
+    }
+
+    /// Builds a tree on the point cloud with the parameters. Fails with `GokoError::InvalidParameters` if they don't
+    /// pass `CoverTreeParams::validate`.
+    pub fn with_params(
+        point_cloud: Arc<D>,
+        params: &CoverTreeParams,
+    ) -> GokoResult<CoverTreeWriter<D>> {
+        params.build_on(point_cloud, None, None, false, false)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use protobuf::Message;
     use std::{thread, time};
+    use tempdir::TempDir;
 
     pub fn create_test_parameters(
         data: Vec<f32>,
@@ -474,10 +1061,13 @@ mod tests {
             leaf_cutoff: 0,
             min_res_index: -9,
             use_singletons: true,
+            singleton_threshold: 1,
             partition_type: PartitionType::Nearest,
             point_cloud,
             verbosity: 0,
             plugins: RwLock::new(TreePluginSet::new()),
+            generation: atomic::AtomicUsize::new(0),
+            normalized: false,
         })
     }
 
@@ -490,7 +1080,7 @@ mod tests {
         data.push(0.0);
 
         let test_parameters = create_test_parameters(data, 1);
-        let build_node = BuilderNode::new(&test_parameters, PartitionType::Nearest).unwrap();
+        let build_node = BuilderNode::new(&test_parameters, PartitionType::Nearest, false).unwrap();
         let (scale_index, center_index) = build_node.address();
 
         println!("{:?}", build_node);
@@ -528,7 +1118,7 @@ mod tests {
         data.push(0.0);
 
         let test_parameters = create_test_parameters(data, 1);
-        let build_node = BuilderNode::new(&test_parameters, PartitionType::First).unwrap();
+        let build_node = BuilderNode::new(&test_parameters, PartitionType::First, false).unwrap();
         let (scale_index, center_index) = build_node.address();
 
         println!("{:?}", build_node);
@@ -561,7 +1151,7 @@ mod tests {
         let data = vec![0.49, 0.491, -0.49, 0.0];
         let test_parameters = create_test_parameters(data, 1);
 
-        let build_node = BuilderNode::new(&test_parameters, PartitionType::First).unwrap();
+        let build_node = BuilderNode::new(&test_parameters, PartitionType::First, false).unwrap();
 
         let (node_sender, node_receiver): (
             Sender<GokoResult<(i32, PointIndex, CoverNode<DefaultCloud<L2>>)>>,
@@ -598,7 +1188,7 @@ mod tests {
         let data = vec![0.49, 0.491, -0.49, 0.0];
         let test_parameters = create_test_parameters(data, 1);
 
-        let build_node = BuilderNode::new(&test_parameters, PartitionType::Nearest).unwrap();
+        let build_node = BuilderNode::new(&test_parameters, PartitionType::Nearest, false).unwrap();
 
         let (node_sender, node_receiver): (
             Sender<GokoResult<(i32, PointIndex, CoverNode<DefaultCloud<L2>>)>>,
@@ -669,7 +1259,7 @@ mod tests {
             .get_node_and((-2, 3), |n| n.children().is_none())
             .unwrap());
         println!("-0.49 is a singleton that shouldn't be here.");
-        assert!(reader.get_node_and((-2, 2), |n| n.is_leaf()).is_none());
+        assert!(reader.get_node_and((-2, 2), |n| n.is_leaf()).is_err());
         assert!(reader.no_dangling_refs());
     }
 
@@ -691,7 +1281,319 @@ mod tests {
         let reader = tree.reader();
 
         println!("-0.49 is a singleton that should be here.");
-        assert!(reader.get_node_and((-2, 2), |n| n.is_leaf()).is_some());
+        assert!(reader.get_node_and((-2, 2), |n| n.is_leaf()).is_ok());
         assert!(reader.no_dangling_refs());
     }
+
+    #[test]
+    fn build_with_runtime_matches_build() {
+        let data = vec![0.49, 0.491, -0.49, 0.0];
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 1).unwrap());
+        let builder = CoverTreeBuilder::new();
+        let runtime = GokoRuntime::new(1).unwrap();
+
+        let tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let runtime_tree = builder
+            .build_with_runtime(Arc::clone(&point_cloud), &runtime)
+            .unwrap();
+        assert_eq!(
+            tree.reader().node_count(),
+            runtime_tree.reader().node_count()
+        );
+        assert!(runtime_tree.reader().no_dangling_refs());
+
+        // A runtime's memory budget spills the build, a budget of 0 spills every waiting node
+        let mut budgeted = GokoRuntime::new(1).unwrap();
+        budgeted.set_memory_budget(0);
+        let spilled_tree = builder.build_with_runtime(point_cloud, &budgeted).unwrap();
+        assert_eq!(
+            tree.reader().node_count(),
+            spilled_tree.reader().node_count()
+        );
+        assert!(spilled_tree.reader().no_dangling_refs());
+    }
+
+    #[test]
+    fn fixed_dim_build_matches_build() {
+        let data: Vec<f32> = (0..200).map(|i| ((i * 7) % 13) as f32).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data.clone(), 4).unwrap());
+        let fixed_cloud = Arc::new(DefaultCloud::<FixedDim<L2, 4>>::new(data, 4).unwrap());
+        let builder = CoverTreeBuilder::new();
+
+        let tree = builder.build(point_cloud).unwrap();
+        let fixed_tree = builder.build(fixed_cloud).unwrap();
+        assert_eq!(tree.reader().node_count(), fixed_tree.reader().node_count());
+        let query = [3.0f32, 1.0, 4.0, 1.0];
+        let dists = |knn: Vec<(f32, PointIndex)>| knn.iter().map(|(d, _)| *d).collect::<Vec<f32>>();
+        assert_eq!(
+            dists(tree.reader().knn(&query, 5).unwrap()),
+            dists(fixed_tree.reader().knn(&query, 5).unwrap())
+        );
+    }
+
+    #[test]
+    fn build_with_stop_makes_pure_leaves() {
+        let mut data = Vec::new();
+        let mut labels = Vec::new();
+        for i in 0..200 {
+            let offset = if i < 100 { 0.0 } else { 20.0 };
+            data.push(offset + (i % 10) as f32);
+            data.push(((i / 10) % 10) as f32);
+            labels.push(if i < 100 { 1 } else { 2 });
+        }
+        let point_cloud = Arc::new(DefaultLabeledCloud::<L2>::new_simple(data, 2, labels));
+        let builder = CoverTreeBuilder::new();
+        let full_tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+
+        let labels_cloud = Arc::clone(&point_cloud);
+        let pure_tree = builder
+            .build_with_stop(Arc::clone(&point_cloud), move |stats| {
+                let center_label = labels_cloud.label(stats.address.1).unwrap();
+                stats
+                    .covered
+                    .iter()
+                    .all(|pi| labels_cloud.label(*pi).unwrap() == center_label)
+            })
+            .unwrap();
+        let reader = pure_tree.reader();
+        assert!(reader.node_count() < full_tree.reader().node_count());
+        assert!(reader.no_dangling_refs());
+        let query = [3.0f32, 3.1];
+        let dists = |knn: Vec<(f32, PointIndex)>| knn.iter().map(|(d, _)| *d).collect::<Vec<f32>>();
+        assert_eq!(
+            dists(reader.knn(&query, 5).unwrap()),
+            dists(full_tree.reader().knn(&query, 5).unwrap())
+        );
+        for pi in 0..point_cloud.len() {
+            assert!(reader.known_path(pi).is_ok());
+        }
+    }
+
+    #[test]
+    fn deterministic_build_is_reproducible() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 500,
+            dim: 3,
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        for partition_type in &[PartitionType::Nearest, PartitionType::First] {
+            let builder = CoverTreeBuilder {
+                partition_type: *partition_type,
+                ..CoverTreeBuilder::new()
+            };
+            let saved =
+                |tree: CoverTreeWriter<DefaultCloud<L2>>| tree.save().write_to_bytes().unwrap();
+            let tree = saved(
+                builder
+                    .build_deterministic(Arc::clone(&point_cloud), 7)
+                    .unwrap(),
+            );
+            for _ in 0..3 {
+                assert_eq!(
+                    tree,
+                    saved(
+                        builder
+                            .build_deterministic(Arc::clone(&point_cloud), 7)
+                            .unwrap()
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_build_matches_deterministic() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 3 * PARALLEL_CHUNK,
+            dim: 3,
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        for partition_type in &[PartitionType::Nearest, PartitionType::First] {
+            let builder = CoverTreeBuilder {
+                partition_type: *partition_type,
+                leaf_cutoff: 50,
+                ..CoverTreeBuilder::new()
+            };
+            let saved =
+                |tree: CoverTreeWriter<DefaultCloud<L2>>| tree.save().write_to_bytes().unwrap();
+            assert_eq!(
+                saved(
+                    builder
+                        .build_parallel(Arc::clone(&point_cloud), Some(3))
+                        .unwrap()
+                ),
+                saved(
+                    builder
+                        .build_deterministic(Arc::clone(&point_cloud), 3)
+                        .unwrap()
+                )
+            );
+            let tree = builder
+                .build_parallel(Arc::clone(&point_cloud), None)
+                .unwrap();
+            let reader = tree.reader();
+            assert!(reader.no_dangling_refs());
+            let root_coverage = reader
+                .get_node_and(reader.root_address(), |n| n.coverage_count())
+                .unwrap();
+            assert_eq!(root_coverage, point_cloud.len());
+        }
+    }
+
+    #[test]
+    fn memmap_build_matches_ram() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 3 * PARALLEL_CHUNK,
+            dim: 3,
+            ..Default::default()
+        }
+        .data();
+        let ram = DefaultCloud::<L2>::new(data, 3).unwrap();
+        let dir = TempDir::new("memmap_build").unwrap();
+        let memmap = Arc::new(ram.to_memmap(dir.path()).unwrap());
+        let ram = Arc::new(ram);
+        for partition_type in &[PartitionType::Nearest, PartitionType::First] {
+            let builder = CoverTreeBuilder {
+                partition_type: *partition_type,
+                leaf_cutoff: 50,
+                ..CoverTreeBuilder::new()
+            };
+            let from_ram = builder.build_deterministic(Arc::clone(&ram), 5).unwrap();
+            let from_memmap = builder.build_deterministic(Arc::clone(&memmap), 5).unwrap();
+            let in_parallel = builder
+                .build_parallel(Arc::clone(&memmap), Some(5))
+                .unwrap();
+            let saved = from_ram.save().write_to_bytes().unwrap();
+            assert_eq!(saved, from_memmap.save().write_to_bytes().unwrap());
+            assert_eq!(saved, in_parallel.save().write_to_bytes().unwrap());
+        }
+    }
+
+    #[test]
+    fn spilled_build_matches_ram() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 5000,
+            dim: 3,
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        let dir = TempDir::new("spill_build").unwrap();
+        for partition_type in &[PartitionType::Nearest, PartitionType::First] {
+            let builder = CoverTreeBuilder {
+                partition_type: *partition_type,
+                leaf_cutoff: 20,
+                ..CoverTreeBuilder::new()
+            };
+            let in_ram = builder
+                .build_deterministic(Arc::clone(&point_cloud), 3)
+                .unwrap();
+            let mut params = CoverTreeParams::from(&builder);
+            params
+                .set_seed(3)
+                .set_memory_budget(500 * COVERED_POINT_BYTES)
+                .set_spill_dir(dir.path());
+            let spilled = CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).unwrap();
+            assert_eq!(
+                in_ram.save().write_to_bytes().unwrap(),
+                spilled.save().write_to_bytes().unwrap()
+            );
+            // The build cleans up after itself
+            assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Squared {}
+    impl MetricTag for Squared {
+        fn name() -> &'static str {
+            "Squared"
+        }
+        fn slot() -> &'static MetricSlot {
+            static SLOT: MetricSlot = MetricSlot::new();
+            &SLOT
+        }
+    }
+
+    #[test]
+    fn audited_build_rejects_non_metrics() {
+        UserMetric::<Squared>::register(|x, y| L2::dense(x, y).powi(2)).unwrap();
+        let data: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let builder = CoverTreeBuilder::new();
+
+        let squared_cloud =
+            Arc::new(DefaultCloud::<UserMetric<Squared>>::new(data.clone(), 1).unwrap());
+        match builder.build_audited(squared_cloud, 200) {
+            Err(GokoError::NotAMetric(audit)) => assert!(audit.triangle_violations > 0),
+            _ => panic!("squared L2 passed the audit"),
+        }
+
+        let cloud = Arc::new(DefaultCloud::<L2>::new(data, 1).unwrap());
+        let tree = builder.build_audited(cloud, 200).unwrap();
+        assert!(tree.reader().no_dangling_refs());
+    }
+
+    #[test]
+    fn params_control_the_tree_shape() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 2000,
+            dim: 2,
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 2).unwrap());
+        let mut params = CoverTreeParams::new();
+        params.set_leaf_cutoff(5).set_min_res_index(-20).set_seed(7);
+        let wide = CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).unwrap();
+        params.set_scale_base(1.3);
+        let deep = CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).unwrap();
+        let depth = |tree: &CoverTreeWriter<DefaultCloud<L2>>| -> usize {
+            let reader = tree.reader();
+            (0..100)
+                .map(|pi| reader.path(point_cloud.point(pi).unwrap()).unwrap().len())
+                .sum()
+        };
+        assert!(depth(&deep) > depth(&wide));
+        let again = CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).unwrap();
+        assert_eq!(
+            deep.save().write_to_bytes().unwrap(),
+            again.save().write_to_bytes().unwrap()
+        );
+
+        params.set_max_res_index(-2).set_singleton_threshold(4);
+        let capped = CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).unwrap();
+        let reader = capped.reader();
+        assert_eq!(reader.root_address().0, -2);
+        assert!(reader.no_dangling_refs());
+        let root_coverage = reader
+            .get_node_and(reader.root_address(), |n| n.coverage_count())
+            .unwrap();
+        assert_eq!(root_coverage, point_cloud.len());
+        let query = [0.5f32, 0.5];
+        let mut brute: Vec<f32> = point_cloud
+            .distances_to_point(&query[..], &point_cloud.reference_indexes())
+            .unwrap();
+        brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let knn: Vec<f32> = reader
+            .knn(&query[..], 5)
+            .unwrap()
+            .iter()
+            .map(|(d, _)| *d)
+            .collect();
+        assert_eq!(knn, brute[..5].to_vec());
+        let reloaded = CoverTreeWriter::load(&capped.save(), Arc::clone(&point_cloud)).unwrap();
+        assert_eq!(reloaded.parameters.singleton_threshold, 4);
+
+        params.set_scale_base(1.0);
+        assert!(CoverTreeWriter::with_params(Arc::clone(&point_cloud), &params).is_err());
+        params.set_scale_base(2.0).set_max_res_index(-30);
+        match CoverTreeWriter::with_params(point_cloud, &params) {
+            Err(GokoError::InvalidParameters(_)) => (),
+            _ => panic!("built with the max resolution below the min"),
+        }
+    }
 }
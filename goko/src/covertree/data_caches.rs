@@ -20,10 +20,44 @@
 use crate::errors::GokoResult;
 use pointcloud::*;
 use rand::seq::SliceRandom;
-use rand::{thread_rng, Rng};
-use std::cmp::Ordering;
+use rand::Rng;
+use rayon::prelude::*;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+/// Covered sets with more points than this have their distances computed in chunks of this size, and are assigned to
+/// their nearest center in parallel if the build is parallel.
+pub(crate) const PARALLEL_CHUNK: usize = 1 << 12;
+
+/// The distances from the center to the points, in order. Chunks give the same distances as computing them in one go,
+/// and `distances_to_point` already spreads each chunk over rayon's pool.
+///
+/// The covered points stay in the order of `reference_indexes` as nodes are split, so for a memmapped cloud this is a
+/// forward scan of the file. Each chunk is prefetched, see `PointCloud::prefetch`, while the one before it is computed.
+fn center_distances<D: PointCloud>(
+    point_cloud: &Arc<D>,
+    center_index: PointIndex,
+    indexes: &[PointIndex],
+) -> GokoResult<Vec<f32>> {
+    if indexes.len() <= PARALLEL_CHUNK {
+        return Ok(point_cloud.distances_to_point_index(center_index, indexes)?);
+    }
+    let mut dists = Vec::with_capacity(indexes.len());
+    let mut chunks = indexes.chunks(PARALLEL_CHUNK).peekable();
+    point_cloud.prefetch(chunks.peek().unwrap());
+    while let Some(chunk) = chunks.next() {
+        if let Some(next) = chunks.peek() {
+            point_cloud.prefetch(next);
+        }
+        dists.extend(point_cloud.distances_to_point_index(center_index, chunk)?);
+    }
+    Ok(dists)
+}
+
 #[derive(Clone, Debug)]
 pub(crate) enum CoveredData {
     FirstCoveredData(FirstCoveredData),
@@ -44,6 +78,14 @@ impl CoveredData {
         }
     }
 
+    /// The covered points, not counting the center
+    pub(crate) fn indexes(&self) -> &[PointIndex] {
+        match self {
+            Self::FirstCoveredData(a) => &a.coverage,
+            Self::NearestCoveredData(a) => &a.point_indexes,
+        }
+    }
+
     pub(crate) fn len(&self) -> usize {
         match self {
             Self::FirstCoveredData(a) => a.len(),
@@ -59,28 +101,121 @@ impl CoveredData {
     }
 }
 
+/// The bytes a pending node holds per covered point, its index and its distance to the center.
+pub(crate) const COVERED_POINT_BYTES: usize = size_of::<PointIndex>() + size_of::<f32>();
+
+/// The coverage of a node that hasn't been split yet, written out to a file by `CoveredData::spill`.
+#[derive(Debug)]
+pub(crate) struct SpilledCoverage {
+    path: PathBuf,
+    center_index: PointIndex,
+    len: usize,
+    nearest: bool,
+    parallel: bool,
+}
+
+impl CoveredData {
+    /// Writes the covered indexes and their distances to the center to `path`, and frees them. Only for nodes that
+    /// haven't started splitting, the working state of a split isn't written.
+    pub(crate) fn spill(self, path: PathBuf) -> GokoResult<SpilledCoverage> {
+        let (center_index, indexes, dists, nearest, parallel) = match self {
+            Self::FirstCoveredData(a) => (a.center_index, a.coverage, a.dists, false, a.parallel),
+            Self::NearestCoveredData(a) => (
+                a.center_index,
+                a.point_indexes,
+                a.center_dists,
+                true,
+                a.parallel,
+            ),
+        };
+        let mut file = BufWriter::new(File::create(&path)?);
+        for pi in &indexes {
+            file.write_all(&(*pi as u64).to_le_bytes())?;
+        }
+        for d in &dists {
+            file.write_all(&d.to_le_bytes())?;
+        }
+        file.flush()?;
+        Ok(SpilledCoverage {
+            path,
+            center_index,
+            len: indexes.len(),
+            nearest,
+            parallel,
+        })
+    }
+}
+
+impl SpilledCoverage {
+    /// The number of covered points, not counting the center.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Reads the coverage back and deletes the file.
+    pub(crate) fn load(self) -> GokoResult<CoveredData> {
+        let bytes = fs::read(&self.path)?;
+        fs::remove_file(&self.path)?;
+        if bytes.len() != 12 * self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("the spill file {:?} was truncated", self.path),
+            )
+            .into());
+        }
+        let (index_bytes, dist_bytes) = bytes.split_at(8 * self.len);
+        let indexes: Vec<PointIndex> = index_bytes
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as PointIndex)
+            .collect();
+        let dists: Vec<f32> = dist_bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        Ok(if self.nearest {
+            CoveredData::NearestCoveredData(NearestCoveredData {
+                centers: vec![],
+                nearest: vec![],
+                point_indexes: indexes,
+                center_dists: dists,
+                center_index: self.center_index,
+                parallel: self.parallel,
+            })
+        } else {
+            CoveredData::FirstCoveredData(FirstCoveredData {
+                dists,
+                coverage: indexes,
+                center_index: self.center_index,
+                parallel: self.parallel,
+            })
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct FirstCoveredData {
     dists: Vec<f32>,
     coverage: Vec<PointIndex>,
     pub(crate) center_index: PointIndex,
+    parallel: bool,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct UncoveredData {
     coverage: Vec<PointIndex>,
+    parallel: bool,
 }
 
 impl UncoveredData {
-    pub(crate) fn pick_center<D: PointCloud>(
+    pub(crate) fn pick_center<D: PointCloud, R: Rng>(
         &mut self,
         radius: f32,
         point_cloud: &Arc<D>,
+        rng: &mut R,
     ) -> GokoResult<FirstCoveredData> {
-        let mut rng = thread_rng();
         let new_center: usize = rng.gen_range(0, self.coverage.len());
         let center_index = self.coverage.remove(new_center);
-        let dists = point_cloud.distances_to_point_index(center_index, &self.coverage)?;
+        let dists = center_distances(point_cloud, center_index, &self.coverage)?;
 
         let mut close_index = Vec::with_capacity(self.coverage.len());
         let mut close_dist = Vec::with_capacity(self.coverage.len());
@@ -97,6 +232,7 @@ impl UncoveredData {
             coverage: close_index,
             dists: close_dist,
             center_index,
+            parallel: self.parallel,
         };
         self.coverage = far;
         Ok(close)
@@ -128,14 +264,38 @@ fn find_split(dist_indexes: &[(f32, usize)], thresh: f32) -> usize {
 }
 
 impl FirstCoveredData {
-    pub(crate) fn new<D: PointCloud>(point_cloud: &Arc<D>) -> GokoResult<FirstCoveredData> {
+    /// Covers nothing, a placeholder for a node whose coverage is elsewhere.
+    pub(crate) fn empty() -> FirstCoveredData {
+        FirstCoveredData {
+            dists: vec![],
+            coverage: vec![],
+            center_index: 0,
+            parallel: false,
+        }
+    }
+
+    pub(crate) fn new<D: PointCloud>(
+        point_cloud: &Arc<D>,
+        parallel: bool,
+    ) -> GokoResult<FirstCoveredData> {
         let mut coverage = point_cloud.reference_indexes();
         let center_index = coverage.pop().unwrap();
-        let dists = point_cloud.distances_to_point_index(center_index, &coverage)?;
+        FirstCoveredData::with_center(point_cloud, center_index, coverage, parallel)
+    }
+
+    /// Covers the passed in points, which should not include the center. `parallel` is kept for the splits.
+    pub(crate) fn with_center<D: PointCloud>(
+        point_cloud: &Arc<D>,
+        center_index: PointIndex,
+        coverage: Vec<PointIndex>,
+        parallel: bool,
+    ) -> GokoResult<FirstCoveredData> {
+        let dists = center_distances(point_cloud, center_index, &coverage)?;
         Ok(FirstCoveredData {
             dists,
             coverage,
             center_index,
+            parallel,
         })
     }
 
@@ -155,8 +315,12 @@ impl FirstCoveredData {
             coverage: close_index,
             dists: close_dist,
             center_index: self.center_index,
+            parallel: self.parallel,
+        };
+        let new_far = UncoveredData {
+            coverage: far,
+            parallel: self.parallel,
         };
-        let new_far = UncoveredData { coverage: far };
         Ok((close, new_far))
     }
 
@@ -179,35 +343,52 @@ impl FirstCoveredData {
 #[derive(Clone, Debug)]
 pub(crate) struct NearestCoveredData {
     centers: Vec<PointIndex>,
-    dists: Vec<Vec<f32>>,
+    /// For each point, the position in `centers` of the nearest of them and the distance to it. Only the nearest is
+    /// kept, so a split holds one distance per point however many centers it picks.
+    nearest: Vec<(usize, f32)>,
     point_indexes: Vec<PointIndex>,
     center_dists: Vec<f32>,
     pub(crate) center_index: PointIndex,
+    parallel: bool,
 }
 
 impl NearestCoveredData {
-    pub(crate) fn new<D: PointCloud>(point_cloud: &Arc<D>) -> GokoResult<NearestCoveredData> {
+    pub(crate) fn new<D: PointCloud>(
+        point_cloud: &Arc<D>,
+        parallel: bool,
+    ) -> GokoResult<NearestCoveredData> {
         let mut point_indexes = point_cloud.reference_indexes();
         let center_index = point_indexes.pop().unwrap();
-        let center_dists = point_cloud.distances_to_point_index(center_index, &point_indexes)?;
-        let dists = vec![];
-        let centers = vec![];
+        NearestCoveredData::with_center(point_cloud, center_index, point_indexes, parallel)
+    }
+
+    /// Covers the passed in points, which should not include the center. If `parallel`, this and the splits of it
+    /// assign the points of large sets to their nearest center in parallel.
+    pub(crate) fn with_center<D: PointCloud>(
+        point_cloud: &Arc<D>,
+        center_index: PointIndex,
+        point_indexes: Vec<PointIndex>,
+        parallel: bool,
+    ) -> GokoResult<NearestCoveredData> {
+        let center_dists = center_distances(point_cloud, center_index, &point_indexes)?;
         Ok(NearestCoveredData {
-            centers,
-            dists,
+            centers: vec![],
+            nearest: vec![],
             point_indexes,
             center_index,
             center_dists,
+            parallel,
         })
     }
 
-    fn cover_thyself<D: PointCloud>(
+    fn cover_thyself<D: PointCloud, R: Rng>(
         &mut self,
         radius: f32,
         point_cloud: &Arc<D>,
+        rng: &mut R,
     ) -> GokoResult<()> {
         let mut coverage: Vec<bool> = self.center_dists.iter().map(|d| d < &radius).collect();
-        let mut rng = thread_rng();
+        self.nearest = vec![(0, f32::MAX); self.point_indexes.len()];
 
         while coverage.iter().any(|b| !b) {
             let uncovered_indexes: Vec<PointIndex> = self
@@ -217,14 +398,29 @@ impl NearestCoveredData {
                 .filter(|(_, b)| !**b)
                 .map(|(pi, _)| *pi)
                 .collect();
-            let center_index = *uncovered_indexes.choose(&mut rng).unwrap();
-            let new_dists =
-                point_cloud.distances_to_point_index(center_index, &self.point_indexes)?;
-            coverage
-                .iter_mut()
-                .zip(&new_dists)
-                .for_each(|(a, d)| *a = *a || (d < &radius));
-            self.dists.push(new_dists);
+            let center_index = *uncovered_indexes.choose(rng).unwrap();
+            let new_dists = center_distances(point_cloud, center_index, &self.point_indexes)?;
+            let center = self.centers.len();
+            let update = |((a, n), d): ((&mut bool, &mut (usize, f32)), &f32)| {
+                *a = *a || (d < &radius);
+                if *d < n.1 {
+                    *n = (center, *d);
+                }
+            };
+            if self.parallel && new_dists.len() > PARALLEL_CHUNK {
+                coverage
+                    .par_iter_mut()
+                    .zip(self.nearest.par_iter_mut())
+                    .zip(new_dists.par_iter())
+                    .with_min_len(PARALLEL_CHUNK)
+                    .for_each(update);
+            } else {
+                coverage
+                    .iter_mut()
+                    .zip(self.nearest.iter_mut())
+                    .zip(&new_dists)
+                    .for_each(update);
+            }
             self.centers.push(center_index);
         }
 
@@ -241,31 +437,27 @@ impl NearestCoveredData {
     fn assign_to_nearest(&self) -> (NearestCoveredData, Vec<NearestCoveredData>) {
         let mut new_center_coverage = NearestCoveredData {
             centers: vec![],
-            dists: vec![],
+            nearest: vec![],
             point_indexes: Vec::new(),
             center_index: self.center_index,
             center_dists: Vec::new(),
+            parallel: self.parallel,
         };
         let mut new_coverage: Vec<NearestCoveredData> = self
             .centers
             .iter()
             .map(|center_index| NearestCoveredData {
                 centers: vec![],
-                dists: vec![],
+                nearest: vec![],
                 point_indexes: Vec::new(),
                 center_index: *center_index,
                 center_dists: Vec::new(),
+                parallel: self.parallel,
             })
             .collect();
 
         for (i, pi) in self.point_indexes.iter().enumerate() {
-            let (index, d) = self
-                .dists
-                .iter()
-                .enumerate()
-                .map(|(dist_index, dists)| (dist_index, dists[i]))
-                .min_by(|(_di, d), (_ci, c)| d.partial_cmp(c).unwrap_or(Ordering::Equal))
-                .unwrap_or((0, f32::MAX));
+            let (index, d) = self.nearest.get(i).copied().unwrap_or((0, f32::MAX));
             if self.center_dists[i] < d {
                 new_center_coverage.add_point(*pi, self.center_dists[i]);
             } else {
@@ -276,12 +468,13 @@ impl NearestCoveredData {
         (new_center_coverage, new_coverage)
     }
 
-    pub(crate) fn split<D: PointCloud>(
+    pub(crate) fn split<D: PointCloud, R: Rng>(
         mut self,
         radius: f32,
         point_cloud: &Arc<D>,
+        rng: &mut R,
     ) -> GokoResult<(NearestCoveredData, Vec<NearestCoveredData>)> {
-        self.cover_thyself(radius, point_cloud)?;
+        self.cover_thyself(radius, point_cloud, rng)?;
         Ok(self.assign_to_nearest())
     }
 
@@ -304,6 +497,7 @@ impl NearestCoveredData {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::thread_rng;
     use std::sync::Arc;
 
     #[test]
@@ -318,7 +512,7 @@ mod tests {
 
         let point_cloud = DefaultLabeledCloud::<L2>::new_simple(data, 1, labels);
 
-        let cache = FirstCoveredData::new(&Arc::new(point_cloud)).unwrap();
+        let cache = FirstCoveredData::new(&Arc::new(point_cloud), false).unwrap();
         let (close, far) = cache.split(1.0).unwrap();
 
         assert_eq!(1, close.len());
@@ -339,8 +533,11 @@ mod tests {
 
         let mut cache = UncoveredData {
             coverage: (0..19 as PointIndex).collect(),
+            parallel: false,
         };
-        let close = cache.pick_center(1.0, &point_cloud).unwrap();
+        let close = cache
+            .pick_center(1.0, &point_cloud, &mut thread_rng())
+            .unwrap();
 
         assert!(!close.coverage.contains(&close.center_index));
         assert!(!cache.coverage.contains(&close.center_index));
@@ -365,7 +562,7 @@ mod tests {
         //data.sort_unstable_by(|a, b| (a).partial_cmp(&b).unwrap_or(Ordering::Equal));
         let point_cloud = DefaultLabeledCloud::<L2>::new_simple(data.clone(), 1, labels);
 
-        let cache = FirstCoveredData::new(&Arc::new(point_cloud)).unwrap();
+        let cache = FirstCoveredData::new(&Arc::new(point_cloud), false).unwrap();
 
         let thresh = 0.5;
         let mut true_close = Vec::new();
@@ -397,12 +594,14 @@ mod tests {
 
         let point_cloud = Arc::new(DefaultLabeledCloud::<L2>::new_simple(data, 1, labels));
 
-        let mut cache = NearestCoveredData::new(&point_cloud).unwrap();
-        cache.cover_thyself(1.0, &point_cloud).unwrap();
+        let mut cache = NearestCoveredData::new(&point_cloud, false).unwrap();
+        cache
+            .cover_thyself(1.0, &point_cloud, &mut thread_rng())
+            .unwrap();
 
-        assert_eq!(1, cache.dists.len());
+        assert_eq!(1, cache.centers.len());
         assert_eq!(4, cache.center_dists.len());
-        assert_eq!(4, cache.dists[0].len());
+        assert_eq!(4, cache.nearest.len());
 
         println!("{:#?}", cache);
         let (nested_split, splits) = cache.assign_to_nearest();
@@ -417,10 +616,12 @@ mod tests {
     fn nearest_splits_nearest_1() {
         let cache = NearestCoveredData {
             center_index: 1,
-            dists: vec![vec![0.0, 2.0, 0.0, 1.0, 2.0], vec![1.0, 0.0, 1.0, 2.0, 0.0]],
+            // The nearest of the distances to the centers, [0.0, 2.0, 0.0, 1.0, 2.0] and [1.0, 0.0, 1.0, 2.0, 0.0]
+            nearest: vec![(0, 0.0), (1, 0.0), (0, 0.0), (0, 1.0), (1, 0.0)],
             point_indexes: vec![0, 2, 3, 4, 5],
             centers: vec![0, 2],
             center_dists: vec![2.0, 1.0, 2.0, 0.0, 1.0],
+            parallel: false,
         };
 
         let (nested_split, splits) = cache.assign_to_nearest();
@@ -0,0 +1,365 @@
+//! # Dual tree all nearest neighbors
+//! Finds the knn of every point in one tree by walking it together with a reference tree. Each query node carries
+//! the reference nodes and points that could still hold a neighbor of something it covers. Every point of a query
+//! node is within its radius of the node's center, so the candidates give a bound on the kth neighbor distance shared
+//! by all of them, and a reference node that can't get under that bound is dropped for the whole query subtree at
+//! once. References bigger than the query node are split into their children before the query node is, so the two
+//! sides are refined together. Once a query is down to a single point all of its candidates are points, and the
+//! nearest `k` are its answer.
+
+use crate::errors::GokoResult;
+use crate::*;
+use std::cmp::Ordering;
+
+/// Each point paired with its knn, as returned by `all_knn`.
+pub type KnnGraph = Vec<(PointIndex, Vec<(f32, PointIndex)>)>;
+
+/// A reference subtree or point that might hold a neighbor.
+#[derive(Debug, Clone, Copy)]
+enum Reference {
+    Node {
+        address: NodeAddress,
+        radius: f32,
+        coverage: usize,
+    },
+    Point(PointIndex),
+}
+
+impl Reference {
+    fn center(&self) -> PointIndex {
+        match self {
+            Reference::Node { address, .. } => address.1,
+            Reference::Point(pi) => *pi,
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        match self {
+            Reference::Node { radius, .. } => *radius,
+            Reference::Point(_) => 0.0,
+        }
+    }
+
+    fn coverage(&self) -> usize {
+        match self {
+            Reference::Node { coverage, .. } => *coverage,
+            Reference::Point(_) => 1,
+        }
+    }
+}
+
+/// A query subtree or point, with its radius.
+#[derive(Debug, Clone, Copy)]
+enum Query {
+    Node(NodeAddress, f32),
+    Point(PointIndex),
+}
+
+impl Query {
+    fn center(&self) -> PointIndex {
+        match self {
+            Query::Node(address, _) => address.1,
+            Query::Point(pi) => *pi,
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        match self {
+            Query::Node(_, radius) => *radius,
+            Query::Point(_) => 0.0,
+        }
+    }
+}
+
+/// The parts a node splits into: its nested child first, then the other children, or its center if it is a leaf,
+/// and then its singletons.
+fn split_node<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    address: NodeAddress,
+) -> GokoResult<(Vec<NodeAddress>, Vec<PointIndex>)> {
+    reader.get_node_and(address, |n| {
+        let mut singletons = Vec::with_capacity(n.singletons_len() + 1);
+        let children = match n.children() {
+            Some((nested_scale, children)) => {
+                let mut addresses = Vec::with_capacity(children.len() + 1);
+                addresses.push((nested_scale, address.1));
+                addresses.extend_from_slice(children);
+                addresses
+            }
+            None => {
+                singletons.push(address.1);
+                Vec::new()
+            }
+        };
+        singletons.extend_from_slice(n.singletons());
+        (children, singletons)
+    })
+}
+
+fn node_bounds<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    address: NodeAddress,
+) -> GokoResult<(f32, usize)> {
+    // Leaves that only hold their center have a radius of -inf
+    reader.get_node_and(address, |n| (n.radius().max(0.0), n.coverage_count()))
+}
+
+/// An upper bound on the kth neighbor distance of every point within `query_radius` of the query center.
+fn kth_bound(candidates: &mut [(f32, Reference)], query_radius: f32, needed: usize) -> f32 {
+    candidates.sort_by(|a, b| {
+        (a.0 + a.1.radius())
+            .partial_cmp(&(b.0 + b.1.radius()))
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut count = 0;
+    for (dist, reference) in candidates.iter() {
+        count += reference.coverage();
+        if count >= needed {
+            return dist + reference.radius() + query_radius;
+        }
+    }
+    f32::INFINITY
+}
+
+impl<D: PointCloud> CoverTreeReader<D> {
+    /// The `k` nearest neighbors of every point in the tree, not counting the point itself, as pairs of a point and
+    /// its knn. Sorted by point index. Uses a dual tree traversal, see the `dual_tree` module, which is much faster
+    /// than a knn query per point.
+    pub fn all_knn(&self, k: usize) -> GokoResult<KnnGraph> {
+        self.dual_tree_knn(self, k, true)
+    }
+
+    /// The `k` nearest neighbors in `reference` of every point in this tree, as pairs of a point of this tree and its
+    /// knn in the other. Sorted by point index.
+    pub fn all_knn_against<E: PointCloud<Metric = D::Metric>>(
+        &self,
+        reference: &CoverTreeReader<E>,
+        k: usize,
+    ) -> GokoResult<KnnGraph> {
+        self.dual_tree_knn(reference, k, false)
+    }
+
+    fn dual_tree_knn<E: PointCloud<Metric = D::Metric>>(
+        &self,
+        reference: &CoverTreeReader<E>,
+        k: usize,
+        exclude_self: bool,
+    ) -> GokoResult<KnnGraph> {
+        if self.is_empty() || reference.is_empty() || k == 0 {
+            return Ok(Vec::new());
+        }
+        let query_root = self.root_address();
+        let reference_root = reference.root_address();
+        let (query_radius, _) = node_bounds(self, query_root)?;
+        let (radius, coverage) = node_bounds(reference, reference_root)?;
+        let dist = D::Metric::dist(
+            self.point_cloud().point(query_root.1)?,
+            reference.point_cloud().point(reference_root.1)?,
+        )?;
+        let candidates = vec![(
+            dist,
+            Reference::Node {
+                address: reference_root,
+                radius,
+                coverage,
+            },
+        )];
+        let mut results = Vec::with_capacity(self.len());
+        self.dual_tree_step(
+            reference,
+            Query::Node(query_root, query_radius),
+            candidates,
+            k,
+            exclude_self,
+            &mut results,
+        )?;
+        results.sort_by_key(|(pi, _)| *pi);
+        Ok(results)
+    }
+
+    /// Refines the candidates of the query against the reference tree, then splits the query and recurses.
+    fn dual_tree_step<E: PointCloud<Metric = D::Metric>>(
+        &self,
+        reference: &CoverTreeReader<E>,
+        query: Query,
+        mut candidates: Vec<(f32, Reference)>,
+        k: usize,
+        exclude_self: bool,
+        results: &mut KnnGraph,
+    ) -> GokoResult<()> {
+        let query_point = self.point_cloud().point(query.center())?;
+        let query_radius = query.radius();
+        let is_point = matches!(query, Query::Point(_));
+        let needed = k + exclude_self as usize;
+        loop {
+            let bound = kth_bound(&mut candidates, query_radius, needed);
+            candidates.retain(|(d, r)| d - r.radius() - query_radius <= bound);
+            let (to_split, kept): (Vec<_>, Vec<_>) =
+                candidates.into_iter().partition(|(_, r)| match r {
+                    Reference::Node { radius, .. } => is_point || *radius > query_radius,
+                    Reference::Point(_) => false,
+                });
+            candidates = kept;
+            if to_split.is_empty() {
+                break;
+            }
+            for (dist, r) in to_split {
+                let address = match r {
+                    Reference::Node { address, .. } => address,
+                    Reference::Point(_) => continue,
+                };
+                let (children, singletons) = split_node(reference, address)?;
+                for (i, child) in children.iter().enumerate() {
+                    let (radius, coverage) = node_bounds(reference, *child)?;
+                    // The nested child shares our center
+                    let child_dist = if i == 0 {
+                        dist
+                    } else {
+                        D::Metric::dist(query_point, reference.point_cloud().point(child.1)?)?
+                    };
+                    candidates.push((
+                        child_dist,
+                        Reference::Node {
+                            address: *child,
+                            radius,
+                            coverage,
+                        },
+                    ));
+                }
+                let singleton_dists = reference
+                    .point_cloud()
+                    .distances_to_point(query_point, &singletons)?;
+                candidates.extend(
+                    singleton_dists
+                        .into_iter()
+                        .zip(singletons)
+                        .map(|(d, pi)| (d, Reference::Point(pi))),
+                );
+            }
+        }
+
+        let query_address = match query {
+            Query::Point(pi) => {
+                let mut knn: Vec<(f32, PointIndex)> = candidates
+                    .into_iter()
+                    .map(|(d, r)| (d, r.center()))
+                    .filter(|(_, ri)| !exclude_self || *ri != pi)
+                    .collect();
+                knn.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                knn.truncate(k);
+                results.push((pi, knn));
+                return Ok(());
+            }
+            Query::Node(address, _) => address,
+        };
+
+        let (children, singletons) = split_node(self, query_address)?;
+        let mut queries = Vec::with_capacity(children.len() + singletons.len());
+        for child in children {
+            let (radius, _) = node_bounds(self, child)?;
+            queries.push(Query::Node(child, radius));
+        }
+        queries.extend(singletons.into_iter().map(Query::Point));
+        let reference_cloud = reference.point_cloud();
+        for child in queries {
+            let child_candidates = if child.center() == query_address.1 {
+                candidates.clone()
+            } else {
+                let child_point = self.point_cloud().point(child.center())?;
+                let centers: Vec<PointIndex> = candidates.iter().map(|(_, r)| r.center()).collect();
+                let dists = reference_cloud.distances_to_point(child_point, &centers)?;
+                dists
+                    .into_iter()
+                    .zip(candidates.iter())
+                    .map(|(d, (_, r))| (d, *r))
+                    .collect()
+            };
+            self.dual_tree_step(reference, child, child_candidates, k, exclude_self, results)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::CoverTreeBuilder;
+    use pointcloud::synthetic::Uniform;
+    use std::sync::Arc;
+
+    fn random_tree(count: usize) -> CoverTreeWriter<DefaultCloud<L2>> {
+        let (data, _labels) = Uniform {
+            count,
+            dim: 4,
+            seed: rand::random(),
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 4).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        builder.build(point_cloud).unwrap()
+    }
+
+    fn brute_force<D: PointCloud>(
+        point_cloud: &D,
+        point: PointRef,
+        k: usize,
+        skip: Option<PointIndex>,
+    ) -> Vec<f32> {
+        let indexes: Vec<PointIndex> = point_cloud
+            .reference_indexes()
+            .into_iter()
+            .filter(|pi| Some(*pi) != skip)
+            .collect();
+        let mut dists = point_cloud.distances_to_point(point, &indexes).unwrap();
+        dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        dists.truncate(k);
+        dists
+    }
+
+    fn assert_close(found: &[(f32, PointIndex)], expected: &[f32]) {
+        assert_eq!(found.len(), expected.len());
+        for ((d, _), e) in found.iter().zip(expected) {
+            assert!((d - e).abs() < 1e-5, "{:?} != {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn all_knn_matches_brute_force() {
+        let writer = random_tree(600);
+        let reader = writer.reader();
+        let point_cloud = reader.point_cloud();
+        let all_knn = reader.all_knn(5).unwrap();
+        assert_eq!(all_knn.len(), 600);
+        for (i, (pi, knn)) in all_knn.iter().enumerate() {
+            assert_eq!(i, *pi);
+            assert!(knn.iter().all(|(_, ni)| ni != pi));
+            let point = point_cloud.point(*pi).unwrap();
+            assert_close(knn, &brute_force(point_cloud.as_ref(), point, 5, Some(*pi)));
+        }
+    }
+
+    #[test]
+    fn all_knn_against_matches_brute_force() {
+        let query_writer = random_tree(300);
+        let query = query_writer.reader();
+        let reference_writer = random_tree(500);
+        let reference = reference_writer.reader();
+        let all_knn = query.all_knn_against(&reference, 3).unwrap();
+        assert_eq!(all_knn.len(), 300);
+        for (pi, knn) in all_knn.iter() {
+            let point = query.point_cloud().point(*pi).unwrap();
+            assert_close(
+                knn,
+                &brute_force(reference.point_cloud().as_ref(), point, 3, None),
+            );
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! # Checkpoints and the write-ahead journal
+//! A journaling writer keeps a directory with a full checkpoint of the tree, `checkpoint-<n>.dat` in the same protobuf
+//! format as `utils::save_tree`, and a journal of every change made since, `journal-<n>.log`. Each change is appended
+//! and synced to the journal before the call that made it returns, so after a crash `CoverTreeWriter::recover` loads the
+//! checkpoint and replays the journal instead of rebuilding the tree. The changes are replayed with the same calls that
+//! made them, which are deterministic, so the recovered tree has the same nodes at the same addresses.
+//!
+//! `CoverTreeWriter::checkpoint` writes checkpoint `n+1` and starts journal `n+1` before deleting the old pair, so a
+//! crash at any point leaves at least one complete checkpoint with its journal. A partially written entry at the end
+//! of a journal is dropped.
+//!
+//! Plugins are not journaled, add them again after recovering.
+
+use crate::errors::{ErrorContextExt, GokoError, GokoResult};
+use crate::tree_file_format::CoreProto;
+use crate::*;
+use protobuf::Message;
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const REMOVE_POINT: u8 = 1;
+const SET_ANNOTATION: u8 = 2;
+const SPLIT_NODE: u8 = 3;
+const MERGE_LEAVES: u8 = 4;
+
+/// A change to the tree, as it's recorded in the journal.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JournalEntry {
+    RemovePoint(PointIndex),
+    SetAnnotation(NodeAddress, Option<String>),
+    SplitNode(NodeAddress),
+    MergeLeaves(Vec<NodeAddress>),
+}
+
+impl JournalEntry {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            JournalEntry::RemovePoint(pi) => {
+                bytes.push(REMOVE_POINT);
+                bytes.extend_from_slice(&(*pi as u64).to_le_bytes());
+            }
+            JournalEntry::SetAnnotation(address, annotation) => {
+                bytes.push(SET_ANNOTATION);
+                bytes.extend_from_slice(&address.0.to_le_bytes());
+                bytes.extend_from_slice(&(address.1 as u64).to_le_bytes());
+                match annotation {
+                    Some(text) => {
+                        bytes.push(1);
+                        bytes.extend_from_slice(&(text.len() as u64).to_le_bytes());
+                        bytes.extend_from_slice(text.as_bytes());
+                    }
+                    None => bytes.push(0),
+                }
+            }
+            JournalEntry::SplitNode(address) => {
+                bytes.push(SPLIT_NODE);
+                bytes.extend_from_slice(&address.0.to_le_bytes());
+                bytes.extend_from_slice(&(address.1 as u64).to_le_bytes());
+            }
+            JournalEntry::MergeLeaves(addresses) => {
+                bytes.push(MERGE_LEAVES);
+                bytes.extend_from_slice(&(addresses.len() as u64).to_le_bytes());
+                for address in addresses {
+                    bytes.extend_from_slice(&address.0.to_le_bytes());
+                    bytes.extend_from_slice(&(address.1 as u64).to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Decodes the entry at the start of `bytes` and returns it with its length. `None` if the bytes end partway
+    /// through the entry or it is garbled.
+    fn decode(bytes: &[u8]) -> Option<(JournalEntry, usize)> {
+        let u64_at = |i: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(bytes.get(i..i + 8)?.try_into().ok()?))
+        };
+        let address_at = |i: usize| -> Option<NodeAddress> {
+            let scale_index = i32::from_le_bytes(bytes.get(i..i + 4)?.try_into().ok()?);
+            Some((scale_index, u64_at(i + 4)? as PointIndex))
+        };
+        match *bytes.first()? {
+            REMOVE_POINT => Some((JournalEntry::RemovePoint(u64_at(1)? as PointIndex), 9)),
+            SET_ANNOTATION => {
+                let scale_index = i32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?);
+                let address = (scale_index, u64_at(5)? as PointIndex);
+                match *bytes.get(13)? {
+                    0 => Some((JournalEntry::SetAnnotation(address, None), 14)),
+                    1 => {
+                        let len = u64_at(14)? as usize;
+                        let text = bytes.get(22..22usize.checked_add(len)?)?;
+                        let text = String::from_utf8(text.to_vec()).ok()?;
+                        Some((JournalEntry::SetAnnotation(address, Some(text)), 22 + len))
+                    }
+                    _ => None,
+                }
+            }
+            SPLIT_NODE => Some((JournalEntry::SplitNode(address_at(1)?), 13)),
+            MERGE_LEAVES => {
+                let count = u64_at(1)? as usize;
+                let mut addresses = Vec::new();
+                let mut offset = 9;
+                for _ in 0..count {
+                    addresses.push(address_at(offset)?);
+                    offset += 12;
+                }
+                Some((JournalEntry::MergeLeaves(addresses), offset))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The open journal of a writer.
+#[derive(Debug)]
+pub(crate) struct TreeJournal {
+    dir: PathBuf,
+    sequence: u64,
+    file: File,
+}
+
+fn checkpoint_path(dir: &Path, sequence: u64) -> PathBuf {
+    dir.join(format!("checkpoint-{}.dat", sequence))
+}
+
+fn journal_path(dir: &Path, sequence: u64) -> PathBuf {
+    dir.join(format!("journal-{}.log", sequence))
+}
+
+/// The sequence numbers of the complete checkpoints in the directory, in increasing order.
+fn checkpoints(dir: &Path) -> GokoResult<Vec<u64>> {
+    let mut sequences = Vec::new();
+    for entry in fs::read_dir(dir).at_path(dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(sequence) = name
+            .strip_prefix("checkpoint-")
+            .and_then(|n| n.strip_suffix(".dat"))
+            .and_then(|n| n.parse::<u64>().ok())
+        {
+            sequences.push(sequence);
+        }
+    }
+    sequences.sort_unstable();
+    Ok(sequences)
+}
+
+/// Reads the journal's complete entries, and the number of bytes they take up.
+fn read_journal(path: &Path) -> GokoResult<(Vec<JournalEntry>, usize)> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(e) => return Err(e).at_path(path),
+    };
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some((entry, len)) = JournalEntry::decode(&bytes[offset..]) {
+        entries.push(entry);
+        offset += len;
+    }
+    Ok((entries, offset))
+}
+
+impl TreeJournal {
+    /// Opens the journal for appending, cutting off anything past `len`.
+    fn open(dir: &Path, sequence: u64, len: usize) -> GokoResult<TreeJournal> {
+        let path = journal_path(dir, sequence);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .at_path(&path)?;
+        file.set_len(len as u64).at_path(&path)?;
+        let mut journal = TreeJournal {
+            dir: dir.to_path_buf(),
+            sequence,
+            file,
+        };
+        journal.file.sync_all()?;
+        journal.file.seek(SeekFrom::End(0))?;
+        Ok(journal)
+    }
+
+    pub(crate) fn append(&mut self, entry: &JournalEntry) -> GokoResult<()> {
+        let path = journal_path(&self.dir, self.sequence);
+        self.file.write_all(&entry.encode()).at_path(&path)?;
+        self.file.sync_data().at_path(&path)?;
+        Ok(())
+    }
+}
+
+impl<D: PointCloud> CoverTreeWriter<D> {
+    /// Starts keeping a checkpoint and journal of this tree in `dir`, see the `journal` module. Takes a checkpoint
+    /// right away. Anything already in the directory from an earlier journal is replaced.
+    pub fn start_journal<P: AsRef<Path>>(&mut self, dir: P) -> GokoResult<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir).at_path(dir)?;
+        let sequence = checkpoints(dir)?.last().map(|s| s + 1).unwrap_or(0);
+        self.write_checkpoint(dir, sequence)
+    }
+
+    /// Writes a full checkpoint of the tree and starts a new, empty journal. Keeps the journal short, so recovering
+    /// is quick.
+    pub fn checkpoint(&mut self) -> GokoResult<()> {
+        let (dir, sequence) = match &self.journal {
+            Some(journal) => (journal.dir.clone(), journal.sequence + 1),
+            None => return Err(GokoError::NoJournal),
+        };
+        self.write_checkpoint(&dir, sequence)
+    }
+
+    fn write_checkpoint(&mut self, dir: &Path, sequence: u64) -> GokoResult<()> {
+        let bytes = self.save().write_to_bytes()?;
+        let path = checkpoint_path(dir, sequence);
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path).at_path(&tmp_path)?;
+        file.write_all(&bytes).at_path(&tmp_path)?;
+        file.sync_all().at_path(&tmp_path)?;
+        fs::rename(&tmp_path, &path).at_path(&path)?;
+        self.journal = Some(TreeJournal::open(dir, sequence, 0)?);
+        for old in checkpoints(dir)?.into_iter().filter(|s| *s < sequence) {
+            fs::remove_file(checkpoint_path(dir, old))?;
+            let _ = fs::remove_file(journal_path(dir, old));
+        }
+        Ok(())
+    }
+
+    /// Loads the latest checkpoint in `dir` and replays its journal. The recovered writer keeps journaling to `dir`.
+    pub fn recover<P: AsRef<Path>>(dir: P, point_cloud: Arc<D>) -> GokoResult<CoverTreeWriter<D>> {
+        let dir = dir.as_ref();
+        let sequence = match checkpoints(dir)?.last() {
+            Some(sequence) => *sequence,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "no checkpoint in the journal directory",
+                ))
+                .at_path(dir)
+            }
+        };
+        let path = checkpoint_path(dir, sequence);
+        let bytes = fs::read(&path).at_path(&path)?;
+        let cover_proto = CoreProto::parse_from_bytes(&bytes).at_path(&path)?;
+        let mut tree = CoverTreeWriter::load(&cover_proto, point_cloud).at_path(&path)?;
+        let (entries, len) = read_journal(&journal_path(dir, sequence))?;
+        for entry in entries {
+            match entry {
+                JournalEntry::RemovePoint(pi) => tree.remove_point(pi)?,
+                JournalEntry::SetAnnotation(address, annotation) => {
+                    tree.set_node_annotation(address, annotation)?
+                }
+                JournalEntry::SplitNode(address) => tree.split_node(address)?,
+                JournalEntry::MergeLeaves(addresses) => {
+                    tree.merge_leaves(&addresses)?;
+                }
+            }
+        }
+        tree.journal = Some(TreeJournal::open(dir, sequence, len)?);
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use std::env;
+
+    #[test]
+    fn entries_round_trip() {
+        let entries = vec![
+            JournalEntry::RemovePoint(7),
+            JournalEntry::SetAnnotation((-3, 2), Some("borders".to_string())),
+            JournalEntry::SetAnnotation((4, 0), None),
+        ];
+        let mut bytes: Vec<u8> = entries.iter().flat_map(|e| e.encode()).collect();
+        let decode_all = |bytes: &[u8]| {
+            let mut decoded = Vec::new();
+            let mut offset = 0;
+            while let Some((entry, len)) = JournalEntry::decode(&bytes[offset..]) {
+                decoded.push(entry);
+                offset += len;
+            }
+            (decoded, offset)
+        };
+        assert_eq!(decode_all(&bytes), (entries.clone(), bytes.len()));
+        bytes.pop();
+        let (decoded, len) = decode_all(&bytes);
+        assert_eq!(decoded, entries[..2].to_vec());
+        assert_eq!(len, bytes.len() - 13);
+    }
+
+    #[test]
+    fn surgery_entries_round_trip() {
+        let entries = vec![
+            JournalEntry::SplitNode((-2, 11)),
+            JournalEntry::MergeLeaves(vec![(-4, 3), (-4, 8), (-5, 1)]),
+        ];
+        let bytes: Vec<u8> = entries.iter().flat_map(|e| e.encode()).collect();
+        let (split, len) = JournalEntry::decode(&bytes).unwrap();
+        assert_eq!((split, len), (entries[0].clone(), 13));
+        let (merge, merge_len) = JournalEntry::decode(&bytes[len..]).unwrap();
+        assert_eq!(merge, entries[1]);
+        assert_eq!(len + merge_len, bytes.len());
+        assert!(JournalEntry::decode(&bytes[len..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn recover_replays_the_journal() {
+        let dir = env::temp_dir().join(format!("goko-journal-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = build_basic_tree();
+        let point_cloud = Arc::clone(writer.reader().point_cloud());
+        assert!(writer.checkpoint().is_err());
+        writer.start_journal(&dir).unwrap();
+        writer.remove_point(1).unwrap();
+        let root = writer.reader().root_address();
+        writer
+            .set_node_annotation(root, Some("root".to_string()))
+            .unwrap();
+        drop(writer);
+
+        let mut recovered = CoverTreeWriter::recover(&dir, Arc::clone(&point_cloud)).unwrap();
+        let reader = recovered.reader();
+        assert_eq!(reader.root_address(), root);
+        assert_eq!(
+            reader.node_annotation(root).unwrap(),
+            Some("root".to_string())
+        );
+        assert!(reader.known_path(1).is_err());
+        assert!(reader.known_path(2).is_ok());
+
+        recovered.checkpoint().unwrap();
+        recovered.remove_point(2).unwrap();
+        drop(recovered);
+        // A torn write at the end of the journal is dropped
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .open(journal_path(&dir, 1))
+            .unwrap();
+        journal.write_all(&[REMOVE_POINT, 3, 0]).unwrap();
+        drop(journal);
+        assert_eq!(checkpoints(&dir).unwrap(), vec![1]);
+
+        let recovered = CoverTreeWriter::recover(&dir, point_cloud).unwrap();
+        let reader = recovered.reader();
+        assert!(reader.known_path(1).is_err());
+        assert!(reader.known_path(2).is_err());
+        assert!(reader.known_path(3).is_ok());
+        assert_eq!(
+            reader.node_annotation(root).unwrap(),
+            Some("root".to_string())
+        );
+    }
+
+    #[test]
+    fn failed_removals_are_not_journaled() {
+        let dir = env::temp_dir().join(format!("goko-journal-failed-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = build_basic_tree();
+        let point_cloud = Arc::clone(writer.reader().point_cloud());
+        writer.start_journal(&dir).unwrap();
+        let root = writer.reader().root_address();
+        let mut remaining: Vec<PointIndex> = (0..5).filter(|pi| *pi != root.1).collect();
+        remaining.insert(0, root.1);
+        while remaining.len() > 1 {
+            writer.remove_point(remaining.remove(0)).unwrap();
+        }
+        assert!(writer.remove_point(remaining[0]).is_err());
+        assert!(writer.remove_point(root.1).is_err());
+        drop(writer);
+
+        let recovered = CoverTreeWriter::recover(&dir, point_cloud).unwrap();
+        let reader = recovered.reader();
+        assert!(reader.known_path(remaining[0]).is_ok());
+        assert_eq!(
+            (0..5).filter(|pi| reader.known_path(*pi).is_ok()).count(),
+            1
+        );
+    }
+}
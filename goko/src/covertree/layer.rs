@@ -196,6 +196,8 @@ impl<D: PointCloud> CoverLayerWriter<D> {
         self.node_writer.for_each(|_pi, node| {
             node_protos.push(node.save());
         });
+        // The map's iteration order varies between runs, sort so the same tree always encodes to the same bytes.
+        node_protos.sort_by_key(|n| n.get_center_index());
         layer_proto.set_nodes(node_protos);
         layer_proto.set_scale_index(self.scale_index);
         layer_proto
@@ -205,7 +207,20 @@ impl<D: PointCloud> CoverLayerWriter<D> {
         self.node_writer.insert(index, node);
     }
 
+    pub(crate) fn remove_raw(&mut self, index: PointIndex) {
+        self.node_writer.remove(index);
+    }
+
     pub(crate) fn refresh(&mut self) {
         self.node_writer.refresh();
     }
+
+    /// Publishes at most `count` of the pending writes, oldest first.
+    pub(crate) fn refresh_some(&mut self, count: usize) {
+        self.node_writer.refresh_some(count);
+    }
+
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.node_writer.pending().is_empty()
+    }
 }
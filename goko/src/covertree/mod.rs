@@ -1,10 +1,14 @@
 pub(crate) mod builders;
 pub(crate) mod data_caches;
+mod dual_tree;
+pub mod journal;
 pub mod layer;
 pub mod node;
 pub mod query_tools;
 
+mod surgery;
 mod tree;
 
-pub use builders::CoverTreeBuilder;
+pub use builders::{CoverTreeBuilder, CoverTreeParams, NodeStats};
+pub use dual_tree::KnnGraph;
 pub use tree::*;
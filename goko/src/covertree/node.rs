@@ -20,7 +20,7 @@
 //! # The Node
 //! This is the workhorse of the library. Each node
 //!
-use super::query_tools::{RoutingQueryHeap, SingletonQueryHeap};
+use super::query_tools::{DistanceCache, RoutingQueryHeap, SingletonQueryHeap};
 use crate::errors::{GokoError, GokoResult};
 use crate::plugins::{
     labels::{NodeLabelSummary, NodeMetaSummary},
@@ -31,6 +31,7 @@ use crate::NodeAddress;
 
 use pointcloud::*;
 use smallvec::SmallVec;
+use std::cmp::Ordering;
 use std::marker::PhantomData;
 use std::sync::Arc;
 /// The node children. This is a separate struct from the `CoverNode` to use the rust compile time type checking and
@@ -58,6 +59,8 @@ pub struct CoverNode<D: PointCloud> {
     /// Children
     children: Option<NodeChildren>,
     singles_indexes: SmallVec<[PointIndex; 20]>,
+    /// User notes, saved with the tree
+    annotation: Option<String>,
     plugins: NodePluginSet,
     metic: PhantomData<D>,
 }
@@ -71,6 +74,7 @@ impl<D: PointCloud> Clone for CoverNode<D> {
             coverage_count: self.coverage_count,
             children: self.children.clone(),
             singles_indexes: self.singles_indexes.clone(),
+            annotation: self.annotation.clone(),
             plugins: NodePluginSet::new(),
             metic: PhantomData,
         }
@@ -105,11 +109,21 @@ impl<D: PointCloud> CoverNode<D> {
             coverage_count: 1,
             children: None,
             singles_indexes: SmallVec::new(),
+            annotation: None,
             plugins: NodePluginSet::new(),
             metic: PhantomData,
         }
     }
 
+    /// The user's note on this node, if there is one.
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
+    pub(crate) fn set_annotation(&mut self, annotation: Option<String>) {
+        self.annotation = annotation;
+    }
+
     /// Verifies that this is a leaf by checking there's no nested child
     pub fn is_leaf(&self) -> bool {
         self.children.is_none()
@@ -212,9 +226,25 @@ impl<D: PointCloud> CoverNode<D> {
         point: P,
         point_cloud: &D,
         query_heap: &mut T,
+    ) -> GokoResult<()> {
+        self.singleton_knn_cached(
+            point,
+            point_cloud,
+            query_heap,
+            &mut DistanceCache::default(),
+        )
+    }
+
+    /// Same as `singleton_knn`, but takes the distances through the query's cache.
+    pub(crate) fn singleton_knn_cached<'a, P: Into<PointRef<'a>>, T: SingletonQueryHeap>(
+        &self,
+        point: P,
+        point_cloud: &D,
+        query_heap: &mut T,
+        cache: &mut DistanceCache,
     ) -> GokoResult<()> {
         let point: PointRef<'a> = point.into();
-        let distances = point_cloud.distances_to_point(point, &self.singles_indexes[..])?;
+        let distances = cache.distances(point_cloud, point, &self.singles_indexes[..])?;
         query_heap.push_outliers(&self.singles_indexes[..], &distances[..]);
         Ok(())
     }
@@ -227,10 +257,30 @@ impl<D: PointCloud> CoverNode<D> {
         point: P,
         point_cloud: &D,
         query_heap: &mut T,
+    ) -> GokoResult<()> {
+        self.child_knn_cached(
+            dist_to_center,
+            point,
+            point_cloud,
+            query_heap,
+            &mut DistanceCache::default(),
+        )
+    }
+
+    /// Same as `child_knn`, but takes the distances through the query's cache.
+    pub(crate) fn child_knn_cached<'a, P: Into<PointRef<'a>>, T: RoutingQueryHeap>(
+        &self,
+        dist_to_center: Option<f32>,
+        point: P,
+        point_cloud: &D,
+        query_heap: &mut T,
+        cache: &mut DistanceCache,
     ) -> GokoResult<()> {
         let point: PointRef<'a> = point.into();
-        let dist_to_center =
-            dist_to_center.unwrap_or(point_cloud.distances_to_point(point, &[self.address.1])?[0]);
+        let dist_to_center = match dist_to_center {
+            Some(d) => d,
+            None => cache.distances(point_cloud, point, &[self.address.1])?[0],
+        };
 
         if let Some(children) = &self.children {
             query_heap.push_nodes(
@@ -240,7 +290,7 @@ impl<D: PointCloud> CoverNode<D> {
             );
             let children_indexes: Vec<PointIndex> =
                 children.addresses.iter().map(|(_si, pi)| *pi).collect();
-            let distances = point_cloud.distances_to_point(point, &children_indexes[..])?;
+            let distances = cache.distances(point_cloud, point, &children_indexes[..])?;
             query_heap.push_nodes(&children.addresses[..], &distances, Some(self.address));
         }
         Ok(())
@@ -262,7 +312,7 @@ impl<D: PointCloud> CoverNode<D> {
             let (min_index, min_dist) = distances
                 .iter()
                 .enumerate()
-                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
                 .unwrap_or((0, &std::f32::MAX));
             if dist_to_center < *min_dist {
                 if dist_to_center < scale_base.powi(children.nested_scale) {
@@ -354,11 +404,48 @@ impl<D: PointCloud> CoverNode<D> {
         self.singles_indexes.push(pi);
     }
 
+    /// Removes a singleton child from the node. Returns false if it was not a singleton of this node.
+    pub(crate) fn remove_singleton(&mut self, pi: PointIndex) -> bool {
+        match self.singles_indexes.iter().position(|s| *s == pi) {
+            Some(i) => {
+                self.singles_indexes.remove(i);
+                self.coverage_count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a routing child, and the points it covered, from the node.
+    pub(crate) fn remove_child(&mut self, address: NodeAddress, coverage: usize) {
+        if let Some(children) = &mut self.children {
+            if let Some(i) = children.addresses.iter().position(|a| *a == address) {
+                children.addresses.remove(i);
+                self.coverage_count -= coverage;
+            }
+        }
+    }
+
+    /// Removes points from the coverage count, for when a decendent loses points.
+    pub(crate) fn remove_coverage(&mut self, count: usize) {
+        self.coverage_count -= count;
+    }
+
+    /// Moves the node under a new parent.
+    pub(crate) fn set_parent_address(&mut self, parent_address: Option<NodeAddress>) {
+        self.parent_address = parent_address;
+    }
+
     /// Inserts a single singleton child into the node.
     pub(crate) fn insert_plugin<T: NodePlugin<D> + 'static>(&mut self, plugin: T) {
         self.plugins.insert(plugin);
     }
 
+    /// Drops every plugin component, for when the node changed in a way that makes them stale.
+    pub(crate) fn clear_plugins(&mut self) {
+        self.plugins = NodePluginSet::new();
+    }
+
     /// Updates the radius
     pub(crate) fn set_radius(&mut self, radius: f32) {
         self.radius = radius;
@@ -384,6 +471,11 @@ impl<D: PointCloud> CoverNode<D> {
                 Some((parent_scale_index, parent_center_index as usize))
             };
         let coverage_count = node_proto.get_coverage_count() as usize;
+        let annotation = if node_proto.get_annotation().is_empty() {
+            None
+        } else {
+            Some(node_proto.get_annotation().to_string())
+        };
         let children = if node_proto.get_is_leaf() {
             None
         } else {
@@ -406,6 +498,7 @@ impl<D: PointCloud> CoverNode<D> {
             coverage_count,
             children,
             singles_indexes,
+            annotation,
             plugins: NodePluginSet::new(),
             metic: PhantomData,
         }
@@ -429,6 +522,9 @@ impl<D: PointCloud> CoverNode<D> {
         }
 
         proto.set_radius(self.radius);
+        if let Some(annotation) = &self.annotation {
+            proto.set_annotation(annotation.clone());
+        }
         proto.set_outlier_point_indexes(self.singles_indexes.iter().map(|pi| *pi as u64).collect());
 
         match &self.children {
@@ -487,6 +583,7 @@ mod tests {
             coverage_count: 8,
             children,
             singles_indexes: smallvec![4, 5, 6],
+            annotation: None,
             plugins: NodePluginSet::new(),
             metic: PhantomData,
         }
@@ -500,6 +597,7 @@ mod tests {
             coverage_count: 8,
             children: None,
             singles_indexes: smallvec![1, 2, 3, 4, 5, 6],
+            annotation: Some("bot traffic".to_string()),
             plugins: NodePluginSet::new(),
             metic: PhantomData,
         }
@@ -723,6 +821,7 @@ mod tests {
         assert_eq!(reconstructed_node.radius, 1.0);
         assert_eq!(reconstructed_node.coverage_count, 8);
         assert_eq!(&reconstructed_node.singles_indexes[..], &[4, 5, 6]);
+        assert_eq!(reconstructed_node.annotation(), None);
 
         let reconstructed_children = reconstructed_node.children.unwrap();
         assert_eq!(reconstructed_children.nested_scale, 0);
@@ -743,6 +842,7 @@ mod tests {
         assert_eq!(reconstructed_node.radius, 1.0);
         assert_eq!(reconstructed_node.coverage_count, 8);
         assert_eq!(&reconstructed_node.singles_indexes[..], &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(reconstructed_node.annotation(), Some("bot traffic"));
         assert!(reconstructed_node.children.is_none());
     }
 }
@@ -0,0 +1,121 @@
+//! Memoized distances from a query point.
+//!
+//! A point cloud index can be compared to the query more than once in a query. When a small node is searched by brute
+//! force the centers below it were usually already compared on the way down, and queries that restart the descent
+//! revisit nodes. With the cache turned on each index is compared to the query once, and later lookups are answered
+//! from a map. That's worth it for expensive metrics, like edit distances
+//! or user metrics. For cheap dense metrics the map costs about as much as the kernel, so the cache is off by default.
+
+use crate::errors::GokoResult;
+use pointcloud::*;
+use std::collections::HashMap;
+
+/// Distances from the current query point to point cloud indexes, see the module docs.
+#[derive(Debug, Default)]
+pub struct DistanceCache {
+    enabled: bool,
+    distances: HashMap<PointIndex, f32>,
+    missing: Vec<PointIndex>,
+    hits: usize,
+    misses: usize,
+}
+
+impl DistanceCache {
+    /// A cache that remembers distances if `enabled`, otherwise it passes every request to the point cloud.
+    pub fn new(enabled: bool) -> Self {
+        DistanceCache {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// If distances are remembered.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The distances answered from the cache since the last query started.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The distances computed since the last query started.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.distances.capacity() + self.missing.capacity()
+    }
+
+    /// Forgets the distances, for a new query point.
+    pub(crate) fn clear(&mut self) {
+        self.distances.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    /// The distances from the point to the indexes, only computing the ones that aren't cached.
+    pub fn distances<D: PointCloud>(
+        &mut self,
+        point_cloud: &D,
+        point: PointRef,
+        indexes: &[PointIndex],
+    ) -> GokoResult<Vec<f32>> {
+        if !self.enabled {
+            self.misses += indexes.len();
+            return Ok(point_cloud.distances_to_point(point, indexes)?);
+        }
+        let cached = &self.distances;
+        self.missing.clear();
+        self.missing.extend(
+            indexes
+                .iter()
+                .filter(|pi| !cached.contains_key(pi))
+                .copied(),
+        );
+        self.missing.sort_unstable();
+        self.missing.dedup();
+        if !self.missing.is_empty() {
+            let computed = point_cloud.distances_to_point(point, &self.missing)?;
+            self.distances
+                .extend(self.missing.iter().copied().zip(computed));
+        }
+        self.misses += self.missing.len();
+        self.hits += indexes.len() - self.missing.len();
+        Ok(indexes.iter().map(|pi| self.distances[pi]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pointcloud::data_sources::DataRam;
+
+    #[test]
+    fn distances_are_computed_once() {
+        let point_cloud = DataRam::<L2>::new(vec![0.0, 3.0, 4.0, 5.0], 1).unwrap();
+        let point = PointRef::Dense(&[1.0]);
+        let mut cache = DistanceCache::new(true);
+        assert_eq!(
+            cache.distances(&point_cloud, point, &[1, 2]).unwrap(),
+            vec![2.0, 3.0]
+        );
+        assert_eq!(
+            cache.distances(&point_cloud, point, &[2, 0, 2]).unwrap(),
+            vec![3.0, 1.0, 3.0]
+        );
+        assert_eq!((cache.hits(), cache.misses()), (2, 3));
+        cache.clear();
+        assert_eq!(cache.hits(), 0);
+
+        let mut uncached = DistanceCache::default();
+        uncached.distances(&point_cloud, point, &[1, 1]).unwrap();
+        assert_eq!((uncached.hits(), uncached.misses()), (0, 2));
+    }
+}
@@ -50,6 +50,8 @@ pub struct KnnQueryHeap {
     dist_heap: BinaryHeap<QuerySingleton>,
     k: usize,
     scale_base: f32,
+    epsilon: f32,
+    pub(crate) trace: Option<KnnTrace>,
 }
 
 impl RoutingQueryHeap for KnnQueryHeap {
@@ -65,12 +67,19 @@ impl RoutingQueryHeap for KnnQueryHeap {
         for ((si, pi), d) in indexes.iter().zip(dists) {
             let emd = (d - self.scale_base.powi(*si)).max(0.0);
             parent_est_dist_update = emd.max(parent_est_dist_update);
-            if emd < max_dist {
+            if emd * (1.0 + self.epsilon) < max_dist {
                 self.child_heap.push(QueryAddress {
                     address: (*si, *pi),
                     dist_to_center: *d,
                     min_dist: emd,
                 });
+            } else if let Some(trace) = self.trace.as_mut() {
+                trace.pruned.push(PrunedNode {
+                    address: (*si, *pi),
+                    min_dist: emd,
+                    kth_dist: max_dist,
+                    approximate: emd < max_dist,
+                });
             }
             if !self.known_indexes.contains(pi) {
                 self.known_indexes.insert(*pi);
@@ -129,13 +138,88 @@ impl KnnQueryHeap {
             known_indexes: HashSet::new(),
             k,
             scale_base,
+            epsilon: 0.0,
+            trace: None,
+        }
+    }
+
+    /// Creates a heap for an approximate query. Nodes are skipped once `(1+epsilon)` times their minimum distance is past
+    /// the current kth distance, so each returned distance is at most `(1+epsilon)` times the true one.
+    pub fn with_epsilon(k: usize, scale_base: f32, epsilon: f32) -> KnnQueryHeap {
+        let mut heap = KnnQueryHeap::new(k, scale_base);
+        heap.epsilon = epsilon.max(0.0);
+        heap
+    }
+
+    /// Empties the heap for a new query, keeping the memory it already allocated.
+    pub fn reset(&mut self, k: usize, scale_base: f32, epsilon: f32) {
+        self.child_heap.clear();
+        self.singleton_heap.clear();
+        self.known_indexes.clear();
+        self.est_min_dist.clear();
+        self.dist_heap.clear();
+        self.k = k;
+        self.scale_base = scale_base;
+        self.epsilon = epsilon.max(0.0);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.clear();
         }
     }
 
+    /// Turns recording a `KnnTrace` of the queries run on this heap on or off. Off by default.
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.trace = if enabled {
+            Some(KnnTrace::default())
+        } else {
+            None
+        };
+    }
+
+    /// The trace of the query, if tracing is on.
+    pub fn trace(&self) -> Option<&KnnTrace> {
+        self.trace.as_ref()
+    }
+
+    /// Records a step of the query, if tracing is on.
+    pub(crate) fn trace_step(&mut self, step: TraceStep) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.steps.push(step);
+        }
+    }
+
+    /// The number of entries the heap's buffers can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.child_heap.capacity()
+            + self.singleton_heap.capacity()
+            + self.known_indexes.capacity()
+            + self.est_min_dist.capacity()
+            + self.dist_heap.capacity()
+    }
+
+    /// Approximate queries drop nodes that can't improve the result by more than the error bound. This uses the node's
+    /// own covering radius, as `min_dist` may have been raised past it by `increase_estimated_distance`.
+    fn prunable(&mut self, node: &QueryAddress) -> bool {
+        let min_dist = (node.dist_to_center - self.scale_base.powi(node.address.0)).max(0.0);
+        let kth_dist = self.max_dist();
+        let prunable = self.epsilon > 0.0 && min_dist * (1.0 + self.epsilon) >= kth_dist;
+        if let (true, Some(trace)) = (prunable, self.trace.as_mut()) {
+            trace.pruned.push(PrunedNode {
+                address: node.address,
+                min_dist,
+                kth_dist,
+                approximate: min_dist < kth_dist,
+            });
+        }
+        prunable
+    }
+
     /// Finds the closest node who could have a child node at least the current kth furthest distance away from the query point.
     /// This pops that node and pushes it onto the singleton heap.
     pub fn closest_unvisited_child_covering_address(&mut self) -> Option<(f32, NodeAddress)> {
         while let Some(mut node_to_visit) = self.child_heap.pop() {
+            if self.prunable(&node_to_visit) {
+                continue;
+            }
             if let Some(min_dist_update) = self.est_min_dist.remove(&node_to_visit.address) {
                 if min_dist_update > node_to_visit.min_dist {
                     node_to_visit.min_dist = min_dist_update;
@@ -156,6 +240,9 @@ impl KnnQueryHeap {
     /// This pops the node and sends it to oblivion.
     pub fn closest_unvisited_singleton_covering_address(&mut self) -> Option<(f32, NodeAddress)> {
         while let Some(mut node_to_visit) = self.singleton_heap.pop() {
+            if self.prunable(&node_to_visit) {
+                continue;
+            }
             if let Some(min_dist_update) = self.est_min_dist.remove(&node_to_visit.address) {
                 if min_dist_update > node_to_visit.min_dist {
                     node_to_visit.min_dist = min_dist_update;
@@ -203,6 +290,15 @@ impl KnnQueryHeap {
         result.iter().rev().cloned().collect()
     }
 
+    /// Unpacks the distance heap into `results`, closest first, leaving the heap empty and reusable.
+    pub fn unpack_into(&mut self, results: &mut Vec<(f32, PointIndex)>) {
+        results.clear();
+        while let Some(el) = self.dist_heap.pop() {
+            results.push((el.dist, el.index));
+        }
+        results.reverse();
+    }
+
     /// This allows you to update the minimum distance to the parent of a node, or it's siblings.
     /// If you are well within the radius of coverage of a node, this allows you to remove the parent or sibling from the
     ///  `closest_unvisited_child_covering_address` and `closest_unvisited_singleton_covering_address` queries.
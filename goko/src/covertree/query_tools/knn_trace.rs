@@ -0,0 +1,132 @@
+//! A record of what a knn query did.
+//!
+//! Turn it on with `QueryScratch::set_tracing`, or run `CoverTreeReader::knn_traced`. The query records each node it
+//! works on, in order, each node it prunes with the bounds that pruned it, and the number of distances it computes.
+//! Use it to see why a query is slow, or where an approximate query gave up on a branch.
+
+use crate::NodeAddress;
+
+/// Something a knn query did with a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceStep {
+    /// The distances to the node's children were computed
+    Children(NodeAddress),
+    /// The distances to every point the node covers were computed, see `CoverTreeReader::knn_brute_below`
+    BruteForce(NodeAddress),
+    /// The greedy descent reached this leaf
+    Leaf(NodeAddress),
+    /// The distances to the node's singletons were computed
+    Singletons(NodeAddress),
+}
+
+impl TraceStep {
+    /// The node the step worked on.
+    pub fn address(&self) -> NodeAddress {
+        match *self {
+            TraceStep::Children(a)
+            | TraceStep::BruteForce(a)
+            | TraceStep::Leaf(a)
+            | TraceStep::Singletons(a) => a,
+        }
+    }
+}
+
+/// A node the query didn't look into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrunedNode {
+    /// The pruned node
+    pub address: NodeAddress,
+    /// The lower bound on the distance from the query to the points the node covers
+    pub min_dist: f32,
+    /// The distance to the current kth nearest neighbor when the node was pruned
+    pub kth_dist: f32,
+    /// If the node was only pruned because the query is approximate, `min_dist` is under `kth_dist` but not by
+    /// more than the query's `epsilon`
+    pub approximate: bool,
+}
+
+/// The steps, prunes and distance count of a knn query.
+#[derive(Debug, Clone, Default)]
+pub struct KnnTrace {
+    /// The nodes the query worked on, in order
+    pub steps: Vec<TraceStep>,
+    /// The nodes the query pruned, in order
+    pub pruned: Vec<PrunedNode>,
+    /// The number of distances the query computed, not counting the ones answered by the distance cache
+    pub distance_evaluations: usize,
+}
+
+impl KnnTrace {
+    /// The nodes the query worked on, in order, without repeats.
+    pub fn visited(&self) -> Vec<NodeAddress> {
+        let mut seen = std::collections::HashSet::new();
+        self.steps
+            .iter()
+            .map(|s| s.address())
+            .filter(|a| seen.insert(*a))
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.steps.clear();
+        self.pruned.clear();
+        self.distance_evaluations = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_tools::QueryScratch;
+    use crate::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn traces_match_the_query() {
+        let (data, _) = pointcloud::synthetic::Uniform {
+            count: 2000,
+            dim: 3,
+            ..Default::default()
+        }
+        .data();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        let builder = CoverTreeBuilder {
+            leaf_cutoff: 5,
+            ..CoverTreeBuilder::new()
+        };
+        let tree = builder.build_deterministic(point_cloud, 0).unwrap();
+        let reader = tree.reader();
+        let query = [0.3f32, 0.6, 0.2];
+
+        let (knn, trace) = reader.knn_traced(&query[..], 5, 0.0).unwrap();
+        assert_eq!(knn, reader.knn(&query[..], 5).unwrap());
+        assert_eq!(trace.steps[0], TraceStep::Children(reader.root_address()));
+        assert!(trace
+            .steps
+            .iter()
+            .any(|s| matches!(s, TraceStep::Singletons(_))));
+        assert!(trace.distance_evaluations >= trace.visited().len());
+        assert!(trace.distance_evaluations < 2000);
+        assert!(trace.pruned.iter().all(|p| !p.approximate));
+        assert!(trace.pruned.iter().all(|p| p.min_dist >= p.kth_dist));
+
+        let (_, approx) = reader.knn_traced(&query[..], 5, 1.0).unwrap();
+        assert!(approx.distance_evaluations <= trace.distance_evaluations);
+        assert!(approx
+            .pruned
+            .iter()
+            .all(|p| p.min_dist * 2.0 >= p.kth_dist && p.approximate == (p.min_dist < p.kth_dist)));
+
+        // Tracing is off by default, and doesn't leak into the thread's scratch
+        let mut scratch = QueryScratch::default();
+        reader
+            .knn_with_scratch(&query[..], 5, &mut scratch)
+            .unwrap();
+        assert!(scratch.trace().is_none());
+        scratch.set_tracing(true);
+        reader
+            .knn_with_scratch(&query[..], 5, &mut scratch)
+            .unwrap();
+        assert_eq!(scratch.trace().unwrap().steps, trace.steps);
+    }
+}
@@ -28,6 +28,12 @@ pub(crate) mod knn_query_heap;
 pub use knn_query_heap::KnnQueryHeap;
 pub(crate) mod trace_query_heap;
 pub use trace_query_heap::MultiscaleQueryHeap;
+mod scratch;
+pub use scratch::QueryScratch;
+mod distance_cache;
+pub use distance_cache::DistanceCache;
+mod knn_trace;
+pub use knn_trace::{KnnTrace, PrunedNode, TraceStep};
 
 /// If you have a algorithm that does local brute force KNN on just the children,
 /// implement this to use the node fn
@@ -0,0 +1,113 @@
+//! Reusable buffers for knn queries.
+//!
+//! A knn query fills a handful of heaps, sets and maps, and at high query rates allocating and freeing them is a good
+//! part of the cost. A `QueryScratch` keeps them between queries. `CoverTreeReader::knn` uses one per thread, and
+//! `CoverTreeReader::knn_with_scratch` takes one from the caller and also reuses the result buffer. The buffers are
+//! dropped when a query leaves them holding more than `max_retained` entries, so one huge query doesn't pin its memory.
+//! A scratch can also memoize the query's distances, see `QueryScratch::set_cache_distances`, and record what the
+//! query did, see `QueryScratch::set_tracing`.
+
+use super::{DistanceCache, KnnQueryHeap, KnnTrace};
+use crate::PointIndex;
+use std::cell::RefCell;
+
+/// The default bound on the entries a scratch keeps between queries.
+pub const DEFAULT_MAX_RETAINED: usize = 1 << 16;
+
+thread_local! {
+    static THREAD_SCRATCH: RefCell<QueryScratch> = RefCell::new(QueryScratch::default());
+}
+
+/// The heaps and result buffer of a knn query, kept between queries.
+#[derive(Debug)]
+pub struct QueryScratch {
+    pub(crate) heap: KnnQueryHeap,
+    pub(crate) results: Vec<(f32, PointIndex)>,
+    pub(crate) distances: DistanceCache,
+    max_retained: usize,
+}
+
+impl Default for QueryScratch {
+    fn default() -> Self {
+        QueryScratch::with_max_retained(DEFAULT_MAX_RETAINED)
+    }
+}
+
+impl QueryScratch {
+    /// A scratch that drops its buffers once they grow past `max_retained` entries.
+    pub fn with_max_retained(max_retained: usize) -> Self {
+        QueryScratch {
+            heap: KnnQueryHeap::new(1, 2.0),
+            results: Vec::new(),
+            distances: DistanceCache::default(),
+            max_retained,
+        }
+    }
+
+    /// The results of the last query, closest first.
+    pub fn results(&self) -> &[(f32, PointIndex)] {
+        &self.results
+    }
+
+    /// The number of entries the buffers can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.heap.capacity() + self.results.capacity() + self.distances.capacity()
+    }
+
+    /// Turns memoizing the distances from the query point on or off for the queries run in this scratch. Off by
+    /// default, see `DistanceCache`.
+    pub fn set_cache_distances(&mut self, enabled: bool) {
+        self.distances.set_enabled(enabled);
+    }
+
+    /// The distance cache, with the hit and miss counts of the last query.
+    pub fn distance_cache(&self) -> &DistanceCache {
+        &self.distances
+    }
+
+    /// Turns recording a `KnnTrace` of the queries run in this scratch on or off. Off by default.
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.heap.set_tracing(enabled);
+    }
+
+    /// The trace of the last query, if tracing is on.
+    pub fn trace(&self) -> Option<&KnnTrace> {
+        self.heap.trace()
+    }
+
+    /// Readies the buffers for a new query.
+    pub(crate) fn reset(&mut self, k: usize, scale_base: f32, epsilon: f32) {
+        if self.results.capacity() > self.max_retained {
+            self.results = Vec::new();
+        }
+        if self.distances.capacity() > self.max_retained {
+            self.distances = DistanceCache::new(self.distances.is_enabled());
+        }
+        self.distances.clear();
+        self.heap.reset(k, scale_base, epsilon);
+    }
+
+    /// Moves the heap's result into the result buffer, and frees the heap if the query grew it past the bound.
+    pub(crate) fn finish(&mut self) {
+        self.heap.unpack_into(&mut self.results);
+        if let Some(trace) = self.heap.trace.as_mut() {
+            trace.distance_evaluations = self.distances.misses();
+        }
+        if self.heap.capacity() > self.max_retained {
+            let trace = self.heap.trace.take();
+            self.heap = KnnQueryHeap::new(1, 2.0);
+            self.heap.trace = trace;
+        }
+    }
+
+    /// Runs `f` with this thread's scratch. If it's already in use further up the stack `f` gets a fresh one.
+    pub fn with_thread_local<F, T>(f: F) -> T
+    where
+        F: FnOnce(&mut QueryScratch) -> T,
+    {
+        THREAD_SCRATCH.with(|cell| match cell.try_borrow_mut() {
+            Ok(mut scratch) => f(&mut scratch),
+            Err(_) => f(&mut QueryScratch::default()),
+        })
+    }
+}
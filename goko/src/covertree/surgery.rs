@@ -0,0 +1,314 @@
+//! Manual edits of the tree's structure.
+//!
+//! The builder's choices are good on average, but a region can end up with one leaf covering far too many points, or
+//! with several small sibling leaves that really are one cluster. `CoverTreeWriter::split_node` rebuilds a node's
+//! subtree so that what it covers is partitioned at a finer scale, and `CoverTreeWriter::merge_leaves` folds sibling
+//! leaves back into their parent. Neither changes the coverage of any other node. The plugins of the nodes that
+//! change are dropped, attach the plugins again once you're done editing. Both edits are journaled before they're
+//! applied.
+
+use super::builders::rebuild_subtree;
+use super::journal::JournalEntry;
+use crate::errors::{GokoError, GokoResult};
+use crate::*;
+use std::sync::atomic;
+
+impl<D: PointCloud> CoverTreeWriter<D> {
+    /// Replaces the node's descendants with a subtree that splits everything it covers, even if the builder made it a
+    /// leaf. The node keeps its address, coverage and annotation. The split is deterministic.
+    pub fn split_node(&mut self, address: NodeAddress) -> GokoResult<()> {
+        let reader = self.reader();
+        let (parent, annotation, coverage) = reader.get_node_and(address, |n| {
+            (
+                n.parent_address(),
+                n.annotation().map(|a| a.to_string()),
+                n.coverage_count(),
+            )
+        })?;
+        if address.0 < self.parameters.min_res_index {
+            return Err(GokoError::InvalidEdit(
+                "the node is below the minimum resolution",
+            ));
+        }
+        if coverage < 2 {
+            return Err(GokoError::InvalidEdit("the node only covers its center"));
+        }
+
+        let mut descendants = Vec::new();
+        let mut covered = Vec::new();
+        let mut unvisited = vec![address];
+        while let Some(current) = unvisited.pop() {
+            reader.get_node_and(current, |n| {
+                covered.extend(n.singletons());
+                if let Some((nested_scale, children)) = n.children() {
+                    unvisited.push((nested_scale, current.1));
+                    unvisited.extend(children);
+                }
+            })?;
+            if current != address {
+                covered.push(current.1);
+                descendants.push(current);
+            }
+        }
+        covered.sort_unstable();
+        covered.dedup();
+        covered.retain(|pi| *pi != address.1);
+
+        let total_nodes = self.parameters.total_nodes.load(atomic::Ordering::SeqCst);
+        let rebuilt = match rebuild_subtree(&self.parameters, address, parent, covered) {
+            Ok(rebuilt) => rebuilt,
+            Err(e) => {
+                self.parameters
+                    .total_nodes
+                    .store(total_nodes, atomic::Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(e) = journal.append(&JournalEntry::SplitNode(address)) {
+                self.parameters
+                    .total_nodes
+                    .store(total_nodes, atomic::Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        for a in &descendants {
+            unsafe { self.layer(a.0).remove_raw(a.1) };
+        }
+        self.parameters
+            .total_nodes
+            .fetch_sub(descendants.len(), atomic::Ordering::SeqCst);
+        for mut node in rebuilt {
+            let node_address = node.address();
+            if node_address == address {
+                node.set_annotation(annotation.clone());
+            }
+            for pi in node.singletons() {
+                self.final_addresses.insert(*pi, node_address);
+            }
+            if node.is_leaf() {
+                self.final_addresses.insert(node_address.1, node_address);
+            }
+            unsafe { self.insert_raw(node_address.0, node_address.1, node) };
+        }
+        if let Some(parent) = parent {
+            unsafe { self.update_node(parent, |n| n.clear_plugins()) };
+        }
+        self.layers.iter_mut().rev().for_each(|l| l.refresh());
+        self.final_addresses.refresh();
+        self.final_addresses.refresh();
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        Ok(())
+    }
+
+    /// Merges sibling leaves into their parent, which then covers their points as singletons. Returns the parent's
+    /// address. The leaf centered on the parent's center can only be merged along with all its siblings, which makes
+    /// the parent a leaf.
+    ///
+    /// Siblings can't be merged into one of them, queries rely on each node covering its points within its scale and
+    /// sibling centers are further apart than that.
+    pub fn merge_leaves(&mut self, leaves: &[NodeAddress]) -> GokoResult<NodeAddress> {
+        let mut distinct = leaves.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() != leaves.len() || leaves.is_empty() {
+            return Err(GokoError::InvalidEdit("merging needs distinct leaves"));
+        }
+        let reader = self.reader();
+        let mut parent = None;
+        let mut merged_points = Vec::new();
+        for a in leaves {
+            let (p, is_leaf) = reader.get_node_and(*a, |n| {
+                merged_points.extend(n.singletons());
+                (n.parent_address(), n.is_leaf())
+            })?;
+            if !is_leaf {
+                return Err(GokoError::InvalidEdit("only leaves can be merged"));
+            }
+            match (parent, p) {
+                (_, None) => return Err(GokoError::InvalidEdit("the root can't be merged")),
+                (None, Some(p)) => parent = Some(p),
+                (Some(q), Some(p)) if q != p => {
+                    return Err(GokoError::InvalidEdit("only siblings can be merged"))
+                }
+                _ => {}
+            }
+            if a.1 != p.unwrap().1 {
+                merged_points.push(a.1);
+            }
+        }
+        let parent = parent.unwrap();
+        let (nested_scale, children) = reader
+            .get_node_and(parent, |n| {
+                n.children()
+                    .map(|(nested_scale, children)| (nested_scale, children.to_vec()))
+            })?
+            .unwrap();
+        let nested = (nested_scale, parent.1);
+        let becomes_leaf = leaves.contains(&nested);
+        if becomes_leaf && leaves.len() != children.len() + 1 {
+            return Err(GokoError::InvalidEdit(
+                "the nested child can only be merged along with all its siblings",
+            ));
+        }
+
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(&JournalEntry::MergeLeaves(leaves.to_vec()))?;
+        }
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        let removed = leaves.to_vec();
+        let moved = merged_points.clone();
+        unsafe {
+            self.update_node(parent, move |n| {
+                if becomes_leaf {
+                    n.remove_children();
+                } else {
+                    for a in &removed {
+                        n.remove_child(*a, 0);
+                    }
+                }
+                n.insert_singletons(moved.clone());
+                n.clear_plugins();
+            })
+        };
+        for a in leaves {
+            unsafe { self.layer(a.0).remove_raw(a.1) };
+        }
+        self.parameters
+            .total_nodes
+            .fetch_sub(leaves.len(), atomic::Ordering::SeqCst);
+        for pi in merged_points {
+            self.final_addresses.insert(pi, parent);
+        }
+        if becomes_leaf {
+            self.final_addresses.insert(parent.1, parent);
+        }
+        self.layers.iter_mut().rev().for_each(|l| l.refresh());
+        self.final_addresses.refresh();
+        self.final_addresses.refresh();
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        Ok(parent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pointcloud::data_sources::DataRam;
+    use std::sync::Arc;
+
+    fn build_coarse_tree() -> CoverTreeWriter<DataRam<L2>> {
+        let data: Vec<f32> = (0..300).map(|i| (i as f32 * 0.618_034).fract()).collect();
+        let builder = CoverTreeBuilder {
+            scale_base: 2.0,
+            leaf_cutoff: 60,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        builder
+            .build(Arc::new(DataRam::<L2>::new(data, 1).unwrap()))
+            .unwrap()
+    }
+
+    fn check_tree(
+        reader: &CoverTreeReader<DataRam<L2>>,
+        queries: &[(f32, Vec<(f32, PointIndex)>)],
+    ) {
+        assert!(reader.no_dangling_refs());
+        let point_count = reader.point_cloud().len();
+        let root_coverage = reader
+            .get_node_and(reader.root_address(), |n| n.coverage_count())
+            .unwrap();
+        assert_eq!(root_coverage, point_count);
+        for pi in 0..point_count {
+            assert!(reader.known_path(pi).is_ok());
+        }
+        for (x, expected) in queries {
+            assert_eq!(&reader.knn(&[*x], 5).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn split_then_merge_keeps_queries() {
+        let mut tree = build_coarse_tree();
+        let reader = tree.reader();
+        let queries: Vec<(f32, Vec<(f32, PointIndex)>)> = (0..40)
+            .map(|i| {
+                let x = i as f32 * 0.027 - 0.05;
+                (x, reader.knn(&[x], 5).unwrap())
+            })
+            .collect();
+        let node_count = reader.node_count();
+
+        let (_, leaf) = reader
+            .layers()
+            .flat_map(|(_, l)| {
+                l.map_nodes::<_, _, Vec<_>>(|_, n| (n.is_leaf(), n.singletons_len(), n.address()))
+            })
+            .filter(|(is_leaf, _, _)| *is_leaf)
+            .map(|(_, len, address)| (len, address))
+            .max()
+            .unwrap();
+        tree.split_node(leaf).unwrap();
+        let reader = tree.reader();
+        assert!(!reader.get_node_and(leaf, |n| n.is_leaf()).unwrap());
+        let split_count = reader.node_count();
+        assert!(split_count > node_count);
+        check_tree(&reader, &queries);
+
+        let (nested_scale, children) = reader
+            .get_node_and(leaf, |n| {
+                let (nested_scale, children) = n.children().unwrap();
+                (nested_scale, children.to_vec())
+            })
+            .unwrap();
+        let nested = (nested_scale, leaf.1);
+        assert!(tree.merge_leaves(&[leaf]).is_err());
+        assert!(tree.merge_leaves(&[nested, nested]).is_err());
+        if !children.is_empty() {
+            assert!(tree.merge_leaves(&[nested]).is_err());
+        }
+
+        // Fold the whole split back up, finest scale first
+        let mut unmerged = vec![leaf];
+        let mut parents = Vec::new();
+        while let Some(a) = unmerged.pop() {
+            if let Some((nested_scale, children)) = reader
+                .get_node_and(a, |n| n.children().map(|(s, c)| (s, c.to_vec())))
+                .unwrap()
+            {
+                parents.push(a);
+                unmerged.push((nested_scale, a.1));
+                unmerged.extend(children);
+            }
+        }
+        parents.sort();
+        for parent in parents {
+            let (nested_scale, mut children) = tree
+                .reader()
+                .get_node_and(parent, |n| {
+                    let (nested_scale, children) = n.children().unwrap();
+                    (nested_scale, children.to_vec())
+                })
+                .unwrap();
+            children.push((nested_scale, parent.1));
+            assert_eq!(tree.merge_leaves(&children).unwrap(), parent);
+            let reader = tree.reader();
+            assert!(reader.get_node_and(parent, |n| n.is_leaf()).unwrap());
+            check_tree(&reader, &queries);
+        }
+        assert_eq!(tree.reader().node_count(), node_count);
+    }
+}
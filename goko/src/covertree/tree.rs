@@ -33,25 +33,41 @@
 //!
 //! The hashmap pair idea is in `layer` and originally comes from Jon Gjengset.
 
+use super::journal::{JournalEntry, TreeJournal};
 use super::layer::*;
 use super::node::*;
 use crate::*;
 //use pointcloud::*;
 
 use crate::monomap::{MonoReadHandle, MonoWriteHandle};
+use crate::query_interface::BulkInterface;
 use crate::tree_file_format::*;
 use std::sync::{atomic, Arc, RwLock};
 
-use super::query_tools::{KnnQueryHeap, MultiscaleQueryHeap, RoutingQueryHeap};
-use crate::plugins::{GokoPlugin, TreePluginSet};
-use errors::{GokoError, GokoResult};
+use super::query_tools::{
+    DistanceCache, KnnQueryHeap, KnnTrace, MultiscaleQueryHeap, QueryScratch, RoutingQueryHeap,
+    SingletonQueryHeap, TraceStep,
+};
+use crate::plugins::{GokoPlugin, InstalledPlugins, TreePluginSet};
+use errors::{ErrorContextExt, GokoError, GokoResult, ParsingError};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
 use std::iter::Iterator;
 use std::iter::Rev;
 use std::ops::Range;
 use std::slice::Iter;
+use std::time::{Duration, Instant};
 
 use plugins::labels::*;
+use plugins::utils::CoverageIndexes;
+use pointcloud::data_sources::{is_unit_norm, l2_normalize, TieredCloud};
+use pointcloud::glued_data_cloud::{HashGluedCloud, RemovedSegment, SegmentRemoval};
+use pointcloud::summaries::{taxonomy_contains, CategorySummary, TaxonomySummary};
+
+/// The most writes `CoverTreeWriter::refresh_chunked` publishes to a layer in one swap.
+const REFRESH_CHUNK: usize = 1 << 10;
 
 /// When 2 spheres overlap under a node, and there is a point in the overlap we have to decide
 /// to which sphere it belongs. As we create the nodes in a particular sequence, we can assign them
@@ -64,6 +80,23 @@ pub enum PartitionType {
     First,
 }
 
+/// A query point, owned if it had to be normalized.
+enum QueryPoint<'a> {
+    Borrowed(PointRef<'a>),
+    Dense(Vec<f32>),
+    Sparse(Vec<f32>, &'a [u32]),
+}
+
+impl<'a> QueryPoint<'a> {
+    fn point(&self) -> PointRef<'_> {
+        match self {
+            QueryPoint::Borrowed(point) => *point,
+            QueryPoint::Dense(vals) => PointRef::Dense(vals),
+            QueryPoint::Sparse(vals, inds) => PointRef::Sparse(vals, inds),
+        }
+    }
+}
+
 /// Container for the parameters governing the construction of the covertree
 #[derive(Debug)]
 pub struct CoverTreeParameters<D: PointCloud> {
@@ -78,6 +111,10 @@ pub struct CoverTreeParameters<D: PointCloud> {
     pub min_res_index: i32,
     /// If you don't want singletons messing with your tree and want everything to be a node or a element of leaf node, make this true.
     pub use_singletons: bool,
+    /// While building, a potential child that covers at most this many points is stored as singletons of its parent
+    /// instead. This is 1 if `use_singletons` and 0 otherwise, unless it was set with `CoverTreeParams`. It is saved
+    /// with the tree.
+    pub singleton_threshold: usize,
     /// The partition type of the tree
     pub partition_type: PartitionType,
     /// The point cloud this tree references
@@ -86,6 +123,11 @@ pub struct CoverTreeParameters<D: PointCloud> {
     pub verbosity: u32,
     /// This is where the base plugins are are stored.
     pub plugins: RwLock<TreePluginSet>,
+    /// Incremented by the writer before and after each refresh, so it is odd while a refresh is underway.
+    pub generation: atomic::AtomicUsize,
+    /// If the tree was built on L2 normalized points, see `CoverTreeBuilder::build_normalized`. Queries are
+    /// normalized before they're run.
+    pub normalized: bool,
 }
 
 impl<D: PointCloud> CoverTreeParameters<D> {
@@ -95,11 +137,24 @@ impl<D: PointCloud> CoverTreeParameters<D> {
         if scale_index < self.min_res_index {
             0
         } else {
-            (scale_index - self.min_res_index + 1) as usize
+            scale_index.wrapping_sub(self.min_res_index).wrapping_add(1) as usize
         }
     }
 }
 
+/// The result of a `knn_path_summaries` query. Everything in here was read from the same generation of the tree.
+#[derive(Debug)]
+pub struct KnnPathSummaries<S: Summary> {
+    /// The generation of the tree that the query ran against.
+    pub generation: usize,
+    /// The k nearest neighbors, as returned by `knn`.
+    pub knn: Vec<(f32, PointIndex)>,
+    /// The path the query point would take, as returned by `path`.
+    pub path: Vec<(f32, NodeAddress)>,
+    /// The label summaries of each node in the path, `None` if summaries have not been generated.
+    pub summaries: Vec<Option<Arc<SummaryCounter<S>>>>,
+}
+
 /// Helper struct for iterating thru the reader's of the the layers.
 pub type LayerIter<'a, D> = Rev<std::iter::Zip<Range<i32>, Iter<'a, CoverLayerReader<D>>>>;
 
@@ -136,10 +191,167 @@ impl<D: PointCloud + LabeledCloud> CoverTreeReader<D> {
     pub fn get_node_label_summary(
         &self,
         node_address: (i32, PointIndex),
-    ) -> Option<Arc<SummaryCounter<D::LabelSummary>>> {
-        self.layers[self.parameters.internal_index(node_address.0)]
-            .get_node_and(node_address.1, |n| n.label_summary())
-            .flatten()
+    ) -> GokoResult<Option<Arc<SummaryCounter<D::LabelSummary>>>> {
+        self.get_node_and(node_address, |n| n.label_summary())
+    }
+
+    /// Routes the point down the tree with `path` and returns the label summary of the deepest node on it that has
+    /// labeled points, falling back to the root. The summary is the label distribution of the region the point landed
+    /// in, see `CategorySummary::posterior`. Needs the `LabelSummaryPlugin`, added by `generate_summaries`.
+    pub fn classify<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+    ) -> GokoResult<(NodeAddress, Arc<SummaryCounter<D::LabelSummary>>)> {
+        let path = self.path(point)?;
+        let mut summaries = Vec::with_capacity(path.len());
+        for (_, address) in &path {
+            match self.get_node_label_summary(*address)? {
+                Some(summary) => summaries.push((*address, summary)),
+                None => return Err(GokoError::PluginNotInstalled("LabelSummaryPlugin")),
+            }
+        }
+        let root = summaries.first().cloned();
+        summaries
+            .into_iter()
+            .rev()
+            .find(|(_, summary)| summary.summary.count() > 0)
+            .or(root)
+            .ok_or(GokoError::PluginNotInstalled("LabelSummaryPlugin"))
+    }
+
+    /// Performs a `knn`, a `path` and grabs the label summaries along the path against the same generation of the tree.
+    /// If the writer refreshes while we are reading, the query is retried so that the parts don't mix generations. A
+    /// transient error, see `GokoError::is_transient`, hit during a refresh is retried as well.
+    pub fn knn_path_summaries<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+    ) -> GokoResult<KnnPathSummaries<D::LabelSummary>> {
+        let point: PointRef<'a> = point.into();
+        loop {
+            let generation = self.generation();
+            if generation % 2 == 1 {
+                std::thread::yield_now();
+                continue;
+            }
+            let parts = (|| -> GokoResult<_> {
+                let knn = self.knn(point, k)?;
+                let path = self.path(point)?;
+                let summaries = path
+                    .iter()
+                    .map(|(_, address)| self.get_node_label_summary(*address))
+                    .collect::<GokoResult<Vec<_>>>()?;
+                Ok((knn, path, summaries))
+            })();
+            let refreshed = generation != self.generation();
+            match parts {
+                Ok((knn, path, summaries)) if !refreshed => {
+                    return Ok(KnnPathSummaries {
+                        generation,
+                        knn,
+                        path,
+                        summaries,
+                    })
+                }
+                Err(e) if !(refreshed && e.is_transient()) => return Err(e),
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl<D: PointCloud + LabeledCloud<Label = i64, LabelSummary = CategorySummary>> CoverTreeReader<D> {
+    /// A `classify` that gives the proportion of each label at the node with a confidence interval, so that a node with
+    /// a handful of points isn't trusted like one with thousands. See `class_proportions`.
+    pub fn classify_with_intervals<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        method: IntervalMethod,
+        confidence: f64,
+    ) -> GokoResult<(NodeAddress, Vec<ClassProportion>)> {
+        let (address, summary) = self.classify(point)?;
+        Ok((address, class_proportions(&summary, method, confidence)))
+    }
+}
+
+impl<D: PointCloud + LabeledCloud<Label = str, LabelSummary = TaxonomySummary>> CoverTreeReader<D> {
+    /// The `k` nearest neighbors among the points labeled with the taxonomy node `path` or anything under it. Nodes
+    /// whose label summary has nothing under `path` are skipped, so add the `LabelSummaryPlugin` first to make this
+    /// fast. Without it every node is searched.
+    pub fn knn_under<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        path: &str,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let query = self.query_point(point.into())?;
+        let point = query.point();
+        let point_cloud = &self.parameters.point_cloud;
+        let under = |pi: &PointIndex| -> bool {
+            match point_cloud.label(*pi) {
+                Ok(Some(label)) => taxonomy_contains(path, label),
+                _ => false,
+            }
+        };
+        let mut knn: Vec<(f32, PointIndex)> = Vec::with_capacity(k + 1);
+        let push = |knn: &mut Vec<(f32, PointIndex)>, dist: f32, pi: PointIndex| {
+            let i = knn.iter().position(|(d, _)| dist < *d).unwrap_or(knn.len());
+            if i < k {
+                knn.insert(i, (dist, pi));
+                knn.truncate(k);
+            }
+        };
+        let root_center = point_cloud.point(self.root_address.1)?;
+        let mut to_visit = vec![(D::Metric::dist(root_center, point)?, self.root_address)];
+        while let Some((dist, address)) = to_visit.pop() {
+            self.get_node_and(address, |n| -> GokoResult<()> {
+                let kth_dist = if knn.len() < k {
+                    f32::MAX
+                } else {
+                    knn[k - 1].0
+                };
+                if dist - n.radius().max(0.0) > kth_dist {
+                    return Ok(());
+                }
+                if let Some(summary) = n.label_summary() {
+                    if summary.summary.count_under(path) == 0 {
+                        return Ok(());
+                    }
+                }
+                let singletons: Vec<PointIndex> = n
+                    .singletons()
+                    .iter()
+                    .filter(|pi| under(pi))
+                    .cloned()
+                    .collect();
+                let singleton_dists = point_cloud.distances_to_point(point, &singletons)?;
+                for (d, pi) in singleton_dists.into_iter().zip(singletons) {
+                    push(&mut knn, d, pi);
+                }
+                match n.children() {
+                    Some((nested_scale, children)) => {
+                        let child_indexes: Vec<PointIndex> =
+                            children.iter().map(|(_, pi)| *pi).collect();
+                        let child_dists = point_cloud.distances_to_point(point, &child_indexes)?;
+                        let mut next: Vec<(f32, NodeAddress)> = child_dists
+                            .into_iter()
+                            .zip(children.iter().cloned())
+                            .collect();
+                        next.push((dist, (nested_scale, address.1)));
+                        // Nearest last, so it's visited first
+                        next.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+                        to_visit.extend(next);
+                    }
+                    None => {
+                        if under(&address.1) {
+                            push(&mut knn, dist, address.1);
+                        }
+                    }
+                }
+                Ok(())
+            })??;
+        }
+        Ok(knn)
     }
 }
 
@@ -149,10 +361,8 @@ impl<D: PointCloud + MetaCloud> CoverTreeReader<D> {
     pub fn get_node_metasummary(
         &self,
         node_address: (i32, PointIndex),
-    ) -> Option<Arc<SummaryCounter<D::MetaSummary>>> {
-        self.layers[self.parameters.internal_index(node_address.0)]
-            .get_node_and(node_address.1, |n| n.metasummary())
-            .flatten()
+    ) -> GokoResult<Option<Arc<SummaryCounter<D::MetaSummary>>>> {
+        self.get_node_and(node_address, |n| n.metasummary())
     }
 }
 
@@ -173,23 +383,84 @@ impl<D: PointCloud> CoverTreeReader<D> {
         self.parameters.scale_base.powi(scale_index)
     }
 
-    /// Read only access to the internals of a node.
-    pub fn get_node_and<F, T>(&self, node_address: (i32, PointIndex), f: F) -> Option<T>
+    /// The current generation of the tree. This is even when the tree is stable and odd while the writer is refreshing.
+    /// It's also even between calls of `CoverTreeWriter::refresh_chunked`, while the tree is only partly refreshed.
+    pub fn generation(&self) -> usize {
+        self.parameters.generation.load(atomic::Ordering::Acquire)
+    }
+
+    /// Read only access to the internals of a node, `None` if there's no node at the address.
+    pub(crate) fn node_and<F, T>(&self, node_address: NodeAddress, f: F) -> Option<T>
     where
         F: FnOnce(&CoverNode<D>) -> T,
     {
-        self.layers[self.parameters.internal_index(node_address.0)]
+        self.layers
+            .get(self.parameters.internal_index(node_address.0))?
             .get_node_and(node_address.1, |n| f(n))
     }
 
+    /// The error for a missing node, with the nearest existing ancestor filled in.
+    pub(crate) fn node_not_found(&self, node_address: NodeAddress) -> GokoError {
+        GokoError::NodeNotFound {
+            address: node_address,
+            nearest_ancestor: self.nearest_ancestor(node_address),
+        }
+    }
+
+    /// Walks up from the node that the address's center ended up in, and returns the deepest node with a scale index
+    /// above the address's.
+    fn nearest_ancestor(&self, node_address: NodeAddress) -> Option<NodeAddress> {
+        let mut current = self.final_addresses.get_and(&node_address.1, |a| *a)?;
+        for _ in 0..=self.layers.len() {
+            if current.0 > node_address.0 {
+                return Some(current);
+            }
+            current = self.node_and(current, |n| n.parent_address()).flatten()?;
+        }
+        None
+    }
+
+    /// Read only access to the internals of a node.
+    pub fn get_node_and<F, T>(&self, node_address: (i32, PointIndex), f: F) -> GokoResult<T>
+    where
+        F: FnOnce(&CoverNode<D>) -> T,
+    {
+        match self.node_and(node_address, f) {
+            Some(t) => Ok(t),
+            None => Err(self.node_not_found(node_address)),
+        }
+    }
+
     /// Grabs all children indexes and allows you to query against them. Usually used at the tree level so that you
-    /// can access the child nodes as they are not on this layer.
-    pub fn get_node_children_and<F, T>(&self, node_address: (i32, PointIndex), f: F) -> Option<T>
+    /// can access the child nodes as they are not on this layer. `None` if the node is a leaf.
+    pub fn get_node_children_and<F, T>(
+        &self,
+        node_address: (i32, PointIndex),
+        f: F,
+    ) -> GokoResult<Option<T>>
     where
         F: FnOnce(NodeAddress, &[NodeAddress]) -> T,
     {
-        self.layers[self.parameters.internal_index(node_address.0)]
-            .get_node_children_and(node_address.1, f)
+        self.get_node_and(node_address, |n| {
+            n.children()
+                .map(|(nested_scale, children)| f((nested_scale, node_address.1), children))
+        })
+    }
+
+    /// Checks that a node address is still valid. If it is not, because the tree was refreshed since it was read, this
+    /// returns the deepest node above it that covers its center. Errors with `NodeNotFound` if the center is no longer
+    /// in the tree.
+    pub fn resolve_address(&self, node_address: NodeAddress) -> GokoResult<NodeAddress> {
+        if self.node_and(node_address, |_| ()).is_some() {
+            return Ok(node_address);
+        }
+        match self.nearest_ancestor(node_address) {
+            Some(ancestor) => Ok(ancestor),
+            None => Err(GokoError::NodeNotFound {
+                address: node_address,
+                nearest_ancestor: None,
+            }),
+        }
     }
 
     /// The root of the tree. Pass this to `get_node_and` to get the root node's content and start a traversal of the tree.
@@ -250,13 +521,11 @@ impl<D: PointCloud> CoverTreeReader<D> {
         &self,
         node_address: (i32, PointIndex),
         transform_fn: F,
-    ) -> Option<S>
+    ) -> GokoResult<Option<S>>
     where
         F: FnOnce(&T) -> S,
     {
-        self.layers[self.parameters.internal_index(node_address.0)]
-            .get_node_and(node_address.1, |n| n.get_plugin_and(transform_fn))
-            .flatten()
+        self.get_node_and(node_address, |n| n.get_plugin_and(transform_fn))
     }
 
     /// # The KNN query.
@@ -281,23 +550,140 @@ impl<D: PointCloud> CoverTreeReader<D> {
         point: T,
         k: usize,
     ) -> GokoResult<Vec<(f32, PointIndex)>> {
-        let mut query_heap = KnnQueryHeap::new(k, self.parameters.scale_base);
-        let point: PointRef<'a> = point.into();
+        QueryScratch::with_thread_local(|scratch| {
+            self.knn_into_scratch(point, k, 0.0, 0, scratch)?;
+            Ok(scratch.results().to_vec())
+        })
+    }
 
-        let root_center = self.parameters.point_cloud.point(self.root_address.1)?;
-        let dist_to_root = D::Metric::dist(&root_center, point)?;
+    /// Same as knn, but runs in the caller's scratch buffers and returns the result in them, so repeated queries
+    /// don't allocate once the buffers have grown to fit. Distances are memoized if the scratch has the cache on, see
+    /// `QueryScratch::set_cache_distances`.
+    pub fn knn_with_scratch<'a, 's, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        scratch: &'s mut QueryScratch,
+    ) -> GokoResult<&'s [(f32, PointIndex)]> {
+        self.knn_into_scratch(point, k, 0.0, 0, scratch)?;
+        Ok(scratch.results())
+    }
+
+    /// Same as knn, but stops descending once it reaches a node that covers at most `brute_force_below` points and
+    /// checks every point that node covers instead. Below a few hundred points the bookkeeping of the descent costs
+    /// more than the distances it saves. The covered points come from the `GokoCoverageIndexes` plugin when the node
+    /// holds all of them, otherwise they're gathered from the nodes below it.
+    pub fn knn_brute_below<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        brute_force_below: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        QueryScratch::with_thread_local(|scratch| {
+            self.knn_into_scratch(point, k, 0.0, brute_force_below, scratch)?;
+            Ok(scratch.results().to_vec())
+        })
+    }
+
+    /// Runs a knn query for each of the points in parallel on rayon's global pool, and returns the results in the same
+    /// order as the points. Fails if any of the queries does. To run on a `GokoRuntime`, or to get each query's error
+    /// separately, use `BulkInterface::knn`.
+    pub fn knn_batch<'a>(
+        &self,
+        points: &[PointRef<'a>],
+        k: usize,
+    ) -> GokoResult<Vec<Vec<(f32, PointIndex)>>> {
+        BulkInterface::new(self.clone())
+            .knn(points, k)
+            .into_iter()
+            .collect()
+    }
+
+    /// Same as knn, but trades accuracy for speed. Branches are skipped once they can't get `(1+epsilon)` closer than
+    /// the current kth neighbor, so the ith returned distance is at most `(1+epsilon)` times the true ith nearest
+    /// distance. An `epsilon` of 0 is an exact query.
+    pub fn approx_knn<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        epsilon: f32,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        QueryScratch::with_thread_local(|scratch| {
+            self.knn_into_scratch(point, k, epsilon, 0, scratch)?;
+            Ok(scratch.results().to_vec())
+        })
+    }
+
+    /// Same as approx_knn, but also returns a `KnnTrace` of the nodes the query visited, the nodes it pruned and the
+    /// number of distances it computed. Use an `epsilon` of 0 to trace an exact query.
+    pub fn knn_traced<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        epsilon: f32,
+    ) -> GokoResult<(Vec<(f32, PointIndex)>, KnnTrace)> {
+        let mut scratch = QueryScratch::default();
+        scratch.set_tracing(true);
+        self.knn_into_scratch(point, k, epsilon, 0, &mut scratch)?;
+        let trace = scratch.heap.trace.take().unwrap_or_default();
+        Ok((scratch.results().to_vec(), trace))
+    }
+
+    /// The `k` nearest neighbors of a point that's already in the point cloud, not counting the point itself.
+    pub fn knn_by_index(
+        &self,
+        point_index: PointIndex,
+        k: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point = self.parameters.point_cloud.point(point_index)?;
+        let mut knn = self.knn(point, k + 1)?;
+        knn.retain(|(_, pi)| *pi != point_index);
+        knn.truncate(k);
+        Ok(knn)
+    }
+
+    fn knn_into_scratch<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        epsilon: f32,
+        brute_force_below: usize,
+        scratch: &mut QueryScratch,
+    ) -> GokoResult<()> {
+        let query = self.query_point(point.into())?;
+        let point = query.point();
+        scratch.reset(k, self.parameters.scale_base, epsilon);
+        self.knn_with_heap(
+            point,
+            &mut scratch.heap,
+            &mut scratch.distances,
+            brute_force_below,
+        )?;
+        scratch.finish();
+        Ok(())
+    }
+
+    fn knn_with_heap(
+        &self,
+        point: PointRef,
+        query_heap: &mut KnnQueryHeap,
+        cache: &mut DistanceCache,
+        brute_force_below: usize,
+    ) -> GokoResult<()> {
+        let dist_to_root =
+            cache.distances(&self.parameters.point_cloud, point, &[self.root_address.1])?[0];
         query_heap.push_nodes(&[self.root_address], &[dist_to_root], None);
-        self.greedy_knn_nodes(&point, &mut query_heap);
+        self.greedy_knn_nodes(&point, query_heap, cache, brute_force_below)?;
 
         while let Some((_dist, address)) = query_heap.closest_unvisited_singleton_covering_address()
         {
+            query_heap.trace_step(TraceStep::Singletons(address));
             self.get_node_and(address, |n| {
-                n.singleton_knn(&point, &self.parameters.point_cloud, &mut query_heap)
-            });
-            self.greedy_knn_nodes(&point, &mut query_heap);
+                n.singleton_knn_cached(&point, &self.parameters.point_cloud, query_heap, cache)
+            })??;
+            self.greedy_knn_nodes(&point, query_heap, cache, brute_force_below)?;
         }
-
-        Ok(query_heap.unpack())
+        Ok(())
     }
 
     /// Same as knn, but only deals with non-singleton points
@@ -307,14 +693,16 @@ impl<D: PointCloud> CoverTreeReader<D> {
         k: usize,
     ) -> GokoResult<Vec<(f32, PointIndex)>> {
         let mut query_heap = KnnQueryHeap::new(k, self.parameters.scale_base);
-        let point: PointRef<'a> = point.into();
+        let query = self.query_point(point.into())?;
+        let point = query.point();
 
         let root_center = self.parameters.point_cloud.point(self.root_address.1)?;
         let dist_to_root = D::Metric::dist(&root_center, point)?;
         query_heap.push_nodes(&[self.root_address], &[dist_to_root], None);
-        self.greedy_knn_nodes(&point, &mut query_heap);
+        let mut cache = DistanceCache::default();
+        self.greedy_knn_nodes(&point, &mut query_heap, &mut cache, 0)?;
 
-        while self.greedy_knn_nodes(&point, &mut query_heap) {}
+        while self.greedy_knn_nodes(&point, &mut query_heap, &mut cache, 0)? {}
         Ok(query_heap.unpack())
     }
 
@@ -322,25 +710,170 @@ impl<D: PointCloud> CoverTreeReader<D> {
         &self,
         point: T,
         query_heap: &mut KnnQueryHeap,
-    ) -> bool {
+        cache: &mut DistanceCache,
+        brute_force_below: usize,
+    ) -> GokoResult<bool> {
         let point: PointRef<'a> = point.into();
         let mut did_something = false;
         while let Some((dist, nearest_address)) =
             query_heap.closest_unvisited_child_covering_address()
         {
-            if self
-                .get_node_and(nearest_address, |n| n.is_leaf())
-                .unwrap_or(true)
-            {
+            let (is_leaf, coverage) = self
+                .node_and(nearest_address, |n| (n.is_leaf(), n.coverage_count()))
+                .unwrap_or((true, 0));
+            if is_leaf {
+                query_heap.trace_step(TraceStep::Leaf(nearest_address));
                 break;
+            } else if coverage <= brute_force_below {
+                query_heap.trace_step(TraceStep::BruteForce(nearest_address));
+                let covered = self.covered_indexes(nearest_address)?;
+                let dists = cache.distances(&self.parameters.point_cloud, point, &covered)?;
+                query_heap.push_outliers(&covered, &dists);
             } else {
+                query_heap.trace_step(TraceStep::Children(nearest_address));
                 self.get_node_and(nearest_address, |n| {
-                    n.child_knn(Some(dist), &point, &self.parameters.point_cloud, query_heap)
-                });
+                    n.child_knn_cached(
+                        Some(dist),
+                        &point,
+                        &self.parameters.point_cloud,
+                        query_heap,
+                        cache,
+                    )
+                })??;
             }
             did_something = true;
         }
-        did_something
+        Ok(did_something)
+    }
+
+    /// Every point the node covers. Uses the coverage plugin if the node has it and it isn't a sample, otherwise walks
+    /// the subtree.
+    pub(crate) fn covered_indexes(&self, node_address: NodeAddress) -> GokoResult<Vec<PointIndex>> {
+        let from_plugin = self.get_node_plugin_and::<CoverageIndexes, _, _>(node_address, |p| {
+            if p.is_sample() {
+                None
+            } else {
+                Some(p.point_indexes().to_vec())
+            }
+        })?;
+        if let Some(Some(covered)) = from_plugin {
+            return Ok(covered);
+        }
+        let mut covered = Vec::new();
+        let mut to_visit = vec![node_address];
+        while let Some(address) = to_visit.pop() {
+            self.get_node_and(address, |n| {
+                covered.extend_from_slice(n.singletons());
+                match n.children() {
+                    Some((nested_scale, children)) => {
+                        to_visit.push((nested_scale, address.1));
+                        to_visit.extend_from_slice(children);
+                    }
+                    None => covered.push(address.1),
+                }
+            })?;
+        }
+        Ok(covered)
+    }
+
+    /// Checks the query point, and normalizes it if the tree was built on normalized points.
+    fn query_point<'a>(&self, point: PointRef<'a>) -> GokoResult<QueryPoint<'a>> {
+        self.check_query_point(point)?;
+        if !self.parameters.normalized {
+            return Ok(QueryPoint::Borrowed(point));
+        }
+        Ok(match point {
+            PointRef::Dense(vals) => {
+                let mut vals = vals.to_vec();
+                l2_normalize(&mut vals);
+                QueryPoint::Dense(vals)
+            }
+            PointRef::Sparse(vals, inds) => {
+                let mut vals = vals.to_vec();
+                l2_normalize(&mut vals);
+                QueryPoint::Sparse(vals, inds)
+            }
+            PointRef::Binary(_) | PointRef::Text(_) => return Err(GokoError::InvalidQueryPoint),
+        })
+    }
+
+    /// If the tree was built on normalized points, see `CoverTreeBuilder::build_normalized`.
+    pub fn is_normalized(&self) -> bool {
+        self.parameters.normalized
+    }
+
+    /// Checks that a query point can be compared against this tree's point cloud. Dense points need the
+    /// right dimension, sparse points need matching, sorted, in-bounds indexes, and all values need to be finite. Bit
+    /// packed points need the right number of words, with no bits set past the dimension. Strings can have any length.
+    fn check_query_point(&self, point: PointRef) -> GokoResult<()> {
+        let dim = self.parameters.point_cloud.dim();
+        let valid = match point {
+            PointRef::Dense(vals) => vals.len() == dim && vals.iter().all(|x| x.is_finite()),
+            PointRef::Sparse(vals, inds) => {
+                vals.len() == inds.len()
+                    && vals.iter().all(|x| x.is_finite())
+                    && inds.windows(2).all(|w| w[0] < w[1])
+                    && inds.last().map(|i| (*i as usize) < dim).unwrap_or(true)
+            }
+            PointRef::Binary(words) => {
+                words.len() == (dim + 63) / 64
+                    && (dim % 64 == 0 || words[words.len() - 1] >> (dim % 64) == 0)
+            }
+            PointRef::Text(_) => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(GokoError::InvalidQueryPoint)
+        }
+    }
+
+    /// # Multi-probe KNN
+    /// An approximate KNN that descends the tree one step at a time, keeping only the `beam_width` closest nodes
+    /// at each step instead of backtracking. Every node touched contributes its center and singletons to the result.
+    ///
+    /// The cost is bounded by `beam_width` times the depth of the tree, so this is a predictable recall knob. A
+    /// `beam_width` of 1 is the greedy path, and a large enough beam visits every node.
+    pub fn beam_knn<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        beam_width: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let mut query_heap = KnnQueryHeap::new(k, self.parameters.scale_base);
+        let query = self.query_point(point.into())?;
+        let point = query.point();
+
+        let root_center = self.parameters.point_cloud.point(self.root_address.1)?;
+        let dist_to_root = D::Metric::dist(&root_center, point)?;
+        query_heap.push_outliers(&[self.root_address.1], &[dist_to_root]);
+
+        let mut beam = vec![(dist_to_root, self.root_address)];
+        while !beam.is_empty() {
+            let mut candidates: Vec<(f32, NodeAddress)> = Vec::new();
+            for (dist, address) in beam.drain(..) {
+                self.get_node_and(address, |n| -> GokoResult<()> {
+                    n.singleton_knn(point, &self.parameters.point_cloud, &mut query_heap)?;
+                    if let Some((nested_scale, children)) = n.children() {
+                        candidates.push((dist, (nested_scale, address.1)));
+                        let children_indexes: Vec<PointIndex> =
+                            children.iter().map(|(_si, pi)| *pi).collect();
+                        let distances = self
+                            .parameters
+                            .point_cloud
+                            .distances_to_point(point, &children_indexes[..])?;
+                        query_heap.push_outliers(&children_indexes[..], &distances[..]);
+                        candidates.extend(distances.iter().cloned().zip(children.iter().cloned()));
+                    }
+                    Ok(())
+                })??;
+            }
+            candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            candidates.truncate(beam_width.max(1));
+            beam = candidates;
+        }
+
+        Ok(query_heap.unpack())
     }
 
     /// # Multiscale KNN
@@ -355,7 +888,8 @@ impl<D: PointCloud> CoverTreeReader<D> {
         k: usize,
     ) -> GokoResult<HashMap<i32, Vec<(f32, NodeAddress)>>> {
         let mut query_heap = MultiscaleQueryHeap::new(k, self.parameters.scale_base);
-        let point: PointRef<'a> = point.into();
+        let query = self.query_point(point.into())?;
+        let point = query.point();
         let root_center = self.parameters.point_cloud.point(self.root_address.1)?;
         let dist_to_root = D::Metric::dist(&root_center, point)?;
         query_heap.push_nodes(&[self.root_address], &[dist_to_root], None);
@@ -375,7 +909,7 @@ impl<D: PointCloud> CoverTreeReader<D> {
                                     &self.parameters.point_cloud,
                                     &mut query_heap,
                                 )
-                            });
+                            })??;
                         } else {
                             break;
                         }
@@ -389,15 +923,82 @@ impl<D: PointCloud> CoverTreeReader<D> {
         Ok(query_heap.unpack())
     }
 
+    /// All points within `radius` of the query point, nearest first. Nodes are pruned with the triangle inequality
+    /// against their radius, so only the parts of the tree that could hold a point in the ball are visited.
+    pub fn range_query<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        radius: f32,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let query = self.query_point(point.into())?;
+        let point = query.point();
+        let point_cloud = &self.parameters.point_cloud;
+        let root_center = point_cloud.point(self.root_address.1)?;
+        let mut to_visit = vec![(D::Metric::dist(&root_center, point)?, self.root_address)];
+        let mut results = Vec::new();
+        while let Some((dist, address)) = to_visit.pop() {
+            self.get_node_and(address, |n| -> GokoResult<()> {
+                // Leaves that only hold their center have a radius of -inf
+                if dist - n.radius().max(0.0) > radius {
+                    return Ok(());
+                }
+                let singleton_dists = point_cloud.distances_to_point(point, n.singletons())?;
+                results.extend(
+                    singleton_dists
+                        .iter()
+                        .zip(n.singletons())
+                        .filter(|(d, _)| **d <= radius)
+                        .map(|(d, pi)| (*d, *pi)),
+                );
+                match n.children() {
+                    Some((nested_scale, children)) => {
+                        to_visit.push((dist, (nested_scale, address.1)));
+                        let child_indexes: Vec<PointIndex> =
+                            children.iter().map(|(_, pi)| *pi).collect();
+                        let child_dists = point_cloud.distances_to_point(point, &child_indexes)?;
+                        to_visit.extend(child_dists.into_iter().zip(children.iter().cloned()));
+                    }
+                    None => {
+                        if dist <= radius {
+                            results.push((dist, address.1));
+                        }
+                    }
+                }
+                Ok(())
+            })??;
+        }
+        results.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        Ok(results)
+    }
+
+    /// All points within `radius` of a point that's already in the point cloud, not counting the point itself.
+    pub fn range_by_index(
+        &self,
+        point_index: PointIndex,
+        radius: f32,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point = self.parameters.point_cloud.point(point_index)?;
+        let mut results = self.range_query(point, radius)?;
+        results.retain(|(_, pi)| *pi != point_index);
+        Ok(results)
+    }
+
     /// # Dry Insert Query
+    ///
+    /// The path the point would take down the tree if it were inserted now, from the root to the lowest node that
+    /// covers it. Each entry is the distance from the point to the node's center and the node's address. This is part
+    /// of the stable API: the first entry is always the root, each following node is a child of the one before it at
+    /// a strictly lower scale index, and the descent picks children by the tree's `PartitionType`. For a point that's
+    /// already in the tree use `known_path`, which returns the path the point was actually inserted along.
     pub fn path<'a, T: Into<PointRef<'a>>>(&self, point: T) -> GokoResult<Vec<(f32, NodeAddress)>> {
-        let point: PointRef<'a> = point.into();
+        let query = self.query_point(point.into())?;
+        let point = query.point();
         let root_center = self.parameters.point_cloud.point(self.root_address.1)?;
         let mut current_distance = D::Metric::dist(&root_center, point)?;
         let mut current_address = self.root_address;
         let mut trace = vec![(current_distance, current_address)];
         while let Some(nearest) =
-            self.get_node_and(current_address, |n| match self.parameters.partition_type {
+            self.node_and(current_address, |n| match self.parameters.partition_type {
                 PartitionType::Nearest => n.nearest_covering_child(
                     self.parameters.scale_base,
                     current_distance,
@@ -423,67 +1024,76 @@ impl<D: PointCloud> CoverTreeReader<D> {
         Ok(trace)
     }
 
-    ///
+    /// The dry insert path of a point that's already in the point cloud. Unlike `known_path` this works for points
+    /// that aren't in the tree, and follows the partition the point would get if it were inserted now.
+    pub fn path_by_index(&self, point_index: PointIndex) -> GokoResult<Vec<(f32, NodeAddress)>> {
+        let point = self.parameters.point_cloud.point(point_index)?;
+        self.path(point)
+    }
+
+    /// The path a point in the tree was inserted along, from the root to the node that holds it, with the distance
+    /// from the point to each node's center. Same layout and guarantees as `path`. Errors with `IndexNotInTree` if
+    /// the point isn't in the tree.
     pub fn known_path(&self, point_index: PointIndex) -> GokoResult<Vec<(f32, NodeAddress)>> {
         self.final_addresses
-            .get_and(&point_index, |addr| {
-                let mut path = Vec::with_capacity((self.root_address().0 - addr.0) as usize);
-                let mut parent = Some(*addr);
-                while let Some(addr) = parent {
-                    path.push(addr);
-                    parent = self.get_node_and(addr, |n| n.parent_address()).flatten();
-                }
-                (&mut path[..]).reverse();
-                let point_indexes: Vec<PointIndex> = path.iter().map(|na| na.1).collect();
-                let dists = self
-                    .parameters
-                    .point_cloud
-                    .distances_to_point_index(point_index, &point_indexes[..])
-                    .unwrap();
-                dists.iter().zip(path).map(|(d, a)| (*d, a)).collect()
-            })
-            .ok_or(GokoError::IndexNotInTree(point_index))
+            .get_and(
+                &point_index,
+                |addr| -> GokoResult<Vec<(f32, NodeAddress)>> {
+                    let mut path = Vec::with_capacity(
+                        self.root_address().0.saturating_sub(addr.0).max(0) as usize,
+                    );
+                    let mut parent = Some(*addr);
+                    while let Some(addr) = parent {
+                        path.push(addr);
+                        if path.len() > self.layers.len() {
+                            break;
+                        }
+                        parent = self.node_and(addr, |n| n.parent_address()).flatten();
+                    }
+                    (&mut path[..]).reverse();
+                    let point_indexes: Vec<PointIndex> = path.iter().map(|na| na.1).collect();
+                    let dists = self
+                        .parameters
+                        .point_cloud
+                        .distances_to_point_index(point_index, &point_indexes[..])
+                        .at_point(point_index)?;
+                    Ok(dists.iter().zip(path).map(|(d, a)| (*d, a)).collect())
+                },
+            )
+            .ok_or(GokoError::IndexNotInTree(point_index))?
     }
 
     ///Computes the fractal dimension of a node
-    pub fn node_fractal_dim(&self, node_address: NodeAddress) -> f32 {
-        let count: f32 = self
-            .get_node_and(node_address, |n| {
-                (n.singletons_len() + n.children_len()) as f32
-            })
-            .unwrap() as f32;
-        count.log(self.parameters.scale_base)
+    pub fn node_fractal_dim(&self, node_address: NodeAddress) -> GokoResult<f32> {
+        let count: f32 = self.get_node_and(node_address, |n| {
+            (n.singletons_len() + n.children_len()) as f32
+        })?;
+        Ok(count.log(self.parameters.scale_base))
     }
 
     ///Computes the weighted fractal dimension of a node
-    pub fn node_weighted_fractal_dim(&self, node_address: NodeAddress) -> f32 {
-        let weighted_count: f32 = self
-            .get_node_and(node_address, |n| {
-                let singleton_count = n.singletons().len() as f32;
-                let mut max_pop: usize = 1;
-                let mut weighted_count: f32 = 0.0;
-                if let Some((nested_scale, children)) = n.children() {
-                    let mut pops: Vec<usize> = children
-                        .iter()
-                        .map(|child_addr| {
-                            self.get_node_and(*child_addr, |child| child.coverage_count())
-                                .unwrap()
-                        })
-                        .collect();
-                    pops.push(
-                        self.get_node_and((nested_scale, node_address.1), |child| {
-                            child.coverage_count()
-                        })
-                        .unwrap(),
-                    );
-                    max_pop = *pops.iter().max().unwrap();
-                    pops.iter()
-                        .for_each(|p| weighted_count += (*p as f32) / (max_pop as f32));
-                }
-                weighted_count + singleton_count / (max_pop as f32)
-            })
-            .unwrap();
-        weighted_count.log(self.parameters.scale_base)
+    pub fn node_weighted_fractal_dim(&self, node_address: NodeAddress) -> GokoResult<f32> {
+        let weighted_count: f32 = self.get_node_and(node_address, |n| -> GokoResult<f32> {
+            let singleton_count = n.singletons().len() as f32;
+            let mut max_pop: usize = 1;
+            let mut weighted_count: f32 = 0.0;
+            if let Some((nested_scale, children)) = n.children() {
+                let mut pops: Vec<usize> = children
+                    .iter()
+                    .map(|child_addr| {
+                        self.get_node_and(*child_addr, |child| child.coverage_count())
+                    })
+                    .collect::<GokoResult<Vec<usize>>>()?;
+                pops.push(self.get_node_and((nested_scale, node_address.1), |child| {
+                    child.coverage_count()
+                })?);
+                max_pop = *pops.iter().max().unwrap();
+                pops.iter()
+                    .for_each(|p| weighted_count += (*p as f32) / (max_pop as f32));
+            }
+            Ok(weighted_count + singleton_count / (max_pop as f32))
+        })??;
+        Ok(weighted_count.log(self.parameters.scale_base))
     }
 
     ///Computes the fractal dimension of a layer
@@ -509,11 +1119,11 @@ impl<D: PointCloud> CoverTreeReader<D> {
             singletons_count += n.singletons().len() as f32;
             if let Some((nested_scale, children)) = n.children() {
                 child_coverage_counts.extend(children.iter().map(|child_addr| {
-                    self.get_node_and(*child_addr, |child| child.coverage_count())
+                    self.node_and(*child_addr, |child| child.coverage_count())
                         .unwrap()
                 }));
                 child_coverage_counts.push(
-                    self.get_node_and((nested_scale, *center_index), |child| {
+                    self.node_and((nested_scale, *center_index), |child| {
                         child.coverage_count()
                     })
                     .unwrap(),
@@ -538,20 +1148,25 @@ impl<D: PointCloud> CoverTreeReader<D> {
             - weighted_parent_sum.log(self.parameters.scale_base)
     }
 
+    /// The user's note on a node, see `CoverTreeWriter::set_node_annotation`.
+    pub fn node_annotation(&self, node_address: NodeAddress) -> GokoResult<Option<String>> {
+        self.get_node_and(node_address, |n| n.annotation().map(|a| a.to_string()))
+    }
+
     /// Checks that there are no node addresses in the child list of any node that don't reference a node in the tree.
     /// Please calmly panic if there are, the tree is very invalid.
     pub(crate) fn no_dangling_refs(&self) -> bool {
         let mut refs_to_check = vec![self.root_address];
+        let node_count = self.node_count();
+        let mut checked = 0;
         while let Some(node_addr) = refs_to_check.pop() {
-            println!("checking {:?}", node_addr);
-            println!("refs_to_check: {:?}", refs_to_check);
-            let node_exists = self.get_node_and(node_addr, |n| {
+            // A valid tree visits each node once, any more and there's a cycle.
+            checked += 1;
+            if checked > node_count {
+                return false;
+            }
+            let node_exists = self.node_and(node_addr, |n| {
                 if let Some((nested_scale, other_children)) = n.children() {
-                    println!(
-                        "Pushing: {:?}, {:?}",
-                        (nested_scale, other_children),
-                        other_children
-                    );
                     refs_to_check.push((nested_scale, node_addr.1));
                     refs_to_check.extend(&other_children[..]);
                 }
@@ -564,62 +1179,496 @@ impl<D: PointCloud> CoverTreeReader<D> {
     }
 }
 
+impl<D: MetaCloud> CoverTreeReader<D> {
+    /// A knn that returns at most one point for each id, the closest one. The id is read from the metadata of each
+    /// point with `id`, see `MetaCloud::dedup_by_metadata`. More neighbors are fetched until there are `k` distinct
+    /// ids or the tree runs out of points.
+    pub fn knn_dedup<'a, T, K, F>(
+        &self,
+        point: T,
+        k: usize,
+        id: F,
+    ) -> GokoResult<Vec<(f32, PointIndex)>>
+    where
+        T: Into<PointRef<'a>>,
+        K: Hash + Eq,
+        F: Fn(&D::Metadata) -> Option<K>,
+    {
+        let point = point.into();
+        let mut fetch = k;
+        loop {
+            let results = self.knn(point, fetch)?;
+            let mut deduped = self.point_cloud().dedup_by_metadata(&results, &id)?;
+            if deduped.len() >= k || results.len() < fetch {
+                deduped.truncate(k);
+                return Ok(deduped);
+            }
+            fetch *= 2;
+        }
+    }
+}
+
+impl<D: PointCloud> fmt::Display for CoverTreeReader<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plugins = self
+            .get_plugin_and::<InstalledPlugins, _, _>(|p| p.0.clone())
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "CoverTree over {} points of dim {}, {} metric",
+            self.parameters.point_cloud.len(),
+            self.parameters.point_cloud.dim(),
+            <D::Metric as Metric>::name()
+        )?;
+        writeln!(
+            f,
+            "    scale_base: {}, leaf_cutoff: {}, min_res_index: {}, use_singletons: {}, partition_type: {:?}",
+            self.parameters.scale_base,
+            self.parameters.leaf_cutoff,
+            self.parameters.min_res_index,
+            self.parameters.use_singletons,
+            self.parameters.partition_type
+        )?;
+        writeln!(
+            f,
+            "    {} nodes on {} layers, root {:?}, generation {}",
+            self.node_count(),
+            self.len(),
+            self.root_address,
+            self.generation()
+        )?;
+        write!(f, "    plugins: {:?}", plugins)
+    }
+}
+
 ///
 pub struct CoverTreeWriter<D: PointCloud> {
     pub(crate) parameters: Arc<CoverTreeParameters<D>>,
     pub(crate) layers: Vec<CoverLayerWriter<D>>,
     pub(crate) root_address: NodeAddress,
     pub(crate) final_addresses: MonoWriteHandle<PointIndex, NodeAddress>,
+    pub(crate) journal: Option<TreeJournal>,
+}
+
+impl<D: PointCloud> fmt::Display for CoverTreeWriter<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.reader(), f)
+    }
 }
 
 impl<D: PointCloud + LabeledCloud> CoverTreeWriter<D> {
     ///
-    pub fn generate_summaries(&mut self) {
+    pub fn generate_summaries(&mut self) -> GokoResult<()> {
         self.add_plugin::<LabelSummaryPlugin>(TreeLabelSummary::default())
     }
 }
 
 impl<D: PointCloud + MetaCloud> CoverTreeWriter<D> {
     ///
-    pub fn generate_meta_summaries(&mut self) {
+    pub fn generate_meta_summaries(&mut self) -> GokoResult<()> {
         self.add_plugin::<MetaSummaryPlugin>(TreeMetaSummary::default())
     }
 }
 
 impl<D: PointCloud> CoverTreeWriter<D> {
-    ///
+    /// Glues labels onto the tree's point cloud after the tree was built, and computes the label summaries. The tree
+    /// structure is reused, but plugins have to be added again to the new tree.
+    pub fn attach_labels<L: LabelSet>(
+        &self,
+        labels: L,
+    ) -> GokoResult<CoverTreeWriter<SimpleLabeledCloud<Arc<D>, L>>> {
+        let point_cloud = Arc::clone(&self.parameters.point_cloud).attach_labels(labels)?;
+        let mut tree = CoverTreeWriter::load(&self.save(), Arc::new(point_cloud))?;
+        tree.generate_summaries()?;
+        Ok(tree)
+    }
+
+    /// Attaches a plugin, building its node components from the bottom of the tree up. If a component can't be
+    /// built the error is returned and the plugin isn't installed, though the nodes visited before it keep theirs.
     pub fn add_plugin<P: GokoPlugin<D>>(
         &mut self,
         plug_in: <P as plugins::GokoPlugin<D>>::TreeComponent,
-    ) where
+    ) -> GokoResult<()>
+    where
         <P as plugins::GokoPlugin<D>>::TreeComponent: 'static,
         <P as plugins::GokoPlugin<D>>::NodeComponent: 'static,
     {
-        P::prepare_tree(&plug_in, self);
+        P::prepare_tree(&plug_in, self)?;
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
         let reader = self.reader();
+        let mut failed = None;
         for layer in self.layers.iter_mut() {
             layer.reader().for_each_node(|pi, n| {
-                if let Some(node_component) = P::node_component(&plug_in, n, &reader) {
-                    unsafe {
+                if failed.is_some() {
+                    return;
+                }
+                match P::node_component(&plug_in, n, &reader) {
+                    Ok(Some(node_component)) => unsafe {
                         layer.update_node(*pi, move |n| n.insert_plugin(node_component.clone()))
-                    }
+                    },
+                    Ok(None) => (),
+                    Err(e) => failed = Some(e),
                 }
             });
-            layer.refresh()
+            layer.refresh();
+            if failed.is_some() {
+                break;
+            }
+        }
+        if let Some(e) = failed {
+            self.parameters
+                .generation
+                .fetch_add(1, atomic::Ordering::AcqRel);
+            return Err(e);
+        }
+        {
+            let mut plugins = self.parameters.plugins.write().unwrap();
+            plugins.insert(plug_in);
+            match plugins.get_mut::<InstalledPlugins>() {
+                Some(installed) => installed.0.push(std::any::type_name::<P>()),
+                None => {
+                    plugins.insert(InstalledPlugins(vec![std::any::type_name::<P>()]));
+                }
+            }
         }
-        self.parameters.plugins.write().unwrap().insert(plug_in);
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        Ok(())
     }
 
-    /// Provides a reference to a `CoverLayerWriter`. Do not use, unless you're going to leave the tree in a *valid* state.
-    pub(crate) unsafe fn layer(&mut self, scale_index: i32) -> &mut CoverLayerWriter<D> {
-        &mut self.layers[self.parameters.internal_index(scale_index)]
+    /// Attaches a note, like "this cluster is bot traffic", to a node. Pass `None` to remove it. JSON strings work well.
+    /// Annotations are saved with the tree and are visible to readers once this returns.
+    pub fn set_node_annotation(
+        &mut self,
+        address: NodeAddress,
+        annotation: Option<String>,
+    ) -> GokoResult<()> {
+        self.reader().get_node_and(address, |_| ())?;
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(&JournalEntry::SetAnnotation(address, annotation.clone()))?;
+        }
+        unsafe {
+            self.update_node(address, move |n| n.set_annotation(annotation.clone()));
+            self.layer(address.0).refresh();
+        }
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        Ok(())
     }
 
-    pub(crate) unsafe fn update_node<F>(&mut self, address: NodeAddress, update_fn: F)
-    where
-        F: Fn(&mut CoverNode<D>) + 'static + Send + Sync,
-    {
-        self.layers[self.parameters.internal_index(address.0)].update_node(address.1, update_fn);
+    /// Removes a point from the tree, for aging out old points without a rebuild. If the point is the center of some
+    /// nodes, those nodes are removed and their children and singletons are handed to the removed nodes' parent. If
+    /// it was the root's center, the largest orphaned child is promoted to a new root. Nodes left covering only their
+    /// center are pruned like the builder does.
+    ///
+    /// Node plugins on the touched nodes are not recomputed, add them again once you're done removing points.
+    pub fn remove_point(&mut self, point_index: PointIndex) -> GokoResult<()> {
+        let reader = self.reader();
+        let final_address = reader
+            .final_addresses
+            .get_and(&point_index, |a| *a)
+            .ok_or(GokoError::IndexNotInTree(point_index))?;
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        let removed = if final_address.1 == point_index {
+            self.remove_center(&reader, final_address)
+        } else {
+            self.remove_singleton(&reader, final_address, point_index)
+        };
+        if removed.is_ok() {
+            self.final_addresses.remove(point_index);
+        }
+        self.layers.iter_mut().rev().for_each(|l| l.refresh());
+        self.final_addresses.refresh();
+        self.final_addresses.refresh();
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        removed
+    }
+
+    fn remove_singleton(
+        &mut self,
+        reader: &CoverTreeReader<D>,
+        address: NodeAddress,
+        point_index: PointIndex,
+    ) -> GokoResult<()> {
+        let (parent, is_empty_leaf) = reader.get_node_and(address, |n| {
+            (n.parent_address(), n.is_leaf() && n.singletons_len() == 1)
+        })?;
+        let ancestors = self.ancestors(reader, parent)?;
+        self.journal_removal(point_index)?;
+        for a in ancestors {
+            unsafe { self.update_node(a, |n| n.remove_coverage(1)) };
+        }
+        unsafe {
+            self.update_node(address, move |n| {
+                n.remove_singleton(point_index);
+            })
+        };
+        if is_empty_leaf {
+            self.prune_leaf(reader, address, parent, None)?;
+        }
+        Ok(())
+    }
+
+    fn remove_center(
+        &mut self,
+        reader: &CoverTreeReader<D>,
+        final_address: NodeAddress,
+    ) -> GokoResult<()> {
+        let point_index = final_address.1;
+        // Walk up the nodes centered on the point, collecting what they cover.
+        let mut chain = Vec::new();
+        let mut orphans: Vec<NodeAddress> = Vec::new();
+        let mut singletons: Vec<PointIndex> = Vec::new();
+        let mut current = final_address;
+        let parent = loop {
+            chain.push(current);
+            let parent = reader.get_node_and(current, |n| {
+                if let Some((_, children)) = n.children() {
+                    orphans.extend(children);
+                }
+                singletons.extend(n.singletons());
+                n.parent_address()
+            })?;
+            match parent {
+                Some(p) if p.1 == point_index => current = p,
+                p => break p,
+            }
+        };
+        let top = current;
+        let orphan_coverage = orphans
+            .iter()
+            .map(|a| reader.get_node_and(*a, |n| n.coverage_count()))
+            .collect::<GokoResult<Vec<usize>>>()?;
+
+        let new_parent = match parent {
+            Some(parent) => {
+                let top_coverage = reader.get_node_and(top, |n| n.coverage_count())?;
+                let (grandparent, parent_children, parent_singletons, nested) = reader
+                    .get_node_and(parent, |n| {
+                        let (nested_scale, children) = n.children().unwrap_or((parent.0, &[]));
+                        (
+                            n.parent_address(),
+                            children.len(),
+                            n.singletons_len(),
+                            (nested_scale, parent.1),
+                        )
+                    })?;
+                let nested_is_empty_leaf =
+                    reader.get_node_and(nested, |n| n.is_leaf() && n.singletons_len() == 0)?;
+                // Adopting the orphans can fail, so the new parent is made before anything is written
+                let mut adopted = reader.get_node_and(parent, |n| n.clone())?;
+                adopted.remove_child(top, top_coverage);
+                for (a, c) in orphans.iter().zip(&orphan_coverage) {
+                    adopted.insert_child(*a, *c)?;
+                }
+                adopted.insert_singletons(singletons.clone());
+                let ancestors = self.ancestors(reader, grandparent)?;
+                self.journal_removal(point_index)?;
+                for a in ancestors {
+                    unsafe { self.update_node(a, |n| n.remove_coverage(1)) };
+                }
+                unsafe { self.update_node(parent, move |n| *n = adopted.clone()) };
+                if nested_is_empty_leaf {
+                    let counts = (
+                        parent_children - 1 + orphans.len(),
+                        parent_singletons + singletons.len(),
+                    );
+                    self.prune_leaf(reader, nested, Some(parent), Some(counts))?;
+                }
+                parent
+            }
+            None => {
+                if orphans.is_empty() && singletons.is_empty() {
+                    return Err(GokoError::EmptyTree);
+                }
+                let remaining: Vec<PointIndex> = reader
+                    .final_addresses
+                    .map_into::<_, Vec<PointIndex>, _>(|pi, _| *pi)
+                    .into_iter()
+                    .filter(|pi| *pi != point_index)
+                    .collect();
+                let promoted = orphans
+                    .iter()
+                    .map(|a| a.0)
+                    .max()
+                    .and_then(|si| orphans.iter().position(|a| a.0 == si));
+                let center = match promoted {
+                    Some(i) => orphans[i].1,
+                    None => singletons[0],
+                };
+                let radius = self
+                    .parameters
+                    .point_cloud
+                    .distances_to_point_index(center, &remaining)?
+                    .into_iter()
+                    .fold(0.0f32, f32::max);
+                let scale_index = top
+                    .0
+                    .max(radius.log(self.parameters.scale_base).ceil() as i32);
+
+                let root_address = (scale_index, center);
+                let mut root = CoverNode::new(None, root_address);
+                root.set_radius(radius);
+                match promoted {
+                    Some(i) => {
+                        root.insert_nested_child(orphans[i].0, orphan_coverage[i])?;
+                        for (j, (a, c)) in orphans.iter().zip(&orphan_coverage).enumerate() {
+                            if i != j {
+                                root.insert_child(*a, *c)?;
+                            }
+                        }
+                        root.insert_singletons(singletons.clone());
+                    }
+                    None => {
+                        singletons.remove(0);
+                        root.insert_singletons(singletons.clone());
+                    }
+                }
+                let nested_is_empty_leaf = match promoted {
+                    Some(i) => reader
+                        .get_node_and(orphans[i], |n| n.is_leaf() && n.singletons_len() == 0)?,
+                    None => false,
+                };
+                self.journal_removal(point_index)?;
+
+                while self.parameters.min_res_index + (self.layers.len() as i32) - 2 < scale_index {
+                    let si = self.parameters.min_res_index + self.layers.len() as i32 - 1;
+                    self.layers.push(CoverLayerWriter::new(si));
+                }
+                if promoted.is_none() {
+                    self.final_addresses.insert(center, root_address);
+                }
+                unsafe { self.insert_raw(root_address.0, root_address.1, root) };
+                self.parameters
+                    .total_nodes
+                    .fetch_add(1, atomic::Ordering::SeqCst);
+                self.root_address = root_address;
+                if let Some(i) = promoted {
+                    if nested_is_empty_leaf {
+                        let counts = (orphans.len() - 1, singletons.len());
+                        self.prune_leaf(reader, orphans[i], Some(root_address), Some(counts))?;
+                    }
+                }
+                root_address
+            }
+        };
+
+        for a in &chain {
+            unsafe { self.layer(a.0).remove_raw(a.1) };
+        }
+        self.parameters
+            .total_nodes
+            .fetch_sub(chain.len(), atomic::Ordering::SeqCst);
+        for a in orphans {
+            unsafe { self.update_node(a, move |n| n.set_parent_address(Some(new_parent))) };
+        }
+        for pi in singletons {
+            self.final_addresses.insert(pi, new_parent);
+        }
+        Ok(())
+    }
+
+    /// Journals the removal of a point once it's been checked and all that's left is writing it into the tree, so a
+    /// removal that fails is never replayed. The remaining reads are of nodes the reader already holds.
+    fn journal_removal(&mut self, point_index: PointIndex) -> GokoResult<()> {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(&JournalEntry::RemovePoint(point_index))?;
+        }
+        Ok(())
+    }
+
+    /// The node and all the nodes above it.
+    fn ancestors(
+        &self,
+        reader: &CoverTreeReader<D>,
+        mut address: Option<NodeAddress>,
+    ) -> GokoResult<Vec<NodeAddress>> {
+        let mut ancestors = Vec::new();
+        while let Some(a) = address {
+            ancestors.push(a);
+            address = reader.get_node_and(a, |n| n.parent_address())?;
+        }
+        Ok(ancestors)
+    }
+
+    /// Removes a leaf that only covers its center. A nested leaf is folded into its parent, which can cascade up
+    /// the nested chain, and any other leaf becomes a singleton of its parent. Pass the parent's number of children
+    /// and singletons if they were changed since `reader` was made.
+    fn prune_leaf(
+        &mut self,
+        reader: &CoverTreeReader<D>,
+        mut leaf: NodeAddress,
+        mut parent: Option<NodeAddress>,
+        mut parent_counts: Option<(usize, usize)>,
+    ) -> GokoResult<()> {
+        while let Some(p) = parent {
+            let grandparent = reader.node_and(p, |n| n.parent_address()).flatten();
+            let (children, singletons) = match parent_counts.take() {
+                Some(counts) => counts,
+                None => reader.get_node_and(p, |n| {
+                    (
+                        n.children().map(|(_, c)| c.len()).unwrap_or(0),
+                        n.singletons_len(),
+                    )
+                })?,
+            };
+            if p.1 == leaf.1 {
+                if children > 0 {
+                    break;
+                }
+                unsafe {
+                    self.layer(leaf.0).remove_raw(leaf.1);
+                    self.update_node(p, |n| {
+                        n.remove_children();
+                    });
+                }
+                self.final_addresses.insert(leaf.1, p);
+            } else {
+                if !self.parameters.use_singletons {
+                    break;
+                }
+                unsafe {
+                    self.layer(leaf.0).remove_raw(leaf.1);
+                    self.update_node(p, move |n| {
+                        n.remove_child(leaf, 1);
+                        n.insert_singleton(leaf.1);
+                    });
+                }
+                self.final_addresses.insert(leaf.1, p);
+            }
+            self.parameters
+                .total_nodes
+                .fetch_sub(1, atomic::Ordering::SeqCst);
+            if p.1 != leaf.1 || singletons > 0 {
+                break;
+            }
+            leaf = p;
+            parent = grandparent;
+        }
+        Ok(())
+    }
+
+    /// Provides a reference to a `CoverLayerWriter`. Do not use, unless you're going to leave the tree in a *valid* state.
+    pub(crate) unsafe fn layer(&mut self, scale_index: i32) -> &mut CoverLayerWriter<D> {
+        &mut self.layers[self.parameters.internal_index(scale_index)]
+    }
+
+    pub(crate) unsafe fn update_node<F>(&mut self, address: NodeAddress, update_fn: F)
+    where
+        F: Fn(&mut CoverNode<D>) + 'static + Send + Sync,
+    {
+        self.layers[self.parameters.internal_index(address.0)].update_node(address.1, update_fn);
     }
 
     /// Creates a reader for queries.
@@ -652,6 +1701,9 @@ impl<D: PointCloud> CoverTreeWriter<D> {
         let parameters = Arc::new(CoverTreeParameters {
             total_nodes: atomic::AtomicUsize::new(0),
             use_singletons: cover_proto.use_singletons,
+            // Trees saved before the threshold was stored only have `use_singletons`
+            singleton_threshold: (cover_proto.get_singleton_threshold() as usize)
+                .max(cover_proto.use_singletons as usize),
             scale_base: cover_proto.scale_base as f32,
             leaf_cutoff: cover_proto.cutoff as usize,
             min_res_index: cover_proto.resolution as i32,
@@ -659,7 +1711,20 @@ impl<D: PointCloud> CoverTreeWriter<D> {
             verbosity: 2,
             partition_type,
             plugins: RwLock::new(TreePluginSet::new()),
+            generation: atomic::AtomicUsize::new(0),
+            normalized: cover_proto.get_normalized(),
         });
+        if parameters.normalized {
+            // Reading every point could take a while, a sample catches a cloud that was never normalized
+            let point_cloud = &parameters.point_cloud;
+            let indexes = point_cloud.reference_indexes();
+            let step = (indexes.len() / 1000).max(1);
+            for pi in indexes.iter().step_by(step) {
+                if !is_unit_norm(point_cloud.point(*pi)?) {
+                    return Err(GokoError::NotNormalized(*pi));
+                }
+            }
+        }
         let root_address = (
             cover_proto.get_root_scale(),
             cover_proto.get_root_index() as usize,
@@ -670,6 +1735,18 @@ impl<D: PointCloud> CoverTreeWriter<D> {
             .map(|l| CoverLayerWriter::load(l))
             .collect();
 
+        for (i, layer) in layers.iter().enumerate() {
+            let expected_scale_index = parameters
+                .min_res_index
+                .checked_sub(1)
+                .and_then(|si| si.checked_add(i as i32));
+            if Some(layer.scale_index()) != expected_scale_index {
+                return Err(GokoError::ParsingError(ParsingError::RegularParsingError(
+                    "the layers of the tree protobuf are out of order",
+                )));
+            }
+        }
+
         let (_final_addresses_reader, final_addresses) = monomap::new();
 
         let mut tree = CoverTreeWriter {
@@ -677,8 +1754,15 @@ impl<D: PointCloud> CoverTreeWriter<D> {
             layers,
             root_address,
             final_addresses,
+            journal: None,
         };
 
+        if !tree.reader().no_dangling_refs() {
+            return Err(GokoError::ParsingError(ParsingError::RegularParsingError(
+                "the tree protobuf references nodes that it does not contain",
+            )));
+        }
+
         tree.refresh_final_indexes();
 
         Ok(tree)
@@ -725,13 +1809,138 @@ impl<D: PointCloud> CoverTreeWriter<D> {
         cover_proto.set_root_scale(self.root_address.0);
         cover_proto.set_root_index(self.root_address.1 as u64);
         cover_proto.set_layers(self.layers.iter().map(|l| l.save()).collect());
+        cover_proto.set_normalized(self.parameters.normalized);
+        cover_proto.set_singleton_threshold(self.parameters.singleton_threshold as u64);
         cover_proto
     }
 
+    /// Moves the tree onto a `TieredCloud` that keeps the centers of the nodes in ram and reads the rest of the points
+    /// from this tree's point cloud. Routing a query only needs the centers, so the slow tier is only read for the
+    /// exact distances to singletons and leaf points. Plugins are not carried over, add them again to the new tree.
+    pub fn tiered(&self) -> GokoResult<CoverTreeWriter<TieredCloud<D>>> {
+        let mut centers: Vec<PointIndex> = self
+            .layers
+            .iter()
+            .flat_map(|l| l.reader().node_center_indexes())
+            .collect();
+        centers.sort_unstable();
+        centers.dedup();
+        let point_cloud = TieredCloud::new(Arc::clone(&self.parameters.point_cloud), &centers)?;
+        CoverTreeWriter::load(&self.save(), Arc::new(point_cloud))
+    }
+
     /// Swaps the maps on each layer so that any `CoverTreeReaders` see the updated tree.
     /// Only call once you have a valid tree.
     pub fn refresh(&mut self) {
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
         self.layers.iter_mut().rev().for_each(|l| l.refresh());
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+    }
+
+    /// An incremental `refresh` for writers that share a thread with latency sensitive queries. Publishes the pending
+    /// writes, from the bottom layer of the tree up and at most `REFRESH_CHUNK` writes per swap, until `budget` is
+    /// spent. At least one swap is made per call so this always makes progress. Returns `true` once readers see every
+    /// write.
+    ///
+    /// Between calls readers see a partly refreshed tree. A layer's writes are all published before any of the layer
+    /// above it, so new nodes become reachable once their parent's layer is refreshed. The generation is only odd
+    /// during a call, so between calls `knn_path_summaries` can return a result that mixes refreshed and stale layers
+    /// under a single generation. Its results are only consistent once this has returned `true`.
+    pub fn refresh_chunked(&mut self, budget: Duration) -> bool {
+        self.refresh_in_chunks(budget, REFRESH_CHUNK)
+    }
+
+    fn refresh_in_chunks(&mut self, budget: Duration, chunk: usize) -> bool {
+        let start = Instant::now();
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        let mut swapped = false;
+        let mut done = true;
+        'layers: for layer in self.layers.iter_mut() {
+            while layer.has_pending() {
+                if swapped && start.elapsed() >= budget {
+                    done = false;
+                    break 'layers;
+                }
+                layer.refresh_some(chunk);
+                swapped = true;
+            }
+        }
+        self.parameters
+            .generation
+            .fetch_add(1, atomic::Ordering::AcqRel);
+        done
+    }
+}
+
+impl<D: PointCloud> CoverTreeWriter<HashGluedCloud<D>> {
+    /// Drops a segment of the glued point cloud, for example a day of data that has expired, from the tree and the
+    /// cloud without a rebuild. The segment's points are taken out of the tree with `remove_point`, then the segment is
+    /// removed from the cloud with `HashGluedCloud::remove_segment`. With `SegmentRemoval::Remap` the tree's indexes are
+    /// renumbered with the returned remap table, by reloading it, so its plugins and journal are not carried over.
+    ///
+    /// The point cloud can't change under readers, so this fails with `GokoError::InvalidEdit` before touching anything
+    /// if there are readers of this tree or other references to its cloud.
+    pub fn remove_segment(
+        &mut self,
+        segment: usize,
+        removal: SegmentRemoval,
+    ) -> GokoResult<RemovedSegment<D>> {
+        let shared =
+            GokoError::InvalidEdit("the point cloud is shared with readers or other trees");
+        if Arc::strong_count(&self.parameters) != 1
+            || Arc::strong_count(&self.parameters.point_cloud) != 1
+        {
+            return Err(shared);
+        }
+        let indexes = self.parameters.point_cloud.segment_indexes(segment)?;
+        for pi in indexes {
+            self.remove_point(pi)?;
+        }
+        let removed = Arc::get_mut(&mut self.parameters)
+            .and_then(|parameters| Arc::get_mut(&mut parameters.point_cloud))
+            .ok_or(shared)?
+            .remove_segment(segment, removal)?;
+        if !removed.remap.is_empty() {
+            let remap: HashMap<u64, u64> = removed
+                .remap
+                .iter()
+                .map(|(old, new)| (*old as u64, *new as u64))
+                .collect();
+            let mut cover_proto = self.save();
+            remap_proto_indexes(&mut cover_proto, &remap);
+            *self = CoverTreeWriter::load(&cover_proto, Arc::clone(&self.parameters.point_cloud))?;
+        }
+        Ok(removed)
+    }
+}
+
+/// Renumbers every point index in a saved tree, indexes that aren't in the map are left alone.
+fn remap_proto_indexes(cover_proto: &mut CoreProto, remap: &HashMap<u64, u64>) {
+    let map = |pi: &mut u64| {
+        if let Some(new_pi) = remap.get(pi) {
+            *pi = *new_pi;
+        }
+    };
+    let mut root_index = cover_proto.get_root_index();
+    map(&mut root_index);
+    cover_proto.set_root_index(root_index);
+    for layer in cover_proto.mut_layers().iter_mut() {
+        for node in layer.mut_nodes().iter_mut() {
+            let mut center_index = node.get_center_index();
+            map(&mut center_index);
+            node.set_center_index(center_index);
+            let mut parent_center_index = node.get_parent_center_index();
+            map(&mut parent_center_index);
+            node.set_parent_center_index(parent_center_index);
+            node.mut_children_point_indexes().iter_mut().for_each(map);
+            node.mut_outlier_point_indexes().iter_mut().for_each(map);
+        }
     }
 }
 
@@ -739,7 +1948,10 @@ impl<D: PointCloud> CoverTreeWriter<D> {
 pub(crate) mod tests {
     use super::*;
 
+    use crate::plugins::utils::GokoCoverageIndexes;
     use crate::utils::cover_tree_from_labeled_yaml;
+    use pointcloud::data_sources::DataRam;
+    use pointcloud::label_sources::{SmallIntLabels, TaxonomyLabels};
     use std::path::Path;
 
     pub(crate) fn build_mnist_tree() -> CoverTreeWriter<DefaultLabeledCloud<L2>> {
@@ -797,6 +2009,16 @@ pub(crate) mod tests {
         assert!(got_one);
     }
 
+    #[test]
+    fn display_info() {
+        let mut tree = build_basic_tree();
+        tree.generate_summaries().unwrap();
+        let info = format!("{}", tree);
+        println!("{}", info);
+        assert!(info.starts_with("CoverTree over 5 points of dim 1, L2 metric"));
+        assert!(info.contains("LabelSummaryPlugin"));
+    }
+
     #[test]
     fn greedy_knn_nodes() {
         let data = vec![0.499, 0.49, 0.48, -0.49, 0.0];
@@ -832,7 +2054,9 @@ pub(crate) mod tests {
                 .1
         );
 
-        reader.greedy_knn_nodes(&point, &mut query_heap);
+        reader
+            .greedy_knn_nodes(&point, &mut query_heap, &mut DistanceCache::default(), 0)
+            .unwrap();
         println!("{:#?}", query_heap);
         println!(
             "{:#?}",
@@ -852,6 +2076,25 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn path_contract() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        let point_cloud = reader.point_cloud();
+        for query in &[0.495f32, -3.0, 0.0, 17.0] {
+            let trace = reader.path(&[*query][..]).unwrap();
+            assert_eq!(trace[0].1, reader.root_address());
+            for (d, (_, pi)) in trace.iter() {
+                let center = point_cloud.point(*pi).unwrap();
+                assert_eq!(*d, L2::dist(&center, &[*query][..]).unwrap());
+            }
+            for w in trace.windows(2) {
+                let parent = reader.node_and(w[1].1, |n| n.parent_address()).flatten();
+                assert_eq!(parent, Some(w[0].1));
+            }
+        }
+    }
+
     #[test]
     fn known_path_sanity() {
         let writer = build_basic_tree();
@@ -911,6 +2154,21 @@ pub(crate) mod tests {
         assert!(zero_nbrs[1].1 == 2);
     }
 
+    #[test]
+    fn beam_knn_sanity() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        let exact = reader.knn(&[0.1f32][..], 2).unwrap();
+        let greedy = reader.beam_knn(&[0.1f32][..], 2, 1).unwrap();
+        println!("{:?}", greedy);
+        assert_eq!(greedy.len(), 2);
+        let wide = reader.beam_knn(&[0.1f32][..], 2, 100).unwrap();
+        println!("{:?}", wide);
+        for (e, w) in exact.iter().zip(wide.iter()) {
+            assert_eq!(e.1, w.1);
+        }
+    }
+
     #[test]
     fn label_summary() {
         let data = vec![0.499, 0.49, 0.48, -0.49, 0.0];
@@ -926,7 +2184,7 @@ pub(crate) mod tests {
             verbosity: 0,
         };
         let mut tree = builder.build(Arc::new(point_cloud)).unwrap();
-        tree.generate_summaries();
+        tree.generate_summaries().unwrap();
         let reader = tree.reader();
 
         for (_, layer) in reader.layers() {
@@ -935,12 +2193,53 @@ pub(crate) mod tests {
 
         let l = reader
             .get_node_label_summary(reader.root_address())
+            .unwrap()
             .unwrap();
         assert_eq!(l.summary.items.len(), 2);
         assert_eq!(l.nones, 0);
         assert_eq!(l.errors, 0);
     }
 
+    #[test]
+    fn classify_returns_the_deepest_labeled_summary() {
+        let mut writer = build_basic_tree();
+        let reader = writer.reader();
+        assert!(reader.classify(&[0.49f32][..]).is_err());
+        writer.generate_summaries().unwrap();
+        let reader = writer.reader();
+        let (address, summary) = reader.classify(&[0.49f32][..]).unwrap();
+        let path = reader.path(&[0.49f32][..]).unwrap();
+        assert!(path.iter().any(|(_, a)| *a == address));
+        assert_eq!(summary.summary.posterior()[0].0, 0);
+        // A singleton of the root only reaches the root, and gets the whole tree's distribution
+        let (address, summary) = reader.classify(&[-0.49f32][..]).unwrap();
+        assert_eq!(address, reader.root_address());
+        assert_eq!(summary.summary.posterior(), vec![(0, 0.6), (1, 0.4)]);
+
+        let (address, proportions) = reader
+            .classify_with_intervals(&[-0.49f32][..], IntervalMethod::Wilson, 0.95)
+            .unwrap();
+        assert_eq!(address, reader.root_address());
+        assert_eq!(proportions.len(), 2);
+        assert_eq!((proportions[0].label, proportions[0].count), (0, 3));
+        assert!(proportions[0].lower < 0.6 && 0.6 < proportions[0].upper);
+    }
+
+    #[test]
+    fn knn_path_summaries_sanity() {
+        let mut writer = build_basic_tree();
+        writer.generate_summaries().unwrap();
+        let reader = writer.reader();
+        let result = reader.knn_path_summaries(&[0.1f32][..], 2).unwrap();
+        assert_eq!(result.generation % 2, 0);
+        assert_eq!(result.knn, reader.knn(&[0.1f32][..], 2).unwrap());
+        assert_eq!(result.path, reader.path(&[0.1f32][..]).unwrap());
+        assert_eq!(result.summaries.len(), result.path.len());
+        for summary in result.summaries {
+            assert!(summary.is_some());
+        }
+    }
+
     #[test]
     fn knn_singletons_off() {
         let data = vec![0.499, 0.49, 0.48, -0.49, 0.0];
@@ -965,6 +2264,817 @@ pub(crate) mod tests {
         assert!(zero_nbrs[1].1 == 2);
     }
 
+    #[test]
+    fn malformed_queries_error() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        assert!(reader.knn(&[0.1f32, 0.2][..], 2).is_err());
+        assert!(reader.knn(&[std::f32::NAN][..], 2).is_err());
+        assert!(reader.path(PointRef::Sparse(&[0.1, 0.2], &[0])).is_err());
+        assert!(reader
+            .routing_knn(PointRef::Sparse(&[0.1], &[3]), 2)
+            .is_err());
+    }
+
+    #[test]
+    fn load_rejects_dangling_refs() {
+        let writer = build_basic_tree();
+        let mut proto = writer.save();
+        let layer_count = proto.get_layers().len();
+        for layer in proto.mut_layers().iter_mut().take(layer_count - 1) {
+            layer.clear_nodes();
+        }
+        assert!(CoverTreeWriter::load(&proto, Arc::clone(writer.reader().point_cloud())).is_err());
+    }
+
+    #[test]
+    fn attach_labels_after_build() {
+        let data = vec![0.499, 0.49, 0.48, -0.49, 0.0];
+        let point_cloud = Arc::new(DataRam::<L2>::new(data, 1).unwrap());
+        let tree = CoverTreeBuilder::new().build(point_cloud).unwrap();
+
+        assert!(tree
+            .attach_labels(SmallIntLabels::new(vec![0, 1], None))
+            .is_err());
+        let labeled = tree
+            .attach_labels(SmallIntLabels::new(vec![0, 0, 0, 1, 1], None))
+            .unwrap();
+        let reader = labeled.reader();
+        assert_eq!(reader.node_count(), tree.reader().node_count());
+        let summary = reader
+            .get_node_label_summary(reader.root_address())
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.count(), 5);
+    }
+
+    #[test]
+    fn node_annotations_persist() {
+        let mut writer = build_basic_tree();
+        let root = writer.reader().root_address();
+        writer
+            .set_node_annotation(root, Some("bot traffic".to_string()))
+            .unwrap();
+        assert!(writer
+            .set_node_annotation((root.0 + 100, root.1), None)
+            .is_err());
+        assert_eq!(
+            writer.reader().node_annotation(root).unwrap(),
+            Some("bot traffic".to_string())
+        );
+
+        let proto = writer.save();
+        let loaded =
+            CoverTreeWriter::load(&proto, Arc::clone(writer.reader().point_cloud())).unwrap();
+        assert_eq!(
+            loaded.reader().node_annotation(root).unwrap(),
+            Some("bot traffic".to_string())
+        );
+        writer.set_node_annotation(root, None).unwrap();
+        assert_eq!(writer.reader().node_annotation(root).unwrap(), None);
+        assert!(writer
+            .reader()
+            .node_annotation((root.0 + 100, root.1))
+            .is_err());
+    }
+
+    #[test]
+    fn refresh_chunked_swaps_a_layer_at_a_time() {
+        let mut writer = build_basic_tree();
+        let reader = writer.reader();
+        let mut addresses = Vec::new();
+        for (scale_index, layer) in reader.layers() {
+            layer.for_each_node(|pi, _| addresses.push((scale_index, *pi)));
+        }
+        let touched_layers = {
+            let mut scales: Vec<i32> = addresses.iter().map(|a| a.0).collect();
+            scales.dedup();
+            scales.len()
+        };
+        assert!(touched_layers > 1);
+        for address in &addresses {
+            unsafe {
+                writer.update_node(*address, |n| n.set_annotation(Some("new".to_string())));
+            }
+        }
+
+        let mut calls = 0;
+        while !writer.refresh_chunked(Duration::from_secs(0)) {
+            calls += 1;
+            assert_eq!(reader.generation() % 2, 0);
+        }
+        assert_eq!(calls + 1, touched_layers);
+        for address in &addresses {
+            assert_eq!(
+                reader.node_annotation(*address).unwrap(),
+                Some("new".to_string())
+            );
+        }
+        assert!(writer.refresh_chunked(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn refresh_chunked_splits_large_layers() {
+        let mut writer = build_basic_tree();
+        let reader = writer.reader();
+        let mut addresses = Vec::new();
+        for (scale_index, layer) in reader.layers() {
+            layer.for_each_node(|pi, _| addresses.push((scale_index, *pi)));
+        }
+        let largest_layer = reader.layers().map(|(_, layer)| layer.len()).max().unwrap();
+        assert!(largest_layer > 1);
+        for address in &addresses {
+            unsafe {
+                writer.update_node(*address, |n| n.set_annotation(Some("new".to_string())));
+            }
+        }
+
+        // A nonzero budget that one swap spends, so each call publishes a single write
+        let mut calls = 1;
+        while !writer.refresh_in_chunks(Duration::from_nanos(1), 1) {
+            calls += 1;
+            assert_eq!(reader.generation() % 2, 0);
+        }
+        assert_eq!(calls, addresses.len());
+        for address in &addresses {
+            assert_eq!(
+                reader.node_annotation(*address).unwrap(),
+                Some("new".to_string())
+            );
+        }
+
+        for address in &addresses {
+            unsafe {
+                writer.update_node(*address, |n| n.set_annotation(None));
+            }
+        }
+        assert!(writer.refresh_chunked(Duration::from_secs(10)));
+        assert_eq!(reader.node_annotation(addresses[0]).unwrap(), None);
+    }
+
+    fn check_after_removals<D: PointCloud>(reader: &CoverTreeReader<D>, remaining: &[PointIndex]) {
+        assert!(reader.no_dangling_refs());
+        let mut to_check = vec![reader.root_address()];
+        while let Some(addr) = to_check.pop() {
+            let (coverage, singletons, children) = reader
+                .get_node_and(addr, |n| {
+                    (
+                        n.coverage_count(),
+                        n.singletons_len(),
+                        n.children().map(|(ns, c)| {
+                            let mut c = c.to_vec();
+                            c.push((ns, addr.1));
+                            c
+                        }),
+                    )
+                })
+                .unwrap();
+            let mut expected = singletons;
+            match children {
+                Some(children) => {
+                    for ca in children {
+                        let (parent, child_coverage) = reader
+                            .get_node_and(ca, |n| (n.parent_address(), n.coverage_count()))
+                            .unwrap();
+                        assert_eq!(parent, Some(addr));
+                        expected += child_coverage;
+                        to_check.push(ca);
+                    }
+                }
+                None => expected += 1,
+            }
+            assert_eq!(coverage, expected, "Node {:?}", addr);
+        }
+        assert_eq!(
+            reader
+                .get_node_and(reader.root_address(), |n| n.coverage_count())
+                .unwrap(),
+            remaining.len()
+        );
+
+        let point_cloud = reader.point_cloud();
+        for pi in remaining {
+            let final_address = *reader.known_path(*pi).unwrap().last().unwrap();
+            let found = reader
+                .get_node_and(final_address.1, |n| {
+                    n.singletons().contains(pi) || (n.is_leaf() && n.center_index() == pi)
+                })
+                .unwrap();
+            assert!(found, "Point {} is not at {:?}", pi, final_address.1);
+
+            let point = point_cloud.point(*pi).unwrap();
+            let mut expected = point_cloud.distances_to_point(point, remaining).unwrap();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let knn = reader.knn(point, 3).unwrap();
+            assert_eq!(knn.len(), 3.min(remaining.len()));
+            for ((d, i), e) in knn.iter().zip(&expected) {
+                assert!(remaining.contains(i));
+                assert_approx_eq!(*d, *e);
+            }
+        }
+    }
+
+    #[test]
+    fn remove_points_until_empty() {
+        let mut writer = build_basic_tree();
+        let mut remaining: Vec<PointIndex> = (0..5).collect();
+        check_after_removals(&writer.reader(), &remaining);
+        let root = writer.reader().root_address();
+        remaining.retain(|pi| *pi != root.1);
+        remaining.insert(0, root.1);
+        while remaining.len() > 1 {
+            let pi = remaining.remove(0);
+            writer.remove_point(pi).unwrap();
+            assert!(writer.remove_point(pi).is_err());
+            check_after_removals(&writer.reader(), &remaining);
+        }
+        match writer.remove_point(remaining[0]) {
+            Err(GokoError::EmptyTree) => {}
+            _ => panic!("Removing the last point should fail"),
+        }
+        check_after_removals(&writer.reader(), &remaining);
+    }
+
+    #[test]
+    fn remove_random_points() {
+        for use_singletons in &[true, false] {
+            let data: Vec<f32> = (0..400).map(|_| rand::random::<f32>()).collect();
+            let point_cloud = DefaultCloud::<L2>::new(data, 2).unwrap();
+            let builder = CoverTreeBuilder {
+                scale_base: 1.5,
+                leaf_cutoff: 1,
+                min_res_index: -9,
+                use_singletons: *use_singletons,
+                partition_type: PartitionType::Nearest,
+                verbosity: 0,
+            };
+            let mut writer = builder.build(Arc::new(point_cloud)).unwrap();
+            let mut remaining: Vec<PointIndex> = (0..200).collect();
+            for i in 0..150 {
+                let pi = if i % 10 == 0 {
+                    writer.reader().root_address().1
+                } else {
+                    remaining[(i * 7919) % remaining.len()]
+                };
+                remaining.retain(|r| *r != pi);
+                writer.remove_point(pi).unwrap();
+                if i % 25 == 0 {
+                    check_after_removals(&writer.reader(), &remaining);
+                }
+            }
+            check_after_removals(&writer.reader(), &remaining);
+        }
+    }
+
+    #[test]
+    fn knn_brute_below_matches_knn() {
+        let data: Vec<f32> = (0..1000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 5).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let mut writer = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let dists = |knn: Vec<(f32, PointIndex)>| knn.iter().map(|(d, _)| *d).collect::<Vec<f32>>();
+        for with_plugin in &[false, true] {
+            if *with_plugin {
+                writer
+                    .add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::restricted(30))
+                    .unwrap();
+            }
+            let reader = writer.reader();
+            for _ in 0..20 {
+                let point: Vec<f32> = (0..5).map(|_| rand::random::<f32>()).collect();
+                let expected = dists(reader.knn(&point, 5).unwrap());
+                for threshold in &[0, 10, 50, 200] {
+                    let found = dists(reader.knn_brute_below(&point, 5, *threshold).unwrap());
+                    assert_eq!(expected, found);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn knn_under_matches_brute_force() {
+        let taxonomy = [
+            "animals/mammals/dogs",
+            "animals/mammals/cats",
+            "animals/birds",
+            "plants",
+        ];
+        let data: Vec<f32> = (0..600).map(|_| rand::random::<f32>()).collect();
+        let labels: Vec<String> = (0..200)
+            .map(|i| taxonomy[(i * 7) % 4].to_string())
+            .collect();
+        let point_cloud = Arc::new(SimpleLabeledCloud::new(
+            DataRam::<L2>::new(data, 3).unwrap(),
+            TaxonomyLabels::new(labels.clone(), None),
+        ));
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let mut writer = builder.build(Arc::clone(&point_cloud)).unwrap();
+        for with_plugin in &[false, true] {
+            if *with_plugin {
+                writer
+                    .add_plugin::<LabelSummaryPlugin>(TreeLabelSummary {})
+                    .unwrap();
+            }
+            let reader = writer.reader();
+            for path in &[
+                "animals",
+                "animals/mammals",
+                "animals/birds/",
+                "plants",
+                "fungi",
+            ] {
+                let point: Vec<f32> = (0..3).map(|_| rand::random::<f32>()).collect();
+                let members: Vec<PointIndex> = (0..200)
+                    .filter(|i| taxonomy_contains(path, &labels[*i]))
+                    .collect();
+                let mut expected = point_cloud
+                    .distances_to_point(&point[..], &members)
+                    .unwrap();
+                expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                expected.truncate(5);
+                let found = reader.knn_under(&point, 5, path).unwrap();
+                assert!(found.iter().all(|(_, pi)| members.contains(pi)));
+                let found: Vec<f32> = found.iter().map(|(d, _)| *d).collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn knn_batch_matches_knn() {
+        let data: Vec<f32> = (0..1000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 5).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let tree = builder.build(point_cloud).unwrap();
+        let reader = tree.reader();
+        let queries: Vec<Vec<f32>> = (0..300)
+            .map(|_| (0..5).map(|_| rand::random::<f32>()).collect())
+            .collect();
+        let points: Vec<PointRef> = queries.iter().map(|q| PointRef::from(&q[..])).collect();
+        let batch = reader.knn_batch(&points, 3).unwrap();
+        assert_eq!(batch.len(), queries.len());
+        for (query, knn) in queries.iter().zip(batch) {
+            assert_eq!(knn, reader.knn(query, 3).unwrap());
+        }
+        let bad = vec![0.0f32; 4];
+        let mut points = points;
+        points.push(PointRef::from(&bad[..]));
+        assert!(reader.knn_batch(&points, 3).is_err());
+    }
+
+    #[test]
+    fn knn_with_scratch_matches_knn() {
+        let data: Vec<f32> = (0..1000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 5).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let reader = tree.reader();
+        let mut scratch = QueryScratch::default();
+        for (i, k) in [1, 5, 20, 3].iter().enumerate() {
+            let query = [i as f32 / 4.0; 5];
+            let expected = reader.knn(&query[..], *k).unwrap();
+            let found = reader
+                .knn_with_scratch(&query[..], *k, &mut scratch)
+                .unwrap();
+            assert_eq!(found.len(), *k);
+            for ((a, _), (e, _)) in found.iter().zip(&expected) {
+                assert_approx_eq!(a, e);
+            }
+        }
+        assert!(scratch.capacity() > 0);
+        assert!(reader
+            .knn_with_scratch(&[0.0f32; 4][..], 3, &mut scratch)
+            .is_err());
+
+        let mut small = QueryScratch::with_max_retained(0);
+        assert_eq!(
+            reader
+                .knn_with_scratch(&[0.5f32; 5][..], 5, &mut small)
+                .unwrap()
+                .len(),
+            5
+        );
+        // The heap was dropped after the query, only the results are kept
+        assert!(small.capacity() < scratch.capacity());
+    }
+
+    #[test]
+    fn cached_distances_match_uncached() {
+        let data: Vec<f32> = (0..1000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 5).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let reader = tree.reader();
+        let mut uncached = QueryScratch::default();
+        let mut cached = QueryScratch::default();
+        cached.set_cache_distances(true);
+        let mut total_hits = 0;
+        for i in 0..10 {
+            let query = [i as f32 / 10.0; 5];
+            // Brute forcing small nodes compares their centers to the query again
+            reader
+                .knn_into_scratch(&query[..], 10, 0.0, 50, &mut uncached)
+                .unwrap();
+            reader
+                .knn_into_scratch(&query[..], 10, 0.0, 50, &mut cached)
+                .unwrap();
+            assert_eq!(cached.results(), uncached.results());
+            let cache = cached.distance_cache();
+            assert_eq!(
+                cache.hits() + cache.misses(),
+                uncached.distance_cache().misses()
+            );
+            assert_eq!(uncached.distance_cache().hits(), 0);
+            total_hits += cache.hits();
+        }
+        assert!(total_hits > 0);
+    }
+
+    #[test]
+    fn approx_knn_within_bound() {
+        let data: Vec<f32> = (0..1000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 5).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let reader = tree.reader();
+        let query = [0.5f32; 5];
+        let exact = reader.knn(&query[..], 5).unwrap();
+        assert_eq!(reader.approx_knn(&query[..], 5, 0.0).unwrap(), exact);
+        for epsilon in &[0.1f32, 0.5, 2.0] {
+            let approx = reader.approx_knn(&query[..], 5, *epsilon).unwrap();
+            assert_eq!(approx.len(), 5);
+            for ((a, _), (e, _)) in approx.iter().zip(&exact) {
+                assert!(*a <= (1.0 + epsilon) * e + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn range_query_matches_brute_force() {
+        let data: Vec<f32> = (0..600).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.5,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let tree = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let reader = tree.reader();
+        let indexes = point_cloud.reference_indexes();
+        for (pi, radius) in &[(0, 0.0), (17, 0.1), (42, 0.25), (99, 0.5), (3, 2.0)] {
+            let point = point_cloud.point(*pi).unwrap();
+            let found = reader.range_query(point, *radius).unwrap();
+            let dists = point_cloud.distances_to_point(point, &indexes).unwrap();
+            let mut expected: Vec<PointIndex> = indexes
+                .iter()
+                .zip(&dists)
+                .filter(|(_, d)| **d <= *radius)
+                .map(|(i, _)| *i)
+                .collect();
+            let mut found_indexes: Vec<PointIndex> = found.iter().map(|(_, i)| *i).collect();
+            assert!(found.windows(2).all(|w| w[0].0 <= w[1].0));
+            found_indexes.sort();
+            expected.sort();
+            assert_eq!(found_indexes, expected);
+        }
+        assert_eq!(
+            reader.range_query(&[0.0f32; 3][..], 10.0).unwrap().len(),
+            200
+        );
+    }
+
+    #[test]
+    fn queries_by_index_skip_the_point() {
+        let data: Vec<f32> = (0..300).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 3).unwrap());
+        let tree = CoverTreeBuilder::new()
+            .build(Arc::clone(&point_cloud))
+            .unwrap();
+        let reader = tree.reader();
+        for pi in &[0, 17, 42, 99] {
+            let point = point_cloud.point(*pi).unwrap();
+            let knn = reader.knn_by_index(*pi, 5).unwrap();
+            assert_eq!(knn.len(), 5);
+            assert!(knn.iter().all(|(_, i)| i != pi));
+            assert_eq!(knn[..], reader.knn(point, 6).unwrap()[1..]);
+
+            let range = reader.range_by_index(*pi, 0.3).unwrap();
+            assert!(range.iter().all(|(_, i)| i != pi));
+            assert_eq!(
+                range.len() + 1,
+                reader.range_query(point, 0.3).unwrap().len()
+            );
+
+            assert_eq!(
+                reader.path_by_index(*pi).unwrap(),
+                reader.path(point).unwrap()
+            );
+        }
+        assert!(reader.knn_by_index(100, 5).is_err());
+    }
+
+    #[test]
+    fn unknown_addresses_resolve() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        let root = reader.root_address();
+        let leaf = reader.final_addresses.get_and(&0, |a| *a).unwrap();
+        assert_eq!(reader.resolve_address(leaf).unwrap(), leaf);
+
+        let stale = (leaf.0 - 5, leaf.1);
+        match reader.get_node_and(stale, |_| ()) {
+            Err(GokoError::NodeNotFound {
+                address,
+                nearest_ancestor,
+            }) => {
+                assert_eq!(address, stale);
+                assert_eq!(nearest_ancestor, Some(leaf));
+            }
+            _ => panic!("Expected a NodeNotFound error"),
+        }
+        assert_eq!(reader.resolve_address(stale).unwrap(), leaf);
+
+        match reader.resolve_address((root.0 + 100, root.1)) {
+            Err(GokoError::NodeNotFound {
+                nearest_ancestor: None,
+                ..
+            }) => {}
+            _ => panic!("Expected a NodeNotFound error without an ancestor"),
+        }
+    }
+
+    #[test]
+    fn tiered_tree_only_reads_cold_points_at_the_bottom() {
+        let data: Vec<f32> = (0..2000).map(|_| rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 4).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 5,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let writer = builder.build(Arc::clone(&point_cloud)).unwrap();
+        let tiered = writer.tiered().unwrap();
+        let reader = writer.reader();
+        let tiered_reader = tiered.reader();
+        let tiered_cloud = tiered_reader.point_cloud();
+        assert!(tiered_cloud.hot_len() < point_cloud.len());
+        assert_eq!(tiered_reader.node_count(), reader.node_count());
+
+        let point: Vec<f32> = (0..4).map(|_| rand::random::<f32>()).collect();
+        let before = tiered_cloud.cold_reads();
+        tiered_reader.routing_knn(&point, 3).unwrap();
+        assert_eq!(tiered_cloud.cold_reads(), before);
+        let dists = |knn: Vec<(f32, PointIndex)>| knn.iter().map(|(d, _)| *d).collect::<Vec<f32>>();
+        assert_eq!(
+            dists(tiered_reader.knn(&point, 5).unwrap()),
+            dists(reader.knn(&point, 5).unwrap())
+        );
+    }
+
+    #[test]
+    fn segments_are_removed_from_the_tree() {
+        let segments = || {
+            (0..3)
+                .map(|s| {
+                    let data: Vec<f32> = (0..30).map(|i| (s * 30 + i) as f32 * 0.1).collect();
+                    DataRam::<L2>::new(data, 1).unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        let builder = CoverTreeBuilder {
+            leaf_cutoff: 2,
+            ..CoverTreeBuilder::default()
+        };
+        let mut tree = builder
+            .build(Arc::new(HashGluedCloud::new(segments())))
+            .unwrap();
+        let reader = tree.reader();
+        assert!(tree.remove_segment(1, SegmentRemoval::Tombstone).is_err());
+        drop(reader);
+        assert!(tree.remove_segment(3, SegmentRemoval::Tombstone).is_err());
+
+        let removed = tree.remove_segment(1, SegmentRemoval::Tombstone).unwrap();
+        assert_eq!(removed.removed, (30..60).collect::<Vec<PointIndex>>());
+        let reader = tree.reader();
+        assert!(reader.no_dangling_refs());
+        let root_coverage = reader
+            .get_node_and(reader.root_address(), |n| n.coverage_count())
+            .unwrap();
+        assert_eq!(root_coverage, 60);
+        let knn = reader.knn(&[4.5f32], 5).unwrap();
+        assert!(knn.iter().all(|(_, pi)| *pi < 30 || *pi >= 60));
+        assert_eq!(knn[0].1, 60);
+        drop(reader);
+
+        let removed = tree.remove_segment(0, SegmentRemoval::Remap).unwrap();
+        assert_eq!(removed.remap.len(), 30);
+        let reader = tree.reader();
+        assert!(reader.no_dangling_refs());
+        let covered = reader
+            .get_node_and(reader.root_address(), |n| n.coverage_count())
+            .unwrap();
+        assert_eq!(covered, 30);
+        let knn = reader.knn(&[6.05f32], 3).unwrap();
+        let mut indexes: Vec<PointIndex> = knn.iter().map(|(_, pi)| *pi).collect();
+        indexes.sort_unstable();
+        assert_eq!(indexes, vec![0, 1, 2]);
+        for pi in 0..30 {
+            assert!(reader.final_addresses.get_and(&pi, |_| ()).is_some());
+        }
+    }
+
+    #[test]
+    fn knn_dedup_skips_reingested_points() {
+        use pointcloud::glued_data_cloud::HashGluedCloud;
+        use pointcloud::label_sources::StringMetadata;
+        let segment = |data: Vec<f32>| {
+            let ids = (0..data.len()).map(|i| format!("item-{}", i)).collect();
+            DataRam::<L2>::new(data, 1)
+                .unwrap()
+                .attach_metadata(StringMetadata::new(ids, None))
+                .unwrap()
+        };
+        let data: Vec<f32> = (0..20).map(|i| i as f32 * 0.1).collect();
+        // The second segment is the first loaded again, a little off
+        let moved: Vec<f32> = data.iter().map(|x| x + 0.01).collect();
+        let point_cloud = HashGluedCloud::new(vec![segment(data), segment(moved)]);
+        let tree = CoverTreeBuilder::default()
+            .build(Arc::new(point_cloud))
+            .unwrap();
+        let reader = tree.reader();
+
+        let knn = reader.knn(&[0.52f32], 4).unwrap();
+        let deduped = reader
+            .knn_dedup(&[0.52f32], 4, |id: &String| Some(id.clone()))
+            .unwrap();
+        assert_eq!(deduped.len(), 4);
+        assert_eq!(deduped[0], knn[0]);
+        let mut ids: Vec<&String> = deduped
+            .iter()
+            .map(|(_, i)| reader.point_cloud().metadata(*i).unwrap().unwrap())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+        assert!(deduped.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(
+            reader
+                .knn_dedup(&[0.52f32], 50, |id: &String| Some(id.clone()))
+                .unwrap()
+                .len(),
+            20
+        );
+    }
+
+    #[test]
+    fn binary_trees_match_brute_force() {
+        use pointcloud::data_sources::BinaryRam;
+        let words: Vec<u64> = (0..600u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 28)
+            .collect();
+        // 36 bits, so no bits are set past the dimension
+        let point_cloud = Arc::new(BinaryRam::<Hamming>::new(words, 36).unwrap());
+        let tree = CoverTreeBuilder::default()
+            .build(Arc::clone(&point_cloud))
+            .unwrap();
+        let reader = tree.reader();
+        assert!(reader.no_dangling_refs());
+        let indexes = point_cloud.reference_indexes();
+        for pi in [0, 17, 599] {
+            let query = point_cloud.point(pi).unwrap();
+            let knn = reader.knn(query, 5).unwrap();
+            let mut dists = point_cloud.distances_to_point(query, &indexes).unwrap();
+            dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let knn_dists: Vec<f32> = knn.iter().map(|(d, _)| *d).collect();
+            assert_eq!(knn_dists, dists[..5].to_vec());
+        }
+        assert!(reader.knn(PointRef::Binary(&[1 << 40]), 1).is_err());
+        assert!(reader.knn(&[0.0f32; 36], 1).is_err());
+    }
+
+    #[test]
+    fn string_trees_match_brute_force() {
+        use pointcloud::data_sources::StringCloud;
+        let words: Vec<String> = (0..400u32)
+            .map(|i| format!("{:x}", i.wrapping_mul(2_654_435_761) >> (i % 17)))
+            .collect();
+        let point_cloud = Arc::new(StringCloud::<Levenshtein>::new(&words));
+        let tree = CoverTreeBuilder::default()
+            .build(Arc::clone(&point_cloud))
+            .unwrap();
+        let reader = tree.reader();
+        assert!(reader.no_dangling_refs());
+        let indexes = point_cloud.reference_indexes();
+        for query in ["9e3779b1", "abc", ""] {
+            let knn = reader.knn(query, 5).unwrap();
+            let mut dists = point_cloud.distances_to_point(query, &indexes).unwrap();
+            dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let knn_dists: Vec<f32> = knn.iter().map(|(d, _)| *d).collect();
+            assert_eq!(knn_dists, dists[..5].to_vec());
+        }
+        assert!(reader.knn(&[0.0f32; 8], 1).is_err());
+    }
+
+    #[test]
+    fn normalized_trees_normalize_queries() {
+        let data: Vec<f32> = (0..2000).map(|_| 1.0 + rand::random::<f32>()).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 4).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 1.3,
+            leaf_cutoff: 1,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let writer = builder.build_normalized(Arc::clone(&point_cloud)).unwrap();
+        let reader = writer.reader();
+        assert!(reader.is_normalized());
+        assert!(!builder
+            .build(Arc::clone(&point_cloud))
+            .unwrap()
+            .reader()
+            .is_normalized());
+
+        let query = [3.0f32, 1.0, 2.0, 4.0];
+        let mut normalized_query = query.to_vec();
+        l2_normalize(&mut normalized_query);
+        // Normalizing twice rounds differently, so compare within a tolerance
+        let knn = reader.knn(&query[..], 5).unwrap();
+        let expected = reader.knn(&normalized_query, 5).unwrap();
+        for ((d, _), (e, _)) in knn.iter().zip(&expected) {
+            assert_approx_eq!(d, e);
+        }
+        assert!(knn[0].0 < 1.0);
+        let addresses =
+            |path: Vec<(f32, NodeAddress)>| path.into_iter().map(|(_, a)| a).collect::<Vec<_>>();
+        assert_eq!(
+            addresses(reader.path(&query[..]).unwrap()),
+            addresses(reader.path(&normalized_query).unwrap())
+        );
+
+        let proto = writer.save();
+        assert!(proto.get_normalized());
+        assert!(format!("{:?}", proto).contains("normalized: true"));
+        match CoverTreeWriter::load(&proto, Arc::clone(&point_cloud)) {
+            Err(GokoError::NotNormalized(_)) => (),
+            other => panic!(
+                "expected a NotNormalized error, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+        let reloaded = CoverTreeWriter::load(&proto, Arc::clone(reader.point_cloud())).unwrap();
+        assert!(reloaded.reader().is_normalized());
+    }
+
     #[test]
     fn test_save_load_tree() {
         let data = vec![0.499, 0.49, 0.48, -0.49, 0.0];
@@ -20,14 +20,17 @@
 //! The errors that can occor when a cover tree is loading, working or saving.
 //! Most errors are floated up from `PointCloud` as that's the i/o layer.
 
+use pointcloud::metric_audit::MetricAudit;
 use pointcloud::pc_errors::PointCloudError;
 use protobuf::ProtobufError;
+use rayon::ThreadPoolBuildError;
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::str;
 
-use crate::PointIndex;
+use crate::{NodeAddress, PointIndex};
 
 /// Helper type for a call that could go wrong.
 pub type GokoResult<T> = Result<T, GokoError>;
@@ -49,6 +52,133 @@ pub enum GokoError {
     DoubleNest,
     /// Inserted a node before you changed it from a leaf node into a normal node. Insert the nested child first.
     InsertBeforeNest,
+    /// Unable to build the thread pool for a `GokoRuntime`
+    ThreadPoolError(ThreadPoolBuildError),
+    /// The query point has the wrong dimension, non-finite values, or malformed sparse indexes.
+    InvalidQueryPoint,
+    /// There is no node at the address, usually because it was held across a refresh that changed the tree.
+    NodeNotFound {
+        /// The address that was asked for
+        address: NodeAddress,
+        /// The deepest node above the missing one whose coverage contains the address's center, if the center is
+        /// still in the tree. See `CoverTreeReader::resolve_address`.
+        nearest_ancestor: Option<NodeAddress>,
+    },
+    /// The tree has to keep at least one point, so its last point cannot be removed.
+    EmptyTree,
+    /// There is no retained snapshot with this name or generation
+    SnapshotNotFound(String),
+    /// The writer isn't keeping a journal, start one with `CoverTreeWriter::start_journal`
+    NoJournal,
+    /// The tree doesn't have the plugin this needs, add it with `CoverTreeWriter::add_plugin`
+    PluginNotInstalled(&'static str),
+    /// The tree was built on L2 normalized points, but it was loaded with a point cloud that isn't normalized. Wrap it
+    /// in a `NormalizedCloud`.
+    NotNormalized(PointIndex),
+    /// The point cloud's metric failed the axiom checks of an audited build, see `CoverTreeBuilder::build_audited`
+    NotAMetric(MetricAudit),
+    /// A manual edit of the tree's structure that can't be made, with the reason
+    InvalidEdit(&'static str),
+    /// The tree's construction parameters don't make sense together, with the reason. See `CoverTreeParams`.
+    InvalidParameters(&'static str),
+    /// Another error, with where it happened. Attach these with `ErrorContextExt`. Match on `root` or `into_root` to
+    /// handle the underlying error whether or not it has context.
+    WithContext {
+        /// Where the error happened
+        context: ErrorContext,
+        /// The underlying error
+        source: Box<GokoError>,
+    },
+}
+
+/// Where an error happened.
+#[derive(Debug, Clone)]
+pub enum ErrorContext {
+    /// While working on this node
+    Node(NodeAddress),
+    /// While working on this point
+    Point(PointIndex),
+    /// While reading or writing this file
+    File(PathBuf),
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorContext::Node(ref address) => write!(f, "at node {:?}", address),
+            ErrorContext::Point(ref pi) => write!(f, "at point {}", pi),
+            ErrorContext::File(ref path) => write!(f, "in file {}", path.to_string_lossy()),
+        }
+    }
+}
+
+/// Attaches context to errors as they cross the point cloud and tree boundary.
+pub trait ErrorContextExt<T> {
+    /// Adds context to the error, if there is one
+    fn context(self, context: ErrorContext) -> GokoResult<T>;
+    /// The error happened at this node
+    fn at_node(self, address: NodeAddress) -> GokoResult<T>;
+    /// The error happened at this point
+    fn at_point(self, pi: PointIndex) -> GokoResult<T>;
+    /// The error happened in this file
+    fn at_path<P: AsRef<Path>>(self, path: P) -> GokoResult<T>;
+}
+
+impl<T, E: Into<GokoError>> ErrorContextExt<T> for Result<T, E> {
+    fn context(self, context: ErrorContext) -> GokoResult<T> {
+        self.map_err(|e| GokoError::WithContext {
+            context,
+            source: Box::new(e.into()),
+        })
+    }
+    fn at_node(self, address: NodeAddress) -> GokoResult<T> {
+        self.context(ErrorContext::Node(address))
+    }
+    fn at_point(self, pi: PointIndex) -> GokoResult<T> {
+        self.context(ErrorContext::Point(pi))
+    }
+    fn at_path<P: AsRef<Path>>(self, path: P) -> GokoResult<T> {
+        self.context(ErrorContext::File(path.as_ref().to_path_buf()))
+    }
+}
+
+impl GokoError {
+    /// The innermost error, with all context removed.
+    pub fn root(&self) -> &GokoError {
+        match *self {
+            GokoError::WithContext { ref source, .. } => source.root(),
+            ref e => e,
+        }
+    }
+
+    /// Takes the innermost error, dropping all context, so it can be matched on by value.
+    pub fn into_root(self) -> GokoError {
+        match self {
+            GokoError::WithContext { source, .. } => source.into_root(),
+            e => e,
+        }
+    }
+
+    /// Everywhere the error passed through, the outermost context first.
+    pub fn contexts(&self) -> Vec<&ErrorContext> {
+        let mut contexts = Vec::new();
+        let mut current = self;
+        while let GokoError::WithContext {
+            ref context,
+            ref source,
+        } = *current
+        {
+            contexts.push(context);
+            current = source;
+        }
+        contexts
+    }
+
+    /// Whether the error can come from reading the tree while the writer refreshes it, so the same read may succeed
+    /// once the refresh is done. Only a missing node is, the node may have moved.
+    pub fn is_transient(&self) -> bool {
+        matches!(self.root(), GokoError::NodeNotFound { .. })
+    }
 }
 
 impl fmt::Display for GokoError {
@@ -73,6 +203,44 @@ impl fmt::Display for GokoError {
                 f,
                 "Inserted a node into a node that does not have a nested child"
             ),
+            GokoError::ThreadPoolError(ref e) => write!(f, "{}", e),
+            GokoError::InvalidQueryPoint => write!(
+                f,
+                "The query point has the wrong dimension, non-finite values, or malformed sparse indexes"
+            ),
+            GokoError::NodeNotFound {
+                address,
+                nearest_ancestor,
+            } => match nearest_ancestor {
+                Some(ancestor) => write!(
+                    f,
+                    "There is no node at {:?}, the nearest existing ancestor is {:?}",
+                    address, ancestor
+                ),
+                None => write!(f, "There is no node at {:?}", address),
+            },
+            GokoError::EmptyTree => write!(f, "The tree has to keep at least one point"),
+            GokoError::SnapshotNotFound(ref name) => write!(f, "There is no snapshot for {}", name),
+            GokoError::NoJournal => write!(f, "The writer isn't keeping a journal"),
+            GokoError::PluginNotInstalled(name) => write!(f, "The tree doesn't have the {} plugin", name),
+            GokoError::NotNormalized(pi) => write!(
+                f,
+                "The tree was built on normalized points, but point {} isn't normalized",
+                pi
+            ),
+            GokoError::NotAMetric(ref audit) => write!(
+                f,
+                "The metric failed the axiom checks on {} sampled triples: {:?}",
+                audit.triples, audit
+            ),
+            GokoError::InvalidEdit(reason) => write!(f, "The edit can't be made, {}", reason),
+            GokoError::InvalidParameters(reason) => {
+                write!(f, "The tree can't be built with these parameters, {}", reason)
+            }
+            GokoError::WithContext {
+                ref context,
+                ref source,
+            } => write!(f, "{}, {}", source, context),
         }
     }
 }
@@ -97,10 +265,24 @@ impl Error for GokoError {
             GokoError::InvalidProbDistro => {
                 "The probability distribution you are trying to sample from is invalid, probably because it was infered from 0 points."
             }
+            GokoError::ThreadPoolError(..) => "Unable to build the thread pool",
+            GokoError::InvalidQueryPoint => {
+                "The query point has the wrong dimension, non-finite values, or malformed sparse indexes"
+            }
+            GokoError::NodeNotFound { .. } => "There is no node at the address",
+            GokoError::EmptyTree => "The tree has to keep at least one point",
+            GokoError::SnapshotNotFound(..) => "There is no snapshot with that name or generation",
+            GokoError::NoJournal => "The writer isn't keeping a journal",
+            GokoError::PluginNotInstalled(..) => "The tree doesn't have a plugin it needs",
+            GokoError::NotNormalized(..) => "The tree needs a normalized point cloud",
+            GokoError::NotAMetric(..) => "The metric failed the axiom checks",
+            GokoError::InvalidEdit(..) => "The edit can't be made",
+            GokoError::InvalidParameters(..) => "The tree can't be built with these parameters",
+            GokoError::WithContext { ref source, .. } => source.description(),
         }
     }
 
-    fn cause(&self) -> Option<&dyn Error> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             GokoError::IoError(ref e) => Some(e),
             GokoError::ParsingError(ref e) => Some(e),
@@ -109,6 +291,18 @@ impl Error for GokoError {
             GokoError::DoubleNest => None,
             GokoError::InsertBeforeNest => None,
             GokoError::InvalidProbDistro => None,
+            GokoError::ThreadPoolError(ref e) => Some(e),
+            GokoError::InvalidQueryPoint => None,
+            GokoError::NodeNotFound { .. } => None,
+            GokoError::EmptyTree => None,
+            GokoError::SnapshotNotFound(..) => None,
+            GokoError::NoJournal => None,
+            GokoError::PluginNotInstalled(..) => None,
+            GokoError::NotNormalized(..) => None,
+            GokoError::NotAMetric(..) => None,
+            GokoError::InvalidEdit(..) => None,
+            GokoError::InvalidParameters(..) => None,
+            GokoError::WithContext { ref source, .. } => Some(source.as_ref()),
         }
     }
 }
@@ -125,6 +319,12 @@ impl From<io::Error> for GokoError {
     }
 }
 
+impl From<ThreadPoolBuildError> for GokoError {
+    fn from(err: ThreadPoolBuildError) -> Self {
+        GokoError::ThreadPoolError(err)
+    }
+}
+
 impl From<ProtobufError> for GokoError {
     fn from(err: ProtobufError) -> Self {
         GokoError::ParsingError(ParsingError::ProtobufError(err))
@@ -200,7 +400,7 @@ impl Error for ParsingError {
         }
     }
 
-    fn cause(&self) -> Option<&dyn Error> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             ParsingError::ProtobufError(ref e) => Some(e),
             ParsingError::MalformedYamlError { .. } => None,
@@ -210,3 +410,38 @@ impl Error for ParsingError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_chains() {
+        let res: GokoResult<()> = Err(GokoError::IndexNotInTree(3));
+        let err = res.at_point(3).at_path("tree.dat").unwrap_err();
+        let message = format!("{}", err);
+        assert!(message.contains("at point 3"));
+        assert!(message.contains("in file tree.dat"));
+        assert!(err.source().unwrap().source().is_some());
+        match err.root() {
+            GokoError::IndexNotInTree(3) => {}
+            e => panic!("wrong root error {:?}", e),
+        }
+        assert!(!err.is_transient());
+        match err.contexts()[..] {
+            [ErrorContext::File(ref path), ErrorContext::Point(3)] => {
+                assert_eq!(path, Path::new("tree.dat"))
+            }
+            ref contexts => panic!("wrong contexts {:?}", contexts),
+        }
+        match err.into_root() {
+            GokoError::IndexNotInTree(pi) => assert_eq!(pi, 3),
+            e => panic!("wrong root error {:?}", e),
+        }
+        let res: GokoResult<()> = Err(GokoError::NodeNotFound {
+            address: (0, 3),
+            nearest_ancestor: None,
+        });
+        assert!(res.at_node((0, 3)).unwrap_err().is_transient());
+    }
+}
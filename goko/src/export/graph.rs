@@ -0,0 +1,172 @@
+//! Exports the tree and its knn graph as `petgraph` graphs. Only compiled with the `petgraph` feature.
+
+use super::multiresolution_export;
+use crate::covertree::KnnGraph;
+use crate::*;
+use petgraph::graph::{Graph, NodeIndex};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// The tree down to `min_scale_index` as a directed graph from parents to children. Each graph node is weighted by its
+/// node's address, and each edge by the distance between the centers. The graph's node indexes are the rows of
+/// `multiresolution_export`.
+pub fn tree_graph<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    min_scale_index: i32,
+) -> GokoResult<Graph<NodeAddress, f32>> {
+    let export = multiresolution_export(reader, min_scale_index)?;
+    let point_cloud = reader.point_cloud();
+    let mut graph = Graph::with_capacity(export.addresses.len(), export.edges.len());
+    for address in &export.addresses {
+        graph.add_node(*address);
+    }
+    for (parent, child) in &export.edges {
+        let distance = D::Metric::dist(
+            point_cloud.point(export.addresses[*parent].1)?,
+            point_cloud.point(export.addresses[*child].1)?,
+        )?;
+        graph.add_edge(NodeIndex::new(*parent), NodeIndex::new(*child), distance);
+    }
+    Ok(graph)
+}
+
+/// A knn graph, like the one from `CoverTreeReader::all_knn`, as a directed graph from each point to its neighbors.
+/// Each graph node is weighted by its point index, and each edge by the distance.
+pub fn knn_graph(knn: &KnnGraph) -> Graph<PointIndex, f32> {
+    let edge_count = knn.iter().map(|(_, neighbors)| neighbors.len()).sum();
+    let mut graph = Graph::with_capacity(knn.len(), edge_count);
+    let mut nodes: HashMap<PointIndex, NodeIndex> = HashMap::with_capacity(knn.len());
+    for (pi, _) in knn {
+        nodes.entry(*pi).or_insert_with(|| graph.add_node(*pi));
+    }
+    for (pi, neighbors) in knn {
+        let source = nodes[pi];
+        for (distance, neighbor) in neighbors {
+            let target = *nodes
+                .entry(*neighbor)
+                .or_insert_with(|| graph.add_node(*neighbor));
+            graph.add_edge(source, target, *distance);
+        }
+    }
+    graph
+}
+
+/// The node weights of the exported graphs, written out as integer attributes.
+pub trait ExportNode {
+    /// The attribute names, the same for every node.
+    fn attribute_names() -> &'static [&'static str];
+    /// The attribute values, in the order of `attribute_names`.
+    fn attributes(&self) -> Vec<i64>;
+}
+
+impl ExportNode for NodeAddress {
+    fn attribute_names() -> &'static [&'static str] {
+        &["scale_index", "center_index"]
+    }
+    fn attributes(&self) -> Vec<i64> {
+        vec![self.0 as i64, self.1 as i64]
+    }
+}
+
+impl ExportNode for PointIndex {
+    fn attribute_names() -> &'static [&'static str] {
+        &["point_index"]
+    }
+    fn attributes(&self) -> Vec<i64> {
+        vec![*self as i64]
+    }
+}
+
+/// Writes the edges as `source target weight` lines, with the sources and targets given by their graph node index.
+pub fn write_edge_list<N, W: Write>(graph: &Graph<N, f32>, writer: &mut W) -> io::Result<()> {
+    for edge in graph.raw_edges() {
+        writeln!(
+            writer,
+            "{} {} {}",
+            edge.source().index(),
+            edge.target().index(),
+            edge.weight
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the graph as a directed GraphML graph. The nodes have ids `n<graph node index>` and carry their
+/// `ExportNode` attributes, the edges carry their weight.
+pub fn write_graphml<N: ExportNode, W: Write>(
+    graph: &Graph<N, f32>,
+    writer: &mut W,
+) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for name in N::attribute_names() {
+        writeln!(
+            writer,
+            r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="long"/>"#,
+            name
+        )?;
+    }
+    writeln!(
+        writer,
+        r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#
+    )?;
+    writeln!(writer, r#"  <graph id="G" edgedefault="directed">"#)?;
+    for node in graph.node_indices() {
+        writeln!(writer, r#"    <node id="n{}">"#, node.index())?;
+        for (name, value) in N::attribute_names().iter().zip(graph[node].attributes()) {
+            writeln!(writer, r#"      <data key="{}">{}</data>"#, name, value)?;
+        }
+        writeln!(writer, "    </node>")?;
+    }
+    for edge in graph.raw_edges() {
+        writeln!(
+            writer,
+            r#"    <edge source="n{}" target="n{}"><data key="weight">{}</data></edge>"#,
+            edge.source().index(),
+            edge.target().index(),
+            edge.weight
+        )?;
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn graph_exports() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+
+        let graph = tree_graph(&reader, reader.scale_range().start).unwrap();
+        assert_eq!(graph.node_count(), reader.node_count());
+        assert_eq!(graph.edge_count(), reader.node_count() - 1);
+        assert_eq!(graph[NodeIndex::new(0)], reader.root_address());
+        assert!(graph.raw_edges().iter().all(|e| e.weight >= 0.0));
+
+        let mut edges = Vec::new();
+        write_edge_list(&graph, &mut edges).unwrap();
+        let edges = String::from_utf8(edges).unwrap();
+        assert_eq!(edges.lines().count(), graph.edge_count());
+        assert!(edges.starts_with("0 "));
+
+        let knn = reader.all_knn(2).unwrap();
+        let graph = knn_graph(&knn);
+        assert_eq!(graph.node_count(), reader.point_cloud().len());
+        assert_eq!(graph.edge_count(), 2 * reader.point_cloud().len());
+
+        let mut graphml = Vec::new();
+        write_graphml(&graph, &mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), graph.node_count());
+        assert_eq!(graphml.matches("<edge ").count(), graph.edge_count());
+        assert!(graphml.contains(r#"attr.name="point_index""#));
+        assert!(graphml.trim_end().ends_with("</graphml>"));
+    }
+}
@@ -0,0 +1,410 @@
+//! # Export
+//!
+//! Flattens the tree into arrays or graphs for use outside of goko.
+//!
+//! With the `petgraph` feature the tree and its knn graph can also be exported as `petgraph` graphs, so community
+//! detection and centrality algorithms can run on them directly. They can also be written out as an edge list or
+//! GraphML for other graph tooling.
+//!
+//! For a quick look at the hierarchy, `CoverTreeReader::to_dot` and `CoverTreeReader::to_json` write out the tree's
+//! topology with each node's scale and coverage, and the label summaries on labeled trees. `CoverTreeReader::to_newick`
+//! writes the tree as a dendrogram for phylogenetics and clustering tools.
+
+use crate::covertree::node::CoverNode;
+use crate::*;
+use ndarray::Array2;
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+
+#[cfg(feature = "petgraph")]
+mod graph;
+#[cfg(feature = "petgraph")]
+pub use graph::*;
+
+/// The top of the tree, down to some scale, as arrays. The rows of `centers` can be used directly as the initial
+/// layout for UMAP or t-SNE, with `weights` and `edges` to build the graph between them.
+#[derive(Debug, Clone)]
+pub struct MultiResolutionExport {
+    /// The address of the node on each row
+    pub addresses: Vec<NodeAddress>,
+    /// The center point of each node, one per row
+    pub centers: Array2<f32>,
+    /// The number of points each node covers
+    pub weights: Vec<usize>,
+    /// Parent and child edges, as pairs of rows `(parent, child)`
+    pub edges: Vec<(usize, usize)>,
+}
+
+/// Exports every node with scale index at least `min_scale_index`, along with the edges between them.
+/// The rows are in breadth first order from the root.
+pub fn multiresolution_export<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    min_scale_index: i32,
+) -> GokoResult<MultiResolutionExport> {
+    let mut addresses = Vec::new();
+    let mut weights = Vec::new();
+    let mut edges = Vec::new();
+    let mut rows: HashMap<NodeAddress, usize> = HashMap::new();
+
+    let mut to_visit = vec![(None, reader.root_address())];
+    while !to_visit.is_empty() {
+        let mut next_visit = Vec::new();
+        for (parent_row, addr) in to_visit.drain(..) {
+            if addr.0 < min_scale_index || rows.contains_key(&addr) {
+                continue;
+            }
+            let row = addresses.len();
+            let children = reader.get_node_and(addr, |n| {
+                weights.push(n.coverage_count());
+                n.children()
+                    .map(|(nested_scale, children)| {
+                        let mut c = vec![(nested_scale, addr.1)];
+                        c.extend(children);
+                        c
+                    })
+                    .unwrap_or_default()
+            })?;
+            rows.insert(addr, row);
+            addresses.push(addr);
+            if let Some(parent_row) = parent_row {
+                edges.push((parent_row, row));
+            }
+            next_visit.extend(children.into_iter().map(|c| (Some(row), c)));
+        }
+        to_visit = next_visit;
+    }
+
+    let center_indexes: Vec<PointIndex> = addresses.iter().map(|a| a.1).collect();
+    let centers = reader.point_cloud().points_dense_matrix(&center_indexes)?;
+    Ok(MultiResolutionExport {
+        addresses,
+        centers,
+        weights,
+        edges,
+    })
+}
+
+/// What `to_dot` and `to_json` write about a node.
+struct NodeRecord {
+    address: NodeAddress,
+    parent: Option<NodeAddress>,
+    radius: f32,
+    coverage_count: usize,
+    singletons_len: usize,
+    /// The count, unlabeled count, errored count and the summary itself
+    labels: Option<(usize, usize, usize, String)>,
+}
+
+/// The nodes in breadth first order from the root, down to `depth_limit` edges below it.
+fn node_records<D, F>(
+    reader: &CoverTreeReader<D>,
+    depth_limit: Option<usize>,
+    labels: F,
+) -> GokoResult<Vec<NodeRecord>>
+where
+    D: PointCloud,
+    F: Fn(&CoverNode<D>) -> Option<(usize, usize, usize, String)>,
+{
+    let mut records = Vec::new();
+    let mut to_visit = vec![(None, reader.root_address())];
+    let mut depth = 0;
+    while !to_visit.is_empty() {
+        let mut next_visit = Vec::new();
+        for (parent, address) in to_visit.drain(..) {
+            let children = reader.get_node_and(address, |n| {
+                records.push(NodeRecord {
+                    address,
+                    parent,
+                    // Leaves made from a single nested child have no covered points and a radius of -inf
+                    radius: n.radius().max(0.0),
+                    coverage_count: n.coverage_count(),
+                    singletons_len: n.singletons_len(),
+                    labels: labels(n),
+                });
+                n.children()
+                    .map(|(nested_scale, children)| {
+                        let mut c = vec![(nested_scale, address.1)];
+                        c.extend(children);
+                        c
+                    })
+                    .unwrap_or_default()
+            })?;
+            next_visit.extend(children.into_iter().map(|c| (Some(address), c)));
+        }
+        if Some(depth) == depth_limit {
+            break;
+        }
+        depth += 1;
+        to_visit = next_visit;
+    }
+    Ok(records)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_dot<D: PointCloud>(reader: &CoverTreeReader<D>, records: &[NodeRecord]) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph cover_tree {{").unwrap();
+    writeln!(dot, "  node [shape=box];").unwrap();
+    for record in records {
+        let (si, pi) = record.address;
+        let mut label = format!(
+            "({}, {})\\nscale {}\\ncoverage {}",
+            si,
+            pi,
+            reader.scale(si),
+            record.coverage_count
+        );
+        if let Some((_, _, _, summary)) = &record.labels {
+            write!(label, "\\n{}", escape(summary)).unwrap();
+        }
+        writeln!(dot, "  \"{},{}\" [label=\"{}\"];", si, pi, label).unwrap();
+        if let Some((parent_si, parent_pi)) = record.parent {
+            writeln!(
+                dot,
+                "  \"{},{}\" -> \"{},{}\";",
+                parent_si, parent_pi, si, pi
+            )
+            .unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn write_json<D: PointCloud>(reader: &CoverTreeReader<D>, records: &[NodeRecord]) -> String {
+    let nodes: Vec<String> = records
+        .iter()
+        .map(|record| {
+            let mut node = format!(
+                "{{\"address\":[{},{}],\"parent\":{},\"scale\":{},\"radius\":{},\"coverage_count\":{},\"singletons\":{}",
+                record.address.0,
+                record.address.1,
+                record
+                    .parent
+                    .map_or("null".to_string(), |(si, pi)| format!("[{},{}]", si, pi)),
+                reader.scale(record.address.0),
+                record.radius,
+                record.coverage_count,
+                record.singletons_len
+            );
+            if let Some((count, nones, errors, summary)) = &record.labels {
+                write!(
+                    node,
+                    ",\"label_summary\":{{\"count\":{},\"nones\":{},\"errors\":{},\"summary\":\"{}\"}}",
+                    count,
+                    nones,
+                    errors,
+                    escape(summary)
+                )
+                .unwrap();
+            }
+            node.push('}');
+            node
+        })
+        .collect();
+    format!(
+        "{{\"scale_base\":{},\"root\":[{},{}],\"nodes\":[{}]}}",
+        reader.parameters().scale_base,
+        reader.root_address().0,
+        reader.root_address().1,
+        nodes.join(",")
+    )
+}
+
+/// Writes the subtree of the node at `address`, whose parent has scale `parent_scale`, as a Newick clade.
+fn write_newick<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    address: NodeAddress,
+    parent_scale: Option<f32>,
+    newick: &mut String,
+) -> GokoResult<()> {
+    let scale = reader.scale(address.0);
+    let (children, singletons) = reader.get_node_and(address, |n| {
+        let children = n.children().map(|(nested_scale, children)| {
+            let mut c = vec![(nested_scale, address.1)];
+            c.extend(children);
+            c
+        });
+        (children, n.singletons().to_vec())
+    })?;
+    newick.push('(');
+    let mut first = true;
+    if let Some(children) = children {
+        for child in children {
+            if !first {
+                newick.push(',');
+            }
+            first = false;
+            write_newick(reader, child, Some(scale), newick)?;
+        }
+    } else {
+        // A leaf's center isn't one of its singletons
+        write!(newick, "{}:{}", address.1, scale).unwrap();
+        first = false;
+    }
+    for pi in singletons {
+        if !first {
+            newick.push(',');
+        }
+        first = false;
+        write!(newick, "{}:{}", pi, scale).unwrap();
+    }
+    write!(newick, ")n{}_{}", address.0, address.1).unwrap();
+    if let Some(parent_scale) = parent_scale {
+        write!(newick, ":{}", parent_scale - scale).unwrap();
+    }
+    Ok(())
+}
+
+impl<D: PointCloud> CoverTreeReader<D> {
+    /// The tree as a GraphViz digraph, down to `depth_limit` edges below the root. Each node is labeled with its
+    /// address, scale and coverage count, and the nested children are drawn as ordinary children.
+    pub fn to_dot(&self, depth_limit: Option<usize>) -> GokoResult<String> {
+        let records = node_records(self, depth_limit, |_| None)?;
+        Ok(write_dot(self, &records))
+    }
+
+    /// The tree as JSON, with a flat list of nodes in breadth first order. Each node has its `address`, the `parent`
+    /// address (`null` for the root), `scale`, `radius`, `coverage_count` and the number of `singletons`.
+    pub fn to_json(&self) -> GokoResult<String> {
+        let records = node_records(self, None, |_| None)?;
+        Ok(write_json(self, &records))
+    }
+
+    /// The tree as a dendrogram in Newick format. The leaves are the points, labeled with their indexes, and each
+    /// node is a clade named `n<scale index>_<center index>`. A node's height is its scale and the points are at
+    /// height 0, so each branch is as long as the difference between the scales of its ends. Nested children are
+    /// clades of their own, with the same center as their parent.
+    pub fn to_newick(&self) -> GokoResult<String> {
+        let mut newick = String::new();
+        write_newick(self, self.root_address(), None, &mut newick)?;
+        newick.push(';');
+        Ok(newick)
+    }
+}
+
+impl<D: PointCloud + LabeledCloud> CoverTreeReader<D> {
+    fn label_record(node: &CoverNode<D>) -> Option<(usize, usize, usize, String)> {
+        node.label_summary().map(|s| {
+            (
+                s.summary.count(),
+                s.nones,
+                s.errors,
+                format!("{:?}", s.summary),
+            )
+        })
+    }
+
+    /// Same as `to_dot`, with each node's label summary, if the summaries were generated.
+    pub fn to_dot_labeled(&self, depth_limit: Option<usize>) -> GokoResult<String> {
+        let records = node_records(self, depth_limit, Self::label_record)?;
+        Ok(write_dot(self, &records))
+    }
+
+    /// Same as `to_json`, with each node's `label_summary` if the summaries were generated. The summary has the
+    /// labeled `count`, the `nones` and `errors`, and the `summary` itself as its debug string.
+    pub fn to_json_labeled(&self) -> GokoResult<String> {
+        let records = node_records(self, None, Self::label_record)?;
+        Ok(write_json(self, &records))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn export_sanity() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+
+        let full = multiresolution_export(&reader, reader.scale_range().start).unwrap();
+        assert_eq!(full.addresses.len(), reader.node_count());
+        assert_eq!(full.centers.nrows(), reader.node_count());
+        assert_eq!(full.edges.len(), reader.node_count() - 1);
+        assert_eq!(full.weights[0], reader.point_cloud().len());
+
+        let top = multiresolution_export(&reader, reader.root_address().0).unwrap();
+        assert_eq!(top.addresses, vec![reader.root_address()]);
+        assert!(top.edges.is_empty());
+    }
+
+    #[test]
+    fn dot_and_json_exports() {
+        let mut tree = build_basic_tree();
+        let reader = tree.reader();
+
+        let dot = reader.to_dot(None).unwrap();
+        assert!(dot.starts_with("digraph cover_tree {"));
+        assert_eq!(dot.matches(" [label=").count(), reader.node_count());
+        assert_eq!(dot.matches(" -> ").count(), reader.node_count() - 1);
+        let root_only = reader.to_dot(Some(0)).unwrap();
+        assert_eq!(root_only.matches(" [label=").count(), 1);
+        assert_eq!(root_only.matches(" -> ").count(), 0);
+
+        let json = reader.to_json().unwrap();
+        assert!(json.starts_with(r#"{"scale_base":2,"root":["#));
+        assert_eq!(json.matches(r#""address":"#).count(), reader.node_count());
+        assert_eq!(json.matches(r#""parent":null"#).count(), 1);
+        assert!(json.contains(&format!(
+            r#""coverage_count":{}"#,
+            reader.point_cloud().len()
+        )));
+        assert!(!json.contains("label_summary"));
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for node in parsed["nodes"].as_array().unwrap() {
+            assert!(node["radius"].as_f64().unwrap() >= 0.0);
+        }
+
+        tree.generate_summaries().unwrap();
+        let reader = tree.reader();
+        let json = reader.to_json_labeled().unwrap();
+        assert_eq!(json.matches("label_summary").count(), reader.node_count());
+        assert!(json.contains(r#""label_summary":{"count":5,"nones":0,"errors":0"#));
+        serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert!(reader
+            .to_dot_labeled(None)
+            .unwrap()
+            .contains("CategorySummary"));
+    }
+
+    #[test]
+    fn newick_export() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let newick = reader.to_newick().unwrap();
+        assert!(newick.starts_with('('));
+        assert!(newick.ends_with(&format!(
+            ")n{}_{};",
+            reader.root_address().0,
+            reader.root_address().1
+        )));
+        assert_eq!(newick.matches('(').count(), reader.node_count());
+        assert_eq!(newick.matches(')').count(), reader.node_count());
+        // Each point is a leaf exactly once
+        let mut leaves: Vec<usize> = newick
+            .split(|c| c == '(' || c == ',')
+            .filter_map(|clade| clade.split(':').next()?.parse().ok())
+            .collect();
+        leaves.sort_unstable();
+        assert_eq!(leaves, (0..reader.point_cloud().len()).collect::<Vec<_>>());
+        let lengths: Vec<f32> = newick
+            .split(':')
+            .skip(1)
+            .map(|l| {
+                l.split(|c| c == ',' || c == ')')
+                    .next()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        assert!(lengths.iter().all(|l| *l > 0.0));
+    }
+}
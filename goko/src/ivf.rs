@@ -0,0 +1,144 @@
+//! # IVF Hybrid
+//!
+//! On high dimensional data, like text embeddings, a deep cover tree spends most of its query time routing. This cuts the
+//! tree at a target number of cells and stores the coverage of each cell as a flat posting list. A query picks the `n_probe`
+//! cells with the nearest centers and scans their posting lists with the point cloud's batch distance kernels.
+
+use crate::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+
+/// A cell of the index, a node of the tree and every point it covers.
+#[derive(Debug, Clone)]
+pub struct IvfCell {
+    /// The node of the tree this cell was cut from.
+    pub address: NodeAddress,
+    /// All points that the node covers, including the center.
+    pub postings: Vec<PointIndex>,
+}
+
+/// A flat index over a cut of the cover tree.
+#[derive(Debug)]
+pub struct IvfIndex<D: PointCloud> {
+    point_cloud: Arc<D>,
+    cells: Vec<IvfCell>,
+    centers: Vec<PointIndex>,
+}
+
+impl<D: PointCloud> IvfIndex<D> {
+    /// Cuts the tree into at least `target_cells` cells, if it has enough nodes. The node with the largest coverage is split
+    /// into its children until we reach the target. The singletons of a split node go to the cell of its nested child.
+    pub fn new(reader: &CoverTreeReader<D>, target_cells: usize) -> GokoResult<IvfIndex<D>> {
+        let coverage = |addr: NodeAddress| reader.get_node_and(addr, |n| n.coverage_count());
+        let mut splittable: BinaryHeap<(usize, NodeAddress)> = BinaryHeap::new();
+        let mut leaves = Vec::new();
+        let mut carried: HashMap<NodeAddress, Vec<PointIndex>> = HashMap::new();
+        let root = reader.root_address();
+        splittable.push((coverage(root)?, root));
+
+        while splittable.len() + leaves.len() < target_cells {
+            let (count, addr) = match splittable.pop() {
+                Some(top) => top,
+                None => break,
+            };
+            let split = reader.get_node_and(addr, |n| {
+                n.children().map(|(nested_scale, children)| {
+                    let mut addrs = vec![(nested_scale, addr.1)];
+                    addrs.extend(children);
+                    (addrs, n.singletons().to_vec())
+                })
+            })?;
+            match split {
+                Some((addrs, singletons)) => {
+                    let mut orphans = carried.remove(&addr).unwrap_or_default();
+                    orphans.extend(singletons);
+                    carried.insert(addrs[0], orphans);
+                    for child in addrs {
+                        let child_count = coverage(child)?;
+                        splittable.push((child_count, child));
+                    }
+                }
+                None => leaves.push((count, addr)),
+            }
+        }
+
+        let cells = splittable
+            .into_iter()
+            .chain(leaves)
+            .map(|(_, address)| {
+                let mut postings = reader.covered_indexes(address)?;
+                postings.extend(carried.remove(&address).unwrap_or_default());
+                Ok(IvfCell { address, postings })
+            })
+            .collect::<GokoResult<Vec<IvfCell>>>()?;
+        let centers = cells.iter().map(|c| c.address.1).collect();
+        Ok(IvfIndex {
+            point_cloud: Arc::clone(reader.point_cloud()),
+            cells,
+            centers,
+        })
+    }
+
+    /// The cells of the index.
+    pub fn cells(&self) -> &[IvfCell] {
+        &self.cells
+    }
+
+    /// The number of cells in the index.
+    pub fn num_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// The `k` nearest neighbors of the point among the posting lists of the `n_probe` cells with the nearest centers.
+    /// This is exact if `n_probe` is the number of cells.
+    pub fn knn<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+        n_probe: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point: PointRef<'a> = point.into();
+        let center_dists = self.point_cloud.distances_to_point(point, &self.centers)?;
+        let mut probes: Vec<(f32, usize)> = center_dists
+            .into_iter()
+            .enumerate()
+            .map(|(i, d)| (d, i))
+            .collect();
+        probes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let mut results: Vec<(f32, PointIndex)> = Vec::new();
+        for (_, ci) in probes.iter().take(n_probe) {
+            let postings = &self.cells[*ci].postings;
+            let dists = self.point_cloud.distances_to_point(point, postings)?;
+            results.extend(dists.into_iter().zip(postings.iter().copied()));
+        }
+        results.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        results.truncate(k);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn ivf_exact_with_all_probes() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let ivf = IvfIndex::new(&reader, 3).unwrap();
+        assert!(ivf.num_cells() >= 2);
+        let total: usize = ivf.cells().iter().map(|c| c.postings.len()).sum();
+        assert_eq!(total, reader.point_cloud().len());
+
+        let point = [0.495f32];
+        let exact = reader.knn(&point[..], 3).unwrap();
+        let approx = ivf.knn(&point[..], 3, ivf.num_cells()).unwrap();
+        assert_eq!(exact.len(), approx.len());
+        for ((d1, _), (d2, _)) in exact.iter().zip(approx.iter()) {
+            assert_approx_eq!(d1, d2);
+        }
+    }
+}
@@ -59,6 +59,7 @@ use rayon::prelude::*;
 extern crate assert_approx_eq;
 
 use pointcloud::*;
+pub mod analysis;
 pub mod errors;
 pub use errors::GokoResult;
 
@@ -67,10 +68,18 @@ pub(crate) mod monomap;
 mod covertree;
 pub use covertree::*;
 
+pub mod clustering;
+pub mod export;
+pub mod ivf;
+pub mod model_selection;
 pub mod query_interface;
+pub mod runtime;
+pub use runtime::GokoRuntime;
+pub mod snapshots;
 
 mod tree_file_format;
 pub mod utils;
+pub mod walks;
 
 pub mod plugins;
 
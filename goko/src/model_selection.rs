@@ -0,0 +1,310 @@
+//! # Model Selection
+//!
+//! Tools for picking the parameters of a tree for a downstream task. `grid_search` builds the candidate trees on a
+//! sample of your data, a few thousand points is usually enough to rank the parameters, then you rebuild the winner on
+//! the full dataset.
+
+use crate::*;
+use pointcloud::pc_errors::PointCloudResult;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A cloud whose `reference_indexes` are a random sample of another cloud's. Every point of the original is still
+/// reachable, so a tree built on it covers only the sample but can be queried with any of the original's points.
+#[derive(Debug)]
+pub struct SampledCloud<D: PointCloud> {
+    point_cloud: Arc<D>,
+    sample: Vec<PointIndex>,
+}
+
+impl<D: PointCloud> SampledCloud<D> {
+    /// Picks `sample_size` of the cloud's points, or all of them if there are fewer. The same seed gives the same
+    /// sample.
+    pub fn new(point_cloud: Arc<D>, sample_size: usize, seed: u64) -> SampledCloud<D> {
+        let mut sample = point_cloud.reference_indexes();
+        if sample_size < sample.len() {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut picked: Vec<PointIndex> =
+                rand::seq::index::sample(&mut rng, sample.len(), sample_size)
+                    .into_iter()
+                    .map(|i| sample[i])
+                    .collect();
+            picked.sort_unstable();
+            sample = picked;
+        }
+        SampledCloud {
+            point_cloud,
+            sample,
+        }
+    }
+
+    /// The sampled indexes, in the order of the original's `reference_indexes`.
+    pub fn sample(&self) -> &[PointIndex] {
+        &self.sample
+    }
+}
+
+impl<D: PointCloud> PointCloud for SampledCloud<D> {
+    type Metric = D::Metric;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.point_cloud.dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.point_cloud.len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.sample.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.sample.clone()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.point_cloud.point(i)
+    }
+    #[inline]
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        self.point_cloud.prefetch(indexes)
+    }
+}
+
+impl<D: LabeledCloud> LabeledCloud for SampledCloud<D> {
+    type Label = D::Label;
+    type LabelSummary = D::LabelSummary;
+
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Label>> {
+        self.point_cloud.label(pn)
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        self.point_cloud.label_summary(pns)
+    }
+}
+
+/// A task that we can score a tree against. Higher scores are better.
+pub trait SelectionTask<D: PointCloud>: Sync {
+    /// Scores the tree on the task.
+    fn score(&self, reader: &CoverTreeReader<D>) -> GokoResult<f32>;
+}
+
+/// The fraction of the true `k` nearest neighbors that `routing_knn` recovers, averaged over the query points.
+#[derive(Debug, Clone)]
+pub struct RecallAtK {
+    /// The number of neighbors to query for.
+    pub k: usize,
+    /// The points to use as queries.
+    pub query_indexes: Vec<PointIndex>,
+}
+
+impl<D: PointCloud> SelectionTask<D> for RecallAtK {
+    fn score(&self, reader: &CoverTreeReader<D>) -> GokoResult<f32> {
+        let k = self.k;
+        let recalls = self
+            .query_indexes
+            .par_iter()
+            .map_with(reader.clone(), |reader, qi| -> GokoResult<f32> {
+                let point = reader.point_cloud().point(*qi)?;
+                let exact = reader.knn(point, k)?;
+                let approx = reader.routing_knn(point, k)?;
+                let found = approx
+                    .iter()
+                    .filter(|(_, pi)| exact.iter().any(|(_, ei)| ei == pi))
+                    .count();
+                Ok(found as f32 / exact.len().max(1) as f32)
+            })
+            .collect::<GokoResult<Vec<f32>>>()?;
+        Ok(mean(&recalls))
+    }
+}
+
+/// Leave-one-out accuracy of a majority vote of the `k` nearest neighbors, averaged over the query points.
+#[derive(Debug, Clone)]
+pub struct KnnClassification {
+    /// The number of neighbors that vote.
+    pub k: usize,
+    /// The points to classify.
+    pub query_indexes: Vec<PointIndex>,
+}
+
+impl<D: PointCloud + LabeledCloud> SelectionTask<D> for KnnClassification
+where
+    D::Label: PartialEq,
+{
+    fn score(&self, reader: &CoverTreeReader<D>) -> GokoResult<f32> {
+        let k = self.k;
+        let correct = self
+            .query_indexes
+            .par_iter()
+            .map_with(reader.clone(), |reader, qi| -> GokoResult<f32> {
+                let cloud = reader.point_cloud();
+                let truth = match cloud.label(*qi)? {
+                    Some(label) => label,
+                    None => return Ok(0.0),
+                };
+                let point = cloud.point(*qi)?;
+                let mut labels: Vec<&D::Label> = Vec::with_capacity(k);
+                for (_, pi) in reader.knn(point, k + 1)? {
+                    if pi != *qi && labels.len() < k {
+                        if let Some(label) = cloud.label(pi)? {
+                            labels.push(label);
+                        }
+                    }
+                }
+                let votes = |l: &D::Label| labels.iter().filter(|o| **o == l).count();
+                let winner = labels.iter().max_by_key(|l| votes(*l));
+                match winner {
+                    Some(winner) if *winner == truth => Ok(1.0),
+                    _ => Ok(0.0),
+                }
+            })
+            .collect::<GokoResult<Vec<f32>>>()?;
+        Ok(mean(&correct))
+    }
+}
+
+fn mean(vals: &[f32]) -> f32 {
+    if vals.is_empty() {
+        0.0
+    } else {
+        vals.iter().sum::<f32>() / vals.len() as f32
+    }
+}
+
+/// One row of the report produced by `grid_search`.
+#[derive(Debug, Clone)]
+pub struct GridSearchResult {
+    /// The parameters the tree was built with.
+    pub builder: CoverTreeBuilder,
+    /// The task's score for the tree.
+    pub score: f32,
+    /// The number of nodes in the tree.
+    pub node_count: usize,
+    /// How long the build took.
+    pub build_time: Duration,
+}
+
+/// Builds a tree for each candidate set of parameters on the same `sample_size` points of the cloud, and scores it on
+/// the task. The builds run one after another, each already spreads its splits over rayon's pool and waits on them,
+/// so the build times aren't skewed by the other candidates. The built candidates are then scored concurrently on
+/// rayon's pool. The task's queries may be any of the cloud's points, including ones outside the sample. Returns the
+/// results with the best score first.
+pub fn grid_search<D: PointCloud, T: SelectionTask<SampledCloud<D>>>(
+    config_grid: &[CoverTreeBuilder],
+    task: &T,
+    point_cloud: Arc<D>,
+    sample_size: usize,
+) -> GokoResult<Vec<GridSearchResult>> {
+    let sample = Arc::new(SampledCloud::new(point_cloud, sample_size, 0));
+    // The readers only see the trees while their writers are alive
+    let mut writers = Vec::with_capacity(config_grid.len());
+    let mut built = Vec::with_capacity(config_grid.len());
+    for builder in config_grid {
+        let now = Instant::now();
+        let tree = builder.build(Arc::clone(&sample))?;
+        built.push((builder, tree.reader(), now.elapsed()));
+        writers.push(tree);
+    }
+    let mut results = built
+        .into_par_iter()
+        .map(
+            |(builder, reader, build_time)| -> GokoResult<GridSearchResult> {
+                Ok(GridSearchResult {
+                    builder: builder.clone(),
+                    score: task.score(&reader)?,
+                    node_count: reader.node_count(),
+                    build_time,
+                })
+            },
+        )
+        .collect::<GokoResult<Vec<GridSearchResult>>>()?;
+    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_search_ranks() {
+        let data = vec![0.499, 0.49, 0.48, -0.49, 0.0, -0.48];
+        let labels = vec![0, 0, 0, 1, 1, 1];
+        let point_cloud = Arc::new(DefaultLabeledCloud::<L2>::new_simple(data, 1, labels));
+
+        let mut coarse = CoverTreeBuilder::new();
+        coarse.set_scale_base(4.0).set_leaf_cutoff(3);
+        let fine = CoverTreeBuilder::new();
+
+        let task = KnnClassification {
+            k: 1,
+            query_indexes: (0..6).collect(),
+        };
+        let results = grid_search(&[coarse, fine], &task, Arc::clone(&point_cloud), 6).unwrap();
+        println!("{:#?}", results);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].score >= results[1].score);
+
+        let task = RecallAtK {
+            k: 2,
+            query_indexes: (0..6).collect(),
+        };
+        let results = grid_search(
+            &[CoverTreeBuilder::new()],
+            &task,
+            Arc::clone(&point_cloud),
+            6,
+        )
+        .unwrap();
+        assert!(results[0].score > 0.0 && results[0].score <= 1.0);
+
+        let results = grid_search(
+            &[CoverTreeBuilder::new()],
+            &task,
+            Arc::clone(&point_cloud),
+            4,
+        )
+        .unwrap();
+        assert!(results[0].score > 0.0 && results[0].score <= 1.0);
+    }
+
+    #[test]
+    fn sampled_cloud() {
+        let data: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 1).unwrap());
+        let sample = Arc::new(SampledCloud::new(Arc::clone(&point_cloud), 10, 0));
+        assert_eq!(sample.sample().len(), 10);
+        assert!(sample.sample().windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            sample.sample(),
+            SampledCloud::new(Arc::clone(&point_cloud), 10, 0).sample()
+        );
+        assert_eq!(
+            SampledCloud::new(Arc::clone(&point_cloud), 200, 0)
+                .sample()
+                .len(),
+            100
+        );
+
+        let tree = CoverTreeBuilder::new().build(Arc::clone(&sample)).unwrap();
+        let reader = tree.reader();
+        let root_coverage = reader
+            .get_node_and(reader.root_address(), |n| n.coverage_count())
+            .unwrap();
+        assert_eq!(root_coverage, 10);
+        // Points outside the sample can still be queried, and only find sampled neighbors
+        let outside = (0..100).find(|i| !sample.sample().contains(i)).unwrap();
+        for (_, pi) in reader.knn_by_index(outside, 3).unwrap() {
+            assert!(sample.sample().contains(&pi));
+        }
+    }
+}
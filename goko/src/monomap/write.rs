@@ -148,6 +148,12 @@ where
     /// the operational log onto the stale map copy the readers used to use. This can take some
     /// time, especially if readers are executing slow operations, or if there are many of them.
     pub fn refresh(&mut self) -> &mut Self {
+        self.refresh_some(usize::MAX)
+    }
+
+    /// Refresh the handle used by readers so that the oldest `count` pending writes are made visible. The rest stay
+    /// pending, in order, for a later refresh. This bounds the work each swap does, at the cost of more swaps.
+    pub fn refresh_some(&mut self, count: usize) -> &mut Self {
         // we need to wait until all epochs have changed since the swaps *or* until a "finished"
         // flag has been observed to be on for two subsequent iterations (there still may be some
         // readers present since we did the previous refresh)
@@ -208,12 +214,14 @@ where
                     Self::apply_second(w_handle, op);
                 }
             }
-            // the rest have to be cloned because they'll also be needed by the r_handle map
-            for op in self.oplog.iter_mut() {
+            // the next `count` have to be cloned because they'll also be needed by the r_handle map. any after
+            // those are in neither map, so they stay at the end of the oplog for a later refresh.
+            let published = self.oplog.len().min(count);
+            for op in self.oplog[..published].iter_mut() {
                 Self::apply_first(w_handle, op);
             }
-            // the w_handle map is about to become the r_handle, and can ignore the oplog
-            self.swap_index = self.oplog.len();
+            // the w_handle map is about to become the r_handle, and can ignore the published part of the oplog
+            self.swap_index = published;
             // ensure meta-information is up to date
             w_handle.meta = self.meta.clone();
             w_handle.mark_ready();
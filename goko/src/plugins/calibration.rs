@@ -0,0 +1,185 @@
+//! # Distance Calibration
+//!
+//! A single distance threshold means different things in dense and sparse parts of the space. This plugin fits a monotone
+//! map from distance to a similarity in `[0, 1]` at each node, from the distances between the node's center and the children
+//! and singletons it covers. A similarity of 0.5 means the distance is about the median spread of the node.
+
+use super::*;
+use crate::errors::GokoError;
+use std::cmp::Ordering;
+
+/// The fitted map of a node, the distances at evenly spaced quantiles of the local distance distribution.
+#[derive(Debug, Clone)]
+pub struct DistanceCalibration {
+    knots: Vec<f32>,
+    radius: f32,
+}
+
+impl<D: PointCloud> NodePlugin<D> for DistanceCalibration {}
+
+impl DistanceCalibration {
+    /// The distances at the quantiles, in increasing order.
+    pub fn knots(&self) -> &[f32] {
+        &self.knots
+    }
+
+    /// One minus the interpolated quantile of the distance, so 1 for the closest points and 0 beyond the farthest. Nodes
+    /// without enough local distances fall back to a linear map over the radius.
+    pub fn similarity(&self, distance: f32) -> f32 {
+        if self.knots.len() < 2 {
+            return if self.radius > 0.0 {
+                (1.0 - distance / self.radius).clamp(0.0, 1.0)
+            } else if distance <= 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let last = self.knots.len() - 1;
+        if distance <= self.knots[0] {
+            return 1.0;
+        }
+        if distance >= self.knots[last] {
+            return 0.0;
+        }
+        let i = self.knots.iter().rposition(|k| *k <= distance).unwrap_or(0);
+        let width = self.knots[i + 1] - self.knots[i];
+        let frac = if width > 0.0 {
+            (distance - self.knots[i]) / width
+        } else {
+            0.0
+        };
+        1.0 - (i as f32 + frac) / last as f32
+    }
+}
+
+/// Tree component of the calibration plugin, sets how many quantiles each node keeps.
+#[derive(Debug, Clone)]
+pub struct GokoDistanceCalibration {
+    /// The number of knots of each node's map, at least 2
+    pub quantiles: usize,
+}
+
+impl Default for GokoDistanceCalibration {
+    fn default() -> Self {
+        GokoDistanceCalibration { quantiles: 9 }
+    }
+}
+
+impl<D: PointCloud> TreePlugin<D> for GokoDistanceCalibration {}
+
+impl<D: PointCloud> GokoPlugin<D> for GokoDistanceCalibration {
+    type NodeComponent = DistanceCalibration;
+    type TreeComponent = GokoDistanceCalibration;
+    fn node_component(
+        parameters: &Self::TreeComponent,
+        my_node: &CoverNode<D>,
+        my_tree: &CoverTreeReader<D>,
+    ) -> GokoResult<Option<Self::NodeComponent>> {
+        let mut local: Vec<PointIndex> = my_node.singletons().to_vec();
+        if let Some((_, child_addresses)) = my_node.children() {
+            local.extend(child_addresses.iter().map(|(_, pi)| *pi));
+        }
+        let mut dists = my_tree
+            .point_cloud()
+            .distances_to_point_index(*my_node.center_index(), &local)?;
+        dists.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let quantiles = parameters.quantiles.max(2);
+        let knots = if dists.len() < 2 {
+            Vec::new()
+        } else {
+            (0..quantiles)
+                .map(|q| {
+                    let pos = (q * (dists.len() - 1)) as f32 / (quantiles - 1) as f32;
+                    let lo = pos.floor() as usize;
+                    let hi = pos.ceil() as usize;
+                    dists[lo] + (pos - lo as f32) * (dists[hi] - dists[lo])
+                })
+                .collect()
+        };
+        Ok(Some(DistanceCalibration {
+            knots,
+            radius: my_node.radius(),
+        }))
+    }
+}
+
+/// The `k` nearest neighbors of the point with a calibrated similarity, as `(similarity, distance, index)`. The similarity
+/// comes from the smallest node on the query's path whose radius covers the farthest neighbor. Requires the
+/// `GokoDistanceCalibration` plugin, errors with `PluginNotInstalled` if no node on the path has it.
+pub fn calibrated_knn<'a, D: PointCloud, T: Into<PointRef<'a>>>(
+    reader: &CoverTreeReader<D>,
+    point: T,
+    k: usize,
+) -> GokoResult<Vec<(f32, f32, PointIndex)>> {
+    let point: PointRef<'a> = point.into();
+    let knn = reader.knn(point, k)?;
+    let farthest = knn.last().map(|(d, _)| *d).unwrap_or(0.0);
+    let path = reader.path(point)?;
+    let calibration = path
+        .iter()
+        .rev()
+        .filter(|(_, addr)| {
+            reader
+                .get_node_and(*addr, |n| n.radius() >= farthest)
+                .unwrap_or(false)
+        })
+        .find_map(|(_, addr)| {
+            reader
+                .get_node_plugin_and::<DistanceCalibration, _, _>(*addr, |c| c.clone())
+                .ok()
+                .flatten()
+        })
+        .ok_or(GokoError::PluginNotInstalled("GokoDistanceCalibration"))?;
+    Ok(knn
+        .into_iter()
+        .map(|(d, pi)| (calibration.similarity(d), d, pi))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn similarity_is_monotone() {
+        let calibration = DistanceCalibration {
+            knots: vec![0.1, 0.2, 0.2, 0.5],
+            radius: 1.0,
+        };
+        assert_approx_eq!(calibration.similarity(0.0), 1.0);
+        assert_approx_eq!(calibration.similarity(1.0), 0.0);
+        let mut last = 1.0;
+        for i in 0..100 {
+            let s = calibration.similarity(i as f32 / 100.0);
+            assert!(s <= last);
+            last = s;
+        }
+    }
+
+    #[test]
+    fn calibrated_knn_sanity() {
+        let mut tree = build_basic_tree();
+        match calibrated_knn(&tree.reader(), &[0.495f32][..], 3) {
+            Err(GokoError::PluginNotInstalled(name)) => assert_eq!(name, "GokoDistanceCalibration"),
+            other => panic!("expected a missing plugin, got {:?}", other),
+        }
+        tree.add_plugin::<GokoDistanceCalibration>(GokoDistanceCalibration::default())
+            .unwrap();
+        let reader = tree.reader();
+        let point = [0.495f32];
+        let knn = reader.knn(&point[..], 3).unwrap();
+        let calibrated = calibrated_knn(&reader, &point[..], 3).unwrap();
+        assert_eq!(knn.len(), calibrated.len());
+        for ((d, pi), (s, cd, cpi)) in knn.iter().zip(calibrated.iter()) {
+            assert_eq!(pi, cpi);
+            assert_approx_eq!(d, cd);
+            assert!(*s >= 0.0 && *s <= 1.0);
+        }
+        for pair in calibrated.windows(2) {
+            assert!(pair[0].0 >= pair[1].0);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! # Query Distance Quantiles
+//!
+//! Tracks a streaming quantile of the query-to-center distance for each node that queries pass through. The distances are
+//! divided by the node's radius, so a value near 1 means the queries are arriving at the edge of the node's coverage.
+//! This tends to show up before the KL divergence of the child distributions moves.
+
+use crate::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Streaming estimate of a single quantile with the P² algorithm of Jain and Chlamtac. Uses constant memory.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Creates an estimator for the `p` quantile, `p` should be between 0 and 1.
+    pub fn new(p: f64) -> P2Quantile {
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    /// The number of observations so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Adds an observation.
+    pub fn add(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights
+                    .sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            }
+            return;
+        }
+        self.count += 1;
+
+        let k = if x < self.heights[0] {
+            self.heights[0] = x;
+            0
+        } else if x >= self.heights[4] {
+            self.heights[4] = x;
+            3
+        } else {
+            (1..5).find(|i| x < self.heights[*i]).unwrap_or(4) - 1
+        };
+        for position in self.positions[(k + 1)..].iter_mut() {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments.iter()) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            if (d >= 1.0 && self.positions[i + 1] - self.positions[i] > 1.0)
+                || (d <= -1.0 && self.positions[i - 1] - self.positions[i] < -1.0)
+            {
+                let s = d.signum();
+                let candidate = self.parabolic(i, s);
+                if self.heights[i - 1] < candidate && candidate < self.heights[i + 1] {
+                    self.heights[i] = candidate;
+                } else {
+                    self.heights[i] = self.linear(i, s);
+                }
+                self.positions[i] += s;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, s: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + s / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + s) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - s) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, s: f64) -> f64 {
+        let j = if s > 0.0 { i + 1 } else { i - 1 };
+        self.heights[i]
+            + s * (self.heights[j] - self.heights[i]) / (self.positions[j] - self.positions[i])
+    }
+
+    /// The current estimate of the quantile, None if there are no observations.
+    pub fn quantile(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            c if c < 5 => {
+                let mut seen = self.heights[..c].to_vec();
+                seen.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                Some(seen[(self.p * (c - 1) as f64).round() as usize])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+/// Tracks the quantile of the radius-normalized query distance for each node the queries' paths visit.
+pub struct DistanceQuantileTracker<D: PointCloud> {
+    p: f64,
+    estimators: HashMap<NodeAddress, P2Quantile>,
+    reader: CoverTreeReader<D>,
+}
+
+impl<D: PointCloud> fmt::Debug for DistanceQuantileTracker<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DistanceQuantileTracker {{ p: {}, estimators: {:?}}}",
+            self.p, self.estimators,
+        )
+    }
+}
+
+impl<D: PointCloud> DistanceQuantileTracker<D> {
+    /// Creates a tracker for the `p` quantile, 0.9 is a good default.
+    pub fn new(p: f64, reader: CoverTreeReader<D>) -> DistanceQuantileTracker<D> {
+        DistanceQuantileTracker {
+            p,
+            estimators: HashMap::new(),
+            reader,
+        }
+    }
+
+    /// Adds the path of a query, as returned by `CoverTreeReader::path`.
+    pub fn add_path(&mut self, path: &[(f32, NodeAddress)]) {
+        for (dist, address) in path {
+            if let Ok(radius) = self.reader.get_node_and(*address, |n| n.radius()) {
+                if radius > 0.0 {
+                    let p = self.p;
+                    self.estimators
+                        .entry(*address)
+                        .or_insert_with(|| P2Quantile::new(p))
+                        .add((*dist / radius) as f64);
+                }
+            }
+        }
+    }
+
+    /// The quantile of the normalized query distance for a node, if any queries have visited it.
+    pub fn node_quantile(&self, address: NodeAddress) -> Option<f64> {
+        self.estimators.get(&address).and_then(|e| e.quantile())
+    }
+
+    /// Nodes whose quantile is at least `threshold`, with at least `min_count` queries. Largest first.
+    pub fn edge_nodes(&self, threshold: f64, min_count: usize) -> Vec<(f64, NodeAddress)> {
+        let mut nodes: Vec<(f64, NodeAddress)> = self
+            .estimators
+            .iter()
+            .filter(|(_, e)| e.count() >= min_count)
+            .filter_map(|(address, e)| e.quantile().map(|q| (q, *address)))
+            .filter(|(q, _)| *q >= threshold)
+            .collect();
+        nodes.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn p2_uniform() {
+        let mut est = P2Quantile::new(0.9);
+        assert_eq!(est.quantile(), None);
+        for i in 0..1000 {
+            // A fixed permutation of 0..1000
+            est.add(((i * 337) % 1000) as f64);
+        }
+        let q = est.quantile().unwrap();
+        assert!((q - 900.0).abs() < 20.0, "{}", q);
+    }
+
+    #[test]
+    fn tracker_sanity() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let mut tracker = DistanceQuantileTracker::new(0.9, tree.reader());
+        for _ in 0..10 {
+            tracker.add_path(&reader.path(&[0.49f32][..]).unwrap());
+        }
+        let root = reader.root_address();
+        assert!(tracker.node_quantile(root).is_some());
+        let edge = tracker.edge_nodes(0.0, 10);
+        assert!(edge.iter().any(|(_, a)| *a == root));
+        assert!(tracker.edge_nodes(0.0, 11).is_empty());
+    }
+}
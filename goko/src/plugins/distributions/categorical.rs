@@ -171,7 +171,7 @@ impl<D: PointCloud> GokoPlugin<D> for GokoCategorical {
         _parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         let mut bucket = Categorical::new();
 
         // If we're a routing node then grab the childen's values
@@ -184,17 +184,17 @@ impl<D: PointCloud> GokoPlugin<D> for GokoCategorical {
                         p.total() as f64,
                     );
                 },
-            );
+            )?;
             for ca in child_addresses {
                 my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                     bucket.add_child_pop(Some(*ca), p.total() as f64);
-                });
+                })?;
             }
             bucket.add_child_pop(None, my_node.singletons_len() as f64);
         } else {
             bucket.add_child_pop(None, my_node.singletons_len() as f64 + 1.0);
         }
-        Some(bucket)
+        Ok(Some(bucket))
     }
 }
 
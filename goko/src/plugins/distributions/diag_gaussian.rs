@@ -136,7 +136,7 @@ impl DiagGaussian {
                 .iter_mut()
                 .zip(point.dense_iter(dim))
                 .for_each(|(m, p)| *m -= p * p);
-            self.count += 1;
+            self.count -= 1;
         }
     }
 
@@ -173,6 +173,35 @@ impl DiagGaussian {
     pub fn count(&self) -> usize {
         self.count
     }
+
+    /// The Mahalanobis distance of the point from the mean, under this diagonal covariance. A coordinate with no
+    /// variance adds nothing if the point matches the mean there, and makes the distance infinite if it doesn't.
+    /// `None` if no points were added.
+    pub fn mahalanobis(&self, point: &PointRef) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let mean_vars = internal_mean!(self.moment1, self.count).zip(internal_var!(
+            self.moment1,
+            self.moment2,
+            self.count
+        ));
+        let squared: f64 = point
+            .dense_iter(self.dim())
+            .zip(mean_vars)
+            .map(|(xi, (ui, vi))| {
+                let diff = (xi - ui) as f64;
+                if vi > 0.0 {
+                    diff * diff / vi as f64
+                } else if diff == 0.0 {
+                    0.0
+                } else {
+                    f64::INFINITY
+                }
+            })
+            .sum();
+        Some(squared.sqrt())
+    }
 }
 
 impl<D: PointCloud> NodePlugin<D> for DiagGaussian {}
@@ -207,17 +236,15 @@ impl<D: PointCloud> GokoPlugin<D> for GokoDiagGaussian {
         parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         let moment1 = my_tree
             .parameters()
             .point_cloud
-            .moment_subset(1, my_node.singletons())
-            .unwrap();
+            .moment_subset(1, my_node.singletons())?;
         let moment2 = my_tree
             .parameters()
             .point_cloud
-            .moment_subset(2, my_node.singletons())
-            .unwrap();
+            .moment_subset(2, my_node.singletons())?;
         let count = my_node.singletons_len();
         let mut my_dg = DiagGaussian {
             moment1,
@@ -232,11 +259,11 @@ impl<D: PointCloud> GokoPlugin<D> for GokoDiagGaussian {
                     |p| {
                         my_dg.merge(p);
                     },
-                );
+                )?;
                 for ca in child_addresses {
                     my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                         my_dg.merge(p);
-                    });
+                    })?;
                 }
             }
         } else {
@@ -244,11 +271,10 @@ impl<D: PointCloud> GokoPlugin<D> for GokoDiagGaussian {
                 &my_tree
                     .parameters()
                     .point_cloud
-                    .point(*my_node.center_index())
-                    .unwrap(),
+                    .point(*my_node.center_index())?,
             );
         }
-        Some(my_dg)
+        Ok(Some(my_dg))
     }
 }
 
@@ -264,7 +290,8 @@ pub(crate) mod tests {
         let moment2 = basic_tree_data.iter().fold(0.0, |a, x| a + x * x);
         let count = basic_tree_data.len();
         let mut tree = build_basic_tree();
-        tree.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive());
+        tree.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive())
+            .unwrap();
         println!("{:?}", tree.reader().len());
         let reader = tree.reader();
 
@@ -332,25 +359,28 @@ pub(crate) mod tests {
             });
         }
 
-        reader.get_node_plugin_and::<DiagGaussian, _, _>(reader.root_address(), |p| {
-            println!(
-                "First moment, expected: {:?}, calculated: {:?}",
-                moment1, p.moment1[0]
-            );
-            assert_approx_eq!(moment1, p.moment1[0]);
-            println!(
-                "Second moment, expected: {:?}, calculated: {:?}",
-                moment2, p.moment2[0]
-            );
-            assert_approx_eq!(moment2, p.moment2[0]);
-            assert_eq!(count, p.count);
-        });
+        reader
+            .get_node_plugin_and::<DiagGaussian, _, _>(reader.root_address(), |p| {
+                println!(
+                    "First moment, expected: {:?}, calculated: {:?}",
+                    moment1, p.moment1[0]
+                );
+                assert_approx_eq!(moment1, p.moment1[0]);
+                println!(
+                    "Second moment, expected: {:?}, calculated: {:?}",
+                    moment2, p.moment2[0]
+                );
+                assert_approx_eq!(moment2, p.moment2[0]);
+                assert_eq!(count, p.count);
+            })
+            .unwrap();
     }
 
     #[test]
     fn diag_gaussian_sanity_check() {
         let mut ct = build_basic_tree();
-        ct.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive());
+        ct.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive())
+            .unwrap();
         let ct_reader = ct.reader();
         let mut untested_addresses = vec![ct_reader.root_address()];
         while let Some(addr) = untested_addresses.pop() {
@@ -364,15 +394,39 @@ pub(crate) mod tests {
                     });
                     p.count
                 })
+                .unwrap()
+                .unwrap();
+            ct_reader
+                .get_node_and(addr, |n| {
+                    assert_eq!(n.coverage_count(), count, "Node: {:?}", n)
+                })
                 .unwrap();
-            ct_reader.get_node_and(addr, |n| {
-                assert_eq!(n.coverage_count(), count, "Node: {:?}", n)
-            });
 
-            ct_reader.get_node_children_and(addr, |covered, children| {
-                untested_addresses.push(covered);
-                untested_addresses.extend(children);
-            });
+            ct_reader
+                .get_node_children_and(addr, |covered, children| {
+                    untested_addresses.push(covered);
+                    untested_addresses.extend(children);
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn mahalanobis_and_removal() {
+        let mut dg = DiagGaussian::new(2);
+        assert_eq!(dg.mahalanobis(&PointRef::Dense(&[0.0, 0.0])), None);
+        for p in &[[1.0f32, 5.0], [3.0, 5.0]] {
+            dg.add_point(&PointRef::Dense(p));
         }
+        // Mean (2, 5), variance (1, 0)
+        assert_approx_eq!(dg.mahalanobis(&PointRef::Dense(&[4.0, 5.0])).unwrap(), 2.0);
+        assert_eq!(
+            dg.mahalanobis(&PointRef::Dense(&[2.0, 6.0])),
+            Some(f64::INFINITY)
+        );
+        dg.add_point(&PointRef::Dense(&[10.0, 10.0]));
+        dg.remove_point(&PointRef::Dense(&[10.0, 10.0]));
+        assert_eq!(dg.count(), 2);
+        assert_approx_eq!(dg.mean()[0], 2.0);
     }
 }
@@ -237,7 +237,7 @@ impl<D: PointCloud> GokoPlugin<D> for GokoDirichlet {
         _parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         let mut bucket = Dirichlet::new();
 
         // If we're a routing node then grab the childen's values
@@ -247,17 +247,17 @@ impl<D: PointCloud> GokoPlugin<D> for GokoDirichlet {
                 |p| {
                     bucket.add_child_pop(Some((nested_scale, *my_node.center_index())), p.total());
                 },
-            );
+            )?;
             for ca in child_addresses {
                 my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                     bucket.add_child_pop(Some(*ca), p.total());
-                });
+                })?;
             }
             bucket.add_child_pop(None, my_node.singletons_len() as f64);
         } else {
             bucket.add_child_pop(None, (my_node.singletons_len() + 1) as f64);
         }
-        Some(bucket)
+        Ok(Some(bucket))
     }
 }
 
@@ -318,6 +318,7 @@ impl<D: PointCloud> BayesCategoricalTracker<D> {
         let mut prob = self
             .reader
             .get_node_plugin_and::<Dirichlet, _, _>(address, |p| p.clone())
+            .unwrap()
             .unwrap();
         let total = prob.total();
         if total > self.window_size as f64 {
@@ -369,6 +370,8 @@ impl<D: PointCloud> BayesCategoricalTracker<D> {
                 }
                 dir.prob_vector()
             })
+            .ok()
+            .flatten()
             .flatten()
     }
 
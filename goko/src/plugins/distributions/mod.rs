@@ -86,6 +86,7 @@ pub trait DiscreteBayesianSequenceTracker<D: PointCloud>: Debug {
                     .get_node_plugin_and::<Self::Distribution, _, _>(*address, |p| {
                         p.posterior_kl_divergence(sequence_pdf).unwrap()
                     })
+                    .unwrap()
                     .unwrap();
                 (kl, *address)
             })
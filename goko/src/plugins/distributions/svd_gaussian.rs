@@ -7,6 +7,7 @@
 use super::*;
 use crate::covertree::node::CoverNode;
 use crate::covertree::CoverTreeReader;
+use crate::errors::GokoError;
 use crate::plugins::utils::*;
 
 use ndarray::prelude::*;
@@ -73,47 +74,49 @@ impl GokoSvdGaussian {
 impl<D: PointCloud> GokoPlugin<D> for GokoSvdGaussian {
     type NodeComponent = SvdGaussian;
     type TreeComponent = SvdGaussianTree;
-    fn prepare_tree(parameters: &Self::TreeComponent, my_tree: &mut CoverTreeWriter<D>) {
+    fn prepare_tree(
+        parameters: &Self::TreeComponent,
+        my_tree: &mut CoverTreeWriter<D>,
+    ) -> GokoResult<()> {
         my_tree.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::restricted(
             parameters.max_points,
-        ));
-        my_tree.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive());
+        ))?;
+        my_tree.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive())
     }
     fn node_component(
         parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         if my_node.coverage_count() > parameters.min_points {
-            let points = my_node.get_plugin_and::<CoverageIndexes, _, _>(|p| {
-                my_tree
-                    .parameters()
-                    .point_cloud
-                    .points_dense_matrix(p.point_indexes())
-                    .unwrap()
-            });
+            let points = my_node
+                .get_plugin_and::<CoverageIndexes, _, _>(|p| {
+                    my_tree
+                        .parameters()
+                        .point_cloud
+                        .points_dense_matrix(p.point_indexes())
+                })
+                .transpose()?;
             if let Some(mut points) = points {
                 let mean = my_node
-                    .get_plugin_and::<DiagGaussian, _, _>(|p| {
-                        Array1::from_shape_vec((p.dim(),), p.mean()).unwrap()
-                    })
-                    .unwrap();
+                    .get_plugin_and::<DiagGaussian, _, _>(|p| Array1::from(p.mean()))
+                    .ok_or(GokoError::PluginNotInstalled("GokoDiagGaussian"))?;
                 for mut p in points.axis_iter_mut(Axis(0)) {
                     p -= &mean;
                 }
 
                 let (_u, singular_vals, vt) = points.svd(false, true).unwrap();
                 let vt = vt.unwrap();
-                Some(SvdGaussian {
+                Ok(Some(SvdGaussian {
                     singular_vals,
                     vt,
                     mean,
-                })
+                }))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     }
 }
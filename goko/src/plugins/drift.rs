@@ -0,0 +1,329 @@
+//! # Drift Detection
+//!
+//! Turns the tree into a streaming covariate drift detector. The plugin keeps the categorical distribution of where
+//! the training data goes at each node, the same one as `GokoCategorical`, as the baseline. The paths of the observed
+//! queries are counted per node into a second categorical, with older observations decayed exponentially so it follows
+//! recent traffic. `CoverTreeReader::drifting_nodes` compares the two at each node the traffic reached, and reports the
+//! nodes where the recent traffic doesn't look like the training data.
+//!
+//! The decay is applied by growing the weight of each new observation rather than shrinking all the old counts, so an
+//! observation only touches the nodes on its path.
+
+use super::distributions::{Categorical, CategoricalTree, GokoCategorical};
+use super::*;
+use crate::errors::GokoError;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Rescale the counts once the observation weight grows past this
+const MAX_WEIGHT: f64 = 1e100;
+
+/// How to compare a node's recent traffic to its baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftTest {
+    /// KL divergence of the recent distribution from the baseline. Infinite if traffic went where no training data did.
+    KL,
+    /// Pearson's chi-square statistic of the decayed recent counts against the counts the baseline expects.
+    ChiSquare,
+}
+
+#[derive(Debug, Default)]
+struct RecentTraffic {
+    counts: HashMap<NodeAddress, Categorical>,
+    weight: f64,
+    observations: usize,
+}
+
+impl RecentTraffic {
+    fn rescale(&mut self) {
+        let scale = 1.0 / self.weight;
+        for counts in self.counts.values_mut() {
+            for (_, c) in counts.child_counts.iter_mut() {
+                *c *= scale;
+            }
+            counts.singleton_count *= scale;
+        }
+        self.weight = 1.0;
+    }
+}
+
+/// Tree component of the drift plugin. Holds the decayed counts of the recent traffic.
+#[derive(Debug, Clone)]
+pub struct GokoDrift {
+    /// Each new observation is worth `1/decay` times the previous one. Use `with_half_life` to set it by observations
+    pub decay: f64,
+    /// Nodes whose decayed traffic is below this aren't reported
+    pub min_evidence: f64,
+    recent: Arc<Mutex<RecentTraffic>>,
+}
+
+impl GokoDrift {
+    /// The weight of an observation halves after `half_life` newer ones.
+    pub fn with_half_life(half_life: f64) -> Self {
+        GokoDrift {
+            decay: 0.5f64.powf(1.0 / half_life),
+            min_evidence: 10.0,
+            recent: Arc::new(Mutex::new(RecentTraffic::default())),
+        }
+    }
+
+    /// Counts a query's path, as given by `CoverTreeReader::path`.
+    pub fn observe_path(&self, path: &[(f32, NodeAddress)]) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.weight = if recent.observations == 0 {
+            1.0
+        } else {
+            recent.weight / self.decay
+        };
+        recent.observations += 1;
+        let weight = recent.weight;
+        for pair in path.windows(2) {
+            recent
+                .counts
+                .entry(pair[0].1)
+                .or_default()
+                .add_child_pop(Some(pair[1].1), weight);
+        }
+        if let Some((_, last)) = path.last() {
+            recent
+                .counts
+                .entry(*last)
+                .or_default()
+                .add_child_pop(None, weight);
+        }
+        if recent.weight > MAX_WEIGHT {
+            recent.rescale();
+        }
+    }
+
+    /// Forgets the recent traffic.
+    pub fn clear(&self) {
+        *self.recent.lock().unwrap() = RecentTraffic::default();
+    }
+
+    /// The number of observations so far.
+    pub fn observations(&self) -> usize {
+        self.recent.lock().unwrap().observations
+    }
+
+    /// The decayed recent counts at each node the traffic reached, normalized so the newest observation counts as 1.
+    fn recent_counts(&self) -> Vec<(NodeAddress, Categorical)> {
+        let recent = self.recent.lock().unwrap();
+        let scale = 1.0 / recent.weight;
+        recent
+            .counts
+            .iter()
+            .map(|(address, counts)| {
+                let mut counts = counts.clone();
+                for (_, c) in counts.child_counts.iter_mut() {
+                    *c *= scale;
+                }
+                counts.singleton_count *= scale;
+                (*address, counts)
+            })
+            .collect()
+    }
+}
+
+impl Default for GokoDrift {
+    fn default() -> Self {
+        GokoDrift::with_half_life(1000.0)
+    }
+}
+
+impl<D: PointCloud> TreePlugin<D> for GokoDrift {}
+
+impl<D: PointCloud> GokoPlugin<D> for GokoDrift {
+    type NodeComponent = Categorical;
+    type TreeComponent = GokoDrift;
+    fn node_component(
+        _parameters: &Self::TreeComponent,
+        my_node: &CoverNode<D>,
+        my_tree: &CoverTreeReader<D>,
+    ) -> GokoResult<Option<Self::NodeComponent>> {
+        <GokoCategorical as GokoPlugin<D>>::node_component(&CategoricalTree {}, my_node, my_tree)
+    }
+}
+
+/// The recent and baseline mass on each outcome of the node, the children then the singletons.
+fn outcomes(recent: &Categorical, baseline: &Categorical) -> Vec<(f64, f64)> {
+    let mut pairs: Vec<(f64, f64)> = baseline
+        .child_counts
+        .iter()
+        .map(|(address, base)| {
+            let seen = recent
+                .child_counts
+                .binary_search_by_key(&address, |(a, _)| a)
+                .map(|i| recent.child_counts[i].1)
+                .unwrap_or(0.0);
+            (seen, *base)
+        })
+        .collect();
+    // Traffic to a child the baseline never saw
+    for (address, seen) in &recent.child_counts {
+        if baseline
+            .child_counts
+            .binary_search_by_key(&address, |(a, _)| a)
+            .is_err()
+        {
+            pairs.push((*seen, 0.0));
+        }
+    }
+    pairs.push((recent.singleton_count, baseline.singleton_count));
+    pairs
+}
+
+fn drift_statistic(recent: &Categorical, baseline: &Categorical, test: DriftTest) -> Option<f64> {
+    let recent_total = recent.total();
+    let baseline_total = baseline.total();
+    if recent_total <= 0.0 || baseline_total <= 0.0 {
+        return None;
+    }
+    let pairs = outcomes(recent, baseline);
+    let statistic = match test {
+        DriftTest::KL => pairs
+            .iter()
+            .filter(|(seen, _)| *seen > 0.0)
+            .map(|(seen, base)| {
+                let p = seen / recent_total;
+                p * (p.ln() - (base / baseline_total).ln())
+            })
+            .sum(),
+        DriftTest::ChiSquare => pairs
+            .iter()
+            .map(|(seen, base)| {
+                let expected = recent_total * base / baseline_total;
+                if expected > 0.0 {
+                    (seen - expected).powi(2) / expected
+                } else if *seen > 0.0 {
+                    f64::INFINITY
+                } else {
+                    0.0
+                }
+            })
+            .sum(),
+    };
+    Some(statistic)
+}
+
+impl<D: PointCloud> CoverTreeReader<D> {
+    /// Routes the point down the tree and counts its path in the `GokoDrift` plugin's recent traffic.
+    pub fn observe_drift<'a, T: Into<PointRef<'a>>>(&self, point: T) -> GokoResult<()> {
+        let path = self.path(point)?;
+        self.get_plugin_and::<GokoDrift, _, _>(|drift| drift.observe_path(&path))
+            .ok_or(GokoError::PluginNotInstalled("GokoDrift"))
+    }
+
+    /// The nodes whose recent traffic diverges from the training data by more than `threshold`, most divergent first.
+    /// Nodes with less recent traffic than the plugin's `min_evidence` are skipped.
+    pub fn drifting_nodes(
+        &self,
+        test: DriftTest,
+        threshold: f64,
+    ) -> GokoResult<Vec<(f64, NodeAddress)>> {
+        let (recent, min_evidence) = self
+            .get_plugin_and::<GokoDrift, _, _>(|drift| (drift.recent_counts(), drift.min_evidence))
+            .ok_or(GokoError::PluginNotInstalled("GokoDrift"))?;
+        let mut drifting = Vec::new();
+        for (address, counts) in recent {
+            if counts.total() < min_evidence {
+                continue;
+            }
+            let statistic = self
+                .get_node_plugin_and::<Categorical, _, _>(address, |baseline| {
+                    drift_statistic(&counts, baseline, test)
+                })
+                .ok()
+                .flatten()
+                .flatten();
+            if let Some(statistic) = statistic {
+                if statistic > threshold {
+                    drifting.push((statistic, address));
+                }
+            }
+        }
+        drifting.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        Ok(drifting)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::CoverTreeBuilder;
+
+    #[test]
+    fn statistics_are_zero_on_the_baseline() {
+        let mut baseline = Categorical::new();
+        baseline.add_child_pop(Some((0, 0)), 6.0);
+        baseline.add_child_pop(Some((0, 1)), 2.0);
+        baseline.add_child_pop(None, 2.0);
+        let mut recent = baseline.clone();
+        recent.add_child_pop(Some((0, 0)), 6.0);
+        recent.add_child_pop(Some((0, 1)), 2.0);
+        recent.add_child_pop(None, 2.0);
+        assert_approx_eq!(
+            drift_statistic(&recent, &baseline, DriftTest::KL).unwrap(),
+            0.0
+        );
+        assert_approx_eq!(
+            drift_statistic(&recent, &baseline, DriftTest::ChiSquare).unwrap(),
+            0.0
+        );
+        recent.add_child_pop(Some((0, 2)), 1.0);
+        assert_eq!(
+            drift_statistic(&recent, &baseline, DriftTest::KL),
+            Some(f64::INFINITY)
+        );
+        assert_eq!(
+            drift_statistic(&Categorical::new(), &baseline, DriftTest::KL),
+            None
+        );
+    }
+
+    #[test]
+    fn drift_is_found_where_traffic_moved() {
+        // Two clusters, the left one 4 times as big
+        let mut data: Vec<f32> = (0..400).map(|_| rand::random::<f32>()).collect();
+        data.extend((0..100).map(|_| 10.0 + rand::random::<f32>()));
+        let point_cloud = Arc::new(DefaultCloud::<L2>::new(data, 1).unwrap());
+        let builder = CoverTreeBuilder {
+            scale_base: 2.0,
+            leaf_cutoff: 5,
+            min_res_index: -9,
+            use_singletons: true,
+            partition_type: PartitionType::Nearest,
+            verbosity: 0,
+        };
+        let mut writer = builder.build(point_cloud).unwrap();
+        let reader = writer.reader();
+        assert!(reader.observe_drift(&[0.5f32][..]).is_err());
+        writer
+            .add_plugin::<GokoDrift>(GokoDrift::with_half_life(50.0))
+            .unwrap();
+        let reader = writer.reader();
+
+        for i in 0..500 {
+            let x = if i % 5 == 0 { 10.5f32 } else { 0.5 };
+            reader.observe_drift(&[x][..]).unwrap();
+        }
+        let root = reader.root_address();
+        let before = reader.drifting_nodes(DriftTest::ChiSquare, 0.0).unwrap();
+        let root_before = before.iter().find(|(_, a)| *a == root).map(|(s, _)| *s);
+
+        for _ in 0..500 {
+            reader.observe_drift(&[10.5f32][..]).unwrap();
+        }
+        let after = reader.drifting_nodes(DriftTest::ChiSquare, 0.0).unwrap();
+        let root_after = after.iter().find(|(_, a)| *a == root).unwrap().0;
+        assert!(root_after > root_before.unwrap_or(0.0) * 10.0);
+        assert!(after.windows(2).all(|w| w[0].0 >= w[1].0));
+        let kl = reader.drifting_nodes(DriftTest::KL, 0.5).unwrap();
+        assert!(kl.iter().any(|(_, a)| *a == root));
+        assert_eq!(
+            reader.get_plugin_and::<GokoDrift, _, _>(|d| d.observations()),
+            Some(1000)
+        );
+    }
+}
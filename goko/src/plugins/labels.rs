@@ -4,6 +4,9 @@ use super::*;
 use crate::covertree::node::CoverNode;
 use crate::covertree::CoverTreeReader;
 //use pointcloud::*;
+use pointcloud::summaries::CategorySummary;
+use statrs::distribution::{InverseCDF, Normal};
+use statrs::function::beta::beta_reg;
 use std::sync::Arc;
 
 /// Wrapper around the summary found in the point cloud
@@ -40,23 +43,22 @@ impl<D: PointCloud + LabeledCloud> GokoPlugin<D> for LabelSummaryPlugin {
         _parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         let mut bucket = my_tree
             .parameters()
             .point_cloud
-            .label_summary(my_node.singletons())
-            .unwrap();
+            .label_summary(my_node.singletons())?;
         // If we're a routing node then grab the childen's values
         if let Some((nested_scale, child_addresses)) = my_node.children() {
             my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
                 (nested_scale, *my_node.center_index()),
                 |p| bucket.combine(p.summary.as_ref()),
-            );
+            )?;
 
             for ca in child_addresses {
                 my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                     bucket.combine(p.summary.as_ref())
-                });
+                })?;
             }
         } else {
             bucket.add(
@@ -66,9 +68,9 @@ impl<D: PointCloud + LabeledCloud> GokoPlugin<D> for LabelSummaryPlugin {
                     .label(*my_node.center_index()),
             );
         }
-        Some(NodeLabelSummary {
+        Ok(Some(NodeLabelSummary {
             summary: Arc::new(bucket),
-        })
+        }))
     }
 }
 
@@ -106,23 +108,22 @@ impl<D: PointCloud + MetaCloud> GokoPlugin<D> for MetaSummaryPlugin {
         _parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
+    ) -> GokoResult<Option<Self::NodeComponent>> {
         let mut bucket = my_tree
             .parameters()
             .point_cloud
-            .metasummary(my_node.singletons())
-            .unwrap();
+            .metasummary(my_node.singletons())?;
         // If we're a routing node then grab the childen's values
         if let Some((nested_scale, child_addresses)) = my_node.children() {
             my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
                 (nested_scale, *my_node.center_index()),
                 |p| bucket.combine(p.summary.as_ref()),
-            );
+            )?;
 
             for ca in child_addresses {
                 my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                     bucket.combine(p.summary.as_ref())
-                });
+                })?;
             }
         } else {
             bucket.add(
@@ -132,8 +133,149 @@ impl<D: PointCloud + MetaCloud> GokoPlugin<D> for MetaSummaryPlugin {
                     .metadata(*my_node.center_index()),
             );
         }
-        Some(NodeMetaSummary {
+        Ok(Some(NodeMetaSummary {
             summary: Arc::new(bucket),
+        }))
+    }
+}
+
+/// How to compute a confidence interval for a proportion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalMethod {
+    /// The Wilson score interval
+    Wilson,
+    /// The equal tailed interval of the Jeffreys prior's posterior, `Beta(x + 1/2, n - x + 1/2)`
+    Jeffreys,
+}
+
+/// The fraction of a node's labeled points that have a label, with a confidence interval around it. Nodes with few
+/// points have wide intervals, so threshold on `lower` to only act on the proportions the node has evidence for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassProportion {
+    /// The label
+    pub label: i64,
+    /// The number of points with the label
+    pub count: usize,
+    /// The fraction of the labeled points with the label
+    pub proportion: f32,
+    /// The lower end of the interval
+    pub lower: f32,
+    /// The upper end of the interval
+    pub upper: f32,
+}
+
+/// A confidence interval for the proportion `successes / trials`, at a `confidence` like `0.95`. Returns `(0, 1)` when
+/// there are no trials.
+pub fn proportion_interval(
+    successes: usize,
+    trials: usize,
+    method: IntervalMethod,
+    confidence: f64,
+) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let alpha = 1.0 - confidence;
+    let x = successes as f64;
+    let n = trials as f64;
+    match method {
+        IntervalMethod::Wilson => {
+            let z = Normal::new(0.0, 1.0)
+                .unwrap()
+                .inverse_cdf(1.0 - alpha / 2.0);
+            let p = x / n;
+            let z2 = z * z;
+            let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+            let half_width = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+            (
+                (center - half_width).max(0.0),
+                (center + half_width).min(1.0),
+            )
+        }
+        IntervalMethod::Jeffreys => {
+            let (a, b) = (x + 0.5, n - x + 0.5);
+            let lower = if successes == 0 {
+                0.0
+            } else {
+                beta_quantile(a, b, alpha / 2.0)
+            };
+            let upper = if successes == trials {
+                1.0
+            } else {
+                beta_quantile(a, b, 1.0 - alpha / 2.0)
+            };
+            (lower, upper)
+        }
+    }
+}
+
+/// Inverts the regularized incomplete beta function by bisection, it's monotone on `[0, 1]`.
+fn beta_quantile(a: f64, b: f64, q: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..60 {
+        let mid = 0.5 * (low + high);
+        if beta_reg(a, b, mid) < q {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// The proportion of each label in a category summary with its confidence interval, most common first. Unlabeled
+/// points aren't counted.
+pub fn class_proportions(
+    summary: &SummaryCounter<CategorySummary>,
+    method: IntervalMethod,
+    confidence: f64,
+) -> Vec<ClassProportion> {
+    let trials = summary.summary().count();
+    let mut proportions: Vec<ClassProportion> = summary
+        .summary()
+        .items
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(label, count)| {
+            let (lower, upper) = proportion_interval(*count, trials, method, confidence);
+            ClassProportion {
+                label: *label,
+                count: *count,
+                proportion: *count as f32 / trials as f32,
+                lower: lower as f32,
+                upper: upper as f32,
+            }
         })
+        .collect();
+    proportions.sort_by(|a, b| b.count.cmp(&a.count).then(a.label.cmp(&b.label)));
+    proportions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proportion_intervals() {
+        // 8 successes out of 20 at 95%, the Jeffreys bounds are the 2.5% and 97.5% quantiles of Beta(8.5, 12.5)
+        let (lower, upper) = proportion_interval(8, 20, IntervalMethod::Wilson, 0.95);
+        assert!((lower - 0.2188).abs() < 1e-3, "{}", lower);
+        assert!((upper - 0.6134).abs() < 1e-3, "{}", upper);
+        let (lower, upper) = proportion_interval(8, 20, IntervalMethod::Jeffreys, 0.95);
+        assert!((lower - 0.2106).abs() < 1e-3, "{}", lower);
+        assert!((upper - 0.6161).abs() < 1e-3, "{}", upper);
+
+        // Small nodes get wide intervals
+        let (small_lower, _) = proportion_interval(2, 2, IntervalMethod::Wilson, 0.95);
+        let (large_lower, _) = proportion_interval(200, 200, IntervalMethod::Wilson, 0.95);
+        assert!(small_lower < 0.5 && large_lower > 0.95);
+        assert_eq!(
+            proportion_interval(0, 5, IntervalMethod::Jeffreys, 0.95).0,
+            0.0
+        );
+        assert_eq!(
+            proportion_interval(0, 0, IntervalMethod::Wilson, 0.95),
+            (0.0, 1.0)
+        );
     }
 }
@@ -15,8 +15,12 @@ use crate::*;
 use std::fmt::Debug;
 use type_map::concurrent::TypeMap;
 
+pub mod calibration;
+pub mod distance_quantiles;
 pub mod distributions;
+pub mod drift;
 pub mod labels;
+pub mod outliers;
 pub mod utils;
 
 /// Mockup for the plugin interface attached to the node. These are meant to be functions that Goko uses to maintain the plugin.
@@ -32,18 +36,28 @@ pub trait GokoPlugin<D: PointCloud> {
     /// This should largely be an object that manages the parameters of the plugin.
     type TreeComponent: TreePlugin<D> + Clone + 'static;
     /// This is called just before we build the tree to prepare it for the upcomming plugin creations.
-    fn prepare_tree(_parameters: &Self::TreeComponent, _my_tree: &mut CoverTreeWriter<D>) {}
-    /// The function that actually builds the node components.
+    fn prepare_tree(
+        _parameters: &Self::TreeComponent,
+        _my_tree: &mut CoverTreeWriter<D>,
+    ) -> GokoResult<()> {
+        Ok(())
+    }
+    /// The function that actually builds the node components. Return `Ok(None)` to leave a node without one, an
+    /// error stops `CoverTreeWriter::add_plugin`.
     fn node_component(
         parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent>;
+    ) -> GokoResult<Option<Self::NodeComponent>>;
 }
 
 pub(crate) type NodePluginSet = TypeMap;
 pub(crate) type TreePluginSet = TypeMap;
 
+/// The names of the plugins attached to a tree, in the order they were added. Stored in the `TreePluginSet`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InstalledPlugins(pub(crate) Vec<&'static str>);
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -75,7 +89,7 @@ pub(crate) mod tests {
             parameters: &Self::TreeComponent,
             my_node: &CoverNode<D>,
             my_tree: &CoverTreeReader<D>,
-        ) -> Option<Self::NodeComponent> {
+        ) -> GokoResult<Option<Self::NodeComponent>> {
             println!(
                 "Building Dumb Plugin for {:?}",
                 (my_node.scale_index(), my_node.center_index())
@@ -92,22 +106,23 @@ pub(crate) mod tests {
                             (nested_scale, *my_node.center_index()),
                             |p| p.cover_count,
                         )
+                        ?
                         .unwrap();
                     for ca in child_addresses {
                         cover_count += my_tree
                             .get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
                                 p.cover_count
-                            })
+                            })?
                             .unwrap();
                     }
                     cover_count
                 }
             };
-            Some(DumbNode1 {
+            Ok(Some(DumbNode1 {
                 id: parameters.id,
                 pi: *my_node.center_index(),
                 cover_count,
-            })
+            }))
         }
     }
 
@@ -115,7 +130,7 @@ pub(crate) mod tests {
     fn dumb_plugins() {
         let d = DumbTree1 { id: 1 };
         let mut tree = build_basic_tree();
-        tree.add_plugin::<DumbGoko1>(d);
+        tree.add_plugin::<DumbGoko1>(d).unwrap();
         println!("{:?}", tree.reader().len());
         for (si, layer) in tree.reader().layers() {
             println!("Scale Index: {:?}", si);
@@ -127,5 +142,11 @@ pub(crate) mod tests {
                 });
             });
         }
+        let names = tree
+            .reader()
+            .get_plugin_and::<InstalledPlugins, _, _>(|p| p.0.clone())
+            .unwrap();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].ends_with("DumbGoko1"));
     }
 }
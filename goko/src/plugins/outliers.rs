@@ -0,0 +1,145 @@
+//! # Node Outliers
+//!
+//! Keeps the `m` covered points farthest from each node's center, for pulling up boundary examples of a region without
+//! scanning its coverage. Leaves rank all of their singletons. Routing nodes rank their singletons, their children's
+//! centers and their children's outlier lists, so for them the list is the farthest of those candidates rather than an
+//! exact ranking of everything they cover.
+
+use super::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The farthest points a node covers, as `(distance to the center, index)` in decreasing order of distance.
+#[derive(Debug, Clone)]
+pub struct NodeOutliers {
+    outliers: Arc<Vec<(f32, PointIndex)>>,
+}
+
+impl<D: PointCloud> NodePlugin<D> for NodeOutliers {}
+
+impl NodeOutliers {
+    /// The farthest points, farthest first.
+    pub fn outliers(&self) -> &[(f32, PointIndex)] {
+        self.outliers.as_ref()
+    }
+
+    /// Just the indexes of the farthest points, farthest first.
+    pub fn point_indexes(&self) -> Vec<PointIndex> {
+        self.outliers.iter().map(|(_, pi)| *pi).collect()
+    }
+}
+
+/// Tree component of the outlier plugin, sets how many points each node keeps.
+#[derive(Debug, Clone)]
+pub struct GokoOutliers {
+    /// The number of points each node keeps
+    pub max: usize,
+}
+
+impl Default for GokoOutliers {
+    fn default() -> Self {
+        GokoOutliers { max: 10 }
+    }
+}
+
+impl<D: PointCloud> TreePlugin<D> for GokoOutliers {}
+
+impl<D: PointCloud> GokoPlugin<D> for GokoOutliers {
+    type NodeComponent = NodeOutliers;
+    type TreeComponent = GokoOutliers;
+    fn node_component(
+        parameters: &Self::TreeComponent,
+        my_node: &CoverNode<D>,
+        my_tree: &CoverTreeReader<D>,
+    ) -> GokoResult<Option<Self::NodeComponent>> {
+        let center = *my_node.center_index();
+        let mut candidates: HashSet<PointIndex> = my_node.singletons().iter().cloned().collect();
+        if let Some((nested_scale, child_addresses)) = my_node.children() {
+            let mut gather = |p: &NodeOutliers| {
+                candidates.extend(p.outliers().iter().map(|(_, pi)| *pi));
+            };
+            my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
+                (nested_scale, center),
+                &mut gather,
+            )?;
+            for ca in child_addresses {
+                my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, &mut gather)?;
+            }
+            candidates.extend(child_addresses.iter().map(|(_, pi)| *pi));
+        }
+        candidates.remove(&center);
+        let candidates: Vec<PointIndex> = candidates.into_iter().collect();
+        let dists = my_tree
+            .point_cloud()
+            .distances_to_point_index(center, &candidates)?;
+        let mut outliers: Vec<(f32, PointIndex)> = dists.into_iter().zip(candidates).collect();
+        outliers.sort_by(|a, b| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        });
+        outliers.truncate(parameters.max);
+        Ok(Some(NodeOutliers {
+            outliers: Arc::new(outliers),
+        }))
+    }
+}
+
+/// The farthest points covered by the node, farthest first. Requires the `GokoOutliers` plugin, returns `None` if the
+/// node doesn't have it.
+pub fn node_outliers<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    node_address: NodeAddress,
+) -> GokoResult<Option<Vec<(f32, PointIndex)>>> {
+    reader.get_node_plugin_and::<NodeOutliers, _, _>(node_address, |p| p.outliers().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use crate::plugins::utils::*;
+
+    #[test]
+    fn leaf_outliers_are_exact() {
+        let mut tree = build_basic_tree();
+        tree.add_plugin::<GokoOutliers>(GokoOutliers { max: 2 })
+            .unwrap();
+        tree.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::new())
+            .unwrap();
+        let reader = tree.reader();
+        let mut unvisited = vec![reader.root_address()];
+        while let Some(addr) = unvisited.pop() {
+            let outliers = node_outliers(&reader, addr).unwrap().unwrap();
+            assert!(outliers.len() <= 2);
+            for pair in outliers.windows(2) {
+                assert!(pair[0].0 >= pair[1].0);
+            }
+            let covered = reader
+                .get_node_plugin_and::<CoverageIndexes, _, _>(addr, |p| p.point_indexes().to_vec())
+                .unwrap()
+                .unwrap();
+            let is_leaf = reader.get_node_and(addr, |n| n.is_leaf()).unwrap();
+            if is_leaf {
+                let mut dists = reader
+                    .point_cloud()
+                    .distances_to_point_index(addr.1, &covered)
+                    .unwrap();
+                dists.sort_by(|a, b| b.partial_cmp(a).unwrap());
+                dists.truncate(outliers.len());
+                let found: Vec<f32> = outliers.iter().map(|(d, _)| *d).collect();
+                assert_eq!(found, dists);
+            }
+            for (_, pi) in outliers {
+                assert!(covered.contains(&pi));
+            }
+            reader
+                .get_node_children_and(addr, |covered, children| {
+                    unvisited.push(covered);
+                    unvisited.extend(children);
+                })
+                .unwrap();
+        }
+    }
+}
@@ -4,28 +4,44 @@ use super::*;
 use crate::covertree::node::CoverNode;
 use crate::covertree::CoverTreeReader;
 //use pointcloud::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use roaring::RoaringTreemap;
+use std::cmp::Ordering;
 use std::sync::Arc;
 
-/// Contains all points that this node covers, if the coverage is lower than the limit set in the parameters.
+/// Contains all points that this node covers if the coverage is lower than the limit set in the parameters,
+/// otherwise a uniform sample of the points of the size of the limit.
 #[derive(Debug, Clone)]
 pub struct CoverageIndexes {
     pis: Arc<Vec<PointIndex>>,
+    count: usize,
 }
 
 impl<D: PointCloud> NodePlugin<D> for CoverageIndexes {}
 
 impl CoverageIndexes {
-    /// Returns all point indexes that the node covers
+    /// Returns all point indexes that the node covers, or the sample of them if this `is_sample`.
     pub fn point_indexes(&self) -> &[PointIndex] {
         self.pis.as_ref()
     }
+
+    /// The exact number of points the node covers, even if we only hold a sample.
+    pub fn coverage_count(&self) -> usize {
+        self.count
+    }
+
+    /// If the point indexes are a sample of the coverage, rather than all of it.
+    pub fn is_sample(&self) -> bool {
+        self.pis.len() < self.count
+    }
 }
 
 /// Parameters to control the gathering of indexes.
 /// It's wize to set this to some reasonable limit so you don't consume all the ram.
 #[derive(Debug, Clone)]
 pub struct CoverageIndexesParams {
-    /// The actual limit
+    /// The actual limit, nodes that cover more points than this store a uniform sample of this size.
     pub max: usize,
 }
 
@@ -54,31 +70,135 @@ impl<D: PointCloud> GokoPlugin<D> for GokoCoverageIndexes {
         parameters: &Self::TreeComponent,
         my_node: &CoverNode<D>,
         my_tree: &CoverTreeReader<D>,
-    ) -> Option<Self::NodeComponent> {
-        if my_node.coverage_count() < parameters.max {
-            let mut indexes = my_node.singletons().to_vec();
-            // If we're a routing node then grab the childen's values
-            if let Some((nested_scale, child_addresses)) = my_node.children() {
-                my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
-                    (nested_scale, *my_node.center_index()),
-                    |p| {
-                        indexes.extend(p.point_indexes());
-                    },
-                );
-                for ca in child_addresses {
-                    my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
-                        indexes.extend(p.point_indexes());
-                    });
-                }
-            } else {
-                indexes.push(*my_node.center_index());
+    ) -> GokoResult<Option<Self::NodeComponent>> {
+        // Each index is weighted by the number of points it stands in for.
+        let mut weighted: Vec<(PointIndex, f32)> =
+            my_node.singletons().iter().map(|pi| (*pi, 1.0)).collect();
+        // If we're a routing node then grab the childen's values
+        if let Some((nested_scale, child_addresses)) = my_node.children() {
+            let mut gather = |p: &CoverageIndexes| {
+                let weight = p.count as f32 / p.pis.len().max(1) as f32;
+                weighted.extend(p.point_indexes().iter().map(|pi| (*pi, weight)));
+            };
+            my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
+                (nested_scale, *my_node.center_index()),
+                &mut gather,
+            )?;
+            for ca in child_addresses {
+                my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, &mut gather)?;
+            }
+        } else {
+            weighted.push((*my_node.center_index(), 1.0));
+        }
+
+        let count = my_node.coverage_count();
+        let indexes = if count < parameters.max {
+            weighted.drain(..).map(|(pi, _)| pi).collect()
+        } else {
+            // Weighted reservoir sampling, A-Res, seeded by the node so rebuilds are reproducible.
+            let mut rng = StdRng::seed_from_u64(
+                *my_node.center_index() as u64 ^ ((*my_node.scale_index() as u64) << 32),
+            );
+            let mut keyed: Vec<(f32, PointIndex)> = weighted
+                .drain(..)
+                .map(|(pi, w)| (rng.gen::<f32>().powf(1.0 / w), pi))
+                .collect();
+            keyed.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+            keyed.truncate(parameters.max);
+            keyed.drain(..).map(|(_, pi)| pi).collect()
+        };
+        Ok(Some(CoverageIndexes {
+            pis: Arc::new(indexes),
+            count,
+        }))
+    }
+}
+
+/// Contains all points that this node covers as a compressed bitmap.
+#[derive(Debug, Clone)]
+pub struct CoverageBitmap {
+    bits: Arc<RoaringTreemap>,
+}
+
+impl<D: PointCloud> NodePlugin<D> for CoverageBitmap {}
+
+impl CoverageBitmap {
+    /// The underlying bitmap of covered point indexes
+    pub fn bitmap(&self) -> &RoaringTreemap {
+        self.bits.as_ref()
+    }
+
+    /// The number of points covered
+    pub fn len(&self) -> u64 {
+        self.bits.len()
+    }
+
+    /// If this covers no points, should never happen
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// If the node covers the point
+    pub fn contains(&self, pi: PointIndex) -> bool {
+        self.bits.contains(pi as u64)
+    }
+
+    /// Returns all point indexes that the node covers, in sorted order
+    pub fn point_indexes(&self) -> Vec<PointIndex> {
+        self.bits.iter().map(|pi| pi as PointIndex).collect()
+    }
+
+    /// The points covered by both nodes
+    pub fn intersection(&self, other: &CoverageBitmap) -> RoaringTreemap {
+        self.bitmap() & other.bitmap()
+    }
+
+    /// The points covered by either node
+    pub fn union(&self, other: &CoverageBitmap) -> RoaringTreemap {
+        self.bitmap() | other.bitmap()
+    }
+
+    /// The points covered by this node, but not the other
+    pub fn difference(&self, other: &CoverageBitmap) -> RoaringTreemap {
+        self.bitmap() - other.bitmap()
+    }
+}
+
+/// A variant of `GokoCoverageIndexes` that stores the coverage as a Roaring bitmap. These are compact enough that there is
+/// no cap on the coverage.
+#[derive(Debug, Clone)]
+pub struct GokoCoverageBitmap {}
+
+impl<D: PointCloud> TreePlugin<D> for GokoCoverageBitmap {}
+
+impl<D: PointCloud> GokoPlugin<D> for GokoCoverageBitmap {
+    type NodeComponent = CoverageBitmap;
+    type TreeComponent = GokoCoverageBitmap;
+    fn node_component(
+        _parameters: &Self::TreeComponent,
+        my_node: &CoverNode<D>,
+        my_tree: &CoverTreeReader<D>,
+    ) -> GokoResult<Option<Self::NodeComponent>> {
+        let mut bits: RoaringTreemap = my_node.singletons().iter().map(|pi| *pi as u64).collect();
+        // If we're a routing node then grab the childen's values
+        if let Some((nested_scale, child_addresses)) = my_node.children() {
+            my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(
+                (nested_scale, *my_node.center_index()),
+                |p| {
+                    bits |= p.bitmap();
+                },
+            )?;
+            for ca in child_addresses {
+                my_tree.get_node_plugin_and::<Self::NodeComponent, _, _>(*ca, |p| {
+                    bits |= p.bitmap();
+                })?;
             }
-            Some(CoverageIndexes {
-                pis: Arc::new(indexes),
-            })
         } else {
-            None
+            bits.insert(*my_node.center_index() as u64);
         }
+        Ok(Some(CoverageBitmap {
+            bits: Arc::new(bits),
+        }))
     }
 }
 
@@ -90,21 +210,98 @@ pub(crate) mod tests {
     #[test]
     fn coverage_sanity() {
         let mut ct = build_basic_tree();
-        ct.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::new());
+        ct.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::new())
+            .unwrap();
         let ct_reader = ct.reader();
         let mut untested_addresses = vec![ct_reader.root_address()];
         while let Some(addr) = untested_addresses.pop() {
             let count = ct_reader
                 .get_node_plugin_and::<CoverageIndexes, _, _>(addr, |p| p.point_indexes().len())
+                .unwrap()
+                .unwrap();
+            ct_reader
+                .get_node_and(addr, |n| {
+                    assert_eq!(n.coverage_count(), count, "Node: {:?}", n)
+                })
+                .unwrap();
+
+            ct_reader
+                .get_node_children_and(addr, |covered, children| {
+                    untested_addresses.push(covered);
+                    untested_addresses.extend(children);
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn coverage_sampled() {
+        let mut ct = build_basic_tree();
+        ct.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::restricted(2))
+            .unwrap();
+        let ct_reader = ct.reader();
+        let mut untested_addresses = vec![ct_reader.root_address()];
+        while let Some(addr) = untested_addresses.pop() {
+            let (len, count, is_sample) = ct_reader
+                .get_node_plugin_and::<CoverageIndexes, _, _>(addr, |p| {
+                    (p.point_indexes().len(), p.coverage_count(), p.is_sample())
+                })
+                .unwrap()
+                .unwrap();
+            ct_reader
+                .get_node_and(addr, |n| assert_eq!(n.coverage_count(), count))
+                .unwrap();
+            if count < 2 {
+                assert_eq!(len, count);
+                assert!(!is_sample);
+            } else {
+                assert_eq!(len, 2);
+            }
+
+            ct_reader
+                .get_node_children_and(addr, |covered, children| {
+                    untested_addresses.push(covered);
+                    untested_addresses.extend(children);
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn coverage_bitmap_sanity() {
+        let mut ct = build_basic_tree();
+        ct.add_plugin::<GokoCoverageIndexes>(GokoCoverageIndexes::new())
+            .unwrap();
+        ct.add_plugin::<GokoCoverageBitmap>(GokoCoverageBitmap {})
+            .unwrap();
+        let ct_reader = ct.reader();
+        let root = ct_reader.root_address();
+        let mut untested_addresses = vec![root];
+        while let Some(addr) = untested_addresses.pop() {
+            let mut indexes = ct_reader
+                .get_node_plugin_and::<CoverageIndexes, _, _>(addr, |p| p.point_indexes().to_vec())
+                .unwrap()
+                .unwrap();
+            indexes.sort();
+            let bitmap = ct_reader
+                .get_node_plugin_and::<CoverageBitmap, _, _>(addr, |p| p.clone())
+                .unwrap()
+                .unwrap();
+            assert_eq!(bitmap.point_indexes(), indexes);
+
+            let root_bitmap = ct_reader
+                .get_node_plugin_and::<CoverageBitmap, _, _>(root, |p| p.clone())
+                .unwrap()
+                .unwrap();
+            assert_eq!(bitmap.intersection(&root_bitmap).len(), bitmap.len());
+            assert!(bitmap.difference(&root_bitmap).is_empty());
+
+            ct_reader
+                .get_node_children_and(addr, |covered, children| {
+                    untested_addresses.push(covered);
+                    untested_addresses.extend(children);
+                })
                 .unwrap();
-            ct_reader.get_node_and(addr, |n| {
-                assert_eq!(n.coverage_count(), count, "Node: {:?}", n)
-            });
-
-            ct_reader.get_node_children_and(addr, |covered, children| {
-                untested_addresses.push(covered);
-                untested_addresses.extend(children);
-            });
         }
     }
 }
@@ -0,0 +1,252 @@
+//! Admission control for readers shared between interactive and batch traffic.
+//!
+//! Queries are split into cheap ones, like a single kNN, and heavy ones, like range and batch queries. Each class has its
+//! own concurrency limit, so heavy queries queue up without holding back cheap ones. Within a class the waiting queries
+//! are admitted round robin by client key, so one client with a deep queue can't starve the others.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The kind of query asking to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryClass {
+    /// Single point queries, like `knn` and `path`
+    Cheap,
+    /// Range queries and batches
+    Heavy,
+}
+
+/// The concurrency limits for each class. `None` lets the class run unlimited.
+#[derive(Debug, Clone, Copy)]
+pub struct AdmissionConfig {
+    /// Maximum number of cheap queries running at once
+    pub max_cheap: Option<usize>,
+    /// Maximum number of heavy queries running at once
+    pub max_heavy: Option<usize>,
+}
+
+impl Default for AdmissionConfig {
+    fn default() -> Self {
+        AdmissionConfig {
+            max_cheap: None,
+            max_heavy: Some(2),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClassQueue {
+    limit: Option<usize>,
+    in_flight: usize,
+    next_ticket: u64,
+    /// Clients with waiting queries, in the order they will next be served
+    clients: VecDeque<String>,
+    waiting: HashMap<String, VecDeque<u64>>,
+    granted: HashSet<u64>,
+}
+
+impl ClassQueue {
+    fn new(limit: Option<usize>) -> ClassQueue {
+        ClassQueue {
+            limit: limit.map(|l| l.max(1)),
+            ..Default::default()
+        }
+    }
+
+    fn has_room(&self) -> bool {
+        self.limit.map(|l| self.in_flight < l).unwrap_or(true)
+    }
+
+    fn queued(&self) -> usize {
+        self.waiting.values().map(|w| w.len()).sum()
+    }
+
+    /// Hands free slots to the waiting clients, one query per client per round.
+    fn dispatch(&mut self) -> bool {
+        let mut granted_any = false;
+        while self.has_room() {
+            let client = match self.clients.pop_front() {
+                Some(client) => client,
+                None => break,
+            };
+            let tickets = self.waiting.get_mut(&client).unwrap();
+            let ticket = tickets.pop_front().unwrap();
+            if tickets.is_empty() {
+                self.waiting.remove(&client);
+            } else {
+                self.clients.push_back(client);
+            }
+            self.granted.insert(ticket);
+            self.in_flight += 1;
+            granted_any = true;
+        }
+        granted_any
+    }
+}
+
+#[derive(Debug)]
+struct AdmissionInner {
+    cheap: (Mutex<ClassQueue>, Condvar),
+    heavy: (Mutex<ClassQueue>, Condvar),
+}
+
+impl AdmissionInner {
+    fn class(&self, class: QueryClass) -> &(Mutex<ClassQueue>, Condvar) {
+        match class {
+            QueryClass::Cheap => &self.cheap,
+            QueryClass::Heavy => &self.heavy,
+        }
+    }
+}
+
+/// Limits how many queries of each class run at once, and queues the rest fairly between clients. Clones share the
+/// same limits, so hand one to each request handler.
+#[derive(Debug, Clone)]
+pub struct AdmissionController {
+    inner: Arc<AdmissionInner>,
+}
+
+/// A slot for a running query. The slot is handed to the next waiting query when this is dropped.
+#[derive(Debug)]
+pub struct AdmissionPermit {
+    inner: Arc<AdmissionInner>,
+    class: QueryClass,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        let (queue, condvar) = self.inner.class(self.class);
+        let mut queue = queue.lock().unwrap();
+        queue.in_flight -= 1;
+        if queue.dispatch() {
+            condvar.notify_all();
+        }
+    }
+}
+
+impl AdmissionController {
+    /// Creates a controller with the given limits.
+    pub fn new(config: AdmissionConfig) -> Self {
+        AdmissionController {
+            inner: Arc::new(AdmissionInner {
+                cheap: (
+                    Mutex::new(ClassQueue::new(config.max_cheap)),
+                    Condvar::new(),
+                ),
+                heavy: (
+                    Mutex::new(ClassQueue::new(config.max_heavy)),
+                    Condvar::new(),
+                ),
+            }),
+        }
+    }
+
+    /// Blocks until a query of this class from this client is allowed to run.
+    pub fn acquire(&self, class: QueryClass, client_key: &str) -> AdmissionPermit {
+        let (queue, condvar) = self.inner.class(class);
+        let mut queue = queue.lock().unwrap();
+        if queue.clients.is_empty() && queue.has_room() {
+            queue.in_flight += 1;
+            return self.permit(class);
+        }
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        if !queue.waiting.contains_key(client_key) {
+            queue.clients.push_back(client_key.to_string());
+        }
+        queue
+            .waiting
+            .entry(client_key.to_string())
+            .or_default()
+            .push_back(ticket);
+        while !queue.granted.remove(&ticket) {
+            queue = condvar.wait(queue).unwrap();
+        }
+        self.permit(class)
+    }
+
+    /// Admits the query if there's room right now and nobody is waiting, without blocking.
+    pub fn try_acquire(&self, class: QueryClass) -> Option<AdmissionPermit> {
+        let (queue, _) = self.inner.class(class);
+        let mut queue = queue.lock().unwrap();
+        if queue.clients.is_empty() && queue.has_room() {
+            queue.in_flight += 1;
+            Some(self.permit(class))
+        } else {
+            None
+        }
+    }
+
+    /// Runs the query once it is admitted.
+    pub fn run<F, T>(&self, class: QueryClass, client_key: &str, query: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let _permit = self.acquire(class, client_key);
+        query()
+    }
+
+    /// The number of queries of this class that are running.
+    pub fn in_flight(&self, class: QueryClass) -> usize {
+        self.inner.class(class).0.lock().unwrap().in_flight
+    }
+
+    /// The number of queries of this class that are waiting to run.
+    pub fn queued(&self, class: QueryClass) -> usize {
+        self.inner.class(class).0.lock().unwrap().queued()
+    }
+
+    fn permit(&self, class: QueryClass) -> AdmissionPermit {
+        AdmissionPermit {
+            inner: Arc::clone(&self.inner),
+            class,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn heavy_queries_are_shared_fairly() {
+        let controller = AdmissionController::new(AdmissionConfig {
+            max_cheap: None,
+            max_heavy: Some(1),
+        });
+        let held = controller.acquire(QueryClass::Heavy, "batch");
+        assert!(controller.try_acquire(QueryClass::Heavy).is_none());
+        assert!(controller.try_acquire(QueryClass::Cheap).is_some());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for client in vec!["batch", "batch", "batch", "interactive"] {
+            let queued = controller.queued(QueryClass::Heavy);
+            let client_controller = controller.clone();
+            let order = Arc::clone(&order);
+            handles.push(thread::spawn(move || {
+                client_controller.run(QueryClass::Heavy, client, || {
+                    order.lock().unwrap().push(client);
+                })
+            }));
+            let start = Instant::now();
+            while controller.queued(QueryClass::Heavy) == queued
+                && start.elapsed() < Duration::from_secs(10)
+            {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        assert_eq!(controller.queued(QueryClass::Heavy), 4);
+        drop(held);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["batch", "interactive", "batch", "batch"]
+        );
+        assert_eq!(controller.in_flight(QueryClass::Heavy), 0);
+    }
+}
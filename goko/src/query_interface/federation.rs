@@ -0,0 +1,137 @@
+//! Queries across several trees that share point indexes, but are built on different feature spaces.
+//!
+//! For example, an image tree and a text tree over the same items. Each tree is queried separately and the ranked lists are
+//! merged into one with a score fusion rule. Fused scores are similarities, higher is better.
+
+use crate::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// How to merge the ranked lists from each tree.
+#[derive(Debug, Clone, Copy)]
+pub enum ScoreFusion {
+    /// Reciprocal rank fusion, each list contributes `1/(k + rank)` with ranks starting at 1. `k = 60.0` is the usual choice.
+    ReciprocalRank {
+        /// Damping constant
+        k: f32,
+    },
+    /// Each list's distances are standardized and negated. Points missing from a list get that list's worst score.
+    ZScore,
+}
+
+impl Default for ScoreFusion {
+    fn default() -> Self {
+        ScoreFusion::ReciprocalRank { k: 60.0 }
+    }
+}
+
+/// Merges several ranked lists of `(distance, index)` pairs, as returned by `knn`. Returns `(score, index)` pairs with the
+/// highest score first.
+pub fn fuse(lists: &[Vec<(f32, PointIndex)>], fusion: ScoreFusion) -> Vec<(f32, PointIndex)> {
+    let mut scores: HashMap<PointIndex, f32> = HashMap::new();
+    match fusion {
+        ScoreFusion::ReciprocalRank { k } => {
+            for list in lists {
+                for (rank, (_, pi)) in list.iter().enumerate() {
+                    *scores.entry(*pi).or_insert(0.0) += 1.0 / (k + (rank + 1) as f32);
+                }
+            }
+        }
+        ScoreFusion::ZScore => {
+            let standardized: Vec<(f32, HashMap<PointIndex, f32>)> = lists
+                .iter()
+                .map(|list| {
+                    let n = list.len().max(1) as f32;
+                    let mean = list.iter().map(|(d, _)| d).sum::<f32>() / n;
+                    let var = list
+                        .iter()
+                        .map(|(d, _)| (d - mean) * (d - mean))
+                        .sum::<f32>()
+                        / n;
+                    let std = if var > 0.0 { var.sqrt() } else { 1.0 };
+                    let zs: HashMap<PointIndex, f32> =
+                        list.iter().map(|(d, pi)| (*pi, (mean - d) / std)).collect();
+                    let worst = zs.values().cloned().fold(0.0, f32::min);
+                    (worst, zs)
+                })
+                .collect();
+            for (_, zs) in &standardized {
+                for pi in zs.keys() {
+                    scores.entry(*pi).or_insert(0.0);
+                }
+            }
+            for (pi, score) in scores.iter_mut() {
+                *score = standardized
+                    .iter()
+                    .map(|(worst, zs)| zs.get(pi).copied().unwrap_or(*worst))
+                    .sum();
+            }
+        }
+    }
+    let mut fused: Vec<(f32, PointIndex)> = scores.into_iter().map(|(pi, s)| (s, pi)).collect();
+    fused.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap_or(Ordering::Equal)
+            .then(a.1.cmp(&b.1))
+    });
+    fused
+}
+
+/// Queries two trees with a point from each one's feature space, and fuses the `k` nearest neighbors of each into one list
+/// of at most `k` points.
+pub fn federated_knn<'a, 'b, D1, D2, T1, T2>(
+    first: (&CoverTreeReader<D1>, T1),
+    second: (&CoverTreeReader<D2>, T2),
+    k: usize,
+    fusion: ScoreFusion,
+) -> GokoResult<Vec<(f32, PointIndex)>>
+where
+    D1: PointCloud,
+    D2: PointCloud,
+    T1: Into<PointRef<'a>>,
+    T2: Into<PointRef<'b>>,
+{
+    let lists = vec![first.0.knn(first.1, k)?, second.0.knn(second.1, k)?];
+    let mut fused = fuse(&lists, fusion);
+    fused.truncate(k);
+    Ok(fused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn rrf_prefers_agreement() {
+        let a = vec![(0.1, 1), (0.2, 2), (0.3, 3)];
+        let b = vec![(1.0, 2), (2.0, 1), (3.0, 4)];
+        let fused = fuse(&[a.clone(), b.clone()], ScoreFusion::default());
+        assert_eq!(fused.len(), 4);
+        assert_eq!(fused[0].1, 1);
+        assert_eq!(fused[1].1, 2);
+
+        let fused = fuse(&[a, b], ScoreFusion::ZScore);
+        assert_eq!(fused.len(), 4);
+        assert!(fused[0].1 == 1 || fused[0].1 == 2);
+        assert_eq!(fused[3].1, 4);
+    }
+
+    #[test]
+    fn federated_same_tree() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let point = [0.495f32];
+        let knn = reader.knn(&point[..], 2).unwrap();
+        let fused = federated_knn(
+            (&reader, &point[..]),
+            (&reader, &point[..]),
+            2,
+            ScoreFusion::ZScore,
+        )
+        .unwrap();
+        let knn_indexes: Vec<PointIndex> = knn.iter().map(|(_, pi)| *pi).collect();
+        let fused_indexes: Vec<PointIndex> = fused.iter().map(|(_, pi)| *pi).collect();
+        assert_eq!(knn_indexes, fused_indexes);
+    }
+}
@@ -3,17 +3,48 @@
 //use crossbeam_channel::unbounded;
 use crate::*;
 use rayon::iter::repeatn;
-use ndarray::ArrayView2;
+use ndarray::{Array1, ArrayView2};
+
+pub mod admission;
+pub mod federation;
+pub mod recall_monitor;
+pub mod query_log;
+pub mod subscriptions;
+mod triage;
+pub use triage::PendingCounts;
 
 /// Inteface for bulk queries. Handles cloning the readers for you
 pub struct BulkInterface<D: PointCloud> {
     reader: CoverTreeReader<D>,
+    runtime: Option<GokoRuntime>,
 }
 
 impl<D: PointCloud> BulkInterface<D> {
     /// Creates a new one.
     pub fn new(reader: CoverTreeReader<D>) -> Self {
-        BulkInterface { reader }
+        BulkInterface {
+            reader,
+            runtime: None,
+        }
+    }
+
+    /// Creates a new one that runs its queries on the runtime's pool, rather than rayon's global pool.
+    pub fn with_runtime(reader: CoverTreeReader<D>, runtime: GokoRuntime) -> Self {
+        BulkInterface {
+            reader,
+            runtime: Some(runtime),
+        }
+    }
+
+    fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.runtime {
+            Some(runtime) => runtime.install(op),
+            None => op(),
+        }
     }
 
     /// Applies the passed in fn to the passed in indexes and collects the result in a vector. Core function for this struct.
@@ -22,24 +53,24 @@ impl<D: PointCloud> BulkInterface<D> {
         F: Fn(&CoverTreeReader<D>, PointIndex) -> T + Send + Sync,
         T: Send + Sync,
     {
-        let indexes_iter = point_indexes.par_chunks(100);
-        let reader_copies = indexes_iter.len();
-        let mut chunked_results: Vec<Vec<T>> = indexes_iter
-            .zip(repeatn(self.reader.clone(), reader_copies))
-            .map(|(chunk_indexes, reader)| {
-                chunk_indexes
-                    .iter()
-                    .map(|p| f(&reader, *p))
-                    .collect()
-            })
-            .collect();
+        let reader = self.reader.clone();
+        let mut chunked_results: Vec<Vec<T>> = self.install(move || {
+            let indexes_iter = point_indexes.par_chunks(100);
+            let reader_copies = indexes_iter.len();
+            indexes_iter
+                .zip(repeatn(reader, reader_copies))
+                .map(|(chunk_indexes, reader)| {
+                    chunk_indexes.iter().map(|p| f(&reader, *p)).collect()
+                })
+                .collect()
+        });
         chunked_results
             .drain(..)
             .fold_first(|mut a, mut x| {
                 a.extend(x.drain(..));
                 a
             })
-            .unwrap()
+            .unwrap_or_default()
     }
 
     /// Applies the passed in fn to the passed in indexes and collects the result in a vector. Core function for this struct.
@@ -48,24 +79,22 @@ impl<D: PointCloud> BulkInterface<D> {
         F: Fn(&CoverTreeReader<D>, PointRef) -> T + Send + Sync,
         T: Send + Sync,
     {
-        let point_iter = points.par_chunks(100);
-        let reader_copies = point_iter.len();
-        let mut chunked_results: Vec<Vec<T>> = point_iter
-            .zip(repeatn(self.reader.clone(), reader_copies))
-            .map(|(chunk_points, reader)| {
-                chunk_points
-                    .iter()
-                    .map(|p| f(&reader, *p))
-                    .collect()
-            })
-            .collect();
+        let reader = self.reader.clone();
+        let mut chunked_results: Vec<Vec<T>> = self.install(move || {
+            let point_iter = points.par_chunks(100);
+            let reader_copies = point_iter.len();
+            point_iter
+                .zip(repeatn(reader, reader_copies))
+                .map(|(chunk_points, reader)| chunk_points.iter().map(|p| f(&reader, *p)).collect())
+                .collect()
+        });
         chunked_results
             .drain(..)
             .fold_first(|mut a, mut x| {
                 a.extend(x.drain(..));
                 a
             })
-            .unwrap()
+            .unwrap_or_default()
     }
 
     /// Applies the passed in fn to the passed in indexes and collects the result in a vector. Core function for this struct.
@@ -75,25 +104,27 @@ impl<D: PointCloud> BulkInterface<D> {
         T: Send + Sync,
     {
         let indexes: Vec<usize> = (0..points.nrows()).collect();
-        let point_iter = indexes.par_chunks(100);
-        let reader_copies = point_iter.len();
-
-        let mut chunked_results: Vec<Vec<T>> = point_iter
-            .zip(repeatn(self.reader.clone(), reader_copies))
-            .map(|(chunk_points, reader)| {
-                chunk_points
-                    .iter()
-                    .map(|i| f(&reader, PointRef::from(points.row(*i).as_slice().unwrap())))
-                    .collect()
-            })
-            .collect();
+        let reader = self.reader.clone();
+        let mut chunked_results: Vec<Vec<T>> = self.install(move || {
+            let point_iter = indexes.par_chunks(100);
+            let reader_copies = point_iter.len();
+            point_iter
+                .zip(repeatn(reader, reader_copies))
+                .map(|(chunk_points, reader)| {
+                    chunk_points
+                        .iter()
+                        .map(|i| f(&reader, PointRef::from(points.row(*i).as_slice().unwrap())))
+                        .collect()
+                })
+                .collect()
+        });
         chunked_results
             .drain(..)
             .fold_first(|mut a, mut x| {
                 a.extend(x.drain(..));
                 a
             })
-            .unwrap()
+            .unwrap_or_default()
     }
 
     /// Bulk known path
@@ -124,6 +155,21 @@ impl<D: PointCloud> BulkInterface<D> {
         self.point_map_with_reader(points,|reader,p| reader.knn(p,k))
     }
 
+    /// Bulk knn on the rows of a matrix, with the distances and indexes of each row's neighbors as arrays. The rows
+    /// have to be contiguous, so the matrix has to be row major.
+    pub fn knn_array<'a>(
+        &self,
+        points: ArrayView2<'a, f32>,
+        k: usize,
+    ) -> Vec<GokoResult<(Array1<f32>, Array1<PointIndex>)>> {
+        self.array_map_with_reader(points, |reader, p| {
+            reader
+                .knn(p, k)
+                .map(|knn| knn.into_iter().unzip::<_, _, Vec<f32>, Vec<PointIndex>>())
+                .map(|(dists, indexes)| (Array1::from(dists), Array1::from(indexes)))
+        })
+    }
+
     /// Bulk routing knn
     pub fn routing_knn<'a>(
         &self,
@@ -137,9 +183,41 @@ impl<D: PointCloud> BulkInterface<D> {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use std::convert::TryFrom;
     use std::env;
 
-    use crate::covertree::tests::build_mnist_tree;
+    use crate::covertree::tests::{build_basic_tree, build_mnist_tree};
+
+    #[test]
+    fn bulk_knn_with_runtime() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let interface = BulkInterface::with_runtime(tree.reader(), GokoRuntime::new(1).unwrap());
+        let cloud = reader.point_cloud();
+
+        let points: Vec<PointRef> = (0..5).map(|i| cloud.point(i).unwrap()).collect();
+        let knn_results = interface.knn(&points, 2);
+        for (i, knn) in knn_results.iter().enumerate() {
+            let old_knn = reader.knn(cloud.point(i).unwrap(), 2).unwrap();
+            assert_eq!(knn.as_ref().unwrap(), &old_knn);
+        }
+    }
+
+    #[test]
+    fn bulk_knn_on_arrays() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let interface = BulkInterface::new(tree.reader());
+        let points = ndarray::arr2(&[[0.495f32], [-0.3], [0.01]]);
+
+        let knn_results = interface.knn_array(points.view(), 2);
+        for (row, knn) in points.genrows().into_iter().zip(&knn_results) {
+            let (dists, indexes) = knn.as_ref().unwrap();
+            let old_knn = reader.knn(PointRef::try_from(row).unwrap(), 2).unwrap();
+            assert_eq!(dists.to_vec(), old_knn.iter().map(|(d, _)| *d).collect::<Vec<f32>>());
+            assert_eq!(indexes.to_vec(), old_knn.iter().map(|(_, i)| *i).collect::<Vec<PointIndex>>());
+        }
+    }
 
     #[test]
     fn bulk_path() {
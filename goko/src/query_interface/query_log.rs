@@ -0,0 +1,298 @@
+//! A persistent log of the queries made against a tree, for audits and capacity planning.
+//!
+//! Each query is one tab separated line: the unix time in milliseconds, the kind of query, a fingerprint of the query
+//! point, the query's parameters, the latency in microseconds, and the number of results. The fingerprint is a 64 bit
+//! FNV-1a hash of a secret salt and the point's values as little endian bytes, so repeated queries can be matched up
+//! without the log holding the vectors. FNV-1a is fixed by its spec, so logs written on any platform or toolchain match.
+//! Keep the salt out of the log's reach, with it a guessed vector can be checked against the fingerprints. The raw
+//! values are only written, as a last column, if `persist_points` is turned on.
+//!
+//! The log rotates when the current file passes `max_bytes`. `queries.log` moves to `queries.log.1`, `queries.log.1`
+//! to `queries.log.2` and so on, and the file past `max_files` is deleted.
+
+use crate::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Where and how to write the query log.
+#[derive(Debug, Clone)]
+pub struct QueryLogConfig {
+    /// The current log file, older files get a numbered suffix
+    pub path: PathBuf,
+    /// Secret mixed into the fingerprints
+    pub salt: Vec<u8>,
+    /// The size the current file can reach before it's rotated
+    pub max_bytes: u64,
+    /// The number of rotated files kept
+    pub max_files: usize,
+    /// Also write the raw query values. Off by default
+    pub persist_points: bool,
+}
+
+impl QueryLogConfig {
+    /// Logs to `path` with the given salt, rotating every 64MB and keeping 4 old files. Points are not persisted.
+    pub fn new<P: AsRef<Path>>(path: P, salt: &[u8]) -> Self {
+        QueryLogConfig {
+            path: path.as_ref().to_path_buf(),
+            salt: salt.to_vec(),
+            max_bytes: 64 << 20,
+            max_files: 4,
+            persist_points: false,
+        }
+    }
+}
+
+/// 64 bit FNV-1a, which unlike the std hashers is specified and won't change between releases.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+struct LogFile {
+    writer: BufWriter<File>,
+    len: u64,
+}
+
+/// Writes query records to a rotating file. Can be shared between the threads answering queries.
+pub struct QueryLog {
+    config: QueryLogConfig,
+    file: Mutex<LogFile>,
+}
+
+impl QueryLog {
+    /// Opens the log, appending to the current file if there is one.
+    pub fn open(config: QueryLogConfig) -> GokoResult<QueryLog> {
+        let file = open_log_file(&config.path)?;
+        Ok(QueryLog {
+            config,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The salted fingerprint of a query point.
+    pub fn fingerprint<'a, T: Into<PointRef<'a>>>(&self, point: T) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(&self.config.salt);
+        match point.into() {
+            PointRef::Dense(vals) => {
+                vals.iter()
+                    .for_each(|x| hasher.write(&x.to_bits().to_le_bytes()));
+            }
+            PointRef::Sparse(vals, inds) => {
+                for (x, i) in vals.iter().zip(inds) {
+                    hasher.write(&i.to_le_bytes());
+                    hasher.write(&x.to_bits().to_le_bytes());
+                }
+            }
+            PointRef::Binary(words) => {
+                words.iter().for_each(|w| hasher.write(&w.to_le_bytes()));
+            }
+            PointRef::Text(text) => hasher.write(text.as_bytes()),
+        }
+        hasher.0
+    }
+
+    /// Writes one query's record. `parameters` is free text, like `k=10`, and mustn't contain tabs or newlines.
+    pub fn record<'a, T: Into<PointRef<'a>>>(
+        &self,
+        kind: &str,
+        point: T,
+        parameters: &str,
+        latency: Duration,
+        result_count: usize,
+    ) -> GokoResult<()> {
+        let point: PointRef<'a> = point.into();
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut line = format!(
+            "{}\t{}\t{:016x}\t{}\t{}\t{}",
+            time,
+            kind,
+            self.fingerprint(point),
+            parameters,
+            latency.as_micros(),
+            result_count
+        );
+        if self.config.persist_points {
+            line.push('\t');
+            line.push_str(&point_to_text(point));
+        }
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.len > 0 && file.len + line.len() as u64 > self.config.max_bytes {
+            file.writer.flush()?;
+            rotate(&self.config.path, self.config.max_files)?;
+            *file = open_log_file(&self.config.path)?;
+        }
+        file.writer.write_all(line.as_bytes())?;
+        file.writer.flush()?;
+        file.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// Runs a knn query on the tree and logs it.
+    pub fn knn<'a, D: PointCloud, T: Into<PointRef<'a>>>(
+        &self,
+        reader: &CoverTreeReader<D>,
+        point: T,
+        k: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point: PointRef<'a> = point.into();
+        let start = Instant::now();
+        let results = reader.knn(point, k)?;
+        self.record(
+            "knn",
+            point,
+            &format!("k={}", k),
+            start.elapsed(),
+            results.len(),
+        )?;
+        Ok(results)
+    }
+
+    /// Runs a range query on the tree and logs it.
+    pub fn range_query<'a, D: PointCloud, T: Into<PointRef<'a>>>(
+        &self,
+        reader: &CoverTreeReader<D>,
+        point: T,
+        radius: f32,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point: PointRef<'a> = point.into();
+        let start = Instant::now();
+        let results = reader.range_query(point, radius)?;
+        self.record(
+            "range",
+            point,
+            &format!("radius={}", radius),
+            start.elapsed(),
+            results.len(),
+        )?;
+        Ok(results)
+    }
+}
+
+fn open_log_file(path: &Path) -> GokoResult<LogFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let len = file.metadata()?.len();
+    Ok(LogFile {
+        writer: BufWriter::new(file),
+        len,
+    })
+}
+
+fn rotated_path(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+fn rotate(path: &Path, max_files: usize) -> GokoResult<()> {
+    if max_files == 0 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    let _ = fs::remove_file(rotated_path(path, max_files));
+    for i in (1..max_files).rev() {
+        let from = rotated_path(path, i);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, i + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))?;
+    Ok(())
+}
+
+fn point_to_text(point: PointRef) -> String {
+    match point {
+        PointRef::Dense(vals) => vals
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(","),
+        PointRef::Sparse(vals, inds) => vals
+            .iter()
+            .zip(inds)
+            .map(|(x, i)| format!("{}:{}", i, x))
+            .collect::<Vec<String>>()
+            .join(","),
+        PointRef::Binary(words) => words
+            .iter()
+            .map(|w| format!("{:016x}", w))
+            .collect::<Vec<String>>()
+            .join(","),
+        PointRef::Text(text) => format!("{:?}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use std::env;
+
+    #[test]
+    fn logs_and_rotates_without_points() {
+        let dir = env::temp_dir().join(format!("goko-query-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("queries.log");
+        let mut config = QueryLogConfig::new(&path, b"pepper");
+        config.max_bytes = 200;
+        config.max_files = 2;
+        let log = QueryLog::open(config).unwrap();
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+
+        let point = [0.49f32];
+        let knn = log.knn(&reader, &point[..], 2).unwrap();
+        assert_eq!(knn.len(), 2);
+        assert_eq!(knn, reader.knn(&point[..], 2).unwrap());
+        log.range_query(&reader, &point[..], 0.1).unwrap();
+        for _ in 0..10 {
+            log.knn(&reader, &[0.123456f32][..], 1).unwrap();
+        }
+
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(current.len() <= 200);
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        let line = current.lines().last().unwrap();
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[1], "knn");
+        assert_eq!(
+            fields[2],
+            format!("{:016x}", log.fingerprint(&[0.123456f32][..]))
+        );
+        assert_eq!(fields[3], "k=1");
+        assert_eq!(fields[5], "1");
+        assert!(!current.contains("0.123456"));
+
+        let other_salt =
+            QueryLog::open(QueryLogConfig::new(dir.join("other.log"), b"salt")).unwrap();
+        assert_ne!(
+            other_salt.fingerprint(&point[..]),
+            log.fingerprint(&point[..])
+        );
+        // Pinned, so a change to the hash that would break matching old logs fails here
+        assert_eq!(log.fingerprint(&point[..]), 0x1221_6f76_b9cb_76be);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
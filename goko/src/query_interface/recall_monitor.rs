@@ -0,0 +1,242 @@
+//! Continuous quality monitoring for live queries.
+//!
+//! A sampled fraction of the queries passed to the monitor are re-run by brute force in the background, and the recall of
+//! the tree's answer against the exact one is kept in a rolling window along with the query latency. Serve `stats` from
+//! whatever metrics endpoint the service has.
+//!
+//! The brute force runs on one worker thread per monitor, fed by a queue of `SAMPLE_QUEUE_CAPACITY` queries. When the
+//! worker falls behind, new samples are dropped rather than queued, so the monitor's cost is bounded under load.
+
+use crate::*;
+use crossbeam_channel::{bounded, Sender};
+use rand::Rng;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of sampled queries that can wait for the worker, further samples are dropped until it catches up.
+pub const SAMPLE_QUEUE_CAPACITY: usize = 64;
+
+enum Job {
+    Sample {
+        point: Point,
+        results: Vec<(f32, PointIndex)>,
+        latency: Duration,
+    },
+    Flush(Sender<()>),
+}
+
+/// The recall and latency of one sampled query.
+#[derive(Debug, Clone, Copy)]
+pub struct RecallSample {
+    /// Fraction of the returned neighbors that are within the exact k-th nearest distance
+    pub recall: f32,
+    /// How long the tree query took
+    pub latency: Duration,
+}
+
+/// Summary of the rolling window.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecallStats {
+    /// The number of samples in the window
+    pub samples: usize,
+    /// Mean recall over the window
+    pub mean_recall: f32,
+    /// Worst recall in the window
+    pub min_recall: f32,
+    /// Mean latency over the window
+    pub mean_latency: Duration,
+    /// Worst latency in the window
+    pub max_latency: Duration,
+}
+
+/// Samples live queries and tracks their recall against brute force ground truth.
+pub struct RecallMonitor<D: PointCloud> {
+    reader: CoverTreeReader<D>,
+    sample_rate: f64,
+    jobs: Sender<Job>,
+    samples: Arc<Mutex<VecDeque<RecallSample>>>,
+}
+
+impl<D: PointCloud> RecallMonitor<D> {
+    /// Evaluates a `sample_rate` fraction of the observed queries, and keeps the last `window` of them.
+    /// The ground truth is computed on the monitor's worker thread.
+    pub fn new(reader: CoverTreeReader<D>, sample_rate: f64, window: usize) -> Self {
+        RecallMonitor::start(reader, sample_rate, window, None)
+    }
+
+    /// Like `new`, but the worker computes the ground truth's distances on the runtime's pool.
+    pub fn with_runtime(
+        reader: CoverTreeReader<D>,
+        sample_rate: f64,
+        window: usize,
+        runtime: GokoRuntime,
+    ) -> Self {
+        RecallMonitor::start(reader, sample_rate, window, Some(runtime))
+    }
+
+    fn start(
+        reader: CoverTreeReader<D>,
+        sample_rate: f64,
+        window: usize,
+        runtime: Option<GokoRuntime>,
+    ) -> Self {
+        let window = window.max(1);
+        let samples = Arc::new(Mutex::new(VecDeque::new()));
+        let (jobs, queue) = bounded::<Job>(SAMPLE_QUEUE_CAPACITY);
+        let point_cloud = Arc::clone(reader.point_cloud());
+        let worker_samples = Arc::clone(&samples);
+        // The worker stops when the monitor, and with it the only sender, is dropped
+        thread::Builder::new()
+            .name("goko-recall".to_string())
+            .spawn(move || {
+                for job in queue {
+                    match job {
+                        Job::Sample {
+                            point,
+                            results,
+                            latency,
+                        } => {
+                            let recall = match &runtime {
+                                Some(runtime) => runtime.install(|| {
+                                    exact_recall(point_cloud.as_ref(), &point, &results)
+                                }),
+                                None => exact_recall(point_cloud.as_ref(), &point, &results),
+                            };
+                            if let Ok(recall) = recall {
+                                let mut samples = worker_samples.lock().unwrap();
+                                samples.push_back(RecallSample { recall, latency });
+                                while samples.len() > window {
+                                    samples.pop_front();
+                                }
+                            }
+                        }
+                        Job::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .expect("failed to spawn the recall monitor's worker");
+        RecallMonitor {
+            reader,
+            sample_rate,
+            jobs,
+            samples,
+        }
+    }
+
+    /// Runs a knn query on the tree and observes it.
+    pub fn knn<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        k: usize,
+    ) -> GokoResult<Vec<(f32, PointIndex)>> {
+        let point: PointRef<'a> = point.into();
+        let start = Instant::now();
+        let results = self.reader.knn(point, k)?;
+        self.observe(point, &results, start.elapsed());
+        Ok(results)
+    }
+
+    /// Records a query that was answered elsewhere. Returns true if it was sampled and queued for evaluation, a sample
+    /// is dropped if the queue is full.
+    pub fn observe<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        results: &[(f32, PointIndex)],
+        latency: Duration,
+    ) -> bool {
+        if results.is_empty() || rand::thread_rng().gen::<f64>() >= self.sample_rate {
+            return false;
+        }
+        let point = match point.into() {
+            PointRef::Dense(v) => Point::Dense(v.to_vec()),
+            PointRef::Sparse(v, i) => Point::Sparse(v.to_vec(), i.to_vec()),
+            PointRef::Binary(w) => Point::Binary(w.to_vec()),
+            PointRef::Text(t) => Point::Text(t.to_string()),
+        };
+        let job = Job::Sample {
+            point,
+            results: results.to_vec(),
+            latency,
+        };
+        self.jobs.try_send(job).is_ok()
+    }
+
+    /// Blocks until every query queued before this call has been evaluated.
+    pub fn flush(&self) {
+        let (done, wait) = bounded(1);
+        if self.jobs.send(Job::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// The samples currently in the window, oldest first.
+    pub fn samples(&self) -> Vec<RecallSample> {
+        self.samples.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Summarizes the window.
+    pub fn stats(&self) -> RecallStats {
+        let samples = self.samples.lock().unwrap();
+        if samples.is_empty() {
+            return RecallStats::default();
+        }
+        let n = samples.len();
+        RecallStats {
+            samples: n,
+            mean_recall: samples.iter().map(|s| s.recall).sum::<f32>() / n as f32,
+            min_recall: samples.iter().map(|s| s.recall).fold(1.0, f32::min),
+            mean_latency: samples.iter().map(|s| s.latency).sum::<Duration>() / n as u32,
+            max_latency: samples.iter().map(|s| s.latency).max().unwrap_or_default(),
+        }
+    }
+}
+
+/// The fraction of `results` within the exact distance of the `results.len()`-th nearest neighbor, so ties do not count
+/// against the tree.
+fn exact_recall<D: PointCloud>(
+    point_cloud: &D,
+    point: &Point,
+    results: &[(f32, PointIndex)],
+) -> GokoResult<f32> {
+    let indexes = point_cloud.reference_indexes();
+    let mut dists = point_cloud.distances_to_point(point, &indexes)?;
+    let k = results.len().min(dists.len());
+    if k == 0 {
+        return Ok(1.0);
+    }
+    dists.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let kth = dists[k - 1];
+    let found = results
+        .iter()
+        .filter(|(d, _)| *d <= kth + f32::EPSILON * kth.abs().max(1.0))
+        .count();
+    Ok(found as f32 / results.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn exact_tree_has_full_recall() {
+        let tree = build_basic_tree();
+        let monitor = RecallMonitor::new(tree.reader(), 1.0, 2);
+        for x in &[0.1f32, 0.3, 0.495] {
+            monitor.knn(&[*x][..], 3).unwrap();
+        }
+        monitor.flush();
+        let stats = monitor.stats();
+        assert_eq!(stats.samples, 2);
+        assert_approx_eq!(stats.mean_recall, 1.0);
+        assert_approx_eq!(stats.min_recall, 1.0);
+
+        let never = RecallMonitor::new(tree.reader(), 0.0, 2);
+        assert!(!never.observe(&[0.1f32][..], &[(0.0, 0)], Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,322 @@
+//! Notifications when activity reaches chosen parts of the tree.
+//!
+//! A subscriber registers a set of node addresses and gets a `RegionEvent` on a channel, or through a callback, each
+//! time a point's path passes through one of them. Paths come from `RegionSubscriptions::observe`, which routes a new
+//! point down the tree the same way an insert would, and from trackers wrapped in a `SubscribedTracker`. This lets a
+//! model retrain when its own region of the data sees traffic, rather than on global triggers.
+//!
+//! Callbacks are run on the thread that reported the path, after the subscription lock is released. A channel
+//! subscription is dropped once its receiver is.
+
+use crate::plugins::distributions::{
+    DiscreteBayesianDistribution, DiscreteBayesianSequenceTracker,
+};
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Identifies a subscription, for `unsubscribe`.
+pub type SubscriptionId = usize;
+
+/// What sent a path through the region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionActivity {
+    /// A point was routed through the tree with `observe`
+    Point,
+    /// A tracker added a path
+    Tracker,
+}
+
+/// A path passed through a subscribed node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionEvent {
+    /// The subscription this was sent to
+    pub subscription: SubscriptionId,
+    /// The deepest of the subscription's nodes that the path went through
+    pub address: NodeAddress,
+    /// The distance from the point to the center of that node
+    pub distance: f32,
+    /// Where the path came from
+    pub activity: RegionActivity,
+}
+
+#[derive(Clone)]
+enum Sink {
+    Channel(Sender<RegionEvent>),
+    Callback(Arc<dyn Fn(&RegionEvent) + Send + Sync>),
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: SubscriptionId,
+    sinks: HashMap<SubscriptionId, (Sink, Vec<NodeAddress>)>,
+    regions: HashMap<NodeAddress, Vec<SubscriptionId>>,
+}
+
+impl Registry {
+    fn insert(&mut self, addresses: &[NodeAddress], sink: Sink) -> SubscriptionId {
+        let id = self.next_id;
+        self.next_id += 1;
+        for address in addresses {
+            let subscribers = self.regions.entry(*address).or_default();
+            if !subscribers.contains(&id) {
+                subscribers.push(id);
+            }
+        }
+        self.sinks.insert(id, (sink, addresses.to_vec()));
+        id
+    }
+
+    fn remove(&mut self, id: SubscriptionId) -> bool {
+        match self.sinks.remove(&id) {
+            Some((_, addresses)) => {
+                for address in addresses {
+                    if let Some(subscribers) = self.regions.get_mut(&address) {
+                        subscribers.retain(|s| *s != id);
+                        if subscribers.is_empty() {
+                            self.regions.remove(&address);
+                        }
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The set of region subscriptions. Clones share the same subscriptions, so one can be handed to each tracker.
+#[derive(Clone, Default)]
+pub struct RegionSubscriptions {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl fmt::Debug for RegionSubscriptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registry = self.registry.lock().unwrap();
+        write!(
+            f,
+            "RegionSubscriptions {{ subscriptions: {}, regions: {} }}",
+            registry.sinks.len(),
+            registry.regions.len()
+        )
+    }
+}
+
+impl RegionSubscriptions {
+    /// An empty set of subscriptions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the nodes, and returns the receiving end of the events.
+    pub fn subscribe(&self, addresses: &[NodeAddress]) -> (SubscriptionId, Receiver<RegionEvent>) {
+        let (sender, receiver) = channel();
+        let id = self
+            .registry
+            .lock()
+            .unwrap()
+            .insert(addresses, Sink::Channel(sender));
+        (id, receiver)
+    }
+
+    /// Subscribes to the nodes, calling `callback` with each event.
+    pub fn subscribe_with<F>(&self, addresses: &[NodeAddress], callback: F) -> SubscriptionId
+    where
+        F: Fn(&RegionEvent) + Send + Sync + 'static,
+    {
+        self.registry
+            .lock()
+            .unwrap()
+            .insert(addresses, Sink::Callback(Arc::new(callback)))
+    }
+
+    /// Removes the subscription. Returns false if there was no such subscription.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.registry.lock().unwrap().remove(id)
+    }
+
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.registry.lock().unwrap().sinks.len()
+    }
+
+    /// If there are no live subscriptions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Routes the point down the tree, see `CoverTreeReader::path`, and notifies the subscribers of the nodes it went
+    /// through. Returns the path.
+    pub fn observe<'a, D: PointCloud, T: Into<PointRef<'a>>>(
+        &self,
+        reader: &CoverTreeReader<D>,
+        point: T,
+    ) -> GokoResult<Vec<(f32, NodeAddress)>> {
+        let path = reader.path(point)?;
+        self.notify_path(&path, RegionActivity::Point);
+        Ok(path)
+    }
+
+    /// Notifies the subscribers of the nodes on the path. Each subscription gets at most one event per path, for the
+    /// deepest of its nodes on it.
+    pub fn notify_path(&self, path: &[(f32, NodeAddress)], activity: RegionActivity) {
+        let deliveries: Vec<(Sink, RegionEvent)> = {
+            let registry = self.registry.lock().unwrap();
+            if registry.regions.is_empty() {
+                return;
+            }
+            let mut hits: HashMap<SubscriptionId, (f32, NodeAddress)> = HashMap::new();
+            for (distance, address) in path {
+                if let Some(subscribers) = registry.regions.get(address) {
+                    for id in subscribers {
+                        hits.insert(*id, (*distance, *address));
+                    }
+                }
+            }
+            hits.into_iter()
+                .filter_map(|(subscription, (distance, address))| {
+                    registry.sinks.get(&subscription).map(|(sink, _)| {
+                        let event = RegionEvent {
+                            subscription,
+                            address,
+                            distance,
+                            activity,
+                        };
+                        (sink.clone(), event)
+                    })
+                })
+                .collect()
+        };
+        let mut disconnected = Vec::new();
+        for (sink, event) in deliveries {
+            match sink {
+                Sink::Channel(sender) => {
+                    if sender.send(event).is_err() {
+                        disconnected.push(event.subscription);
+                    }
+                }
+                Sink::Callback(callback) => callback(&event),
+            }
+        }
+        if !disconnected.is_empty() {
+            let mut registry = self.registry.lock().unwrap();
+            for id in disconnected {
+                registry.remove(id);
+            }
+        }
+    }
+}
+
+/// Wraps a tracker so the paths added to it are reported to the subscriptions.
+#[derive(Debug)]
+pub struct SubscribedTracker<T> {
+    tracker: T,
+    subscriptions: RegionSubscriptions,
+}
+
+impl<T> SubscribedTracker<T> {
+    /// Wraps the tracker.
+    pub fn new(tracker: T, subscriptions: RegionSubscriptions) -> Self {
+        SubscribedTracker {
+            tracker,
+            subscriptions,
+        }
+    }
+
+    /// The wrapped tracker.
+    pub fn tracker(&self) -> &T {
+        &self.tracker
+    }
+
+    /// Unwraps the tracker.
+    pub fn into_inner(self) -> T {
+        self.tracker
+    }
+}
+
+impl<D: PointCloud, T: DiscreteBayesianSequenceTracker<D>> DiscreteBayesianSequenceTracker<D>
+    for SubscribedTracker<T>
+{
+    type Distribution = T::Distribution;
+
+    fn add_path(&mut self, trace: Vec<(f32, NodeAddress)>) {
+        self.subscriptions
+            .notify_path(&trace, RegionActivity::Tracker);
+        self.tracker.add_path(trace);
+    }
+
+    fn running_evidence(
+        &self,
+    ) -> &HashMap<NodeAddress, <Self::Distribution as DiscreteBayesianDistribution>::Evidence> {
+        self.tracker.running_evidence()
+    }
+
+    fn tree_reader(&self) -> &CoverTreeReader<D> {
+        self.tracker.tree_reader()
+    }
+
+    fn sequence_len(&self) -> usize {
+        self.tracker.sequence_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use crate::plugins::distributions::BayesCategoricalTracker;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn events_reach_subscribers_of_the_path() {
+        let writer = build_basic_tree();
+        let reader = writer.reader();
+        let subscriptions = RegionSubscriptions::new();
+        let path = reader.path(&[-0.5f32][..]).unwrap();
+        let (root_distance, root) = path[0];
+        let (leaf_distance, leaf) = *path.last().unwrap();
+        let (root_id, root_events) = subscriptions.subscribe(&[root, leaf]);
+        let unrelated = (-100, 0);
+        let (_, unrelated_events) = subscriptions.subscribe(&[unrelated]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let callback_calls = Arc::clone(&calls);
+        let callback_id = subscriptions.subscribe_with(&[root], move |event| {
+            assert_eq!(event.address, root);
+            callback_calls.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert_eq!(
+            subscriptions.observe(&reader, &[-0.5f32][..]).unwrap(),
+            path
+        );
+        let event = root_events.try_recv().unwrap();
+        assert_eq!(event.subscription, root_id);
+        assert_eq!(event.address, leaf);
+        assert_eq!(event.distance, leaf_distance);
+        assert_eq!(event.activity, RegionActivity::Point);
+        assert!(root_events.try_recv().is_err());
+        assert!(unrelated_events.try_recv().is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let mut tracker = SubscribedTracker::new(
+            BayesCategoricalTracker::new(1.0, 1.0, 0, reader.clone()),
+            subscriptions.clone(),
+        );
+        tracker.add_path(vec![(root_distance, root)]);
+        assert_eq!(tracker.sequence_len(), 1);
+        let event = root_events.try_recv().unwrap();
+        assert_eq!(event.address, root);
+        assert_eq!(event.activity, RegionActivity::Tracker);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        assert!(subscriptions.unsubscribe(callback_id));
+        assert!(!subscriptions.unsubscribe(callback_id));
+        drop(root_events);
+        subscriptions.notify_path(&path, RegionActivity::Point);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(subscriptions.len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+//! Assigning batches of new points to the nodes of a tree, without inserting them.
+//!
+//! Each point is routed the way an insert would route it, with `CoverTreeReader::path`, and is assigned to the finest
+//! node that covers it. `PendingCounts` keeps a running count of the points assigned to each node across batches, and
+//! weights it by the coverage of the node so that small nodes receiving a lot of new data stand out.
+
+use super::BulkInterface;
+use crate::*;
+use std::collections::HashMap;
+
+/// The number of new points assigned to each node. See `BulkInterface::assign`.
+#[derive(Debug, Clone, Default)]
+pub struct PendingCounts {
+    counts: HashMap<NodeAddress, usize>,
+    total: usize,
+}
+
+impl PendingCounts {
+    /// No points assigned yet.
+    pub fn new() -> PendingCounts {
+        PendingCounts::default()
+    }
+
+    /// Records a point assigned to the node.
+    pub fn add(&mut self, address: NodeAddress) {
+        *self.counts.entry(address).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// The number of points assigned to the node.
+    pub fn get(&self, address: NodeAddress) -> usize {
+        self.counts.get(&address).copied().unwrap_or(0)
+    }
+
+    /// The number of points assigned to any node.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Forgets all assignments, for example once the points have been inserted or labeled.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.total = 0;
+    }
+
+    /// The nodes with assigned points and their counts, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeAddress, usize)> + '_ {
+        self.counts.iter().map(|(a, c)| (*a, *c))
+    }
+
+    /// The pending count of each node divided by the number of points it already covers, largest first. Nodes that
+    /// are no longer in the tree are skipped.
+    pub fn coverage_weighted<D: PointCloud>(
+        &self,
+        reader: &CoverTreeReader<D>,
+    ) -> Vec<(f32, NodeAddress)> {
+        let mut weighted: Vec<(f32, NodeAddress)> = self
+            .counts
+            .iter()
+            .filter_map(|(address, count)| {
+                reader
+                    .get_node_and(*address, |n| n.coverage_count())
+                    .ok()
+                    .map(|coverage| (*count as f32 / coverage.max(1) as f32, *address))
+            })
+            .collect();
+        weighted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)));
+        weighted
+    }
+}
+
+impl<D: PointCloud> BulkInterface<D> {
+    /// Assigns each point to the finest node that covers it, and the distance to that node's center. The points are
+    /// not inserted, the assignments are added to `pending`.
+    pub fn assign<'a>(
+        &self,
+        points: &[PointRef<'a>],
+        pending: &mut PendingCounts,
+    ) -> Vec<GokoResult<(f32, NodeAddress)>> {
+        let assignments = self.point_map_with_reader(points, |reader, p| {
+            reader.path(p).map(|path| *path.last().unwrap())
+        });
+        for (_, address) in assignments.iter().flatten() {
+            pending.add(*address);
+        }
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn assign_matches_path() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let interface = BulkInterface::new(tree.reader());
+        let points: Vec<[f32; 1]> = vec![[0.495], [0.485], [0.01], [-0.3]];
+        let point_refs: Vec<PointRef> = points.iter().map(PointRef::from).collect();
+
+        let mut pending = PendingCounts::new();
+        let assignments = interface.assign(&point_refs, &mut pending);
+        assert_eq!(assignments.len(), 4);
+        for (point, assignment) in points.iter().zip(&assignments) {
+            let path = reader.path(point).unwrap();
+            assert_eq!(assignment.as_ref().unwrap(), path.last().unwrap());
+        }
+        assert_eq!(pending.total(), 4);
+        interface.assign(&point_refs[..1], &mut pending);
+        assert_eq!(pending.total(), 5);
+        let first = assignments[0].as_ref().unwrap().1;
+        assert!(pending.get(first) >= 2);
+
+        let weighted = pending.coverage_weighted(&reader);
+        assert_eq!(weighted.len(), pending.iter().count());
+        assert!(weighted.windows(2).all(|w| w[0].0 >= w[1].0));
+        let expected = pending.get(first) as f32
+            / reader.get_node_and(first, |n| n.coverage_count()).unwrap() as f32;
+        assert!(weighted.contains(&(expected, first)));
+
+        pending.clear();
+        assert_eq!(pending.total(), 0);
+        assert_eq!(pending.get(first), 0);
+    }
+}
@@ -0,0 +1,95 @@
+//! # Runtime
+//!
+//! By default goko spawns its work onto rayon's global pool, which is sized to the whole machine. If goko is embedded in a
+//! larger service this oversubscribes the host. A `GokoRuntime` owns a dedicated pool and the resource limits that go with it,
+//! and can be shared between tree construction (`CoverTreeBuilder::build_with_runtime`), plugin installation
+//! (`GokoRuntime::install`), and batch queries (`BulkInterface::with_runtime`).
+//!
+//! There is no IO pool to size. Goko reads points through the point cloud on whichever thread needs them, and the
+//! spills of a budgeted build are written by the thread doing the build, so IO threads are not configured here.
+
+use crate::errors::GokoResult;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::fmt;
+use std::sync::Arc;
+
+/// A shared, cheaply clonable handle to a rayon pool and the memory budget of the builds run on it.
+#[derive(Clone)]
+pub struct GokoRuntime {
+    pool: Arc<ThreadPool>,
+    memory_budget: Option<usize>,
+}
+
+impl fmt::Debug for GokoRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GokoRuntime")
+            .field("num_threads", &self.num_threads())
+            .field("memory_budget", &self.memory_budget)
+            .finish()
+    }
+}
+
+impl GokoRuntime {
+    /// Creates a runtime with a pool of `num_threads` threads. Pass 0 to let rayon decide.
+    pub fn new(num_threads: usize) -> GokoResult<GokoRuntime> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("goko-{}", i))
+            .build()?;
+        Ok(GokoRuntime {
+            pool: Arc::new(pool),
+            memory_budget: None,
+        })
+    }
+
+    /// Sets the bytes a build on this runtime keeps in ram for the nodes waiting to be split, the rest are spilled to
+    /// disk. See `CoverTreeParams::memory_budget`, which takes precedence over this.
+    ///
+    /// A budgeted build splits its nodes one at a time on the calling thread, so it doesn't use the pool to split nodes
+    /// concurrently like an unbudgeted build does. Only the distances of large nodes are computed across the pool.
+    pub fn set_memory_budget(&mut self, x: usize) -> &mut Self {
+        self.memory_budget = Some(x);
+        self
+    }
+
+    /// The number of threads in the compute pool.
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// The memory budget in bytes, if one was set.
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Runs the closure inside the pool. Any rayon parallel iterators or spawns inside the closure stay on this pool.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        self.pool.install(op)
+    }
+
+    /// Spawns a task on the pool. Any rayon spawns inside the task stay on this pool.
+    pub fn spawn<OP>(&self, op: OP)
+    where
+        OP: FnOnce() + Send + 'static,
+    {
+        self.pool.spawn(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_pool_size() {
+        let mut runtime = GokoRuntime::new(2).unwrap();
+        runtime.set_memory_budget(1024);
+        assert_eq!(runtime.num_threads(), 2);
+        assert_eq!(runtime.memory_budget(), Some(1024));
+        assert_eq!(runtime.install(rayon::current_num_threads), 2);
+    }
+}
@@ -0,0 +1,287 @@
+//! Named copies of a tree, kept around so queries can be run "as of" an older version of the index.
+//!
+//! A snapshot is a frozen copy of the tree's structure, taken from the writer with `SnapshotStore::retain`. It shares the
+//! point cloud with the live tree. Node plugins are not copied, add the ones you need to the snapshot's tree after
+//! retaining it. This is mostly for debugging why a query's results changed between index versions.
+
+use crate::errors::GokoError;
+use crate::plugins::distributions::DiscreteBayesianSequenceTracker;
+use crate::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A retained copy of the tree.
+pub struct TreeSnapshot<D: PointCloud> {
+    name: String,
+    generation: usize,
+    created: SystemTime,
+    tree: CoverTreeWriter<D>,
+}
+
+impl<D: PointCloud> TreeSnapshot<D> {
+    /// The name it was retained under.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The generation of the live tree when this was taken.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// When this was taken.
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    /// A reader for querying the snapshot.
+    pub fn reader(&self) -> CoverTreeReader<D> {
+        self.tree.reader()
+    }
+
+    /// The snapshot's own tree, for adding plugins to it.
+    pub fn tree_mut(&mut self) -> &mut CoverTreeWriter<D> {
+        &mut self.tree
+    }
+}
+
+/// How the knn results for a query differ between two snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct KnnDiff {
+    /// The results from the first snapshot
+    pub before: Vec<(f32, PointIndex)>,
+    /// The results from the second snapshot
+    pub after: Vec<(f32, PointIndex)>,
+    /// Points only the first snapshot returned
+    pub dropped: Vec<PointIndex>,
+    /// Points only the second snapshot returned
+    pub added: Vec<PointIndex>,
+}
+
+/// Holds named snapshots, oldest first.
+pub struct SnapshotStore<D: PointCloud> {
+    snapshots: Vec<TreeSnapshot<D>>,
+    max_retained: Option<usize>,
+}
+
+impl<D: PointCloud> Default for SnapshotStore<D> {
+    fn default() -> Self {
+        SnapshotStore {
+            snapshots: Vec::new(),
+            max_retained: None,
+        }
+    }
+}
+
+impl<D: PointCloud> SnapshotStore<D> {
+    /// An empty store that keeps everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the `x` most recent snapshots, older ones are dropped as new ones are retained.
+    pub fn set_max_retained(&mut self, x: usize) -> &mut Self {
+        self.max_retained = Some(x.max(1));
+        self.evict();
+        self
+    }
+
+    /// Copies the writer's current tree and keeps it under `name`, replacing any snapshot that already has the name.
+    pub fn retain(
+        &mut self,
+        name: &str,
+        writer: &CoverTreeWriter<D>,
+    ) -> GokoResult<&mut TreeSnapshot<D>> {
+        let reader = writer.reader();
+        let tree = CoverTreeWriter::load(&writer.save(), Arc::clone(reader.point_cloud()))?;
+        self.snapshots.retain(|s| s.name != name);
+        self.snapshots.push(TreeSnapshot {
+            name: name.to_string(),
+            generation: reader.generation(),
+            created: SystemTime::now(),
+            tree,
+        });
+        self.evict();
+        Ok(self.snapshots.last_mut().unwrap())
+    }
+
+    fn evict(&mut self) {
+        if let Some(max) = self.max_retained {
+            if self.snapshots.len() > max {
+                let extra = self.snapshots.len() - max;
+                self.snapshots.drain(..extra);
+            }
+        }
+    }
+
+    /// Drops a snapshot. Returns false if there wasn't one with that name.
+    pub fn release(&mut self, name: &str) -> bool {
+        let len = self.snapshots.len();
+        self.snapshots.retain(|s| s.name != name);
+        len != self.snapshots.len()
+    }
+
+    /// The names of the retained snapshots, oldest first.
+    pub fn names(&self) -> Vec<&str> {
+        self.snapshots.iter().map(|s| s.name()).collect()
+    }
+
+    /// The snapshot with this name.
+    pub fn get(&self, name: &str) -> GokoResult<&TreeSnapshot<D>> {
+        self.snapshots
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| GokoError::SnapshotNotFound(name.to_string()))
+    }
+
+    /// The snapshot with this name, for adding plugins to it.
+    pub fn get_mut(&mut self, name: &str) -> GokoResult<&mut TreeSnapshot<D>> {
+        self.snapshots
+            .iter_mut()
+            .find(|s| s.name == name)
+            .ok_or_else(|| GokoError::SnapshotNotFound(name.to_string()))
+    }
+
+    /// The tree as of the given generation, the most recent snapshot taken at or before it.
+    pub fn at_generation(&self, generation: usize) -> GokoResult<&TreeSnapshot<D>> {
+        self.snapshots
+            .iter()
+            .filter(|s| s.generation <= generation)
+            .max_by_key(|s| s.generation)
+            .ok_or_else(|| GokoError::SnapshotNotFound(format!("generation {}", generation)))
+    }
+
+    /// Runs a query against the named snapshot.
+    pub fn query<F, T>(&self, name: &str, query: F) -> GokoResult<T>
+    where
+        F: FnOnce(&CoverTreeReader<D>) -> GokoResult<T>,
+    {
+        query(&self.get(name)?.reader())
+    }
+
+    /// Runs the same knn query against two snapshots and reports which results changed.
+    pub fn compare_knn<'a, T: Into<PointRef<'a>>>(
+        &self,
+        before: &str,
+        after: &str,
+        point: T,
+        k: usize,
+    ) -> GokoResult<KnnDiff> {
+        let point: PointRef<'a> = point.into();
+        let before = self.get(before)?.reader().knn(point, k)?;
+        let after = self.get(after)?.reader().knn(point, k)?;
+        let before_set: HashSet<PointIndex> = before.iter().map(|(_, pi)| *pi).collect();
+        let after_set: HashSet<PointIndex> = after.iter().map(|(_, pi)| *pi).collect();
+        let dropped = before
+            .iter()
+            .map(|(_, pi)| *pi)
+            .filter(|pi| !after_set.contains(pi))
+            .collect();
+        let added = after
+            .iter()
+            .map(|(_, pi)| *pi)
+            .filter(|pi| !before_set.contains(pi))
+            .collect();
+        Ok(KnnDiff {
+            before,
+            after,
+            dropped,
+            added,
+        })
+    }
+
+    /// Feeds the same sequence of points to a tracker on each of two snapshots, so their KL divergence stats can be
+    /// compared. The snapshots need the tracker's distribution plugin.
+    pub fn compare_trackers<'a, T, F>(
+        &self,
+        before: &str,
+        after: &str,
+        points: &[PointRef<'a>],
+        new_tracker: F,
+    ) -> GokoResult<(T, T)>
+    where
+        T: DiscreteBayesianSequenceTracker<D>,
+        F: Fn(CoverTreeReader<D>) -> T,
+    {
+        let run = |name: &str| -> GokoResult<T> {
+            let reader = self.get(name)?.reader();
+            let mut tracker = new_tracker(reader.clone());
+            for point in points {
+                tracker.add_path(reader.path(*point)?);
+            }
+            Ok(tracker)
+        };
+        Ok((run(before)?, run(after)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use crate::plugins::distributions::*;
+
+    #[test]
+    fn queries_as_of_snapshots() {
+        let mut writer = build_basic_tree();
+        let mut store = SnapshotStore::new();
+        store
+            .retain("before", &writer)
+            .unwrap()
+            .tree_mut()
+            .add_plugin::<GokoDirichlet>(DirichletTree {})
+            .unwrap();
+        let before_generation = store.get("before").unwrap().generation();
+        writer.remove_point(1).unwrap();
+        store
+            .retain("after", &writer)
+            .unwrap()
+            .tree_mut()
+            .add_plugin::<GokoDirichlet>(DirichletTree {})
+            .unwrap();
+
+        assert_eq!(store.names(), vec!["before", "after"]);
+        assert_eq!(
+            store.at_generation(before_generation).unwrap().name(),
+            "before"
+        );
+        assert_eq!(
+            store
+                .at_generation(writer.reader().generation())
+                .unwrap()
+                .name(),
+            "after"
+        );
+        assert!(store.get("yesterday").is_err());
+
+        let count = store
+            .query("before", |r| {
+                r.get_node_and(r.root_address(), |n| n.coverage_count())
+            })
+            .unwrap();
+        assert_eq!(count, 5);
+
+        let diff = store
+            .compare_knn("before", "after", &[0.49f32][..], 1)
+            .unwrap();
+        assert_eq!(diff.dropped, vec![1]);
+        assert_eq!(diff.added.len(), 1);
+
+        let point_cloud = Arc::clone(writer.reader().point_cloud());
+        let points: Vec<PointRef> = (0..5).map(|i| point_cloud.point(i).unwrap()).collect();
+        let (before, after) = store
+            .compare_trackers("before", "after", &points, |r| {
+                BayesCategoricalTracker::new(1.0, 1.0, 0, r)
+            })
+            .unwrap();
+        assert_eq!(before.sequence_len(), 5);
+        assert_eq!(after.sequence_len(), 5);
+        before.kl_div_stats();
+        after.kl_div_stats();
+
+        store.set_max_retained(1);
+        assert_eq!(store.names(), vec!["after"]);
+        assert!(store.release("after"));
+    }
+}
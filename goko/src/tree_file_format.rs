@@ -38,6 +38,7 @@ pub struct NodeProto {
     pub outlier_point_indexes: ::std::vec::Vec<u64>,
     pub outlier_summary_json: ::std::string::String,
     pub radius: f32,
+    pub annotation: ::std::string::String,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -274,6 +275,32 @@ impl NodeProto {
     pub fn set_radius(&mut self, v: f32) {
         self.radius = v;
     }
+
+    // string annotation = 13;
+
+
+    pub fn get_annotation(&self) -> &str {
+        &self.annotation
+    }
+    pub fn clear_annotation(&mut self) {
+        self.annotation.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_annotation(&mut self, v: ::std::string::String) {
+        self.annotation = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_annotation(&mut self) -> &mut ::std::string::String {
+        &mut self.annotation
+    }
+
+    // Take field
+    pub fn take_annotation(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.annotation, ::std::string::String::new())
+    }
 }
 
 impl ::protobuf::Message for NodeProto {
@@ -353,6 +380,9 @@ impl ::protobuf::Message for NodeProto {
                     let tmp = is.read_float()?;
                     self.radius = tmp;
                 },
+                13 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.annotation)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -401,6 +431,9 @@ impl ::protobuf::Message for NodeProto {
         if self.radius != 0. {
             my_size += 5;
         }
+        if !self.annotation.is_empty() {
+            my_size += ::protobuf::rt::string_size(13, &self.annotation);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -443,6 +476,9 @@ impl ::protobuf::Message for NodeProto {
         if self.radius != 0. {
             os.write_float(12, self.radius)?;
         }
+        if !self.annotation.is_empty() {
+            os.write_string(13, &self.annotation)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -541,6 +577,11 @@ impl ::protobuf::Message for NodeProto {
                 |m: &NodeProto| { &m.radius },
                 |m: &mut NodeProto| { &mut m.radius },
             ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "annotation",
+                |m: &NodeProto| { &m.annotation },
+                |m: &mut NodeProto| { &mut m.annotation },
+            ));
             ::protobuf::reflect::MessageDescriptor::new_pb_name::<NodeProto>(
                 "NodeProto",
                 fields,
@@ -569,6 +610,7 @@ impl ::protobuf::Clear for NodeProto {
         self.outlier_point_indexes.clear();
         self.outlier_summary_json.clear();
         self.radius = 0.;
+        self.annotation.clear();
         self.unknown_fields.clear();
     }
 }
@@ -799,6 +841,8 @@ pub struct CoreProto {
     pub root_scale: i32,
     pub root_index: u64,
     pub layers: ::protobuf::RepeatedField<LayerProto>,
+    pub normalized: bool,
+    pub singleton_threshold: u64,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -985,6 +1029,36 @@ impl CoreProto {
     pub fn take_layers(&mut self) -> ::protobuf::RepeatedField<LayerProto> {
         ::std::mem::replace(&mut self.layers, ::protobuf::RepeatedField::new())
     }
+
+    // bool normalized = 12;
+
+
+    pub fn get_normalized(&self) -> bool {
+        self.normalized
+    }
+    pub fn clear_normalized(&mut self) {
+        self.normalized = false;
+    }
+
+    // Param is passed by value, moved
+    pub fn set_normalized(&mut self, v: bool) {
+        self.normalized = v;
+    }
+
+    // uint64 singleton_threshold = 13;
+
+
+    pub fn get_singleton_threshold(&self) -> u64 {
+        self.singleton_threshold
+    }
+    pub fn clear_singleton_threshold(&mut self) {
+        self.singleton_threshold = 0;
+    }
+
+    // Param is passed by value, moved
+    pub fn set_singleton_threshold(&mut self, v: u64) {
+        self.singleton_threshold = v;
+    }
 }
 
 impl ::protobuf::Message for CoreProto {
@@ -1063,6 +1137,20 @@ impl ::protobuf::Message for CoreProto {
                 11 => {
                     ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.layers)?;
                 },
+                12 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_bool()?;
+                    self.normalized = tmp;
+                },
+                13 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.singleton_threshold = tmp;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -1106,6 +1194,12 @@ impl ::protobuf::Message for CoreProto {
             let len = value.compute_size();
             my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
         };
+        if self.normalized != false {
+            my_size += 2;
+        }
+        if self.singleton_threshold != 0 {
+            my_size += ::protobuf::rt::value_size(13, self.singleton_threshold, ::protobuf::wire_format::WireTypeVarint);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -1144,6 +1238,12 @@ impl ::protobuf::Message for CoreProto {
             os.write_raw_varint32(v.get_cached_size())?;
             v.write_to_with_cached_sizes(os)?;
         };
+        if self.normalized != false {
+            os.write_bool(12, self.normalized)?;
+        }
+        if self.singleton_threshold != 0 {
+            os.write_uint64(13, self.singleton_threshold)?;
+        }
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1232,6 +1332,16 @@ impl ::protobuf::Message for CoreProto {
                 |m: &CoreProto| { &m.layers },
                 |m: &mut CoreProto| { &mut m.layers },
             ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeBool>(
+                "normalized",
+                |m: &CoreProto| { &m.normalized },
+                |m: &mut CoreProto| { &mut m.normalized },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "singleton_threshold",
+                |m: &CoreProto| { &m.singleton_threshold },
+                |m: &mut CoreProto| { &mut m.singleton_threshold },
+            ));
             ::protobuf::reflect::MessageDescriptor::new_pb_name::<CoreProto>(
                 "CoreProto",
                 fields,
@@ -1258,6 +1368,8 @@ impl ::protobuf::Clear for CoreProto {
         self.root_scale = 0;
         self.root_index = 0;
         self.layers.clear();
+        self.normalized = false;
+        self.singleton_threshold = 0;
         self.unknown_fields.clear();
     }
 }
@@ -1275,7 +1387,7 @@ impl ::protobuf::reflect::ProtobufValue for CoreProto {
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x16tree_file_format.proto\x12\tCoverTree\"\x85\x04\n\tNodeProto\x12%\
+    \n\x16tree_file_format.proto\x12\tCoverTree\"\xa5\x04\n\tNodeProto\x12%\
     \n\x0ecoverage_count\x18\x01\x20\x01(\x04R\rcoverageCount\x12!\n\x0ccent\
     er_index\x18\x02\x20\x01(\x04R\x0bcenterIndex\x12\x1f\n\x0bscale_index\
     \x18\x03\x20\x01(\x05R\nscaleIndex\x12.\n\x13parent_center_index\x18\x04\
@@ -1287,17 +1399,19 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \x20\x01(\x05R\x10nestedScaleIndex\x122\n\x15outlier_point_indexes\x18\n\
     \x20\x03(\x04R\x13outlierPointIndexes\x120\n\x14outlier_summary_json\x18\
     \x0b\x20\x01(\tR\x12outlierSummaryJson\x12\x16\n\x06radius\x18\x0c\x20\
-    \x01(\x02R\x06radius\"Y\n\nLayerProto\x12\x1f\n\x0bscale_index\x18\x01\
-    \x20\x01(\x05R\nscaleIndex\x12*\n\x05nodes\x18\x02\x20\x03(\x0b2\x14.Cov\
-    erTree.NodeProtoR\x05nodes\"\xc5\x02\n\tCoreProto\x12%\n\x0euse_singleto\
-    ns\x18\x01\x20\x01(\x08R\ruseSingletons\x12\x1d\n\nscale_base\x18\x02\
-    \x20\x01(\x02R\tscaleBase\x12\x16\n\x06cutoff\x18\x03\x20\x01(\x04R\x06c\
-    utoff\x12\x1e\n\nresolution\x18\x04\x20\x01(\x11R\nresolution\x12%\n\x0e\
-    partition_type\x18\x05\x20\x01(\tR\rpartitionType\x12\x10\n\x03dim\x18\
-    \x07\x20\x01(\x04R\x03dim\x12\x14\n\x05count\x18\x08\x20\x01(\x04R\x05co\
-    unt\x12\x1d\n\nroot_scale\x18\t\x20\x01(\x05R\trootScale\x12\x1d\n\nroot\
-    _index\x18\n\x20\x01(\x04R\trootIndex\x12-\n\x06layers\x18\x0b\x20\x03(\
-    \x0b2\x15.CoverTree.LayerProtoR\x06layersb\x06proto3\
+    \x01(\x02R\x06radius\x12\x1e\n\nannotation\x18\r\x20\x01(\tR\nannotation\
+    \"Y\n\nLayerProto\x12\x1f\n\x0bscale_index\x18\x01\x20\x01(\x05R\nscaleI\
+    ndex\x12*\n\x05nodes\x18\x02\x20\x03(\x0b2\x14.CoverTree.NodeProtoR\x05n\
+    odes\"\x96\x03\n\tCoreProto\x12%\n\x0euse_singletons\x18\x01\x20\x01(\
+    \x08R\ruseSingletons\x12\x1d\n\nscale_base\x18\x02\x20\x01(\x02R\tscaleB\
+    ase\x12\x16\n\x06cutoff\x18\x03\x20\x01(\x04R\x06cutoff\x12\x1e\n\nresol\
+    ution\x18\x04\x20\x01(\x11R\nresolution\x12%\n\x0epartition_type\x18\x05\
+    \x20\x01(\tR\rpartitionType\x12\x10\n\x03dim\x18\x07\x20\x01(\x04R\x03di\
+    m\x12\x14\n\x05count\x18\x08\x20\x01(\x04R\x05count\x12\x1d\n\nroot_scal\
+    e\x18\t\x20\x01(\x05R\trootScale\x12\x1d\n\nroot_index\x18\n\x20\x01(\
+    \x04R\trootIndex\x12-\n\x06layers\x18\x0b\x20\x03(\x0b2\x15.CoverTree.La\
+    yerProtoR\x06layers\x12\x1e\n\nnormalized\x18\x0c\x20\x01(\x08R\nnormalized\x12/\n\x13single\
+    ton_threshold\x18\r\x20\x01(\x04R\x12singletonThresholdb\x06proto3\
 ";
 
 static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::rt::LazyV2::INIT;
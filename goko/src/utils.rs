@@ -19,11 +19,12 @@
 
 //! Utility functions for i/o
 
-use crate::errors::{GokoError, GokoResult};
+use crate::errors::{ErrorContextExt, GokoError, GokoResult, ParsingError};
 use crate::tree_file_format::*;
 use protobuf::{CodedInputStream, CodedOutputStream, Message};
 use std::fs::File;
-use std::fs::{read_to_string, remove_file, OpenOptions};
+use std::fs::{create_dir_all, read_to_string, remove_file, OpenOptions};
+use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 use yaml_rust::YamlLoader;
@@ -32,7 +33,8 @@ use crate::builders::CoverTreeBuilder;
 
 use crate::CoverTreeWriter;
 
-use pointcloud::loaders::{labeled_ram_from_yaml, ram_from_yaml};
+use pointcloud::data_sources::{replace_file, DataBackend, DataMemmap};
+use pointcloud::loaders::{backend_from_yaml, labeled_ram_from_yaml, ram_from_yaml};
 use pointcloud::*;
 
 /// Given a yaml file on disk, it builds a covertree.
@@ -56,7 +58,7 @@ pub fn cover_tree_from_labeled_yaml<P: AsRef<Path>>(
     let params_files = YamlLoader::load_from_str(&config).unwrap();
     let params = &params_files[0];
 
-    let point_cloud = labeled_ram_from_yaml::<_, L2>(&path)?;
+    let point_cloud = labeled_ram_from_yaml::<_, L2>(&path).at_path(&path)?;
     if let Some(count) = params["count"].as_i64() {
         if count as usize != point_cloud.len() {
             panic!(
@@ -96,7 +98,7 @@ pub fn cover_tree_from_yaml<P: AsRef<Path>>(
     let params_files = YamlLoader::load_from_str(&config).unwrap();
     let params = &params_files[0];
 
-    let point_cloud = ram_from_yaml::<_, L2>(&path)?;
+    let point_cloud = ram_from_yaml::<_, L2>(&path).at_path(&path)?;
     if let Some(count) = params["count"].as_i64() {
         if count as usize != point_cloud.len() {
             panic!(
@@ -142,6 +144,15 @@ pub fn load_tree<P: AsRef<Path>, D: PointCloud>(
         panic!("Proto buff was unable to read {:#?}", e)
     }
 
+    CoverTreeWriter::load(&cover_proto, point_cloud).at_path(tree_path_ref)
+}
+
+/// Decodes a tree from an in-memory protobuf. Malformed bytes and trees return an error rather than panicking.
+pub fn load_tree_from_bytes<D: PointCloud>(
+    bytes: &[u8],
+    point_cloud: Arc<D>,
+) -> GokoResult<CoverTreeWriter<D>> {
+    let cover_proto = CoreProto::parse_from_bytes(bytes)?;
     CoverTreeWriter::load(&cover_proto, point_cloud)
 }
 
@@ -159,7 +170,7 @@ pub fn save_tree<P: AsRef<Path>, D: PointCloud>(
             None => panic!("Unicode error with the tree path"),
         };
         println!("\t \t {:?} exists, removing", tree_path_str);
-        remove_file(&tree_path).map_err(GokoError::from)?;
+        remove_file(&tree_path).at_path(tree_path_ref)?;
     }
 
     let cover_proto = cover_tree.save();
@@ -172,7 +183,216 @@ pub fn save_tree<P: AsRef<Path>, D: PointCloud>(
         .unwrap();
 
     let mut cos = CodedOutputStream::new(&mut core_file);
-    cover_proto.write_to(&mut cos).map_err(GokoError::from)?;
-    cos.flush().map_err(GokoError::from)?;
+    cover_proto.write_to(&mut cos).at_path(tree_path_ref)?;
+    cos.flush().at_path(tree_path_ref)?;
     Ok(())
 }
+
+const SHARED_TREE_FILE: &str = "tree.dat";
+const SHARED_POINTS_FILE: &str = "points.f32";
+
+/// Writes the tree and its points into a directory, usually under `/dev/shm`, so that the worker processes on a host can
+/// all attach to it with `attach_shared_tree`. The points are written at their index, so the attached tree sees the same
+/// indexes.
+pub fn publish_shared_tree<P: AsRef<Path>, D: PointCloud>(
+    dir: P,
+    cover_tree: &CoverTreeWriter<D>,
+) -> GokoResult<()> {
+    let dir: &Path = dir.as_ref();
+    create_dir_all(dir).at_path(dir)?;
+
+    // Workers attached to an earlier publish have the files mapped, so they're written to temporaries and renamed
+    // over the old ones rather than truncated in place.
+    let points_path = dir.join(SHARED_POINTS_FILE);
+    let point_cloud = cover_tree.reader().point_cloud().clone();
+    let dim = point_cloud.dim();
+    // The memmap is indexed by position, so indexes the cloud doesn't have, like the tombstones of a removed segment,
+    // are written as NaNs to keep the rest in place. The tree doesn't reference them.
+    let mut indexes = point_cloud.reference_indexes();
+    indexes.sort_unstable();
+    replace_file(&points_path, |points_file| {
+        let mut next = 0;
+        for i in indexes {
+            for _ in next..i {
+                for _ in 0..dim {
+                    points_file.write_all(&f32::NAN.to_ne_bytes())?;
+                }
+            }
+            for x in point_cloud.point(i)?.dense_iter(dim) {
+                points_file.write_all(&x.to_ne_bytes())?;
+            }
+            next = i + 1;
+        }
+        Ok(())
+    })
+    .at_path(&points_path)?;
+
+    let tree_path = dir.join(SHARED_TREE_FILE);
+    let bytes = cover_tree.save().write_to_bytes().at_path(&tree_path)?;
+    replace_file(&tree_path, |tree_file| {
+        tree_file.write_all(&bytes)?;
+        Ok(())
+    })
+    .at_path(&tree_path)
+}
+
+/// Attaches to a tree written by `publish_shared_tree`. The points are memory mapped read-only, so every process that
+/// attaches shares one copy of them. The nodes are decoded into this process, they are small next to the points.
+pub fn attach_shared_tree<P: AsRef<Path>, M: Metric>(
+    dir: P,
+) -> GokoResult<CoverTreeWriter<DataMemmap<M>>> {
+    let dir: &Path = dir.as_ref();
+    let tree_path = dir.join(SHARED_TREE_FILE);
+    let points_path = dir.join(SHARED_POINTS_FILE);
+
+    let mut file = File::open(&tree_path).at_path(&tree_path)?;
+    let mut cis = CodedInputStream::new(&mut file);
+    let mut cover_proto = CoreProto::new();
+    cover_proto.merge_from(&mut cis).at_path(&tree_path)?;
+
+    let point_cloud = DataMemmap::<M>::open_read_only(cover_proto.get_dim() as usize, &points_path)
+        .at_path(&points_path)?;
+    CoverTreeWriter::load(&cover_proto, Arc::new(point_cloud)).at_path(&tree_path)
+}
+
+const BUNDLE_MANIFEST_FILE: &str = "bundle.yml";
+
+/// Reads the points named in a yaml config, builds the tree with the config's parameters, and writes a bundle directory
+/// that `open_bundle` can load. The config is the same one `cover_tree_from_yaml` takes. The bundle holds the points
+/// packed row-major as native `f32`s, which is how every metric reads them, the tree, and a `bundle.yml` manifest with
+/// the metric name, dimension and point count. The points are opened with `backend_from_yaml`, so the config's
+/// `backend` decides if they're built from ram or memory mapped.
+pub fn build_packed<P: AsRef<Path>, Q: AsRef<Path>, M: Metric>(
+    config: P,
+    bundle_dir: Q,
+) -> GokoResult<CoverTreeWriter<DataBackend<M>>> {
+    let point_cloud = backend_from_yaml::<_, M>(&config).at_path(config.as_ref())?;
+    let builder = CoverTreeBuilder::from_yaml(&config);
+    let tree = builder.build(Arc::new(point_cloud))?;
+
+    let bundle_dir: &Path = bundle_dir.as_ref();
+    publish_shared_tree(bundle_dir, &tree)?;
+    let manifest_path = bundle_dir.join(BUNDLE_MANIFEST_FILE);
+    let manifest = format!(
+        "---\nmetric: {}\ndata_dim: {}\ncount: {}\npoints_path: {}\ntree_path: {}\n",
+        M::name(),
+        tree.reader().point_cloud().dim(),
+        tree.reader().point_cloud().len(),
+        SHARED_POINTS_FILE,
+        SHARED_TREE_FILE,
+    );
+    let mut manifest_file = File::create(&manifest_path).at_path(&manifest_path)?;
+    manifest_file
+        .write_all(manifest.as_bytes())
+        .at_path(&manifest_path)?;
+    Ok(tree)
+}
+
+/// Opens a bundle written by `build_packed`. The metric must match the one the bundle was built with.
+pub fn open_bundle<P: AsRef<Path>, M: Metric>(
+    bundle_dir: P,
+) -> GokoResult<CoverTreeWriter<DataMemmap<M>>> {
+    let bundle_dir: &Path = bundle_dir.as_ref();
+    let manifest_path = bundle_dir.join(BUNDLE_MANIFEST_FILE);
+    let manifest = read_to_string(&manifest_path).at_path(&manifest_path)?;
+    let file_name = manifest_path.to_string_lossy().to_string();
+    let params = YamlLoader::load_from_str(&manifest)
+        .ok()
+        .and_then(|mut docs| docs.pop())
+        .ok_or_else(|| {
+            GokoError::ParsingError(ParsingError::MalformedYamlError {
+                file_name: file_name.clone(),
+                field: "".to_string(),
+            })
+        })?;
+    match params["metric"].as_str() {
+        Some(metric) if metric == M::name() => {}
+        Some(_) => {
+            return Err(GokoError::ParsingError(ParsingError::MalformedYamlError {
+                file_name,
+                field: "metric".to_string(),
+            }))
+        }
+        None => {
+            return Err(GokoError::ParsingError(ParsingError::MissingYamlError {
+                file_name,
+                field: "metric".to_string(),
+            }))
+        }
+    }
+
+    let tree = attach_shared_tree::<_, M>(bundle_dir)?;
+    if params["count"].as_i64() != Some(tree.reader().point_cloud().len() as i64) {
+        return Err(GokoError::ParsingError(ParsingError::MalformedYamlError {
+            file_name,
+            field: "count".to_string(),
+        }));
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+    use std::env;
+    use std::fs::remove_dir_all;
+
+    #[test]
+    fn shared_tree_round_trip() {
+        let dir = env::temp_dir().join(format!("goko-shared-{}", std::process::id()));
+        let tree = build_basic_tree();
+        publish_shared_tree(&dir, &tree).unwrap();
+
+        let shared = attach_shared_tree::<_, L2>(&dir).unwrap();
+        let reader = shared.reader();
+        assert_eq!(reader.node_count(), tree.reader().node_count());
+        assert_eq!(
+            reader.knn(&[0.49f32][..], 2).unwrap(),
+            tree.reader().knn(&[0.49f32][..], 2).unwrap()
+        );
+
+        // Republishing a smaller tree leaves the attached tree's points in place
+        let smaller = CoverTreeBuilder::new()
+            .build(Arc::new(
+                DefaultCloud::<L2>::new(vec![1.0, 2.0], 1).unwrap(),
+            ))
+            .unwrap();
+        publish_shared_tree(&dir, &smaller).unwrap();
+        assert_eq!(
+            reader.knn(&[0.49f32][..], 2).unwrap(),
+            tree.reader().knn(&[0.49f32][..], 2).unwrap()
+        );
+        let republished = attach_shared_tree::<_, L2>(&dir).unwrap();
+        assert_eq!(republished.reader().point_cloud().len(), 2);
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn packed_bundle_round_trip() {
+        let dir = env::temp_dir().join(format!("goko-bundle-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let data: Vec<f32> = (0..50).map(|i| ((i * 7) % 50) as f32 / 10.0).collect();
+        let mut data_file = File::create(dir.join("data.f32")).unwrap();
+        for x in &data {
+            data_file.write_all(&x.to_ne_bytes()).unwrap();
+        }
+        let config = dir.join("config.yml");
+        let mut config_file = File::create(&config).unwrap();
+        config_file
+            .write_all(b"---\nleaf_cutoff: 1\nmin_res_index: -5\nscale_base: 2.0\ndata_path: data.f32\ndata_dim: 2\nverbosity: 0\n")
+            .unwrap();
+
+        let bundle = dir.join("bundle");
+        let built = build_packed::<_, _, L2>(&config, &bundle).unwrap();
+        let opened = open_bundle::<_, L2>(&bundle).unwrap();
+        assert_eq!(opened.reader().point_cloud().len(), 25);
+        assert_eq!(opened.reader().node_count(), built.reader().node_count());
+        assert_eq!(
+            opened.reader().knn(&[0.7f32, 1.4][..], 3).unwrap(),
+            built.reader().knn(&[0.7f32, 1.4][..], 3).unwrap()
+        );
+        assert!(open_bundle::<_, L1>(&bundle).is_err());
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+//! # Random Walks
+//!
+//! Node2vec style random walks over the tree. Each step moves to the parent, a child, or a sibling of the current node,
+//! chosen with configurable biases. The walks can be fed to a skip-gram model to learn embeddings of regions of the space.
+
+use crate::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The biases of each kind of move, and the length of the walks. The biases are relative weights, a move with 0 weight is
+/// never taken.
+#[derive(Debug, Clone)]
+pub struct WalkParameters {
+    /// The number of nodes in each walk, including the start
+    pub walk_length: usize,
+    /// Weight of moving up to the parent
+    pub parent_weight: f64,
+    /// Weight of moving down to a child, split evenly among the children
+    pub child_weight: f64,
+    /// Weight of moving to another child of the parent, split evenly among the siblings
+    pub sibling_weight: f64,
+    /// Seed for the random number generator
+    pub seed: u64,
+}
+
+impl Default for WalkParameters {
+    fn default() -> Self {
+        WalkParameters {
+            walk_length: 20,
+            parent_weight: 1.0,
+            child_weight: 1.0,
+            sibling_weight: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+fn neighbors<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    address: NodeAddress,
+) -> (Option<NodeAddress>, Vec<NodeAddress>, Vec<NodeAddress>) {
+    let children_of = |addr: NodeAddress| {
+        reader
+            .get_node_children_and(addr, |nested, children| {
+                let mut c = vec![nested];
+                c.extend(children);
+                c
+            })
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    };
+    let parent = reader
+        .get_node_and(address, |n| n.parent_address())
+        .ok()
+        .flatten();
+    let children = children_of(address);
+    let siblings = match parent {
+        Some(parent) => children_of(parent)
+            .into_iter()
+            .filter(|a| *a != address)
+            .collect(),
+        None => Vec::new(),
+    };
+    (parent, children, siblings)
+}
+
+/// A single walk starting at `start`. Stops early if the node has no moves with positive weight.
+pub fn random_walk<D: PointCloud, R: Rng>(
+    reader: &CoverTreeReader<D>,
+    start: NodeAddress,
+    parameters: &WalkParameters,
+    rng: &mut R,
+) -> Vec<NodeAddress> {
+    let mut walk = Vec::with_capacity(parameters.walk_length);
+    if parameters.walk_length == 0 {
+        return walk;
+    }
+    walk.push(start);
+    let mut current = start;
+    while walk.len() < parameters.walk_length {
+        let (parent, children, siblings) = neighbors(reader, current);
+        let parent = parent.map(|p| [p]);
+        let mut moves: Vec<(f64, &[NodeAddress])> = Vec::with_capacity(3);
+        if let Some(parent) = &parent {
+            moves.push((parameters.parent_weight, &parent[..]));
+        }
+        moves.push((parameters.child_weight, &children[..]));
+        moves.push((parameters.sibling_weight, &siblings[..]));
+        moves.retain(|(w, m)| *w > 0.0 && !m.is_empty());
+
+        let total: f64 = moves.iter().map(|(w, _)| w).sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut choice = rng.gen::<f64>() * total;
+        let mut next = moves[moves.len() - 1].1;
+        for (w, m) in &moves {
+            if choice < *w {
+                next = *m;
+                break;
+            }
+            choice -= w;
+        }
+        current = next[rng.gen_range(0, next.len())];
+        walk.push(current);
+    }
+    walk
+}
+
+/// Starts `walks_per_node` walks at every node of the tree. The walks are sequences of node addresses, the second
+/// element of each is the index of the node's center point.
+pub fn random_walks<D: PointCloud>(
+    reader: &CoverTreeReader<D>,
+    walks_per_node: usize,
+    parameters: &WalkParameters,
+) -> Vec<Vec<NodeAddress>> {
+    let mut rng = StdRng::seed_from_u64(parameters.seed);
+    let mut starts = Vec::new();
+    for (si, layer) in reader.layers() {
+        starts.extend(layer.node_center_indexes().into_iter().map(|pi| (si, pi)));
+    }
+    starts.sort_unstable();
+    let mut walks = Vec::with_capacity(starts.len() * walks_per_node);
+    for start in starts {
+        for _ in 0..walks_per_node {
+            walks.push(random_walk(reader, start, parameters, &mut rng));
+        }
+    }
+    walks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::covertree::tests::build_basic_tree;
+
+    #[test]
+    fn walks_follow_edges() {
+        let tree = build_basic_tree();
+        let reader = tree.reader();
+        let parameters = WalkParameters {
+            walk_length: 10,
+            ..Default::default()
+        };
+        let walks = random_walks(&reader, 2, &parameters);
+        assert_eq!(walks.len(), 2 * reader.node_count());
+        for walk in &walks {
+            for pair in walk.windows(2) {
+                let (parent, children, siblings) = neighbors(&reader, pair[0]);
+                assert!(
+                    parent == Some(pair[1])
+                        || children.contains(&pair[1])
+                        || siblings.contains(&pair[1])
+                );
+            }
+        }
+        assert_eq!(walks, random_walks(&reader, 2, &parameters));
+
+        let down_only = WalkParameters {
+            walk_length: 100,
+            parent_weight: 0.0,
+            sibling_weight: 0.0,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let walk = random_walk(&reader, reader.root_address(), &down_only, &mut rng);
+        for pair in walk.windows(2) {
+            assert!(pair[1].0 < pair[0].0);
+        }
+    }
+}
@@ -125,7 +125,8 @@ fn run_knn_query() {
 fn gaussian_is_not_nan() {
     if env::var("TRAVIS_RUST_VERSION").is_err() {
         let mut ct = build_tree();
-        ct.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive());
+        ct.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::recursive())
+            .unwrap();
         let ct_reader = ct.reader();
         let mut untested_addresses = vec![ct_reader.root_address()];
         while let Some(addr) = untested_addresses.pop() {
@@ -139,13 +140,18 @@ fn gaussian_is_not_nan() {
                     });
                     p.count
                 })
+                .unwrap()
+                .unwrap();
+            ct_reader
+                .get_node_and(addr, |n| assert_eq!(n.coverage_count(), count))
                 .unwrap();
-            ct_reader.get_node_and(addr, |n| assert_eq!(n.coverage_count(), count));
 
-            ct_reader.get_node_children_and(addr, |covered, children| {
-                untested_addresses.push(covered);
-                untested_addresses.extend(children);
-            });
+            ct_reader
+                .get_node_children_and(addr, |covered, children| {
+                    untested_addresses.push(covered);
+                    untested_addresses.extend(children);
+                })
+                .unwrap();
         }
     }
 }
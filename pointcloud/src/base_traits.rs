@@ -1,8 +1,11 @@
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use rayon::prelude::*;
-use std::cmp::min;
+use std::cmp::{min, Ordering};
+use std::collections::HashSet;
+use std::fmt;
 use std::fmt::Debug;
+use std::hash::Hash;
 
 use crate::distances::*;
 use crate::pc_errors::*;
@@ -32,6 +35,60 @@ pub trait PointCloud: Debug + Send + Sync + 'static {
     /// Gets a point from this dataset
     fn point(&self, pn: PointIndex) -> PointCloudResult<PointRef>;
 
+    /// Glues a label set onto this cloud, for when the labels arrive after the data. The label set must be the same length.
+    fn attach_labels<L: LabelSet>(self, labels: L) -> PointCloudResult<SimpleLabeledCloud<Self, L>>
+    where
+        Self: Sized,
+    {
+        if labels.len() != self.len() {
+            return Err(PointCloudError::LengthMismatch {
+                expected: self.len(),
+                found: labels.len(),
+            });
+        }
+        Ok(SimpleLabeledCloud::new(self, labels))
+    }
+
+    /// Glues a set of metadata, like document ids or timestamps, onto this cloud. Any of the label sets can hold it,
+    /// the metadata is kept apart from the labels so that it doesn't end up in the label summaries. The set must be the
+    /// same length.
+    fn attach_metadata<M: LabelSet>(self, metadata: M) -> PointCloudResult<SimpleMetaCloud<Self, M>>
+    where
+        Self: Sized,
+    {
+        if metadata.len() != self.len() {
+            return Err(PointCloudError::LengthMismatch {
+                expected: self.len(),
+                found: metadata.len(),
+            });
+        }
+        Ok(SimpleMetaCloud::new(self, metadata))
+    }
+
+    /// The reference indexes as a parallel iterator.
+    fn par_indexes(&self) -> rayon::vec::IntoIter<PointIndex> {
+        self.reference_indexes().into_par_iter()
+    }
+
+    /// Every point with its index as a parallel iterator, in the order of `reference_indexes`. Use this to stream the
+    /// whole cloud on rayon's pool, each point is read with `point` on the thread that handles it.
+    fn par_points(&self) -> ParPoints<'_, Self> {
+        ParPoints::new(self)
+    }
+
+    /// A hint that the points are about to be read, for point clouds that page their points in from disk. Does
+    /// nothing by default.
+    fn prefetch(&self, _indexes: &[PointIndex]) {}
+
+    /// Borrows a batch of points at once, in the order of the indexes.
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        let points = indexes
+            .iter()
+            .map(|i| self.point(*i))
+            .collect::<PointCloudResult<Vec<PointRef>>>()?;
+        Ok(PointBatch::new(points))
+    }
+
     /// Returns a dense array
     fn point_dense_array(&self, index: PointIndex) -> PointCloudResult<Array1<f32>> {
         let pref = self.point(index)?;
@@ -116,6 +173,15 @@ pub trait PointCloud: Debug + Send + Sync + 'static {
         self.distances_to_point(&self.point(i)?, indexes)
     }
 
+    /// Same as `distances_to_point`, with the distances in an `ndarray` vector.
+    fn distances_to_point_array<'a, T: Into<PointRef<'a>>>(
+        &self,
+        point: T,
+        indexes: &[PointIndex],
+    ) -> PointCloudResult<Array1<f32>> {
+        Ok(Array1::from(self.distances_to_point(point, indexes)?))
+    }
+
     /// The main distance function. This paralizes if there are more than 100 points.
     fn distances_to_point<'a, T: Into<PointRef<'a>>>(
         &self,
@@ -175,6 +241,11 @@ pub trait PointCloud: Debug + Send + Sync + 'static {
                             moment_vec[*i as usize] += v.powi(moment);
                         }
                     }
+                    PointRef::Binary(_) | PointRef::Text(_) => {
+                        for (m, yy) in moment_vec.iter_mut().zip(y.dense_iter(self.dim())) {
+                            *m += yy.powi(moment);
+                        }
+                    }
                 },
                 Err(e) => {
                     return Err(e);
@@ -238,6 +309,36 @@ pub trait PointCloud: Debug + Send + Sync + 'static {
     }
 }
 
+/// Shares a point cloud, this lets a tree's cloud be glued to labels without copying the data.
+impl<D: PointCloud> PointCloud for Arc<D> {
+    type Metric = D::Metric;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.as_ref().dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.as_ref().len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.as_ref().is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.as_ref().reference_indexes()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.as_ref().point(i)
+    }
+    #[inline]
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        self.as_ref().points(indexes)
+    }
+}
+
 /// A sparse adjacency matrix.
 #[derive(Debug)]
 pub struct AdjMatrix {
@@ -281,7 +382,9 @@ pub trait Summary: Debug + Default + Send + Sync + 'static {
     /// Adding a single value to the summary.
     fn add(&mut self, v: &Self::Label);
     /// Merging several summaries of your data source together. This results in a summary of underlying column over
-    /// the union of the indexes used to create the input summaries.
+    /// the union of the indexes used to create the input summaries. Trees combine their children's summaries in
+    /// whatever order they finish, so this has to be associative and commutative, and the default summary has to be
+    /// an identity for it.
     fn combine(&mut self, other: &Self);
     /// The number of elements this summary covers
     fn count(&self) -> usize;
@@ -323,6 +426,10 @@ pub trait LabeledCloud: PointCloud {
         &self,
         pns: &[PointIndex],
     ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>>;
+    /// Every label with its index as a parallel iterator, in the order of `reference_indexes`.
+    fn par_labels(&self) -> ParLabels<'_, Self> {
+        ParLabels::new(self)
+    }
 }
 
 /// Simply shoves together a point cloud and a label set, for a modular label system
@@ -415,6 +522,16 @@ impl<D: PointCloud, L: LabelSet> PointCloud for SimpleLabeledCloud<D, L> {
     fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
         self.data.point(i)
     }
+    #[inline]
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        self.data.prefetch(indexes)
+    }
+}
+
+impl<D: PointCloud + fmt::Display, L: LabelSet> fmt::Display for SimpleLabeledCloud<D, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, with {} labels", self.data, self.labels.len())
+    }
 }
 
 impl<D: PointCloud, L: LabelSet> LabeledCloud for SimpleLabeledCloud<D, L> {
@@ -432,6 +549,100 @@ impl<D: PointCloud, L: LabelSet> LabeledCloud for SimpleLabeledCloud<D, L> {
     }
 }
 
+impl<D: MetaCloud, L: LabelSet> MetaCloud for SimpleLabeledCloud<D, L> {
+    type Metadata = D::Metadata;
+    type MetaSummary = D::MetaSummary;
+
+    fn metadata(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Metadata>> {
+        self.data.metadata(pn)
+    }
+    fn metasummary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>> {
+        self.data.metasummary(pns)
+    }
+}
+
+/// Shoves together a point cloud and a set of metadata. The metadata is never used for distances or label summaries.
+#[derive(Debug)]
+pub struct SimpleMetaCloud<D: PointCloud, M: LabelSet> {
+    data: D,
+    metadata: M,
+}
+
+impl<D: PointCloud, M: LabelSet> SimpleMetaCloud<D, M> {
+    /// Creates a new one
+    pub fn new(data: D, metadata: M) -> Self {
+        SimpleMetaCloud { data, metadata }
+    }
+}
+
+impl<D: PointCloud, M: LabelSet> PointCloud for SimpleMetaCloud<D, M> {
+    type Metric = D::Metric;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.data.dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.data.reference_indexes()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.data.point(i)
+    }
+    #[inline]
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        self.data.prefetch(indexes)
+    }
+}
+
+impl<D: PointCloud + fmt::Display, M: LabelSet> fmt::Display for SimpleMetaCloud<D, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, with {} metadata", self.data, self.metadata.len())
+    }
+}
+
+impl<D: LabeledCloud, M: LabelSet> LabeledCloud for SimpleMetaCloud<D, M> {
+    type Label = D::Label;
+    type LabelSummary = D::LabelSummary;
+
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Label>> {
+        self.data.label(pn)
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        self.data.label_summary(pns)
+    }
+}
+
+impl<D: PointCloud, M: LabelSet> MetaCloud for SimpleMetaCloud<D, M> {
+    type Metadata = M::Label;
+    type MetaSummary = M::LabelSummary;
+
+    fn metadata(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Metadata>> {
+        self.metadata.label(pn)
+    }
+    fn metasummary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>> {
+        self.metadata.label_summary(pns)
+    }
+}
+
 /// Enables the points in the underlying cloud to be named with strings.
 pub trait NamedCloud: PointCloud {
     /// Name type, could be a string or a 
@@ -459,4 +670,29 @@ pub trait MetaCloud: PointCloud {
         &self,
         pns: &[PointIndex],
     ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>>;
+
+    /// Keeps the closest of the results for each id, for when the same item was loaded more than once, like in a
+    /// re-ingested segment of a `HashGluedCloud`. The id is read from each result's metadata with `id`, results
+    /// without metadata or an id are all kept. The results come back sorted by distance, closest first.
+    fn dedup_by_metadata<K, F>(
+        &self,
+        results: &[(f32, PointIndex)],
+        id: F,
+    ) -> PointCloudResult<Vec<(f32, PointIndex)>>
+    where
+        K: Hash + Eq,
+        F: Fn(&Self::Metadata) -> Option<K>,
+    {
+        let mut sorted = results.to_vec();
+        sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let mut seen = HashSet::new();
+        let mut deduped = Vec::with_capacity(sorted.len());
+        for (d, pi) in sorted {
+            let key = self.metadata(pi)?.and_then(&id);
+            if key.map_or(true, |k| seen.insert(k)) {
+                deduped.push((d, pi));
+            }
+        }
+        Ok(deduped)
+    }
 }
@@ -0,0 +1,143 @@
+//! Points held in an Arrow `FixedSizeList<f32>` column. Only compiled with the `arrow` feature.
+//!
+//! The cloud keeps a reference to the column's buffers, `point` hands out slices of them, so nothing is copied out of
+//! the record batch.
+
+use arrow::array::{Array, FixedSizeListArray, Float32Array};
+use arrow::ipc::reader::FileReader;
+use arrow::record_batch::RecordBatch;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::base_traits::*;
+use crate::glued_data_cloud::HashGluedCloud;
+use crate::pc_errors::*;
+use crate::{Metric, PointBatch, PointIndex, PointRef};
+
+/// A column of a record batch, each list in it is a point.
+#[derive(Debug)]
+pub struct DataArrow<M: Metric> {
+    name: String,
+    values: Float32Array,
+    offset: usize,
+    len: usize,
+    dim: usize,
+    metric: PhantomData<M>,
+}
+
+fn arrow_error<E: fmt::Display>(e: E) -> PointCloudError {
+    PointCloudError::IoError(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+impl<M: Metric> DataArrow<M> {
+    /// Wraps the named column of the batch. Errors if the column isn't a `FixedSizeList<f32>` or has nulls.
+    pub fn new(batch: &RecordBatch, column: &str) -> PointCloudResult<DataArrow<M>> {
+        let index = batch.schema().index_of(column).map_err(arrow_error)?;
+        let list = batch
+            .column(index)
+            .as_any()
+            .downcast_ref::<FixedSizeListArray>()
+            .ok_or_else(|| arrow_error(format!("{} is not a FixedSizeList column", column)))?;
+        let values = list.values();
+        let values = values
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .ok_or_else(|| arrow_error(format!("{} does not hold f32 values", column)))?;
+        if list.null_count() > 0 || values.null_count() > 0 {
+            return Err(arrow_error(format!("{} has null values", column)));
+        }
+        let dim = list.value_length() as usize;
+        let len = list.len();
+        let offset = if len > 0 {
+            list.value_offset(0) as usize
+        } else {
+            0
+        };
+        Ok(DataArrow {
+            name: column.to_string(),
+            values: Float32Array::from(values.data()),
+            offset,
+            len,
+            dim,
+            metric: PhantomData,
+        })
+    }
+
+    /// Reads an Arrow IPC file, which is also the Feather v2 format, and wraps the named column of each of its
+    /// batches. The batches are glued together in the order they appear in the file.
+    pub fn open_ipc<P: AsRef<Path>>(
+        path: P,
+        column: &str,
+    ) -> PointCloudResult<HashGluedCloud<DataArrow<M>>> {
+        let reader = FileReader::try_new(File::open(path)?).map_err(arrow_error)?;
+        let batches: PointCloudResult<Vec<DataArrow<M>>> = reader
+            .map(|batch| DataArrow::new(&batch.map_err(arrow_error)?, column))
+            .collect();
+        Ok(HashGluedCloud::new(batches?))
+    }
+
+    #[inline]
+    fn data(&self) -> &[f32] {
+        self.values.value_slice(self.offset, self.len * self.dim)
+    }
+}
+
+impl<M: Metric> PointCloud for DataArrow<M> {
+    type Metric = M;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        (0..self.len).collect()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        if i >= self.len {
+            return Err(PointCloudError::data_access(i, self.name.clone()));
+        }
+        Ok(PointRef::Dense(
+            &self.data()[self.dim * i..self.dim * (i + 1)],
+        ))
+    }
+    #[inline]
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        if let Some(i) = indexes.iter().find(|i| **i >= self.len) {
+            return Err(PointCloudError::data_access(*i, self.name.clone()));
+        }
+        let dim = self.dim;
+        let data = self.data();
+        Ok(PointBatch::new(
+            indexes
+                .iter()
+                .map(|i| PointRef::Dense(&data[dim * i..dim * (i + 1)]))
+                .collect(),
+        ))
+    }
+}
+
+impl<M: Metric> fmt::Display for DataArrow<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DataArrow {}: {} points of dim {}, {} metric",
+            self.name,
+            self.len,
+            self.dim,
+            M::name()
+        )
+    }
+}
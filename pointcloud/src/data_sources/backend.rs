@@ -0,0 +1,214 @@
+//! Picks between holding the points in ram and memory mapping them at runtime.
+//!
+//! A memmap only keeps the pages that are read in memory, so it works on a machine with less ram than the data, while
+//! a copy in ram avoids page faults on a machine with plenty. `DataBackend` is either, so code written against it runs
+//! unchanged on both, and `BackendChoice::Auto` decides by comparing the size of the data with the available memory.
+
+use std::fmt;
+use std::path::Path;
+
+use super::{DataMemmap, DataRam};
+use crate::base_traits::*;
+use crate::distances::Metric;
+use crate::glued_data_cloud::HashGluedCloud;
+use crate::pc_errors::*;
+use crate::{PointIndex, PointRef};
+
+/// How to hold the points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendChoice {
+    /// Copy the points into ram
+    Ram,
+    /// Memory map the points
+    Memmap,
+    /// Use ram when the points take up at most half of the available memory, otherwise memmap them
+    Auto,
+}
+
+impl BackendChoice {
+    /// Parses `"ram"`, `"memmap"` or `"auto"`.
+    pub fn from_name(name: &str) -> Option<BackendChoice> {
+        match name {
+            "ram" => Some(BackendChoice::Ram),
+            "memmap" => Some(BackendChoice::Memmap),
+            "auto" => Some(BackendChoice::Auto),
+            _ => None,
+        }
+    }
+
+    /// If points taking up `bytes` should go in ram. `Auto` picks the memmap when the available memory is unknown.
+    pub fn use_ram(&self, bytes: usize) -> bool {
+        match self {
+            BackendChoice::Ram => true,
+            BackendChoice::Memmap => false,
+            BackendChoice::Auto => available_memory()
+                .map(|available| bytes <= available / 2)
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// The memory the OS reports as available for new allocations, in bytes. Only known on linux.
+pub fn available_memory() -> Option<usize> {
+    if cfg!(target_os = "linux") {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+        let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+        let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kb * 1024)
+    } else {
+        None
+    }
+}
+
+/// Points either in ram or memory mapped.
+#[derive(Debug)]
+pub enum DataBackend<M: Metric> {
+    /// The points are in ram
+    Ram(DataRam<M>),
+    /// The points are memory mapped
+    Memmap(DataMemmap<M>),
+    /// The points are memory mapped from several files, glued together in the order of the files
+    GluedMemmap(HashGluedCloud<DataMemmap<M>>),
+}
+
+impl<M: Metric> DataBackend<M> {
+    /// Keeps the memmap, or copies it into ram, depending on the choice.
+    pub fn from_memmap(data: DataMemmap<M>, choice: BackendChoice) -> DataBackend<M> {
+        if choice.use_ram(data.len() * data.dim() * std::mem::size_of::<f32>()) {
+            DataBackend::Ram(data.convert_to_ram())
+        } else {
+            DataBackend::Memmap(data)
+        }
+    }
+
+    /// Keeps the points in ram, or writes them to `dir` and maps them, depending on the choice.
+    pub fn from_ram<P: AsRef<Path>>(
+        data: DataRam<M>,
+        choice: BackendChoice,
+        dir: P,
+    ) -> PointCloudResult<DataBackend<M>> {
+        if choice.use_ram(data.len() * data.dim() * std::mem::size_of::<f32>()) {
+            Ok(DataBackend::Ram(data))
+        } else {
+            Ok(DataBackend::Memmap(data.to_memmap(dir)?))
+        }
+    }
+
+    /// If the points are in ram.
+    pub fn is_ram(&self) -> bool {
+        match self {
+            DataBackend::Ram(_) => true,
+            DataBackend::Memmap(_) | DataBackend::GluedMemmap(_) => false,
+        }
+    }
+}
+
+impl<M: Metric> PointCloud for DataBackend<M> {
+    type Metric = M;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        match self {
+            DataBackend::Ram(data) => data.dim(),
+            DataBackend::Memmap(data) => data.dim(),
+            DataBackend::GluedMemmap(data) => data.dim(),
+        }
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        match self {
+            DataBackend::Ram(data) => data.len(),
+            DataBackend::Memmap(data) => data.len(),
+            DataBackend::GluedMemmap(data) => data.len(),
+        }
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        match self {
+            DataBackend::Ram(data) => data.is_empty(),
+            DataBackend::Memmap(data) => data.is_empty(),
+            DataBackend::GluedMemmap(data) => data.is_empty(),
+        }
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        match self {
+            DataBackend::Ram(data) => data.reference_indexes(),
+            DataBackend::Memmap(data) => data.reference_indexes(),
+            DataBackend::GluedMemmap(data) => data.reference_indexes(),
+        }
+    }
+    #[inline]
+    fn point(&self, pi: PointIndex) -> PointCloudResult<PointRef> {
+        match self {
+            DataBackend::Ram(data) => data.point(pi),
+            DataBackend::Memmap(data) => data.point(pi),
+            DataBackend::GluedMemmap(data) => data.point(pi),
+        }
+    }
+
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        match self {
+            DataBackend::Ram(_) => {}
+            DataBackend::Memmap(data) => data.prefetch(indexes),
+            DataBackend::GluedMemmap(data) => data.prefetch(indexes),
+        }
+    }
+}
+
+impl<M: Metric> fmt::Display for DataBackend<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataBackend::Ram(data) => data.fmt(f),
+            DataBackend::Memmap(data) => data.fmt(f),
+            DataBackend::GluedMemmap(data) => data.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::tests::build_ram_random_test;
+    use crate::data_sources::MEMMAP_POINTS_FILE;
+    use tempdir::TempDir;
+
+    #[test]
+    fn ram_memmap_round_trip() {
+        let dir = TempDir::new("backend").unwrap();
+        let ram = build_ram_random_test(50, 3);
+        let memmap = ram.to_memmap(dir.path()).unwrap();
+        assert!(dir.path().join(MEMMAP_POINTS_FILE).exists());
+        let back = memmap.to_ram();
+        for pi in 0..50 {
+            let expected: Vec<f32> = ram.point(pi).unwrap().dense_iter(3).collect();
+            assert_eq!(
+                expected,
+                memmap
+                    .point(pi)
+                    .unwrap()
+                    .dense_iter(3)
+                    .collect::<Vec<f32>>()
+            );
+            assert_eq!(
+                expected,
+                back.point(pi).unwrap().dense_iter(3).collect::<Vec<f32>>()
+            );
+        }
+
+        let backend = DataBackend::from_memmap(memmap, BackendChoice::Ram);
+        assert!(backend.is_ram());
+        assert_eq!(backend.len(), 50);
+        let backend = DataBackend::from_ram(back, BackendChoice::Memmap, dir.path()).unwrap();
+        assert!(!backend.is_ram());
+        assert_eq!(
+            backend.distances_to_point_index(0, &[1, 2]).unwrap(),
+            ram.distances_to_point_index(0, &[1, 2]).unwrap()
+        );
+
+        assert!(BackendChoice::Ram.use_ram(usize::MAX));
+        assert!(!BackendChoice::Auto.use_ram(usize::MAX));
+        assert_eq!(BackendChoice::from_name("auto"), Some(BackendChoice::Auto));
+        assert_eq!(BackendChoice::from_name("disk"), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Bit packed points held in ram.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::base_traits::*;
+use crate::distances::Metric;
+use crate::pc_errors::*;
+use crate::{PointBatch, PointIndex, PointRef};
+
+/// Binary points, like hash codes, packed 64 dimensions to a `u64`. Use it with `Hamming` or `Jaccard`, which compare
+/// the points with popcounts. This takes a 32nd of the memory of the same points stored as `f32`s.
+#[derive(Debug)]
+pub struct BinaryRam<M: Metric> {
+    words: Vec<u64>,
+    dim: usize,
+    words_per_point: usize,
+    metric: PhantomData<M>,
+}
+
+impl<M: Metric> BinaryRam<M> {
+    /// Row major points of `dim` bits, each starting on a new word. Dimension `i` of a point is bit `i % 64` of its
+    /// word `i / 64`, and the bits past `dim` in the last word have to be 0.
+    pub fn new(words: Vec<u64>, dim: usize) -> PointCloudResult<BinaryRam<M>> {
+        let words_per_point = (dim + 63) / 64;
+        if dim == 0 || words.len() % words_per_point != 0 {
+            return Err(PointCloudError::data_access(
+                words.len(),
+                format!(
+                    "{} words do not split into points of {} bits",
+                    words.len(),
+                    dim
+                ),
+            ));
+        }
+        if dim % 64 != 0 {
+            let padding = !0u64 << (dim % 64);
+            if let Some(i) = words
+                .chunks(words_per_point)
+                .position(|p| p[words_per_point - 1] & padding != 0)
+            {
+                return Err(PointCloudError::data_access(
+                    i,
+                    "the point has bits set past its dimension".to_string(),
+                ));
+            }
+        }
+        Ok(BinaryRam {
+            words,
+            dim,
+            words_per_point,
+            metric: PhantomData,
+        })
+    }
+
+    /// Packs points given as bools, `dim` to a point.
+    pub fn from_bits(bits: &[bool], dim: usize) -> PointCloudResult<BinaryRam<M>> {
+        if dim == 0 || bits.len() % dim != 0 {
+            return Err(PointCloudError::data_access(
+                bits.len(),
+                format!(
+                    "{} bits do not split into points of {} bits",
+                    bits.len(),
+                    dim
+                ),
+            ));
+        }
+        let words_per_point = (dim + 63) / 64;
+        let mut words = vec![0u64; words_per_point * bits.len() / dim];
+        for (pi, point) in bits.chunks(dim).enumerate() {
+            for (i, bit) in point.iter().enumerate() {
+                if *bit {
+                    words[pi * words_per_point + i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+        BinaryRam::new(words, dim)
+    }
+}
+
+impl<M: Metric> PointCloud for BinaryRam<M> {
+    type Metric = M;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.words.len() / self.words_per_point
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        (0..self.len()).collect()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.words
+            .get(i * self.words_per_point..(i + 1) * self.words_per_point)
+            .map(PointRef::Binary)
+            .ok_or_else(|| PointCloudError::data_access(i, "binary RAM".to_string()))
+    }
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        Ok(PointBatch::new(
+            indexes
+                .iter()
+                .map(|i| self.point(*i))
+                .collect::<PointCloudResult<Vec<PointRef>>>()?,
+        ))
+    }
+}
+
+impl<M: Metric> fmt::Display for BinaryRam<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BinaryRam: {} points of {} bits, {} metric",
+            self.len(),
+            self.dim,
+            M::name()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Hamming, Jaccard, L2};
+
+    #[test]
+    fn popcount_metrics_match_unpacked() {
+        // 70 bits, so each point takes two words
+        let bits: Vec<bool> = (0..210).map(|i| (i * 7) % 5 < 2 || i % 11 == 0).collect();
+        let cloud = BinaryRam::<Hamming>::from_bits(&bits, 70).unwrap();
+        assert_eq!(cloud.len(), 3);
+        let dense: Vec<Vec<f32>> = bits
+            .chunks(70)
+            .map(|p| p.iter().map(|b| *b as u8 as f32).collect())
+            .collect();
+        let hamming = cloud.distances_to_point_index(0, &[1, 2]).unwrap();
+        assert_eq!(hamming[0], Hamming::dense(&dense[0], &dense[1]));
+        assert_eq!(hamming[1], Hamming::dense(&dense[0], &dense[2]));
+        let unpacked: Vec<f32> = cloud.point(2).unwrap().dense_iter(70).collect();
+        assert_eq!(unpacked, dense[2]);
+
+        let (x, y) = (cloud.point(0).unwrap(), cloud.point(1).unwrap());
+        let jaccard = Jaccard::dist(x, y).unwrap();
+        assert_eq!(jaccard, Jaccard::dense(&dense[0], &dense[1]));
+        assert!(jaccard > 0.0 && jaccard < 1.0);
+        assert_eq!(Jaccard::binary(&[0, 0], &[0, 0]), 0.0);
+        assert_eq!(Jaccard::binary(&[0b1100], &[0b0110]), 1.0 - 1.0 / 3.0);
+        assert_eq!(
+            Jaccard::sparse(&[1, 2], &[1.0, 1.0], &[2, 3], &[1.0, 1.0]),
+            1.0 - 1.0 / 3.0
+        );
+        assert_eq!(
+            Hamming::sparse(&[1, 2], &[1.0, 1.0], &[2, 3], &[1.0, 1.0]),
+            2.0
+        );
+        // Metrics without a popcount kernel unpack the bits
+        assert_eq!(L2::binary(&[0b1011], &[0b0001]), 2.0f32.sqrt());
+        assert!(Hamming::dist(x, &dense[1]).is_err());
+
+        assert!(BinaryRam::<Hamming>::new(vec![1 << 10], 10).is_err());
+        assert!(BinaryRam::<Hamming>::new(vec![1, 2, 3], 70).is_err());
+        assert!(cloud.point(3).is_err());
+    }
+}
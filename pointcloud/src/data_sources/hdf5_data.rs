@@ -0,0 +1,100 @@
+//! Reads a 2-D dataset out of an HDF5 file. Only compiled with the `hdf5` feature.
+//!
+//! Each row of the dataset is a point. The dataset is read in blocks of rows, so converting it to a memmap never
+//! holds more than a block in ram. Any numeric element type HDF5 can convert to `f32` works.
+
+use hdf5::{Dataset, File};
+use ndarray::s;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{DataMemmap, DataRam};
+use crate::pc_errors::*;
+use crate::Metric;
+
+/// How many bytes of the dataset are read at once.
+const BLOCK_BYTES: usize = 1 << 26;
+
+fn hdf5_error<E: fmt::Display>(e: E) -> PointCloudError {
+    PointCloudError::IoError(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Opens the dataset and returns it with its number of rows and columns. Errors if it isn't 2-D.
+fn open_dataset(path: &Path, dataset: &str) -> PointCloudResult<(Dataset, usize, usize)> {
+    let file = File::open(path).map_err(hdf5_error)?;
+    let data = file.dataset(dataset).map_err(hdf5_error)?;
+    let shape = data.shape();
+    if shape.len() != 2 {
+        return Err(hdf5_error(format!(
+            "{} in {} has {} dimensions, expected 2",
+            dataset,
+            path.to_string_lossy(),
+            shape.len()
+        )));
+    }
+    Ok((data, shape[0], shape[1]))
+}
+
+/// Calls `f` on each block of rows, flattened in row-major order.
+fn for_each_block<F>(data: &Dataset, rows: usize, dim: usize, mut f: F) -> PointCloudResult<()>
+where
+    F: FnMut(&[f32]) -> PointCloudResult<()>,
+{
+    let block_rows = (BLOCK_BYTES / (4 * dim.max(1))).max(1);
+    let mut start = 0;
+    while start < rows {
+        let end = (start + block_rows).min(rows);
+        let block = data
+            .read_slice_2d::<f32, _>(s![start..end, ..])
+            .map_err(hdf5_error)?;
+        match block.as_slice() {
+            Some(values) => f(values)?,
+            None => f(&block.iter().cloned().collect::<Vec<f32>>())?,
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+impl<M: Metric> DataRam<M> {
+    /// Reads a 2-D dataset from an HDF5 file into ram, one point per row.
+    pub fn from_hdf5<P: AsRef<Path>>(path: P, dataset: &str) -> PointCloudResult<DataRam<M>> {
+        let (data, rows, dim) = open_dataset(path.as_ref(), dataset)?;
+        let mut values = Vec::with_capacity(rows * dim);
+        for_each_block(&data, rows, dim, |block| {
+            values.extend_from_slice(block);
+            Ok(())
+        })?;
+        DataRam::new(values, dim)
+    }
+}
+
+impl<M: Metric> DataMemmap<M> {
+    /// Copies a 2-D dataset from an HDF5 file into a raw `f32` file at `memmap_path`, block by block, and maps it. The
+    /// file is overwritten if it exists.
+    pub fn from_hdf5<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        dataset: &str,
+        memmap_path: Q,
+    ) -> PointCloudResult<DataMemmap<M>> {
+        let (data, rows, dim) = open_dataset(path.as_ref(), dataset)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(memmap_path.as_ref())?;
+        let mut writer = BufWriter::new(file);
+        for_each_block(&data, rows, dim, |block| {
+            for x in block {
+                writer.write_all(&x.to_ne_bytes())?;
+            }
+            Ok(())
+        })?;
+        writer.flush()?;
+        drop(writer);
+        DataMemmap::new(dim, memmap_path.as_ref())
+    }
+}
@@ -21,15 +21,51 @@
 
 use super::memmapf32::Mmapf32;
 use crate::pc_errors::{PointCloudError, PointCloudResult};
-use std::fs::OpenOptions;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{BufWriter, Write};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::{Metric, PointIndex, PointRef};
+use crate::{Metric, PointBatch, PointIndex, PointRef};
 
 use crate::base_traits::*;
 use crate::label_sources::VecLabels;
 
+/// The name of the file `DataRam::to_memmap` writes the points to.
+pub const MEMMAP_POINTS_FILE: &str = "points.f32";
+
+/// Writes a file with `write` to a temporary next to `path`, then renames it over `path`. The file being replaced may
+/// be memory mapped, and truncating it in place would pull the pages out from under the map. The temporary is removed
+/// if the write fails.
+pub fn replace_file<T, F>(path: &Path, write: F) -> PointCloudResult<T>
+where
+    F: FnOnce(&mut BufWriter<File>) -> PointCloudResult<T>,
+{
+    let mut tmp_path: OsString = path.as_os_str().to_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let written = File::create(&tmp_path)
+        .map_err(PointCloudError::from)
+        .and_then(|file| {
+            let mut file = BufWriter::new(file);
+            let t = write(&mut file)?;
+            file.flush()?;
+            Ok(t)
+        });
+    match written {
+        Ok(t) => {
+            fs::rename(&tmp_path, path)?;
+            Ok(t)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(e)
+        }
+    }
+}
+
 /// A thin wrapper to give a `Box<[f32]>` dimensionality.
 #[derive(Debug)]
 pub struct DataMemmap<M: Metric> {
@@ -70,11 +106,73 @@ impl<M: Metric> DataMemmap<M> {
         })
     }
 
+    /// Maps a file read-only, so the file can live somewhere we can't write, like a shared `/dev/shm` segment.
+    /// Processes that map the same file share the pages.
+    pub fn open_read_only(dim: usize, path: &Path) -> PointCloudResult<DataMemmap<M>> {
+        let name = path.to_string_lossy().to_string();
+        if dim == 0 {
+            return Err(PointCloudError::data_access(
+                0,
+                format!("{} can't be split into points of dimension 0", name),
+            ));
+        }
+        let file = OpenOptions::new().read(true).open(&path)?;
+        let data = unsafe { Mmapf32::map(&file)? };
+        if data.len() % dim != 0 {
+            return Err(PointCloudError::LengthMismatch {
+                expected: data.len() - data.len() % dim,
+                found: data.len(),
+            });
+        }
+        Ok(DataMemmap {
+            name,
+            data,
+            dim,
+            metric: PhantomData,
+        })
+    }
+
+    /// Asks the OS to read in the rows of the points, merging rows that are close together into one request. Errors
+    /// are ignored, this is only a hint.
+    fn prefetch_rows(&self, indexes: &[PointIndex]) {
+        let len = self.data.len() / self.dim;
+        // Rows that are less than a page apart are read in together
+        let gap = (4096 / (self.dim * std::mem::size_of::<f32>())).max(1);
+        let mut run: Option<(usize, usize)> = None;
+        for &i in indexes.iter().filter(|i| **i < len) {
+            run = match run {
+                Some((start, end)) if start <= i && i <= end + gap => Some((start, end.max(i + 1))),
+                Some((start, end)) => {
+                    let _ = self
+                        .data
+                        .prefetch(start * self.dim, (end - start) * self.dim);
+                    Some((i, i + 1))
+                }
+                None => Some((i, i + 1)),
+            };
+        }
+        if let Some((start, end)) = run {
+            let _ = self
+                .data
+                .prefetch(start * self.dim, (end - start) * self.dim);
+        }
+    }
+
     /// Reads and consumes this memmap and copies it into ram, then returns it to a labelset
     pub fn convert_to_labels(self) -> VecLabels {
         VecLabels::new(self.data.to_vec(), self.dim, None)
     }
 
+    /// Copies the points into ram, leaving this memmap usable.
+    pub fn to_ram(&self) -> DataRam<M> {
+        DataRam {
+            name: self.name.clone(),
+            data: self.data.to_vec(),
+            dim: self.dim,
+            metric: PhantomData,
+        }
+    }
+
     /// Reads and consumes this memmap and copies it into ram.
     pub fn convert_to_ram(self) -> DataRam<M> {
         let dim = self.dim;
@@ -108,11 +206,29 @@ impl<M: Metric> DataRam<M> {
         VecLabels::new(self.data, self.dim, None)
     }
 
+    /// The points are already in ram.
+    fn prefetch_rows(&self, _indexes: &[PointIndex]) {}
+
     /// Merges two ram sets together.
     pub fn merge(&mut self, other: DataRam<M>) {
         assert!(self.dim == other.dim);
         self.data.extend(other.data);
     }
+
+    /// Writes the points to `MEMMAP_POINTS_FILE` in `dir`, packed row major as native `f32`s, and maps that file
+    /// read-only. Any file already there is replaced by a rename, so clouds still mapping it keep their points.
+    pub fn to_memmap<P: AsRef<Path>>(&self, dir: P) -> PointCloudResult<DataMemmap<M>> {
+        let dir = dir.as_ref();
+        create_dir_all(dir)?;
+        let path = dir.join(MEMMAP_POINTS_FILE);
+        replace_file(&path, |file| {
+            for x in &self.data {
+                file.write_all(&x.to_ne_bytes())?;
+            }
+            Ok(())
+        })?;
+        DataMemmap::open_read_only(self.dim, &path)
+    }
 }
 
 macro_rules! make_point_cloud {
@@ -146,6 +262,39 @@ macro_rules! make_point_cloud {
                     Some(x) => Ok(PointRef::Dense(x)),
                 }
             }
+            #[inline]
+            fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+                let len = self.len();
+                if let Some(i) = indexes.iter().find(|i| **i >= len) {
+                    return Err(PointCloudError::data_access(*i as usize, self.name.clone()));
+                }
+                let dim = self.dim;
+                let data: &[f32] = &self.data;
+                Ok(PointBatch::new(
+                    indexes
+                        .iter()
+                        .map(|i| PointRef::Dense(&data[dim * i..dim * (i + 1)]))
+                        .collect(),
+                ))
+            }
+            #[inline]
+            fn prefetch(&self, indexes: &[PointIndex]) {
+                self.prefetch_rows(indexes)
+            }
+        }
+
+        impl<M: Metric> fmt::Display for $name<M> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "{} {}: {} points of dim {}, {} metric",
+                    stringify!($name),
+                    self.name,
+                    self.len(),
+                    self.dim,
+                    M::name()
+                )
+            }
         }
     };
 }
@@ -223,6 +372,52 @@ pub mod tests {
         .unwrap()
     }
 
+    #[test]
+    fn open_read_only_rejects_zero_dim() {
+        let dir = tempdir::TempDir::new("open_read_only").unwrap();
+        let mapped = build_ram_fixed_test(5, 3).to_memmap(dir.path()).unwrap();
+        assert_eq!(mapped.len(), 5);
+        let path = dir.path().join(MEMMAP_POINTS_FILE);
+        assert!(DataMemmap::<L2>::open_read_only(0, &path).is_err());
+    }
+
+    #[test]
+    fn to_memmap_replaces_mapped_files() {
+        let dir = tempdir::TempDir::new("to_memmap").unwrap();
+        let old = build_ram_fixed_test(5, 3).to_memmap(dir.path()).unwrap();
+        let new = build_ram_fixed_test(2, 3).to_memmap(dir.path()).unwrap();
+        assert_eq!(new.len(), 2);
+        // The old map still sees the old file, it wasn't truncated under it
+        assert_eq!(old.len(), 5);
+        assert_eq!(old.point(4).unwrap().dense_iter(3).sum::<f32>(), 12.0);
+        assert!(!dir.path().join("points.f32.tmp").exists());
+    }
+
+    #[test]
+    fn display_info() {
+        let pc = build_ram_fixed_test(5, 3);
+        assert_eq!(
+            format!("{}", pc),
+            "DataRam RAM: 5 points of dim 3, L2 metric"
+        );
+    }
+
+    #[test]
+    fn points_batch() {
+        let pc = build_ram_fixed_test(5, 3);
+        let batch = pc.points(&[4, 1, 1]).unwrap();
+        assert_eq!(batch.len(), 3);
+        match (batch.get(0).unwrap(), pc.point(4).unwrap()) {
+            (PointRef::Dense(a), PointRef::Dense(b)) => assert_eq!(a, b),
+            _ => panic!("Should return dense data"),
+        }
+        let dists = batch
+            .distances_to_point::<L2, _>(pc.point(1).unwrap())
+            .unwrap();
+        assert_approx_eq!(dists[1], 0.0);
+        assert!(pc.points(&[0, 5]).is_err());
+    }
+
     #[test]
     fn point_correct() {
         let pc = build_ram_fixed_test(5, 5);
@@ -234,7 +429,7 @@ pub mod tests {
                     assert_approx_eq!(1.0, d);
                 }
             }
-            PointRef::Sparse(_, _) => panic!("Should return a sparse datum"),
+            _ => panic!("Should return a sparse datum"),
         };
     }
 
@@ -117,6 +117,15 @@ impl Mmapf32 {
         self.inner.make_mut()?;
         Ok(MmapMutf32 { inner: self.inner })
     }
+
+    /// Asks the OS to start reading `len` floats from `offset` in, so they're in memory when we get to them.
+    pub fn prefetch(&self, offset: usize, len: usize) -> Result<()> {
+        if offset + len > self.deref().len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "prefetch out of range"));
+        }
+        self.inner
+            .prefetch(offset * size_of::<f32>(), len * size_of::<f32>())
+    }
 }
 
 impl Deref for Mmapf32 {
@@ -168,6 +168,24 @@ impl MmapInner {
         }
     }
 
+    pub fn prefetch(&self, offset: usize, len: usize) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        let aligned_offset = offset as isize - alignment as isize;
+        let aligned_len = len + alignment;
+        let result = unsafe {
+            libc::madvise(
+                self.ptr.offset(aligned_offset),
+                aligned_len as libc::size_t,
+                libc::MADV_WILLNEED,
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
     fn mprotect(&mut self, prot: libc::c_int) -> io::Result<()> {
         unsafe {
             let alignment = self.ptr as usize % page_size();
@@ -229,6 +229,10 @@ impl MmapInner {
         }
     }
 
+    pub fn prefetch(&self, _offset: usize, _len: usize) -> io::Result<()> {
+        Ok(())
+    }
+
     fn virtual_protect(&mut self, protect: DWORD) -> io::Result<()> {
         unsafe {
             let alignment = self.ptr as usize % allocation_granularity();
@@ -18,7 +18,11 @@
 */
 
 //! Some data sources and a trait to dimension and uniformly reference the data contained.
-//! The only currently supported are memmaps and ram blobs.
+//! The only currently supported are memmaps and ram blobs, Parquet files with the `parquet` feature,
+//! HDF5 datasets with the `hdf5` feature, and Arrow columns with the `arrow` feature. `TieredCloud` keeps a hot set
+//! of points in ram in front of any of them, and `NormalizedCloud` holds L2 normalized copies of their points.
+//! `DataBackend` is either a memmap or a ram blob, picked at runtime. `ScalarRam` keeps `f64` or quantized points in
+//! their own type, `BinaryRam` keeps bit packed points and `StringCloud` keeps strings.
 
 mod memmap_ram;
 
@@ -27,3 +31,34 @@ mod memmapf32;
 
 #[doc(hidden)]
 pub use memmap_ram::*;
+
+mod backend;
+pub use backend::{available_memory, BackendChoice, DataBackend};
+
+mod tiered;
+pub use tiered::TieredCloud;
+
+mod normalized;
+pub use normalized::{is_unit_norm, l2_normalize, NormalizedCloud};
+
+mod scalar_ram;
+pub use scalar_ram::ScalarRam;
+
+mod binary_ram;
+pub use binary_ram::BinaryRam;
+
+mod string_cloud;
+pub use string_cloud::StringCloud;
+
+#[cfg(feature = "parquet")]
+mod parquet_data;
+#[cfg(feature = "parquet")]
+pub use parquet_data::DataParquet;
+
+#[cfg(feature = "hdf5")]
+mod hdf5_data;
+
+#[cfg(feature = "arrow")]
+mod arrow_data;
+#[cfg(feature = "arrow")]
+pub use arrow_data::DataArrow;
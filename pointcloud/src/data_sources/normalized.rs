@@ -0,0 +1,203 @@
+//! L2 normalized copies of the points of another point cloud.
+//!
+//! Cosine style similarity search is usually done by normalizing every vector onto the unit sphere and using plain L2
+//! distances. That only works if the queries are normalized too, and forgetting to do so silently returns the wrong
+//! neighbors. `NormalizedCloud` does the normalization at ingest, and trees built on it with
+//! `CoverTreeBuilder::build_normalized` normalize their queries the same way.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::base_traits::*;
+use crate::pc_errors::*;
+use crate::{PointIndex, PointRef};
+
+/// How far a norm can be from 1 and still count as a unit vector.
+const UNIT_NORM_TOLERANCE: f32 = 1e-3;
+
+/// Scales the values to have an L2 norm of 1. All zero values are left as they are.
+pub fn l2_normalize(values: &mut [f32]) {
+    let norm = values.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// If the point has an L2 norm of 1, or is all zeros.
+pub fn is_unit_norm(point: PointRef) -> bool {
+    let values = match point {
+        PointRef::Dense(vals) => vals,
+        PointRef::Sparse(vals, _) => vals,
+        PointRef::Binary(_) | PointRef::Text(_) => return false,
+    };
+    let norm = values.iter().map(|x| x * x).sum::<f32>().sqrt();
+    norm == 0.0 || (norm - 1.0).abs() <= UNIT_NORM_TOLERANCE
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    start: usize,
+    end: usize,
+    sparse: bool,
+}
+
+/// A point cloud with every point of another one L2 normalized and copied into ram. Labels and metadata are read
+/// from the original cloud.
+#[derive(Debug)]
+pub struct NormalizedCloud<D: PointCloud> {
+    original: Arc<D>,
+    slots: Vec<Option<Slot>>,
+    values: Vec<f32>,
+    indexes: Vec<u32>,
+}
+
+impl<D: PointCloud> NormalizedCloud<D> {
+    /// Normalizes every point of the cloud.
+    pub fn new(original: Arc<D>) -> PointCloudResult<NormalizedCloud<D>> {
+        let reference_indexes = original.reference_indexes();
+        let max_index = reference_indexes.iter().max().map(|i| i + 1).unwrap_or(0);
+        let mut slots = vec![None; max_index];
+        let mut values = Vec::with_capacity(original.len() * original.dim());
+        let mut indexes = Vec::new();
+        for pi in reference_indexes {
+            let start = values.len();
+            let sparse = match original.point(pi)? {
+                PointRef::Dense(vals) => {
+                    values.extend_from_slice(vals);
+                    false
+                }
+                PointRef::Sparse(vals, inds) => {
+                    indexes.resize(start, 0);
+                    values.extend_from_slice(vals);
+                    indexes.extend_from_slice(inds);
+                    true
+                }
+                PointRef::Binary(_) | PointRef::Text(_) => {
+                    return Err(PointCloudError::data_access(
+                        pi,
+                        "bit packed points and strings can't be normalized".to_string(),
+                    ))
+                }
+            };
+            let end = values.len();
+            l2_normalize(&mut values[start..end]);
+            slots[pi] = Some(Slot { start, end, sparse });
+        }
+        Ok(NormalizedCloud {
+            original,
+            slots,
+            values,
+            indexes,
+        })
+    }
+
+    /// The cloud that was normalized.
+    pub fn original(&self) -> &Arc<D> {
+        &self.original
+    }
+}
+
+impl<D: PointCloud> PointCloud for NormalizedCloud<D> {
+    type Metric = D::Metric;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.original.dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.original.len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.original.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.original.reference_indexes()
+    }
+    #[inline]
+    fn point(&self, pi: PointIndex) -> PointCloudResult<PointRef> {
+        match self.slots.get(pi) {
+            Some(Some(slot)) if slot.sparse => Ok(PointRef::Sparse(
+                &self.values[slot.start..slot.end],
+                &self.indexes[slot.start..slot.end],
+            )),
+            Some(Some(slot)) => Ok(PointRef::Dense(&self.values[slot.start..slot.end])),
+            _ => Err(PointCloudError::data_access(
+                pi,
+                "not a point of the normalized cloud".to_string(),
+            )),
+        }
+    }
+    /// The normalized points are in ram, so there's nothing to page in. The original's points are never read again.
+    #[inline]
+    fn prefetch(&self, _indexes: &[PointIndex]) {}
+}
+
+impl<D: LabeledCloud> LabeledCloud for NormalizedCloud<D> {
+    type Label = D::Label;
+    type LabelSummary = D::LabelSummary;
+
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Label>> {
+        self.original.label(pn)
+    }
+
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        self.original.label_summary(pns)
+    }
+}
+
+impl<D: MetaCloud> MetaCloud for NormalizedCloud<D> {
+    type Metadata = D::Metadata;
+    type MetaSummary = D::MetaSummary;
+
+    fn metadata(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Metadata>> {
+        self.original.metadata(pn)
+    }
+
+    fn metasummary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>> {
+        self.original.metasummary(pns)
+    }
+}
+
+impl<D: PointCloud + fmt::Display> fmt::Display for NormalizedCloud<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NormalizedCloud of {}", self.original)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::tests::build_ram_random_test;
+
+    #[test]
+    fn points_are_normalized() {
+        let original = Arc::new(build_ram_random_test(20, 4));
+        let normalized = NormalizedCloud::new(Arc::clone(&original)).unwrap();
+        assert_eq!(normalized.len(), 20);
+        for pi in 0..20 {
+            let point = normalized.point(pi).unwrap();
+            assert!(is_unit_norm(point));
+            let found: Vec<f32> = point.dense_iter(4).collect();
+            let expected: Vec<f32> = original.point(pi).unwrap().dense_iter(4).collect();
+            let norm = expected.iter().map(|x| x * x).sum::<f32>().sqrt();
+            for (f, e) in found.iter().zip(&expected) {
+                assert!((f * norm - e).abs() < 1e-5);
+            }
+        }
+        assert!(normalized.point(20).is_err());
+
+        let mut zeros = vec![0.0f32; 3];
+        l2_normalize(&mut zeros);
+        assert_eq!(zeros, vec![0.0; 3]);
+        assert!(!is_unit_norm(PointRef::Dense(&[1.0, 1.0])));
+    }
+}
@@ -0,0 +1,222 @@
+//! Reads dense points out of the columns of a Parquet file. Only compiled with the `parquet` feature.
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, Row};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use super::DataRam;
+use crate::base_traits::*;
+use crate::label_sources::SmallIntLabels;
+use crate::pc_errors::*;
+use crate::{Metric, PointBatch, PointIndex, PointRef};
+
+/// Points read from numeric columns of a Parquet file, held in ram. Each row is a point and each of the chosen
+/// columns is a coordinate, in the order the columns were given.
+#[derive(Debug)]
+pub struct DataParquet<M: Metric> {
+    name: String,
+    data: DataRam<M>,
+}
+
+fn parquet_error<E: fmt::Display>(e: E) -> PointCloudError {
+    PointCloudError::IoError(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+fn field_value(field: &Field) -> Option<f64> {
+    match *field {
+        Field::Float(x) => Some(x as f64),
+        Field::Double(x) => Some(x),
+        Field::Byte(x) => Some(x as f64),
+        Field::Short(x) => Some(x as f64),
+        Field::Int(x) => Some(x as f64),
+        Field::Long(x) => Some(x as f64),
+        Field::UByte(x) => Some(x as f64),
+        Field::UShort(x) => Some(x as f64),
+        Field::UInt(x) => Some(x as f64),
+        Field::ULong(x) => Some(x as f64),
+        Field::Bool(x) => Some(if x { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Errors with the first of `columns` that isn't one of the file's `known` columns.
+fn check_columns(path: &Path, known: &[String], columns: &[&str]) -> PointCloudResult<()> {
+    match columns.iter().find(|c| !known.iter().any(|k| k == *c)) {
+        Some(missing) => Err(PointCloudError::ParsingError(
+            ParsingError::MissingColumnError {
+                file_name: path.to_string_lossy().to_string(),
+                column: missing.to_string(),
+            },
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Reads the requested columns of each row, `None` for nulls and non-numeric values.
+fn read_rows(path: &Path, columns: &[&str]) -> PointCloudResult<Vec<Vec<Option<f64>>>> {
+    let file = File::open(path)?;
+    let reader = SerializedFileReader::new(file).map_err(parquet_error)?;
+    let known: Vec<String> = reader
+        .metadata()
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|c| c.name().to_string())
+        .collect();
+    check_columns(path, &known, columns)?;
+    let slots: HashMap<&str, usize> = columns.iter().enumerate().map(|(i, c)| (*c, i)).collect();
+    let rows = reader.get_row_iter(None).map_err(parquet_error)?;
+    Ok(rows
+        .map(|row: Row| {
+            let mut values = vec![None; columns.len()];
+            for (name, field) in row.get_column_iter() {
+                if let Some(slot) = slots.get(name.as_str()) {
+                    values[*slot] = field_value(field);
+                }
+            }
+            values
+        })
+        .collect())
+}
+
+impl<M: Metric> DataParquet<M> {
+    /// Reads the given columns of the file as the coordinates of the points. Errors if a column is missing, or a value
+    /// is null or not a number.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        data_columns: &[&str],
+    ) -> PointCloudResult<DataParquet<M>> {
+        let path = path.as_ref();
+        let name = path.to_string_lossy().to_string();
+        let rows = read_rows(path, data_columns)?;
+        let mut data = Vec::with_capacity(rows.len() * data_columns.len());
+        for (i, row) in rows.iter().enumerate() {
+            for value in row {
+                match value {
+                    Some(x) => data.push(*x as f32),
+                    None => {
+                        return Err(PointCloudError::data_access(
+                            i,
+                            format!("{} has a null or non-numeric value", name),
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(DataParquet {
+            data: DataRam::new(data, data_columns.len())?,
+            name,
+        })
+    }
+
+    /// Reads the points like `open`, and an integer label column. Null and negative labels are masked, like
+    /// `open_int_csv`.
+    pub fn open_labeled<P: AsRef<Path>>(
+        path: P,
+        data_columns: &[&str],
+        label_column: &str,
+    ) -> PointCloudResult<SimpleLabeledCloud<DataParquet<M>, SmallIntLabels>> {
+        let data = DataParquet::open(&path, data_columns)?;
+        let rows = read_rows(path.as_ref(), &[label_column])?;
+        let labels: Vec<i64> = rows.iter().map(|r| r[0].unwrap_or(-1.0) as i64).collect();
+        let mask: Vec<bool> = labels.iter().map(|l| 0 < *l).collect();
+        let labels = if mask.iter().any(|f| !f) {
+            SmallIntLabels::new(labels, Some(mask))
+        } else {
+            SmallIntLabels::new(labels, None)
+        };
+        Ok(SimpleLabeledCloud::new(data, labels))
+    }
+
+    /// Moves the points into a plain ram cloud.
+    pub fn convert_to_ram(self) -> DataRam<M> {
+        self.data
+    }
+}
+
+impl<M: Metric> PointCloud for DataParquet<M> {
+    type Metric = M;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.data.dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.data.reference_indexes()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.data.point(i)
+    }
+    #[inline]
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        self.data.points(indexes)
+    }
+}
+
+impl<M: Metric> fmt::Display for DataParquet<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DataParquet {}: {} points of dim {}, {} metric",
+            self.name,
+            self.len(),
+            self.dim(),
+            M::name()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::L2;
+
+    #[test]
+    fn missing_columns_are_named() {
+        let path = Path::new("points.parquet");
+        let known = vec!["x".to_string(), "y".to_string()];
+        assert!(check_columns(path, &known, &["y", "x"]).is_ok());
+        match check_columns(path, &known, &["x", "z", "w"]) {
+            Err(PointCloudError::ParsingError(ParsingError::MissingColumnError {
+                file_name,
+                column,
+            })) => {
+                assert_eq!(file_name, "points.parquet");
+                assert_eq!(column, "z");
+            }
+            other => panic!("expected a missing column error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        match DataParquet::<L2>::open("/this/file/does/not/exist.parquet", &["x"]) {
+            Err(PointCloudError::IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("expected an io error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn numeric_fields_convert() {
+        assert_eq!(field_value(&Field::Int(3)), Some(3.0));
+        assert_eq!(field_value(&Field::Double(0.5)), Some(0.5));
+        assert_eq!(field_value(&Field::Bool(true)), Some(1.0));
+        assert_eq!(field_value(&Field::Null), None);
+        assert_eq!(field_value(&Field::Str("1.0".to_string())), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! Dense points held in ram in their own `Scalar` type.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::DataRam;
+use crate::distances::Metric;
+use crate::pc_errors::*;
+use crate::{PointIndex, Scalar};
+
+/// Dense points in ram stored as `f64`, `i8` or any other `Scalar`. `f64` data keeps its precision and quantized `i8`
+/// data takes a quarter of the memory of `f32`. Distances are accumulated in `f64` by `Metric::dense_scalar`.
+///
+/// This is not a `PointCloud`, as `PointRef` only borrows `f32` values and trees are built in `f32`. Build the tree
+/// on `to_f32` and use this for the exact distances of the results, or on its own for brute force search.
+#[derive(Debug, Clone)]
+pub struct ScalarRam<T: Scalar, M: Metric> {
+    data: Vec<T>,
+    dim: usize,
+    metric: PhantomData<M>,
+}
+
+impl<T: Scalar, M: Metric> ScalarRam<T, M> {
+    /// Row major points of dimension `dim`.
+    pub fn new(data: Vec<T>, dim: usize) -> PointCloudResult<ScalarRam<T, M>> {
+        if dim == 0 || data.len() % dim != 0 {
+            return Err(PointCloudError::data_access(
+                data.len(),
+                format!(
+                    "{} values do not split into points of dimension {}",
+                    T::NAME,
+                    dim
+                ),
+            ));
+        }
+        Ok(ScalarRam {
+            data,
+            dim,
+            metric: PhantomData,
+        })
+    }
+
+    /// The dimension of the points
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// The number of points
+    pub fn len(&self) -> usize {
+        self.data.len() / self.dim
+    }
+
+    /// If there are no points
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The values of a point.
+    pub fn point(&self, pi: PointIndex) -> PointCloudResult<&[T]> {
+        self.data
+            .get(pi * self.dim..(pi + 1) * self.dim)
+            .ok_or_else(|| PointCloudError::data_access(pi, "point index out of range".to_string()))
+    }
+
+    /// The distances from a point to each of the indexed points.
+    pub fn distances_to_point(
+        &self,
+        x: &[T],
+        indexes: &[PointIndex],
+    ) -> PointCloudResult<Vec<f64>> {
+        if x.len() != self.dim {
+            return Err(PointCloudError::MetricError);
+        }
+        indexes
+            .iter()
+            .map(|pi| Ok(M::dense_scalar(x, self.point(*pi)?)))
+            .collect()
+    }
+
+    /// The distances from the point at `pi` to each of the indexed points.
+    pub fn distances_to_point_index(
+        &self,
+        pi: PointIndex,
+        indexes: &[PointIndex],
+    ) -> PointCloudResult<Vec<f64>> {
+        self.distances_to_point(self.point(pi)?, indexes)
+    }
+
+    /// Rounds the points to `f32`, to build a tree on.
+    pub fn to_f32(&self) -> PointCloudResult<DataRam<M>> {
+        DataRam::new(self.data.iter().map(|v| v.to_f32()).collect(), self.dim)
+    }
+}
+
+impl<T: Scalar, M: Metric> fmt::Display for ScalarRam<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} points of dimension {} stored as {}",
+            self.len(),
+            self.dim,
+            T::NAME
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_traits::PointCloud;
+    use crate::{Cosine, L1, L2};
+
+    #[test]
+    fn scalar_distances_keep_precision() {
+        // These differ past the precision of f32, so rounding them makes them equal
+        let data = vec![1.0f64, 0.0, 1.0 + 1e-9, 0.0, 4.0, 4.0];
+        let cloud = ScalarRam::<f64, L2>::new(data, 2).unwrap();
+        assert_eq!(cloud.len(), 3);
+        let dists = cloud.distances_to_point_index(0, &[1, 2]).unwrap();
+        assert!((dists[0] - 1e-9).abs() < 1e-15);
+        assert!((dists[1] - 5.0).abs() < 1e-12);
+        let rounded = cloud.to_f32().unwrap();
+        assert_eq!(
+            rounded.distances_to_point_index(0, &[1]).unwrap(),
+            vec![0.0]
+        );
+        assert!(cloud.point(3).is_err());
+        assert!(cloud.distances_to_point(&[0.0], &[0]).is_err());
+
+        let quantized = ScalarRam::<i8, L1>::new(vec![-128, 127, 0, 0], 2).unwrap();
+        assert_eq!(
+            quantized.distances_to_point_index(0, &[1]).unwrap(),
+            vec![255.0]
+        );
+        assert!(ScalarRam::<i8, L1>::new(vec![1, 2, 3], 2).is_err());
+
+        let x = [3.0f32, 4.0];
+        let y = [4.0f32, 3.0];
+        assert!((Cosine::dense_scalar(&x, &y) - Cosine::dense(&x, &y) as f64).abs() < 1e-6);
+        assert!((L2::dense_scalar(&x, &y) - L2::dense(&x, &y) as f64).abs() < 1e-6);
+    }
+}
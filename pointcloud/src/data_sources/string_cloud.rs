@@ -0,0 +1,152 @@
+//! Strings held in ram.
+
+use std::fmt;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::base_traits::*;
+use crate::distances::Metric;
+use crate::pc_errors::*;
+use crate::{PointBatch, PointIndex, PointRef};
+
+/// Points that are strings, like names, tokens or DNA reads. Use it with `Levenshtein`, or another metric that
+/// implements `Metric::text`. The strings are packed one after another in a single buffer.
+///
+/// The dimension is the length in characters of the longest string. Strings aren't vectors, so summaries and plugins
+/// that average points see the character codes padded with zeros.
+#[derive(Debug)]
+pub struct StringCloud<M: Metric> {
+    text: String,
+    offsets: Vec<usize>,
+    dim: usize,
+    metric: PhantomData<M>,
+}
+
+impl<M: Metric> StringCloud<M> {
+    /// Packs the strings, in order.
+    pub fn new<S: AsRef<str>>(strings: &[S]) -> StringCloud<M> {
+        let mut text = String::new();
+        let mut offsets = Vec::with_capacity(strings.len() + 1);
+        let mut dim = 0;
+        offsets.push(0);
+        for s in strings {
+            text.push_str(s.as_ref());
+            offsets.push(text.len());
+            dim = dim.max(s.as_ref().chars().count());
+        }
+        StringCloud {
+            text,
+            offsets,
+            dim,
+            metric: PhantomData,
+        }
+    }
+
+    /// Reads a text file with one string to a line. Empty lines are empty strings, except for a final newline.
+    pub fn from_lines<P: AsRef<Path>>(path: P) -> PointCloudResult<StringCloud<M>> {
+        let contents = fs::read_to_string(path)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        Ok(StringCloud::new(&lines))
+    }
+
+    /// The string at the index, if there is one.
+    pub fn string(&self, i: PointIndex) -> Option<&str> {
+        if i + 1 < self.offsets.len() {
+            Some(&self.text[self.offsets[i]..self.offsets[i + 1]])
+        } else {
+            None
+        }
+    }
+}
+
+impl<M: Metric> PointCloud for StringCloud<M> {
+    type Metric = M;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.dim
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.offsets.len() == 1
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        (0..self.len()).collect()
+    }
+    #[inline]
+    fn point(&self, i: PointIndex) -> PointCloudResult<PointRef> {
+        self.string(i)
+            .map(PointRef::Text)
+            .ok_or_else(|| PointCloudError::data_access(i, "string cloud".to_string()))
+    }
+    fn points(&self, indexes: &[PointIndex]) -> PointCloudResult<PointBatch> {
+        Ok(PointBatch::new(
+            indexes
+                .iter()
+                .map(|i| self.point(*i))
+                .collect::<PointCloudResult<Vec<PointRef>>>()?,
+        ))
+    }
+}
+
+impl<M: Metric> fmt::Display for StringCloud<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "StringCloud: {} strings of up to {} characters, {} metric",
+            self.len(),
+            self.dim,
+            M::name()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric_audit::audit_metric;
+    use crate::{Levenshtein, L2};
+
+    #[test]
+    fn edit_distances_between_strings() {
+        let cloud =
+            StringCloud::<Levenshtein>::new(&["kitten", "sitting", "", "flaw", "lawn", "naïve"]);
+        assert_eq!(cloud.len(), 6);
+        assert_eq!(cloud.dim(), 7);
+        assert_eq!(
+            cloud.distances_to_point_index(0, &[1, 2, 3]).unwrap(),
+            vec![3.0, 6.0, 6.0]
+        );
+        assert_eq!(Levenshtein::dist("flaw", "lawn").unwrap(), 2.0);
+        // Characters, not bytes
+        assert_eq!(Levenshtein::dist("naïve", "naive").unwrap(), 1.0);
+        assert_eq!(Levenshtein::within("kitten", "sitting", 3), Some(3));
+        assert_eq!(Levenshtein::within("kitten", "sitting", 2), None);
+        assert_eq!(Levenshtein::within("abc", "abcdef", 2), None);
+        assert_eq!(Levenshtein::dense(&[1.0, 2.0, 3.0], &[2.0, 3.0]), 1.0);
+
+        let codes: Vec<f32> = cloud.point(5).unwrap().dense_iter(7).collect();
+        assert_eq!(codes, vec![110.0, 97.0, 239.0, 118.0, 101.0, 0.0, 0.0]);
+        assert!(cloud.point(6).is_err());
+        assert!(L2::dist("flaw", "lawn").is_err());
+        assert!(Levenshtein::dist("flaw", &[0.0f32, 1.0]).is_err());
+
+        let words: Vec<String> = (0..40)
+            .map(|i: u32| format!("{:b}", i * 37 % 101).replace('0', "ab"))
+            .collect();
+        let audit = audit_metric(
+            &StringCloud::<Levenshtein>::new(&words),
+            30,
+            0.0,
+            &mut rand::thread_rng(),
+        )
+        .unwrap();
+        assert!(audit.is_metric());
+    }
+}
@@ -0,0 +1,195 @@
+//! Keeps a chosen set of points in ram in front of a slower point cloud.
+//!
+//! A cover tree only needs the points at the centers of its nodes to route a query, the singletons and leaf points are
+//! only read by the exact distance checks at the bottom. Holding the centers in ram and leaving everything else on
+//! the slow tier, a memmap or a remote store, cuts the resident memory to the routing points while every point stays
+//! reachable. Points outside the hot set are read from the cold cloud whenever they're asked for.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::base_traits::*;
+use crate::pc_errors::*;
+use crate::{PointIndex, PointRef};
+
+#[derive(Debug, Clone, Copy)]
+struct HotSlot {
+    start: usize,
+    end: usize,
+    sparse: bool,
+}
+
+/// A cold point cloud with a hot set of its points copied into ram.
+#[derive(Debug)]
+pub struct TieredCloud<D: PointCloud> {
+    cold: Arc<D>,
+    hot: HashMap<PointIndex, HotSlot>,
+    values: Vec<f32>,
+    indexes: Vec<u32>,
+    cold_reads: AtomicUsize,
+}
+
+impl<D: PointCloud> TieredCloud<D> {
+    /// Copies the `hot` points of the cold cloud into ram.
+    pub fn new(cold: Arc<D>, hot: &[PointIndex]) -> PointCloudResult<TieredCloud<D>> {
+        let mut slots = HashMap::with_capacity(hot.len());
+        let mut values = Vec::new();
+        let mut indexes = Vec::new();
+        for pi in hot {
+            if slots.contains_key(pi) {
+                continue;
+            }
+            let start = values.len();
+            let sparse = match cold.point(*pi)? {
+                PointRef::Dense(vals) => {
+                    values.extend_from_slice(vals);
+                    false
+                }
+                PointRef::Sparse(vals, inds) => {
+                    indexes.resize(start, 0);
+                    values.extend_from_slice(vals);
+                    indexes.extend_from_slice(inds);
+                    true
+                }
+                PointRef::Binary(_) | PointRef::Text(_) => {
+                    return Err(PointCloudError::data_access(
+                        *pi,
+                        "bit packed points and strings can't be kept hot".to_string(),
+                    ))
+                }
+            };
+            let end = values.len();
+            slots.insert(*pi, HotSlot { start, end, sparse });
+        }
+        Ok(TieredCloud {
+            cold,
+            hot: slots,
+            values,
+            indexes,
+            cold_reads: AtomicUsize::new(0),
+        })
+    }
+
+    /// The cloud behind the hot set.
+    pub fn cold(&self) -> &Arc<D> {
+        &self.cold
+    }
+
+    /// If the point is held in ram.
+    pub fn is_hot(&self, pi: PointIndex) -> bool {
+        self.hot.contains_key(&pi)
+    }
+
+    /// The number of points held in ram.
+    pub fn hot_len(&self) -> usize {
+        self.hot.len()
+    }
+
+    /// How many point reads went to the cold cloud so far.
+    pub fn cold_reads(&self) -> usize {
+        self.cold_reads.load(Ordering::Relaxed)
+    }
+}
+
+impl<D: PointCloud> PointCloud for TieredCloud<D> {
+    type Metric = D::Metric;
+
+    #[inline]
+    fn dim(&self) -> usize {
+        self.cold.dim()
+    }
+    #[inline]
+    fn len(&self) -> usize {
+        self.cold.len()
+    }
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cold.is_empty()
+    }
+    #[inline]
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        self.cold.reference_indexes()
+    }
+    #[inline]
+    fn point(&self, pi: PointIndex) -> PointCloudResult<PointRef> {
+        match self.hot.get(&pi) {
+            Some(slot) if slot.sparse => Ok(PointRef::Sparse(
+                &self.values[slot.start..slot.end],
+                &self.indexes[slot.start..slot.end],
+            )),
+            Some(slot) => Ok(PointRef::Dense(&self.values[slot.start..slot.end])),
+            None => {
+                self.cold_reads.fetch_add(1, Ordering::Relaxed);
+                self.cold.point(pi)
+            }
+        }
+    }
+}
+
+impl<D: LabeledCloud> LabeledCloud for TieredCloud<D> {
+    type Label = D::Label;
+    type LabelSummary = D::LabelSummary;
+
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Label>> {
+        self.cold.label(pn)
+    }
+
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        self.cold.label_summary(pns)
+    }
+}
+
+impl<D: MetaCloud> MetaCloud for TieredCloud<D> {
+    type Metadata = D::Metadata;
+    type MetaSummary = D::MetaSummary;
+
+    fn metadata(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Metadata>> {
+        self.cold.metadata(pn)
+    }
+
+    fn metasummary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>> {
+        self.cold.metasummary(pns)
+    }
+}
+
+impl<D: PointCloud + fmt::Display> fmt::Display for TieredCloud<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TieredCloud: {} hot points in front of {}",
+            self.hot.len(),
+            self.cold
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::tests::build_ram_random_test;
+
+    #[test]
+    fn hot_points_match_cold() {
+        let cold = Arc::new(build_ram_random_test(50, 3));
+        let tiered = TieredCloud::new(Arc::clone(&cold), &[0, 7, 7, 31]).unwrap();
+        assert_eq!(tiered.hot_len(), 3);
+        assert_eq!(tiered.len(), 50);
+        for pi in 0..50 {
+            let expected: Vec<f32> = cold.point(pi).unwrap().dense_iter(3).collect();
+            let found: Vec<f32> = tiered.point(pi).unwrap().dense_iter(3).collect();
+            assert_eq!(expected, found);
+        }
+        assert_eq!(tiered.cold_reads(), 47);
+        assert!(tiered.is_hot(31));
+        assert!(!tiered.is_hot(30));
+        assert!(tiered.point(50).is_err());
+    }
+}
@@ -20,9 +20,13 @@
 //! Supported distances
 
 use super::PointRef;
+use crate::kernels;
 use crate::pc_errors::*;
+use crate::Scalar;
 use packed_simd::*;
+use std::convert::TryInto;
 use std::fmt::Debug;
+use std::marker::PhantomData;
 
 
 
@@ -33,168 +37,121 @@ pub trait Metric: 'static + Send + Sync + Debug + Clone {
     /// Sparse calculation, we assume that the index slices are in accending order and
     /// that the values correspond to the indexes
     fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32;
+    /// Sparse to dense calculation, the sparse indexes are in accending order and index into the dense slice. This
+    /// densifies the sparse point by default, metrics should override it with a kernel that doesn't allocate.
+    fn sparse_dense(x_ind: &[u32], x_val: &[f32], y: &[f32]) -> f32 {
+        let mut x = vec![0.0; y.len()];
+        for (i, v) in x_ind.iter().zip(x_val) {
+            x[*i as usize] = *v;
+        }
+        Self::dense(&x, y)
+    }
     /// The norm, dense(x,x)
     fn norm(x: &[f32]) -> f32;
-    /// Useful external calculation
+    /// Dense calculation for points stored in any `Scalar` type, accumulated in `f64`. The default rounds the points
+    /// to `f32` and calls `dense`, metrics override it to keep the precision of `f64` data.
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        let x: Vec<f32> = x.iter().map(|v| v.to_f32()).collect();
+        let y: Vec<f32> = y.iter().map(|v| v.to_f32()).collect();
+        Self::dense(&x, &y) as f64
+    }
+    /// Bit packed calculation, on points of the same number of words. This unpacks the bits into 0s and 1s and calls
+    /// `dense` by default, metrics on sets of bits should override it with a popcount kernel.
+    fn binary(x: &[u64], y: &[u64]) -> f32 {
+        let unpack = |words: &[u64]| -> Vec<f32> {
+            (0..words.len() * 64)
+                .map(|i| ((words[i / 64] >> (i % 64)) & 1) as f32)
+                .collect()
+        };
+        Self::dense(&unpack(x), &unpack(y))
+    }
+    /// String calculation, `None` if the metric doesn't compare strings, which is the default.
+    fn text(_x: &str, _y: &str) -> Option<f32> {
+        None
+    }
+    /// A short human readable name for the metric.
+    fn name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    /// Useful external calculation. This checks the points before handing them to the kernels, sparse points need
+    /// strictly ascending indexes, and indexes that fit in the dense point they are compared to.
     fn dist<'a, 'b, T, S>(x: T, y: S) -> PointCloudResult<f32>
     where
         T: Into<PointRef<'a>>,
         S: Into<PointRef<'b>>,
     {
         match ((x).into(), (y).into()) {
-            (PointRef::Dense(x_vals), PointRef::Dense(y_vals)) => Ok((Self::dense)(x_vals, y_vals)),
+            (PointRef::Dense(x_vals), PointRef::Dense(y_vals)) => {
+                if x_vals.len() != y_vals.len() {
+                    return Err(PointCloudError::MetricError);
+                }
+                Ok((Self::dense)(x_vals, y_vals))
+            }
             (PointRef::Sparse(x_vals, x_ind), PointRef::Sparse(y_vals, y_inds)) => {
+                if x_vals.len() != x_ind.len()
+                    || y_vals.len() != y_inds.len()
+                    || !ascending(x_ind)
+                    || !ascending(y_inds)
+                {
+                    return Err(PointCloudError::MetricError);
+                }
                 Ok((Self::sparse)(x_ind, x_vals, y_inds, y_vals))
             }
+            (PointRef::Sparse(x_vals, x_ind), PointRef::Dense(y_vals))
+            | (PointRef::Dense(y_vals), PointRef::Sparse(x_vals, x_ind)) => {
+                if x_vals.len() != x_ind.len()
+                    || !ascending(x_ind)
+                    || x_ind.last().map(|i| *i as usize >= y_vals.len()) == Some(true)
+                {
+                    return Err(PointCloudError::MetricError);
+                }
+                Ok((Self::sparse_dense)(x_ind, x_vals, y_vals))
+            }
+            (PointRef::Binary(x_words), PointRef::Binary(y_words)) => {
+                if x_words.len() != y_words.len() {
+                    return Err(PointCloudError::MetricError);
+                }
+                Ok((Self::binary)(x_words, y_words))
+            }
+            (PointRef::Text(x_text), PointRef::Text(y_text)) => {
+                (Self::text)(x_text, y_text).ok_or(PointCloudError::MetricError)
+            }
+            // Bit packed points and strings can only be compared to their own kind
             _ => Err(PointCloudError::MetricError),
         }
     }
 }
 
+/// If the sparse indexes are strictly ascending, which the sparse kernels rely on.
+fn ascending(indexes: &[u32]) -> bool {
+    indexes.windows(2).all(|w| w[0] < w[1])
+}
+
 /// L2 norm, the square root of the sum of squares
 #[derive(Debug, Clone)]
 pub struct L2 {}
 
-/*
-#[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
-#[cfg(target_arch = "x86")]
-use std::arch::x86::*;
-
-fn dense_l2_safe(x: &[f32], y: &[f32]) -> f32 {
-    y.iter().zip(x).map(|(xi, yi)| (xi - yi) * (xi - yi)).fold(0.0, |acc, y| acc + y)
-}
-
-fn dense_l2_avx(mut x: &[f32], mut y: &[f32]) -> f32 {
-    assert_eq!(x.len(),y.len());
-    let mut index = 0;
-    let len = x.len();
-    let mut x_ptr = x.as_ptr();
-    let mut y_ptr = y.as_ptr();
-
-    let mut avx_acc = unsafe {_mm256_setzero_ps()};
-    while len - index > 8 {
-        unsafe {
-            let x_simd = _mm256_loadu_ps(x_ptr);
-            let y_simd = _mm256_loadu_ps(y_ptr);
-            let diff = _mm256_sub_ps(x_simd,y_simd);
-            avx_acc = _mm256_fmadd_ps(diff,diff,avx_acc);
-
-            x_ptr = x_ptr.offset(8);
-            y_ptr = y_ptr.offset(8);
-        }
-        index += 8;
+impl Metric for L2 {
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        x.iter()
+            .zip(y)
+            .map(|(a, b)| (a.to_f64() - b.to_f64()).powi(2))
+            .sum::<f64>()
+            .sqrt()
     }
 
-    let simd: [f32;8] = unsafe { std::mem::transmute(avx_acc) };
-
-    y = &y[index..];
-    x = &x[index..];
-    let leftover: f32 = y
-        .iter()
-        .zip(x)
-        .map(|(xi, yi)| (xi - yi) * (xi - yi))
-        .fold(0.0, |acc, y| acc + y);
-
-    simd.iter().fold(leftover,|a,x| a+x).sqrt()
-}
-
-
-use simdeez::*;
-use simdeez::scalar::*;
-use simdeez::sse2::*;
-use simdeez::sse41::*;
-use simdeez::avx::*;
-use simdeez::avx2::*;
-// If you want your SIMD function to use use runtime feature detection to call
-// the fastest available version, use the simd_runtime_generate macro:
-simd_runtime_generate!(
-fn l2_distance(x: &[f32], y: &[f32]) -> Vec<f32> {
-    
-    /// Set each slice to the same length for iteration efficiency
-    let mut x = &x[..x.len()];
-    let mut y = &y[..x.len()];
-    let mut acc = S::setzero_ps();
-    let mut leftover: f32 = 0;
-
-    // Operations have to be done in terms of the vector width
-    // so that it will work with any size vector.
-    // the width of a vector type is provided as a constant
-    // so the compiler is free to optimize it more.
-    // S::VF32_WIDTH is a constant, 4 when using SSE, 8 when using AVX2, etc
-    while x.len() >= S::VF32_WIDTH {
-        //load data from your vec into an SIMD value
-        let xv = S::loadu_ps(&x[0]);
-        let yv = S::loadu_ps(&y[0]);
-
-        // Use the usual intrinsic syntax if you prefer
-        let mut diff = S::sub_ps(xv1, yv1);
-        acc += diff*diff;
-        
-        // Move each slice to the next position
-        x = &x[S::VF32_WIDTH..];
-        y = &y[S::VF32_WIDTH..];
-    }
-    
-    // (Optional) Compute the remaining elements. Not necessary if you are sure the length
-    // of your data is always a multiple of the maximum S::VF32_WIDTH you compile for (4 for SSE, 8 for AVX2, etc).
-    // This can be asserted by putting `assert_eq!(x1.len(), 0);` here
-    for i in 0..x1.len() {
-        let diff = x[i] - y[i];
-        leftover += diff*diff;
-    }
-    for i in 0..S::VF32_WIDTH {
-        leftover += acc[i];
-    }
-    leftover.sqrt()
-});
-*/
+    fn name() -> &'static str {
+        "L2"
+    }
 
-impl Metric for L2 {
     #[inline]
-    fn dense(mut x: &[f32], mut y: &[f32]) -> f32 {
-        let mut d_acc_16 = f32x16::splat(0.0);
-        while y.len() > 16 {
-            let x_simd = f32x16::from_slice_unaligned(x);
-            let y_simd = f32x16::from_slice_unaligned(y);
-            let diff = x_simd - y_simd;
-            d_acc_16 += diff * diff;
-            y = &y[16..];
-            x = &x[16..];
-        }
-        let mut d_acc_8 = f32x8::splat(0.0);
-        if y.len() > 8 {
-            let x_simd = f32x8::from_slice_unaligned(x);
-            let y_simd = f32x8::from_slice_unaligned(y);
-            let diff = x_simd - y_simd;
-            d_acc_8 += diff * diff;
-            y = &y[8..];
-            x = &x[8..];
-        }
-        let leftover = y
-            .iter()
-            .zip(x)
-            .map(|(xi, yi)| (xi - yi) * (xi - yi))
-            .fold(0.0, |acc, y| acc + y);
-        (leftover + d_acc_8.sum() + d_acc_16.sum()).sqrt()
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        kernels::squared_l2(x, y).sqrt()
     }
 
     #[inline]
-    fn norm(mut x: &[f32]) -> f32 {
-        let mut d_acc_16 = f32x16::splat(0.0);
-        while x.len() > 16 {
-            let x_simd = f32x16::from_slice_unaligned(x);
-            d_acc_16 += x_simd * x_simd;
-            x = &x[16..];
-        }
-        let mut d_acc_8 = f32x8::splat(0.0);
-        if x.len() > 8 {
-            let x_simd = f32x8::from_slice_unaligned(x);
-            d_acc_8 += x_simd * x_simd;
-            x = &x[8..];
-        }
-        let leftover = x.iter().map(|xi| xi * xi).fold(0.0, |acc, xi| acc + xi);
-        (leftover + d_acc_8.sum() + d_acc_16.sum()).sqrt()
+    fn norm(x: &[f32]) -> f32 {
+        kernels::squared_norm(x).sqrt()
     }
 
     fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
@@ -244,6 +201,19 @@ impl Metric for L2 {
             total.sqrt()
         }
     }
+
+    fn sparse_dense(x_ind: &[u32], x_val: &[f32], y: &[f32]) -> f32 {
+        let mut total = 0.0;
+        let mut last = 0;
+        for (i, xv) in x_ind.iter().zip(x_val) {
+            let i = *i as usize;
+            total += Self::norm(&y[last..i]).powi(2);
+            let diff = xv - y[i];
+            total += diff * diff;
+            last = i + 1;
+        }
+        (total + Self::norm(&y[last..]).powi(2)).sqrt()
+    }
 }
 
 /// L infity norm, the max of the absolute values of the elements
@@ -251,6 +221,17 @@ impl Metric for L2 {
 pub struct Linfty {}
 
 impl Metric for Linfty {
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        x.iter()
+            .zip(y)
+            .map(|(a, b)| (a.to_f64() - b.to_f64()).abs())
+            .fold(0.0, f64::max)
+    }
+
+    fn name() -> &'static str {
+        "Linfty"
+    }
+
     #[inline]
     fn dense(mut x: &[f32], mut y: &[f32]) -> f32 {
         let mut d_acc_16 = f32x16::splat(0.0);
@@ -354,32 +335,20 @@ impl Metric for Linfty {
 pub struct L1 {}
 
 impl Metric for L1 {
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        x.iter()
+            .zip(y)
+            .map(|(a, b)| (a.to_f64() - b.to_f64()).abs())
+            .sum()
+    }
+
+    fn name() -> &'static str {
+        "L1"
+    }
+
     #[inline]
-    fn dense(mut x: &[f32], mut y: &[f32]) -> f32 {
-        let mut d_acc_16 = f32x16::splat(0.0);
-        while y.len() > 16 {
-            let y_simd = f32x16::from_slice_unaligned(y);
-            let x_simd = f32x16::from_slice_unaligned(x);
-            let diff = x_simd - y_simd;
-            d_acc_16 += diff.abs();
-            y = &y[16..];
-            x = &x[16..];
-        }
-        let mut d_acc_8 = f32x8::splat(0.0);
-        if y.len() > 8 {
-            let y_simd = f32x8::from_slice_unaligned(y);
-            let x_simd = f32x8::from_slice_unaligned(x);
-            let diff = x_simd - y_simd;
-            d_acc_8 += diff.abs();
-            y = &y[8..];
-            x = &x[8..];
-        }
-        let leftover = y
-            .iter()
-            .zip(x)
-            .map(|(xi, yi)| (xi - yi).abs())
-            .fold(0.0, |acc, y| acc + y);
-        leftover + d_acc_8.sum() + d_acc_16.sum()
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        kernels::l1(x, y)
     }
 
     #[inline]
@@ -447,6 +416,28 @@ impl Metric for L1 {
             total
         }
     }
+
+    fn sparse_dense(x_ind: &[u32], x_val: &[f32], y: &[f32]) -> f32 {
+        let mut total = 0.0;
+        let mut last = 0;
+        for (i, xv) in x_ind.iter().zip(x_val) {
+            let i = *i as usize;
+            total += Self::norm(&y[last..i]);
+            total += (xv - y[i]).abs();
+            last = i + 1;
+        }
+        total + Self::norm(&y[last..])
+    }
+}
+
+/// The dot product and the squared norms of two points, in `f64`.
+fn products<T: Scalar>(x: &[T], y: &[T]) -> (f64, f64, f64) {
+    x.iter()
+        .zip(y)
+        .fold((0.0, 0.0, 0.0), |(dot, x_sq, y_sq), (a, b)| {
+            let (a, b) = (a.to_f64(), b.to_f64());
+            (dot + a * b, x_sq + a * a, y_sq + b * b)
+        })
 }
 
 /// Not a norm! Still, helpful for document clouds and the like
@@ -454,43 +445,19 @@ impl Metric for L1 {
 pub struct CosineSim {}
 
 impl Metric for CosineSim {
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        let (dot, x_sq, y_sq) = products(x, y);
+        dot / (x_sq.sqrt() * y_sq.sqrt()).max(0.00001)
+    }
+
+    fn name() -> &'static str {
+        "CosineSim"
+    }
+
     #[inline]
-    fn dense(mut x: &[f32], mut y: &[f32]) -> f32 {
-        let mut d_acc_16 = f32x16::splat(0.0);
-        let mut x_acc_16 = f32x16::splat(0.0);
-        let mut y_acc_16 = f32x16::splat(0.0);
-        while y.len() > 16 {
-            let y_simd = f32x16::from_slice_unaligned(y);
-            let x_simd = f32x16::from_slice_unaligned(x);
-            d_acc_16 += x_simd * y_simd;
-            x_acc_16 += x_simd * x_simd;
-            y_acc_16 += y_simd * y_simd;
-            y = &y[16..];
-            x = &x[16..];
-        }
-        let mut d_acc_8 = f32x8::splat(0.0);
-        let mut x_acc_8 = f32x8::splat(0.0);
-        let mut y_acc_8 = f32x8::splat(0.0);
-        if y.len() > 8 {
-            let y_simd = f32x8::from_slice_unaligned(y);
-            let x_simd = f32x8::from_slice_unaligned(x);
-            d_acc_8 += x_simd * y_simd;
-            x_acc_8 += x_simd * x_simd;
-            y_acc_8 += y_simd * y_simd;
-            y = &y[8..];
-            x = &x[8..];
-        }
-        let acc_leftover = y
-            .iter()
-            .zip(x)
-            .map(|(xi, yi)| xi * yi)
-            .fold(0.0, |acc, y| acc + y);
-        let y_leftover = y.iter().map(|yi| yi * yi).fold(0.0, |acc, yi| acc + yi);
-        let x_leftover = x.iter().map(|xi| xi * xi).fold(0.0, |acc, xi| acc + xi);
-        let acc = acc_leftover + d_acc_8.sum() + d_acc_16.sum();
-        let xnm = (x_leftover + x_acc_8.sum() + x_acc_16.sum()).sqrt();
-        let ynm = (y_leftover + y_acc_8.sum() + y_acc_16.sum()).sqrt();
-        acc / (xnm * ynm).max(0.00001)
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        let (dot, x_sq, y_sq) = kernels::products(x, y);
+        dot / (x_sq.sqrt() * y_sq.sqrt()).max(0.00001)
     }
 
     fn norm(_x: &[f32]) -> f32 {
@@ -526,3 +493,525 @@ impl Metric for CosineSim {
         }
     }
 }
+
+/// Cosine distance, the distance between the two vectors after normalizing them onto the unit sphere. This is
+/// `sqrt(2 - 2 cos(x, y))`, which unlike `1 - cos(x, y)` satisfies the triangle inequality, and orders neighbors the
+/// same way. A zero vector normalizes to the origin, so it is at distance 1 from every other vector.
+#[derive(Debug, Clone)]
+pub struct Cosine {}
+
+impl Cosine {
+    #[inline]
+    fn from_products(dot: f32, x_sq: f32, y_sq: f32) -> f32 {
+        let (x_nm, y_nm) = (x_sq.sqrt(), y_sq.sqrt());
+        let x_unit = if x_nm > 0.0 { 1.0 } else { 0.0 };
+        let y_unit = if y_nm > 0.0 { 1.0 } else { 0.0 };
+        let cos = if x_nm > 0.0 && y_nm > 0.0 {
+            (dot / (x_nm * y_nm)).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        (x_unit + y_unit - 2.0 * cos).max(0.0).sqrt()
+    }
+}
+
+impl Metric for Cosine {
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        let (dot, x_sq, y_sq) = products(x, y);
+        let (x_nm, y_nm) = (x_sq.sqrt(), y_sq.sqrt());
+        let x_unit = if x_nm > 0.0 { 1.0 } else { 0.0 };
+        let y_unit = if y_nm > 0.0 { 1.0 } else { 0.0 };
+        let cos = if x_nm > 0.0 && y_nm > 0.0 {
+            (dot / (x_nm * y_nm)).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        (x_unit + y_unit - 2.0 * cos).max(0.0).sqrt()
+    }
+
+    fn name() -> &'static str {
+        "Cosine"
+    }
+
+    #[inline]
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        let (dot, x_sq, y_sq) = kernels::products(x, y);
+        Self::from_products(dot, x_sq, y_sq)
+    }
+
+    /// The distance to the origin, 1 for any non-zero vector.
+    fn norm(x: &[f32]) -> f32 {
+        if x.iter().any(|xi| *xi != 0.0) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let mut dot = 0.0;
+        let mut x_iter = x_ind.iter().zip(x_val).peekable();
+        let mut y_iter = y_ind.iter().zip(y_val).peekable();
+        while let (Some((xi, xv)), Some((yi, yv))) = (x_iter.peek(), y_iter.peek()) {
+            if xi < yi {
+                x_iter.next();
+            } else if yi < xi {
+                y_iter.next();
+            } else {
+                dot += *xv * *yv;
+                x_iter.next();
+                y_iter.next();
+            }
+        }
+        let x_sq = x_val.iter().map(|xi| xi * xi).fold(0.0, |acc, xi| acc + xi);
+        let y_sq = y_val.iter().map(|yi| yi * yi).fold(0.0, |acc, yi| acc + yi);
+        Self::from_products(dot, x_sq, y_sq)
+    }
+
+    fn sparse_dense(x_ind: &[u32], x_val: &[f32], y: &[f32]) -> f32 {
+        let dot = x_ind
+            .iter()
+            .zip(x_val)
+            .map(|(i, xv)| xv * y[*i as usize])
+            .fold(0.0, |acc, v| acc + v);
+        let x_sq = x_val.iter().map(|xi| xi * xi).fold(0.0, |acc, xi| acc + xi);
+        Self::from_products(dot, x_sq, L2::norm(y).powi(2))
+    }
+}
+
+/// Wraps a metric for points of a known, fixed dimension. The slices are checked against `DIM` once and then handed
+/// to the inner metric as fixed size arrays, so the compiler can unroll its SIMD loops completely.
+///
+/// This reports the inner metric's name, so trees built with it can be loaded with the plain metric and vice versa.
+#[derive(Debug, Clone)]
+pub struct FixedDim<M: Metric, const DIM: usize> {
+    metric: PhantomData<M>,
+}
+
+impl<M: Metric, const DIM: usize> FixedDim<M, DIM> {
+    /// Copies a point into a stack buffer, useful for queries that shouldn't allocate.
+    pub fn buffer(x: &[f32]) -> PointCloudResult<[f32; DIM]> {
+        x.try_into().map_err(|_| PointCloudError::MetricError)
+    }
+}
+
+impl<M: Metric, const DIM: usize> Metric for FixedDim<M, DIM> {
+    fn name() -> &'static str {
+        M::name()
+    }
+
+    #[inline]
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        let x: &[f32; DIM] = x
+            .try_into()
+            .expect("Point does not have the fixed dimension");
+        let y: &[f32; DIM] = y
+            .try_into()
+            .expect("Point does not have the fixed dimension");
+        M::dense(x, y)
+    }
+
+    #[inline]
+    fn norm(x: &[f32]) -> f32 {
+        let x: &[f32; DIM] = x
+            .try_into()
+            .expect("Point does not have the fixed dimension");
+        M::norm(x)
+    }
+
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        assert!(
+            x.len() == DIM && y.len() == DIM,
+            "Point does not have the fixed dimension"
+        );
+        M::dense_scalar(x, y)
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        M::sparse(x_ind, x_val, y_ind, y_val)
+    }
+
+    fn sparse_dense(x_ind: &[u32], x_val: &[f32], y: &[f32]) -> f32 {
+        M::sparse_dense(x_ind, x_val, y)
+    }
+
+    fn binary(x: &[u64], y: &[u64]) -> f32 {
+        M::binary(x, y)
+    }
+
+    fn text(x: &str, y: &str) -> Option<f32> {
+        M::text(x, y)
+    }
+
+    fn dist<'a, 'b, T, S>(x: T, y: S) -> PointCloudResult<f32>
+    where
+        T: Into<PointRef<'a>>,
+        S: Into<PointRef<'b>>,
+    {
+        match (x.into(), y.into()) {
+            (PointRef::Dense(x_vals), PointRef::Dense(y_vals)) => {
+                if x_vals.len() != DIM || y_vals.len() != DIM {
+                    return Err(PointCloudError::MetricError);
+                }
+                Ok(Self::dense(x_vals, y_vals))
+            }
+            (x, y) => M::dist(x, y),
+        }
+    }
+}
+
+/// Walks two sparse points in index order, calling `f` with the values at each index either of them has.
+fn merge_sparse<F: FnMut(f32, f32)>(
+    x_ind: &[u32],
+    x_val: &[f32],
+    y_ind: &[u32],
+    y_val: &[f32],
+    mut f: F,
+) {
+    let (mut i, mut j) = (0, 0);
+    while i < x_ind.len() || j < y_ind.len() {
+        match (x_ind.get(i), y_ind.get(j)) {
+            (Some(a), Some(b)) if a == b => {
+                f(x_val[i], y_val[j]);
+                i += 1;
+                j += 1;
+            }
+            (Some(a), b) if b.map_or(true, |b| a < b) => {
+                f(x_val[i], 0.0);
+                i += 1;
+            }
+            _ => {
+                f(0.0, y_val[j]);
+                j += 1;
+            }
+        }
+    }
+}
+
+/// The number of dimensions the points differ in. On bit packed points this is the popcount of their xor.
+#[derive(Debug, Clone)]
+pub struct Hamming {}
+
+impl Metric for Hamming {
+    fn name() -> &'static str {
+        "Hamming"
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        x.iter().zip(y).filter(|(a, b)| a != b).count() as f32
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let mut count = 0;
+        merge_sparse(x_ind, x_val, y_ind, y_val, |a, b| {
+            if a != b {
+                count += 1
+            }
+        });
+        count as f32
+    }
+
+    fn norm(x: &[f32]) -> f32 {
+        x.iter().filter(|a| **a != 0.0).count() as f32
+    }
+
+    #[inline]
+    fn binary(x: &[u64], y: &[u64]) -> f32 {
+        x.iter()
+            .zip(y)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum::<u32>() as f32
+    }
+}
+
+/// One minus the size of the intersection over the size of the union, of the sets of non zero dimensions. On bit
+/// packed points these are the set bits, counted with popcounts. Two empty sets are at distance 0.
+#[derive(Debug, Clone)]
+pub struct Jaccard {}
+
+impl Jaccard {
+    fn from_counts(intersection: u32, union: u32) -> f32 {
+        if union == 0 {
+            0.0
+        } else {
+            1.0 - intersection as f32 / union as f32
+        }
+    }
+}
+
+impl Metric for Jaccard {
+    fn name() -> &'static str {
+        "Jaccard"
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        let (intersection, union) = x.iter().zip(y).fold((0, 0), |(i, u), (a, b)| {
+            let (a, b) = (*a != 0.0, *b != 0.0);
+            (i + (a && b) as u32, u + (a || b) as u32)
+        });
+        Jaccard::from_counts(intersection, union)
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let (mut intersection, mut union) = (0, 0);
+        merge_sparse(x_ind, x_val, y_ind, y_val, |a, b| {
+            let (a, b) = (a != 0.0, b != 0.0);
+            intersection += (a && b) as u32;
+            union += (a || b) as u32;
+        });
+        Jaccard::from_counts(intersection, union)
+    }
+
+    /// The distance to the empty set, 1 unless the point is all zeros.
+    fn norm(x: &[f32]) -> f32 {
+        if x.iter().any(|a| *a != 0.0) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    #[inline]
+    fn binary(x: &[u64], y: &[u64]) -> f32 {
+        let (intersection, union) = x.iter().zip(y).fold((0, 0), |(i, u), (a, b)| {
+            (i + (a & b).count_ones(), u + (a | b).count_ones())
+        });
+        Jaccard::from_counts(intersection, union)
+    }
+}
+
+/// The great circle distance in kilometers between points that are `[latitude, longitude]` pairs in degrees, on a
+/// sphere with the Earth's mean radius. See the `geo` loaders for reading points from a CSV. Only the first two
+/// coordinates are read, a missing one is 0 like an absent entry of a sparse point and any past the second are ignored.
+#[derive(Debug, Clone)]
+pub struct Haversine {}
+
+impl Haversine {
+    /// The mean radius of the Earth, in kilometers.
+    pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+    fn haversine(x_lat: f64, x_lon: f64, y_lat: f64, y_lon: f64) -> f64 {
+        let (x_lat, y_lat) = (x_lat.to_radians(), y_lat.to_radians());
+        let half_lat = (y_lat - x_lat) / 2.0;
+        let half_lon = (y_lon - x_lon).to_radians() / 2.0;
+        let h = half_lat.sin().powi(2) + x_lat.cos() * y_lat.cos() * half_lon.sin().powi(2);
+        // Rounding can push `h` just past 1 for antipodal points
+        2.0 * Haversine::EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+    }
+
+    /// The latitude and longitude of a point, 0 for a coordinate the point doesn't have.
+    fn coordinates<T: Scalar>(x: &[T]) -> (f64, f64) {
+        let coordinate = |i: usize| x.get(i).map(|v| v.to_f64()).unwrap_or(0.0);
+        (coordinate(0), coordinate(1))
+    }
+}
+
+impl Metric for Haversine {
+    fn name() -> &'static str {
+        "Haversine"
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        Haversine::dense_scalar(x, y) as f32
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let densify = |ind: &[u32], val: &[f32]| -> [f32; 2] {
+            let mut x = [0.0; 2];
+            for (i, v) in ind.iter().zip(val) {
+                if let Some(coordinate) = x.get_mut(*i as usize) {
+                    *coordinate = *v;
+                }
+            }
+            x
+        };
+        Haversine::dense(&densify(x_ind, x_val), &densify(y_ind, y_val))
+    }
+
+    /// The distance to where the equator crosses the prime meridian.
+    fn norm(x: &[f32]) -> f32 {
+        Haversine::dense(x, &[0.0, 0.0])
+    }
+
+    fn dense_scalar<T: Scalar>(x: &[T], y: &[T]) -> f64 {
+        let (x_lat, x_lon) = Haversine::coordinates(x);
+        let (y_lat, y_lon) = Haversine::coordinates(y);
+        Haversine::haversine(x_lat, x_lon, y_lat, y_lon)
+    }
+}
+
+/// The edit distance, the fewest insertions, deletions and substitutions that turn one sequence into the other. On
+/// strings these are edits of characters, on dense points they're edits of values.
+#[derive(Debug, Clone)]
+pub struct Levenshtein {}
+
+impl Levenshtein {
+    /// The edit distance between two sequences.
+    pub fn distance<T: PartialEq>(x: &[T], y: &[T]) -> usize {
+        // Doubling the band costs at most twice the final band, so this is O(distance * length)
+        let mut bound = 1;
+        loop {
+            if let Some(d) = Levenshtein::banded(x, y, bound) {
+                return d;
+            }
+            bound *= 2;
+        }
+    }
+
+    /// The edit distance between two strings if it's at most `bound`, otherwise `None`. This only fills in the
+    /// diagonal band of the edit table that is within `bound` of the diagonal, so it's fast for small bounds.
+    pub fn within(x: &str, y: &str, bound: usize) -> Option<usize> {
+        let x: Vec<char> = x.chars().collect();
+        let y: Vec<char> = y.chars().collect();
+        Levenshtein::banded(&x, &y, bound)
+    }
+
+    fn banded<T: PartialEq>(x: &[T], y: &[T], bound: usize) -> Option<usize> {
+        let (n, m) = (x.len(), y.len());
+        let gap = if n > m { n - m } else { m - n };
+        if gap > bound {
+            return None;
+        }
+        // Cells outside the band are capped at `bound + 1`, they can't lead to a distance within the bound
+        let outside = bound + 1;
+        let mut prev: Vec<usize> = (0..=m).map(|j| j.min(outside)).collect();
+        let mut cur = vec![outside; m + 1];
+        for i in 1..=n {
+            let lo = i.saturating_sub(bound);
+            let hi = (i + bound).min(m);
+            if lo == 0 {
+                cur[0] = i;
+            } else {
+                cur[lo - 1] = outside;
+            }
+            for j in lo.max(1)..=hi {
+                let substitution = prev[j - 1] + (x[i - 1] != y[j - 1]) as usize;
+                cur[j] = substitution
+                    .min(prev[j] + 1)
+                    .min(cur[j - 1] + 1)
+                    .min(outside);
+            }
+            if hi < m {
+                cur[hi + 1] = outside;
+            }
+            std::mem::swap(&mut prev, &mut cur);
+        }
+        Some(prev[m]).filter(|d| *d <= bound)
+    }
+}
+
+impl Metric for Levenshtein {
+    fn name() -> &'static str {
+        "Levenshtein"
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        Levenshtein::distance(x, y) as f32
+    }
+
+    /// Densifies the points up to their last non zero index.
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let densify = |ind: &[u32], val: &[f32]| -> Vec<f32> {
+            let mut x = vec![0.0; ind.last().map_or(0, |i| *i as usize + 1)];
+            for (i, v) in ind.iter().zip(val) {
+                x[*i as usize] = *v;
+            }
+            x
+        };
+        Levenshtein::dense(&densify(x_ind, x_val), &densify(y_ind, y_val))
+    }
+
+    /// The distance to the empty sequence, the length.
+    fn norm(x: &[f32]) -> f32 {
+        x.len() as f32
+    }
+
+    fn text(x: &str, y: &str) -> Option<f32> {
+        let x: Vec<char> = x.chars().collect();
+        let y: Vec<char> = y.chars().collect();
+        Some(Levenshtein::distance(&x, &y) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_dim_matches_unsized() {
+        let x: Vec<f32> = (0..40).map(|i| (i as f32).sin()).collect();
+        let y: Vec<f32> = (0..40).map(|i| (i as f32).cos()).collect();
+        assert_eq!(FixedDim::<L2, 40>::dense(&x, &y), L2::dense(&x, &y));
+        assert_eq!(FixedDim::<L1, 40>::norm(&x), L1::norm(&x));
+        let x_buffer = FixedDim::<L2, 40>::buffer(&x).unwrap();
+        assert_eq!(
+            FixedDim::<L2, 40>::dist(&x_buffer, &y).unwrap(),
+            L2::dist(&x, &y).unwrap()
+        );
+        assert!(FixedDim::<L2, 39>::dist(&x[..39], &y).is_err());
+        assert!(FixedDim::<L2, 39>::buffer(&x).is_err());
+    }
+
+    #[test]
+    fn cosine_dense_and_sparse_agree() {
+        let x = [1.0f32, 0.0, 2.0, 0.0, 0.0];
+        let y = [0.0f32, 3.0, 4.0, 0.0, 1.0];
+        let zero = [0.0f32; 5];
+        let dense = Cosine::dist(&x, &y).unwrap();
+        let cos = 8.0 / (5.0f32.sqrt() * 26.0f32.sqrt());
+        assert!((dense - (2.0 - 2.0 * cos).sqrt()).abs() < 1e-6);
+
+        let x_sparse: (&[f32], &[u32]) = (&[1.0, 2.0], &[0, 2]);
+        let y_sparse: (&[f32], &[u32]) = (&[3.0, 4.0, 1.0], &[1, 2, 4]);
+        let sparse = Cosine::dist(x_sparse, y_sparse).unwrap();
+        assert!((dense - sparse).abs() < 1e-6);
+
+        assert!(Cosine::dist(&x, &x).unwrap().abs() < 1e-3);
+        assert_eq!(Cosine::dist(&x, &zero).unwrap(), 1.0);
+        assert_eq!(Cosine::dist(&zero, &zero).unwrap(), 0.0);
+        let empty: (&[f32], &[u32]) = (&[], &[]);
+        assert_eq!(Cosine::dist(x_sparse, empty).unwrap(), 1.0);
+        assert_eq!(Cosine::norm(&zero), 0.0);
+        assert_eq!(Cosine::norm(&x), 1.0);
+    }
+
+    fn sparse_kernels_agree<M: Metric>() {
+        let dim = 100_000;
+        let x_ind: Vec<u32> = (0..dim as u32).step_by(997).collect();
+        let x_val: Vec<f32> = x_ind.iter().map(|i| (*i as f32).sin()).collect();
+        let y_ind: Vec<u32> = (0..dim as u32).step_by(1009).collect();
+        let y_val: Vec<f32> = y_ind.iter().map(|i| (*i as f32).cos()).collect();
+        let mut x_dense = vec![0.0; dim];
+        for (i, v) in x_ind.iter().zip(&x_val) {
+            x_dense[*i as usize] = *v;
+        }
+        let mut y_dense = vec![0.0; dim];
+        for (i, v) in y_ind.iter().zip(&y_val) {
+            y_dense[*i as usize] = *v;
+        }
+        let x_sparse = (&x_val[..], &x_ind[..]);
+        let y_sparse = (&y_val[..], &y_ind[..]);
+
+        let dense = M::dist(&x_dense, &y_dense).unwrap();
+        let tolerance = 1e-4 * dense.max(1.0);
+        assert!((M::dist(x_sparse, y_sparse).unwrap() - dense).abs() < tolerance);
+        assert!((M::dist(x_sparse, &y_dense).unwrap() - dense).abs() < tolerance);
+        assert!((M::dist(&x_dense, y_sparse).unwrap() - dense).abs() < tolerance);
+        assert!(M::dist(x_sparse, &y_dense[..10]).is_err());
+
+        let unsorted: (&[f32], &[u32]) = (&[1.0, 2.0], &[5, 2]);
+        let repeated: (&[f32], &[u32]) = (&[1.0, 2.0], &[2, 2]);
+        assert!(M::dist(unsorted, &y_dense).is_err());
+        assert!(M::dist(&x_dense, repeated).is_err());
+        assert!(M::dist(unsorted, y_sparse).is_err());
+        assert!(M::dist(x_sparse, repeated).is_err());
+    }
+
+    #[test]
+    fn sparse_kernels_match_dense() {
+        sparse_kernels_agree::<L1>();
+        sparse_kernels_agree::<L2>();
+        sparse_kernels_agree::<Cosine>();
+        sparse_kernels_agree::<Linfty>();
+    }
+}
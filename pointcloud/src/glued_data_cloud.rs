@@ -8,18 +8,139 @@ use crate::base_traits::*;
 
 use fxhash::FxBuildHasher;
 use hashbrown::HashMap;
+use std::fmt;
+
+/// Provenance of one of the glued point clouds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentMetadata {
+    /// The name of the source, usually a file name
+    pub name: String,
+    /// The inclusive range of timestamps the source covers, if known
+    pub time_range: Option<(i64, i64)>,
+}
+
+impl SegmentMetadata {
+    /// Metadata with just a name.
+    pub fn new<S: Into<String>>(name: S) -> SegmentMetadata {
+        SegmentMetadata {
+            name: name.into(),
+            time_range: None,
+        }
+    }
+
+    /// Adds a time range to the metadata.
+    pub fn with_time_range(mut self, start: i64, end: i64) -> SegmentMetadata {
+        self.time_range = Some((start, end));
+        self
+    }
+}
+
+/// How to treat the indexes of the remaining points when a segment is removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentRemoval {
+    /// The remaining points keep their indexes, the removed segment's indexes are left unused.
+    Tombstone,
+    /// The remaining points are renumbered to fill the gap, in order of their old index.
+    Remap,
+}
+
+/// What was removed from a glued cloud.
+#[derive(Debug)]
+pub struct RemovedSegment<D: PointCloud> {
+    /// The removed point cloud
+    pub source: D,
+    /// The removed segment's metadata
+    pub metadata: SegmentMetadata,
+    /// The indexes that the removed points had
+    pub removed: Vec<PointIndex>,
+    /// Pairs of old and new indexes for the points that moved, empty for a tombstone removal
+    pub remap: Vec<(PointIndex, PointIndex)>,
+}
+
+/// Builds a `HashGluedCloud` one segment at a time, recording the metadata of each.
+#[derive(Debug)]
+pub struct HashGluedCloudBuilder<D: PointCloud> {
+    data_sources: Vec<D>,
+    segments: Vec<SegmentMetadata>,
+}
+
+impl<D: PointCloud> Default for HashGluedCloudBuilder<D> {
+    fn default() -> Self {
+        HashGluedCloudBuilder {
+            data_sources: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+}
+
+impl<D: PointCloud> HashGluedCloudBuilder<D> {
+    /// An empty builder
+    pub fn new() -> HashGluedCloudBuilder<D> {
+        HashGluedCloudBuilder::default()
+    }
+
+    /// Appends a segment, its points are indexed after the segments already added.
+    pub fn add_segment(&mut self, source: D, metadata: SegmentMetadata) -> &mut Self {
+        self.data_sources.push(source);
+        self.segments.push(metadata);
+        self
+    }
+
+    /// Glues the segments together.
+    pub fn build(self) -> HashGluedCloud<D> {
+        HashGluedCloud::glue(self.data_sources, self.segments)
+    }
+}
+
+/// Prefetches the points of each source at their addresses, as `(source, index in the source)`.
+fn prefetch_by_source<D: PointCloud, I: Iterator<Item = (usize, PointIndex)>>(
+    data_sources: &[D],
+    addresses: I,
+) {
+    let mut by_source = vec![Vec::new(); data_sources.len()];
+    for (i, j) in addresses {
+        by_source[i].push(j);
+    }
+    for (source, indexes) in data_sources.iter().zip(&by_source) {
+        if !indexes.is_empty() {
+            source.prefetch(indexes);
+        }
+    }
+}
 
 /// For large numbers of underlying point clouds
 #[derive(Debug)]
 pub struct HashGluedCloud<D: PointCloud> {
     addresses: HashMap<PointIndex, (usize, PointIndex), FxBuildHasher>,
     data_sources: Vec<D>,
+    segments: Vec<SegmentMetadata>,
 }
 
 impl<D: PointCloud> HashGluedCloud<D> {
-    /// Creates a new one, preserves the order in the supplied vec.
+    /// Creates a new one, preserves the order in the supplied vec. Each segment is named after its position.
     pub fn new(data_sources: Vec<D>) -> HashGluedCloud<D> {
-        
+        let segments = (0..data_sources.len())
+            .map(|i| SegmentMetadata::new(i.to_string()))
+            .collect();
+        HashGluedCloud::glue(data_sources, segments)
+    }
+
+    /// Creates a new one with the metadata of each source, preserves the order in the supplied vecs. Errors if
+    /// there isn't exactly one segment's metadata per source.
+    pub fn new_with_metadata(
+        data_sources: Vec<D>,
+        segments: Vec<SegmentMetadata>,
+    ) -> PointCloudResult<HashGluedCloud<D>> {
+        if data_sources.len() != segments.len() {
+            return Err(PointCloudError::LengthMismatch {
+                expected: data_sources.len(),
+                found: segments.len(),
+            });
+        }
+        Ok(HashGluedCloud::glue(data_sources, segments))
+    }
+
+    fn glue(data_sources: Vec<D>, segments: Vec<SegmentMetadata>) -> HashGluedCloud<D> {
         let mut addresses = HashMap::with_hasher(FxBuildHasher::default());
         let mut pi: PointIndex = 0;
         for (i, source) in data_sources.iter().enumerate() {
@@ -31,6 +152,7 @@ impl<D: PointCloud> HashGluedCloud<D> {
         HashGluedCloud {
             addresses,
             data_sources,
+            segments,
         }
     }
 
@@ -63,6 +185,95 @@ impl<D: PointCloud> HashGluedCloud<D> {
         self.data_sources
     }
 
+    /// Drops an entire segment, for example a day of data that has expired. The other segments' points are either left
+    /// where they are, or renumbered and the remap table is returned. After a tombstone removal `len` counts the
+    /// remaining points, which is less than the largest index, so walk the cloud with `reference_indexes`. To drop a
+    /// segment from a tree built on this cloud use `CoverTreeWriter::remove_segment` in goko.
+    pub fn remove_segment(
+        &mut self,
+        segment: usize,
+        removal: SegmentRemoval,
+    ) -> PointCloudResult<RemovedSegment<D>> {
+        if segment >= self.data_sources.len() {
+            return Err(PointCloudError::SegmentNotFound(segment));
+        }
+        let source = self.data_sources.remove(segment);
+        let metadata = self.segments.remove(segment);
+
+        let mut removed = Vec::with_capacity(source.len());
+        let mut remaining: Vec<(PointIndex, (usize, PointIndex))> =
+            Vec::with_capacity(self.addresses.len() - source.len());
+        for (pi, (i, j)) in self.addresses.drain() {
+            if i == segment {
+                removed.push(pi);
+            } else if i > segment {
+                remaining.push((pi, (i - 1, j)));
+            } else {
+                remaining.push((pi, (i, j)));
+            }
+        }
+        removed.sort_unstable();
+
+        let mut remap = Vec::new();
+        if removal == SegmentRemoval::Remap {
+            remaining.sort_unstable_by_key(|(pi, _)| *pi);
+            for (new_pi, (pi, _)) in remaining.iter_mut().enumerate() {
+                let new_pi = new_pi as PointIndex;
+                if *pi != new_pi {
+                    remap.push((*pi, new_pi));
+                    *pi = new_pi;
+                }
+            }
+        }
+        self.addresses.extend(remaining);
+
+        Ok(RemovedSegment {
+            source,
+            metadata,
+            removed,
+            remap,
+        })
+    }
+
+    /// The indexes of a segment's points, in order.
+    pub fn segment_indexes(&self, segment: usize) -> PointCloudResult<Vec<PointIndex>> {
+        if segment >= self.data_sources.len() {
+            return Err(PointCloudError::SegmentNotFound(segment));
+        }
+        let mut indexes: Vec<PointIndex> = self
+            .addresses
+            .iter()
+            .filter(|(_, (i, _))| *i == segment)
+            .map(|(pi, _)| *pi)
+            .collect();
+        indexes.sort_unstable();
+        Ok(indexes)
+    }
+
+    /// The metadata of each segment, in the same order as the data sources
+    pub fn segments(&self) -> &[SegmentMetadata] {
+        &self.segments
+    }
+
+    /// The segment that a point came from.
+    pub fn segment_of(&self, pn: PointIndex) -> PointCloudResult<usize> {
+        self.get_address(pn).map(|(i, _)| i)
+    }
+
+    /// The metadata of the segment that a point came from.
+    pub fn segment_metadata_of(&self, pn: PointIndex) -> PointCloudResult<&SegmentMetadata> {
+        self.segment_of(pn).map(|i| &self.segments[i])
+    }
+
+    /// The number of the supplied points that came from each segment.
+    pub fn segment_counts(&self, pns: &[PointIndex]) -> PointCloudResult<Vec<usize>> {
+        let mut counts = vec![0; self.data_sources.len()];
+        for pn in pns {
+            counts[self.segment_of(*pn)?] += 1;
+        }
+        Ok(counts)
+    }
+
     #[inline]
     fn get_address(&self, pn: PointIndex) -> PointCloudResult<(usize, PointIndex)> {
         match self.addresses.get(&pn) {
@@ -107,6 +318,29 @@ impl<D: PointCloud> PointCloud for HashGluedCloud<D> {
     fn dim(&self) -> usize {
         self.data_sources[0].dim()
     }
+
+    /// Splits the indexes by source and prefetches each source's share. Indexes that aren't in the cloud are skipped.
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        prefetch_by_source(
+            &self.data_sources,
+            indexes.iter().filter_map(|pn| self.get_address(*pn).ok()),
+        )
+    }
+}
+
+impl<D: PointCloud + fmt::Display> fmt::Display for HashGluedCloud<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HashGluedCloud: {} points in {} sources",
+            self.len(),
+            self.data_sources.len()
+        )?;
+        for (source, segment) in self.data_sources.iter().zip(&self.segments) {
+            write!(f, "\n    {}: {}", segment.name, source)?;
+        }
+        Ok(())
+    }
 }
 
 impl<D: LabeledCloud> LabeledCloud for HashGluedCloud<D> {
@@ -130,6 +364,23 @@ impl<D: LabeledCloud> LabeledCloud for HashGluedCloud<D> {
     }
 }
 
+impl<D: LabeledCloud> HashGluedCloud<D> {
+    /// The label summary of the supplied points, split by the segment they came from.
+    pub fn segment_label_summaries(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<Vec<SummaryCounter<D::LabelSummary>>> {
+        let mut summaries: Vec<SummaryCounter<D::LabelSummary>> = (0..self.data_sources.len())
+            .map(|_| SummaryCounter::default())
+            .collect();
+        for pn in pns {
+            let (i, j) = self.get_address(*pn)?;
+            summaries[i].add(self.data_sources[i].label(j));
+        }
+        Ok(summaries)
+    }
+}
+
 impl<D: NamedCloud> NamedCloud for HashGluedCloud<D> {
     type Name = D::Name;
 
@@ -180,6 +431,194 @@ impl<D: MetaCloud> MetaCloud for HashGluedCloud<D> {
     }
 }
 
+/// Glues point clouds end to end like `HashGluedCloud`, but only stores where each source starts. An index is
+/// translated with a binary search over the starts, so the map costs a few bytes per source rather than an entry per
+/// point. The points are numbered in order, the first source's points first, and can't be reindexed.
+#[derive(Debug)]
+pub struct RangeGluedCloud<D: PointCloud> {
+    /// The index of the first point of each source, and the total number of points at the end
+    offsets: Vec<usize>,
+    data_sources: Vec<D>,
+    segments: Vec<SegmentMetadata>,
+}
+
+impl<D: PointCloud> RangeGluedCloud<D> {
+    /// Creates a new one, preserves the order in the supplied vec. Each segment is named after its position.
+    pub fn new(data_sources: Vec<D>) -> RangeGluedCloud<D> {
+        let segments = (0..data_sources.len())
+            .map(|i| SegmentMetadata::new(i.to_string()))
+            .collect();
+        RangeGluedCloud::glue(data_sources, segments)
+    }
+
+    /// Creates a new one with the metadata of each source, preserves the order in the supplied vecs. Errors if
+    /// there isn't exactly one segment's metadata per source.
+    pub fn new_with_metadata(
+        data_sources: Vec<D>,
+        segments: Vec<SegmentMetadata>,
+    ) -> PointCloudResult<RangeGluedCloud<D>> {
+        if data_sources.len() != segments.len() {
+            return Err(PointCloudError::LengthMismatch {
+                expected: data_sources.len(),
+                found: segments.len(),
+            });
+        }
+        Ok(RangeGluedCloud::glue(data_sources, segments))
+    }
+
+    fn glue(data_sources: Vec<D>, segments: Vec<SegmentMetadata>) -> RangeGluedCloud<D> {
+        let mut offsets = Vec::with_capacity(data_sources.len() + 1);
+        offsets.push(0);
+        for source in &data_sources {
+            offsets.push(offsets[offsets.len() - 1] + source.len());
+        }
+        RangeGluedCloud {
+            offsets,
+            data_sources,
+            segments,
+        }
+    }
+
+    /// Borrows the underlying data sources
+    pub fn data_sources(&self) -> &[D] {
+        &self.data_sources
+    }
+
+    /// Extracts the underlying point clouds
+    pub fn take_data_sources(self) -> Vec<D> {
+        self.data_sources
+    }
+
+    /// The metadata of each segment, in the same order as the data sources
+    pub fn segments(&self) -> &[SegmentMetadata] {
+        &self.segments
+    }
+
+    /// The range of indexes of a segment's points.
+    pub fn segment_range(&self, segment: usize) -> PointCloudResult<std::ops::Range<PointIndex>> {
+        if segment >= self.data_sources.len() {
+            return Err(PointCloudError::SegmentNotFound(segment));
+        }
+        Ok(self.offsets[segment]..self.offsets[segment + 1])
+    }
+
+    /// The segment that a point came from.
+    pub fn segment_of(&self, pn: PointIndex) -> PointCloudResult<usize> {
+        self.get_address(pn).map(|(i, _)| i)
+    }
+
+    #[inline]
+    fn get_address(&self, pn: PointIndex) -> PointCloudResult<(usize, PointIndex)> {
+        if pn >= self.offsets[self.offsets.len() - 1] {
+            return Err(PointCloudError::DataAccessError {
+                index: pn,
+                reason: "address not found".to_string(),
+            });
+        }
+        // The last source that starts at or before the point, which skips empty sources
+        let i = match self.offsets.binary_search(&pn) {
+            Ok(mut i) => {
+                while self.offsets[i + 1] == pn {
+                    i += 1;
+                }
+                i
+            }
+            Err(i) => i - 1,
+        };
+        Ok((i, pn - self.offsets[i]))
+    }
+}
+
+impl<D: PointCloud> PointCloud for RangeGluedCloud<D> {
+    type Metric = D::Metric;
+    fn point(&self, pn: PointIndex) -> PointCloudResult<PointRef> {
+        let (i, j) = self.get_address(pn)?;
+        self.data_sources[i].point(j)
+    }
+
+    fn len(&self) -> usize {
+        self.offsets[self.offsets.len() - 1]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn reference_indexes(&self) -> Vec<PointIndex> {
+        (0..self.len()).collect()
+    }
+
+    /// The dimension of the first source, 0 if there are no sources.
+    fn dim(&self) -> usize {
+        self.data_sources.first().map_or(0, |source| source.dim())
+    }
+
+    /// Splits the indexes by source and prefetches each source's share. Indexes that aren't in the cloud are skipped.
+    fn prefetch(&self, indexes: &[PointIndex]) {
+        prefetch_by_source(
+            &self.data_sources,
+            indexes.iter().filter_map(|pn| self.get_address(*pn).ok()),
+        )
+    }
+}
+
+impl<D: PointCloud + fmt::Display> fmt::Display for RangeGluedCloud<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RangeGluedCloud: {} points in {} sources",
+            self.len(),
+            self.data_sources.len()
+        )?;
+        for (source, segment) in self.data_sources.iter().zip(&self.segments) {
+            write!(f, "\n    {}: {}", segment.name, source)?;
+        }
+        Ok(())
+    }
+}
+
+impl<D: LabeledCloud> LabeledCloud for RangeGluedCloud<D> {
+    type Label = D::Label;
+    type LabelSummary = D::LabelSummary;
+
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Label>> {
+        let (i, j) = self.get_address(pn)?;
+        self.data_sources[i].label(j)
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = SummaryCounter::<Self::LabelSummary>::default();
+        for pn in pns {
+            let (i, j) = self.get_address(*pn)?;
+            summary.add(self.data_sources[i].label(j));
+        }
+        Ok(summary)
+    }
+}
+
+impl<D: MetaCloud> MetaCloud for RangeGluedCloud<D> {
+    type Metadata = D::Metadata;
+    type MetaSummary = D::MetaSummary;
+
+    fn metadata(&self, pn: PointIndex) -> PointCloudResult<Option<&Self::Metadata>> {
+        let (i, j) = self.get_address(pn)?;
+        self.data_sources[i].metadata(j)
+    }
+    fn metasummary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::MetaSummary>> {
+        let mut summary = SummaryCounter::<Self::MetaSummary>::default();
+        for pn in pns {
+            let (i, j) = self.get_address(*pn)?;
+            summary.add(self.data_sources[i].metadata(j));
+        }
+        Ok(summary)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -262,7 +701,7 @@ mod tests {
                         assert_approx_eq!(1.0, d);
                     }
                 }
-                PointRef::Sparse(_, _) => panic!("Should return a sparse datum"),
+                _ => panic!("Should return a sparse datum"),
             };
         }
     }
@@ -290,13 +729,159 @@ mod tests {
         assert_eq!(label_summary.summary.items[0], (1, 5));
     }
 
+    #[test]
+    fn segment_provenance() {
+        let mut builder = HashGluedCloudBuilder::new();
+        builder
+            .add_segment(
+                build_ram_fixed_labeled_test(2, 3),
+                SegmentMetadata::new("first").with_time_range(0, 10),
+            )
+            .add_segment(
+                build_ram_fixed_labeled_test(3, 3),
+                SegmentMetadata::new("second"),
+            );
+        let pc = builder.build();
+        assert_eq!(pc.len(), 5);
+        assert_eq!(pc.segment_of(1).unwrap(), 0);
+        assert_eq!(pc.segment_of(2).unwrap(), 1);
+        assert!(pc.segment_of(5).is_err());
+        assert_eq!(pc.segment_metadata_of(0).unwrap().time_range, Some((0, 10)));
+        assert_eq!(pc.segment_metadata_of(4).unwrap().name, "second");
+        assert_eq!(pc.segment_counts(&[0, 2, 3, 4]).unwrap(), vec![1, 3]);
+
+        let summaries = pc.segment_label_summaries(&[0, 2, 3, 4]).unwrap();
+        assert_eq!(summaries[0].summary.items.len(), 1);
+        assert_eq!(summaries[0].summary.items[0], (0, 1));
+        let second_count: usize = summaries[1].summary.items.iter().map(|(_, c)| c).sum();
+        assert_eq!(second_count, 3);
+
+        let mismatched = HashGluedCloud::new_with_metadata(
+            vec![build_ram_fixed_labeled_test(2, 3)],
+            vec![
+                SegmentMetadata::new("first"),
+                SegmentMetadata::new("second"),
+            ],
+        );
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn dedup_across_segments() {
+        let ids =
+            |ids: &[&str]| StringMetadata::new(ids.iter().map(|s| s.to_string()).collect(), None);
+        let first = DataRam::<L2>::new(vec![0.0, 1.0, 2.0], 1)
+            .unwrap()
+            .attach_metadata(ids(&["a", "b", "c"]))
+            .unwrap();
+        // "b" and "c" are loaded again, "c" without an id
+        let second = DataRam::<L2>::new(vec![1.1, 0.1, 2.0], 1)
+            .unwrap()
+            .attach_metadata(VecMetadata::new(
+                vec!["b".to_string(), "a".to_string(), "c".to_string()],
+                Some(vec![true, true, false]),
+            ))
+            .unwrap();
+        let pc = HashGluedCloud::new(vec![first, second]);
+        let results: Vec<(f32, PointIndex)> = pc
+            .distances_to_point(&[0.9f32][..], &[0, 1, 2, 3, 4, 5])
+            .unwrap()
+            .into_iter()
+            .zip(0..6)
+            .collect();
+        let deduped = pc
+            .dedup_by_metadata(&results, |id: &String| Some(id.clone()))
+            .unwrap();
+        let indexes: Vec<PointIndex> = deduped.iter().map(|(_, i)| *i).collect();
+        assert_eq!(indexes, vec![1, 4, 2, 5]);
+        assert!(deduped.windows(2).all(|w| w[0].0 <= w[1].0));
+        let only_a = pc
+            .dedup_by_metadata(
+                &results,
+                |id: &String| if id == "a" { Some(0) } else { None },
+            )
+            .unwrap();
+        assert_eq!(only_a.len(), 5);
+    }
+
+    #[test]
+    fn segment_removal() {
+        let mut pc = build_glue_fixed_test(3, 2, 3);
+        assert_eq!(pc.segment_indexes(1).unwrap(), vec![2, 3]);
+        assert!(pc.remove_segment(3, SegmentRemoval::Tombstone).is_err());
+        let removed = pc.remove_segment(1, SegmentRemoval::Tombstone).unwrap();
+        assert_eq!(removed.removed, vec![2, 3]);
+        assert!(removed.remap.is_empty());
+        assert_eq!(pc.len(), 4);
+        assert!(pc.point(2).is_err());
+        assert_eq!(pc.get_address(4).unwrap(), (1, 0));
+        assert_eq!(pc.segments()[1].name, "2");
+
+        let mut pc = build_glue_fixed_test(3, 2, 3);
+        let removed = pc.remove_segment(0, SegmentRemoval::Remap).unwrap();
+        assert_eq!(removed.removed, vec![0, 1]);
+        assert_eq!(removed.remap, vec![(2, 0), (3, 1), (4, 2), (5, 3)]);
+        assert_eq!(pc.get_address(0).unwrap(), (0, 0));
+        assert_eq!(pc.get_address(3).unwrap(), (1, 1));
+        assert!(pc.point(4).is_err());
+    }
+
+    #[test]
+    fn range_glue_matches_hash_glue() {
+        let sources = || {
+            vec![
+                SimpleLabeledCloud::new(
+                    DataRam::<L2>::new(vec![0.0, 1.0, 2.0], 1).unwrap(),
+                    SmallIntLabels::new(vec![0, 1, 2], None),
+                ),
+                SimpleLabeledCloud::new(
+                    DataRam::<L2>::new(vec![], 1).unwrap(),
+                    SmallIntLabels::new(vec![], None),
+                ),
+                SimpleLabeledCloud::new(
+                    DataRam::<L2>::new(vec![3.0, 4.0], 1).unwrap(),
+                    SmallIntLabels::new(vec![3, 4], None),
+                ),
+            ]
+        };
+        let hashed = HashGluedCloud::new(sources());
+        let ranged = RangeGluedCloud::new(sources());
+        assert_eq!(ranged.len(), 5);
+        assert_eq!(ranged.reference_indexes(), vec![0, 1, 2, 3, 4]);
+        let dense = |p: PointRef| p.dense_iter(1).collect::<Vec<f32>>();
+        for pi in 0..5 {
+            assert_eq!(
+                ranged.get_address(pi).unwrap(),
+                hashed.get_address(pi).unwrap()
+            );
+            assert_eq!(
+                dense(ranged.point(pi).unwrap()),
+                dense(hashed.point(pi).unwrap())
+            );
+            assert_eq!(ranged.label(pi).unwrap(), hashed.label(pi).unwrap());
+        }
+        assert_eq!(ranged.segment_of(3).unwrap(), 2);
+        assert_eq!(ranged.segment_range(1).unwrap(), 3..3);
+        assert!(ranged.segment_range(3).is_err());
+        assert!(ranged.point(5).is_err());
+        assert_eq!(
+            ranged.distances_to_point_index(0, &[1, 4]).unwrap(),
+            hashed.distances_to_point_index(0, &[1, 4]).unwrap()
+        );
+
+        let empty = RangeGluedCloud::<DataRam<L2>>::new(vec![]);
+        assert_eq!(empty.dim(), 0);
+        assert!(empty.is_empty());
+        assert!(RangeGluedCloud::new_with_metadata(sources(), vec![]).is_err());
+    }
+
     #[test]
     fn distance_correct() {
         let pc = build_glue_fixed_test(5, 2, 3);
         println!("{:?}", pc);
 
         let indexes = [1, 3, 5, 7, 9];
-        let point = Point::Dense(vec![0.0; 5]);
+        let point = Point::Dense(vec![0.0; 3]);
 
         let dists = pc.distances_to_point(&point, &indexes).unwrap();
         for d in dists {
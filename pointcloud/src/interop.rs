@@ -0,0 +1,113 @@
+//! Passing `ndarray` and `nalgebra` vectors to the query APIs without copying them into a `Vec` first.
+//!
+//! `ArrayView1<f32>` converts into a `PointRef` with `TryFrom`, and owned arrays and `nalgebra` vectors borrow as a
+//! `PointRef` through `AsPointRef`. The `nalgebra` impls are behind the `nalgebra` feature.
+//!
+//! A point borrows its values, so a strided view, like a column of a row major matrix, can't be one. Those fail with
+//! `PointCloudError::NotContiguous`, call `as_standard_layout` on them first.
+
+use ndarray::{ArrayBase, ArrayView1, Data, Ix1};
+use std::convert::TryFrom;
+
+use crate::pc_errors::*;
+use crate::PointRef;
+
+/// Borrows a vector type from another crate as a dense point.
+pub trait AsPointRef {
+    /// The point, or `NotContiguous` if the values aren't contiguous in memory.
+    fn as_point_ref(&self) -> PointCloudResult<PointRef<'_>>;
+}
+
+impl<'a> TryFrom<ArrayView1<'a, f32>> for PointRef<'a> {
+    type Error = PointCloudError;
+    fn try_from(view: ArrayView1<'a, f32>) -> PointCloudResult<PointRef<'a>> {
+        view.to_slice()
+            .map(PointRef::Dense)
+            .ok_or(PointCloudError::NotContiguous)
+    }
+}
+
+impl<S: Data<Elem = f32>> AsPointRef for ArrayBase<S, Ix1> {
+    fn as_point_ref(&self) -> PointCloudResult<PointRef<'_>> {
+        self.as_slice()
+            .map(PointRef::Dense)
+            .ok_or(PointCloudError::NotContiguous)
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<R, S> AsPointRef for nalgebra::Matrix<f32, R, nalgebra::U1, S>
+where
+    R: nalgebra::Dim,
+    S: nalgebra::storage::ContiguousStorage<f32, R, nalgebra::U1>,
+{
+    fn as_point_ref(&self) -> PointCloudResult<PointRef<'_>> {
+        Ok(PointRef::Dense(self.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_traits::PointCloud;
+    use crate::data_sources::DataRam;
+    use crate::L2;
+    use ndarray::{arr1, arr2, Array1};
+
+    #[test]
+    fn arrays_are_points() {
+        let cloud = DataRam::<L2>::new(vec![0.0, 0.0, 3.0, 4.0, 1.0, 0.0], 2).unwrap();
+        let queries = arr2(&[[0.0f32, 0.0], [3.0, 4.0]]);
+        assert_eq!(
+            cloud
+                .distances_to_point(PointRef::try_from(queries.row(1)).unwrap(), &[0, 1])
+                .unwrap(),
+            vec![5.0, 0.0]
+        );
+        assert_eq!(
+            cloud
+                .distances_to_point_array(arr1(&[0.0f32, 0.0]).as_point_ref().unwrap(), &[1, 2])
+                .unwrap(),
+            Array1::from(vec![5.0, 1.0])
+        );
+        let columns = queries.t();
+        match PointRef::try_from(columns.row(0)) {
+            Err(PointCloudError::NotContiguous) => {}
+            _ => panic!("a strided view was borrowed as a point"),
+        }
+        assert!(columns.row(0).as_point_ref().is_err());
+        let column = columns.row(0).as_standard_layout().into_owned();
+        assert_eq!(
+            cloud
+                .distances_to_point(column.as_point_ref().unwrap(), &[0])
+                .unwrap(),
+            vec![3.0]
+        );
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn nalgebra_vectors_are_points() {
+        let cloud = DataRam::<L2>::new(vec![0.0, 0.0, 3.0, 4.0], 2).unwrap();
+        let dynamic = nalgebra::DVector::from_vec(vec![3.0f32, 4.0]);
+        let fixed = nalgebra::Vector2::new(0.0f32, 0.0);
+        assert_eq!(
+            cloud
+                .distances_to_point(dynamic.as_point_ref().unwrap(), &[0])
+                .unwrap(),
+            vec![5.0]
+        );
+        assert_eq!(
+            cloud
+                .distances_to_point(fixed.as_point_ref().unwrap(), &[1])
+                .unwrap(),
+            vec![5.0]
+        );
+        assert_eq!(
+            cloud
+                .distances_to_point(dynamic.rows(0, 2).as_point_ref().unwrap(), &[1])
+                .unwrap(),
+            vec![0.0]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! The dense kernels behind `L2`, `L1`, `Cosine` and `CosineSim`, picked at runtime for the cpu they run on.
+//!
+//! On x86_64 the AVX2 kernels are used when the cpu supports AVX2 and FMA, and on aarch64 the NEON kernels always are.
+//! Every other target gets the portable kernels, which accumulate in 8 lanes like the SIMD ones so that the compiler
+//! vectorizes them the same way everywhere. The checks are cached by the standard library, so dispatching is a
+//! couple of loads per call.
+
+/// The kernels this cpu runs, `"avx2"`, `"neon"` or `"portable"`.
+pub fn simd_level() -> &'static str {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            return "avx2";
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return "neon";
+    }
+    #[cfg(not(target_arch = "aarch64"))]
+    {
+        "portable"
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn has_avx2() -> bool {
+    is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma")
+}
+
+macro_rules! dispatch {
+    ($kernel:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        {
+            if has_avx2() {
+                return unsafe { avx2::$kernel($($arg),*) };
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            return unsafe { neon::$kernel($($arg),*) };
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        {
+            portable::$kernel($($arg),*)
+        }
+    }};
+}
+
+/// The sum of the squared differences. The slices must have the same length.
+#[inline]
+pub(crate) fn squared_l2(x: &[f32], y: &[f32]) -> f32 {
+    debug_assert_eq!(x.len(), y.len());
+    dispatch!(squared_l2(x, y))
+}
+
+/// The sum of the absolute differences. The slices must have the same length.
+#[inline]
+pub(crate) fn l1(x: &[f32], y: &[f32]) -> f32 {
+    debug_assert_eq!(x.len(), y.len());
+    dispatch!(l1(x, y))
+}
+
+/// The dot product and the squared norms of `x` and `y`. The slices must have the same length.
+#[inline]
+pub(crate) fn products(x: &[f32], y: &[f32]) -> (f32, f32, f32) {
+    debug_assert_eq!(x.len(), y.len());
+    dispatch!(products(x, y))
+}
+
+/// The sum of squares.
+#[inline]
+pub(crate) fn squared_norm(x: &[f32]) -> f32 {
+    dispatch!(squared_norm(x))
+}
+
+mod portable {
+    const LANES: usize = 8;
+
+    pub(super) fn squared_l2(x: &[f32], y: &[f32]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let (x_chunks, y_chunks) = (x.chunks_exact(LANES), y.chunks_exact(LANES));
+        let leftover: f32 = x_chunks
+            .remainder()
+            .iter()
+            .zip(y_chunks.remainder())
+            .map(|(xi, yi)| (xi - yi) * (xi - yi))
+            .sum();
+        for (xs, ys) in x_chunks.zip(y_chunks) {
+            for i in 0..LANES {
+                let diff = xs[i] - ys[i];
+                acc[i] += diff * diff;
+            }
+        }
+        leftover + acc.iter().sum::<f32>()
+    }
+
+    pub(super) fn l1(x: &[f32], y: &[f32]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let (x_chunks, y_chunks) = (x.chunks_exact(LANES), y.chunks_exact(LANES));
+        let leftover: f32 = x_chunks
+            .remainder()
+            .iter()
+            .zip(y_chunks.remainder())
+            .map(|(xi, yi)| (xi - yi).abs())
+            .sum();
+        for (xs, ys) in x_chunks.zip(y_chunks) {
+            for i in 0..LANES {
+                acc[i] += (xs[i] - ys[i]).abs();
+            }
+        }
+        leftover + acc.iter().sum::<f32>()
+    }
+
+    pub(super) fn products(x: &[f32], y: &[f32]) -> (f32, f32, f32) {
+        let mut dot = [0.0f32; LANES];
+        let mut x_sq = [0.0f32; LANES];
+        let mut y_sq = [0.0f32; LANES];
+        let (x_chunks, y_chunks) = (x.chunks_exact(LANES), y.chunks_exact(LANES));
+        let leftover = x_chunks
+            .remainder()
+            .iter()
+            .zip(y_chunks.remainder())
+            .fold((0.0, 0.0, 0.0), |(d, xx, yy), (xi, yi)| {
+                (d + xi * yi, xx + xi * xi, yy + yi * yi)
+            });
+        for (xs, ys) in x_chunks.zip(y_chunks) {
+            for i in 0..LANES {
+                dot[i] += xs[i] * ys[i];
+                x_sq[i] += xs[i] * xs[i];
+                y_sq[i] += ys[i] * ys[i];
+            }
+        }
+        (
+            leftover.0 + dot.iter().sum::<f32>(),
+            leftover.1 + x_sq.iter().sum::<f32>(),
+            leftover.2 + y_sq.iter().sum::<f32>(),
+        )
+    }
+
+    pub(super) fn squared_norm(x: &[f32]) -> f32 {
+        let mut acc = [0.0f32; LANES];
+        let x_chunks = x.chunks_exact(LANES);
+        let leftover: f32 = x_chunks.remainder().iter().map(|xi| xi * xi).sum();
+        for xs in x_chunks {
+            for i in 0..LANES {
+                acc[i] += xs[i] * xs[i];
+            }
+        }
+        leftover + acc.iter().sum::<f32>()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn sum(v: __m256) -> f32 {
+        let mut lanes = [0.0f32; 8];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), v);
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn squared_l2(x: &[f32], y: &[f32]) -> f32 {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let mut acc = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= len {
+            let diff = _mm256_sub_ps(_mm256_loadu_ps(x_ptr.add(i)), _mm256_loadu_ps(y_ptr.add(i)));
+            acc = _mm256_fmadd_ps(diff, diff, acc);
+            i += 8;
+        }
+        let leftover: f32 = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .map(|(xi, yi)| (xi - yi) * (xi - yi))
+            .sum();
+        leftover + sum(acc)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn l1(x: &[f32], y: &[f32]) -> f32 {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let sign = _mm256_set1_ps(-0.0);
+        let mut acc = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= len {
+            let diff = _mm256_sub_ps(_mm256_loadu_ps(x_ptr.add(i)), _mm256_loadu_ps(y_ptr.add(i)));
+            acc = _mm256_add_ps(acc, _mm256_andnot_ps(sign, diff));
+            i += 8;
+        }
+        let leftover: f32 = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .map(|(xi, yi)| (xi - yi).abs())
+            .sum();
+        leftover + sum(acc)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn products(x: &[f32], y: &[f32]) -> (f32, f32, f32) {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let mut dot = _mm256_setzero_ps();
+        let mut x_sq = _mm256_setzero_ps();
+        let mut y_sq = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= len {
+            let x_simd = _mm256_loadu_ps(x_ptr.add(i));
+            let y_simd = _mm256_loadu_ps(y_ptr.add(i));
+            dot = _mm256_fmadd_ps(x_simd, y_simd, dot);
+            x_sq = _mm256_fmadd_ps(x_simd, x_simd, x_sq);
+            y_sq = _mm256_fmadd_ps(y_simd, y_simd, y_sq);
+            i += 8;
+        }
+        let leftover = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .fold((0.0, 0.0, 0.0), |(d, xx, yy), (xi, yi)| {
+                (d + xi * yi, xx + xi * xi, yy + yi * yi)
+            });
+        (
+            leftover.0 + sum(dot),
+            leftover.1 + sum(x_sq),
+            leftover.2 + sum(y_sq),
+        )
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn squared_norm(x: &[f32]) -> f32 {
+        let x_ptr = x.as_ptr();
+        let mut acc = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= x.len() {
+            let x_simd = _mm256_loadu_ps(x_ptr.add(i));
+            acc = _mm256_fmadd_ps(x_simd, x_simd, acc);
+            i += 8;
+        }
+        let leftover: f32 = x[i..].iter().map(|xi| xi * xi).sum();
+        leftover + sum(acc)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn squared_l2(x: &[f32], y: &[f32]) -> f32 {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let mut acc = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i + 4 <= len {
+            let diff = vsubq_f32(vld1q_f32(x_ptr.add(i)), vld1q_f32(y_ptr.add(i)));
+            acc = vfmaq_f32(acc, diff, diff);
+            i += 4;
+        }
+        let leftover: f32 = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .map(|(xi, yi)| (xi - yi) * (xi - yi))
+            .sum();
+        leftover + vaddvq_f32(acc)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn l1(x: &[f32], y: &[f32]) -> f32 {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let mut acc = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i + 4 <= len {
+            acc = vaddq_f32(
+                acc,
+                vabdq_f32(vld1q_f32(x_ptr.add(i)), vld1q_f32(y_ptr.add(i))),
+            );
+            i += 4;
+        }
+        let leftover: f32 = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .map(|(xi, yi)| (xi - yi).abs())
+            .sum();
+        leftover + vaddvq_f32(acc)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn products(x: &[f32], y: &[f32]) -> (f32, f32, f32) {
+        let len = x.len().min(y.len());
+        let (x_ptr, y_ptr) = (x.as_ptr(), y.as_ptr());
+        let mut dot = vdupq_n_f32(0.0);
+        let mut x_sq = vdupq_n_f32(0.0);
+        let mut y_sq = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i + 4 <= len {
+            let x_simd = vld1q_f32(x_ptr.add(i));
+            let y_simd = vld1q_f32(y_ptr.add(i));
+            dot = vfmaq_f32(dot, x_simd, y_simd);
+            x_sq = vfmaq_f32(x_sq, x_simd, x_simd);
+            y_sq = vfmaq_f32(y_sq, y_simd, y_simd);
+            i += 4;
+        }
+        let leftover = x[i..len]
+            .iter()
+            .zip(&y[i..len])
+            .fold((0.0, 0.0, 0.0), |(d, xx, yy), (xi, yi)| {
+                (d + xi * yi, xx + xi * xi, yy + yi * yi)
+            });
+        (
+            leftover.0 + vaddvq_f32(dot),
+            leftover.1 + vaddvq_f32(x_sq),
+            leftover.2 + vaddvq_f32(y_sq),
+        )
+    }
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn squared_norm(x: &[f32]) -> f32 {
+        let x_ptr = x.as_ptr();
+        let mut acc = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i + 4 <= x.len() {
+            let x_simd = vld1q_f32(x_ptr.add(i));
+            acc = vfmaq_f32(acc, x_simd, x_simd);
+            i += 4;
+        }
+        let leftover: f32 = x[i..].iter().map(|xi| xi * xi).sum();
+        leftover + vaddvq_f32(acc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-4 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn dispatched_kernels_match_portable() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for len in 0..70 {
+            let x: Vec<f32> = (0..len).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+            let y: Vec<f32> = (0..len).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+            assert!(close(squared_l2(&x, &y), portable::squared_l2(&x, &y)));
+            assert!(close(l1(&x, &y), portable::l1(&x, &y)));
+            assert!(close(squared_norm(&x), portable::squared_norm(&x)));
+            let (d, xx, yy) = products(&x, &y);
+            let (pd, pxx, pyy) = portable::products(&x, &y);
+            assert!(close(d, pd) && close(xx, pxx) && close(yy, pyy));
+            let naive: f32 = x.iter().zip(&y).map(|(a, b)| (a - b) * (a - b)).sum();
+            assert!(
+                close(squared_l2(&x, &y), naive),
+                "{} at {}",
+                simd_level(),
+                len
+            );
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::base_traits::*;
 use crate::pc_errors::*;
 use crate::summaries::*;
 use crate::PointIndex;
+use std::fmt::Debug;
 
 /// Labels for a small number of categories, using ints
 #[derive(Debug)]
@@ -175,7 +176,7 @@ impl LabelSet for VecLabels {
     type LabelSummary = VecSummary;
 
     fn len(&self) -> usize {
-        self.labels.len()
+        self.labels.len().checked_div(self.label_dim).unwrap_or(0)
     }
     fn is_empty(&self) -> bool {
         self.labels.is_empty()
@@ -229,3 +230,473 @@ impl LabelSet for VecLabels {
         })
     }
 }
+
+/// Labels that are sets of categories, like tags or diagnosis codes. Each point can have any number of categories.
+/// The sets are stored sorted and without repeats.
+#[derive(Debug)]
+pub struct MultiLabels {
+    categories: Vec<i64>,
+    offsets: Vec<usize>,
+    mask: Option<Vec<bool>>,
+}
+
+impl MultiLabels {
+    /// Creates a new multi label set from each point's categories.
+    pub fn new(labels: Vec<Vec<i64>>, mask: Option<Vec<bool>>) -> MultiLabels {
+        let mut categories = Vec::with_capacity(labels.iter().map(|l| l.len()).sum());
+        let mut offsets = Vec::with_capacity(labels.len() + 1);
+        offsets.push(0);
+        for mut set in labels {
+            set.sort_unstable();
+            set.dedup();
+            categories.extend(set);
+            offsets.push(categories.len());
+        }
+        MultiLabels {
+            categories,
+            offsets,
+            mask,
+        }
+    }
+}
+
+impl LabelSet for MultiLabels {
+    type Label = [i64];
+    type LabelSummary = MultiCatSummary;
+
+    fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&[i64]>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        match (self.offsets.get(pn), self.offsets.get(pn + 1)) {
+            (Some(start), Some(end)) => Ok(Some(&self.categories[*start..*end])),
+            _ => Ok(None),
+        }
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = MultiCatSummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(label) => summary.add(label),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Vector labels whose summaries keep the full covariance, see `CovSummary`. Use `VecLabels` if the per-dimension
+/// moments are enough, as the summaries here take `dim^2` floats.
+#[derive(Debug)]
+pub struct CovLabels {
+    labels: VecLabels,
+}
+
+impl CovLabels {
+    /// Creates a new vec label set with covariance summaries.
+    pub fn new(labels: Vec<f32>, label_dim: usize, mask: Option<Vec<bool>>) -> CovLabels {
+        CovLabels {
+            labels: VecLabels::new(labels, label_dim, mask),
+        }
+    }
+
+    /// The dimension of the vectors this labelset contains
+    pub fn dim(&self) -> usize {
+        self.labels.dim()
+    }
+}
+
+impl From<VecLabels> for CovLabels {
+    fn from(labels: VecLabels) -> CovLabels {
+        CovLabels { labels }
+    }
+}
+
+impl LabelSet for CovLabels {
+    type Label = [f32];
+    type LabelSummary = CovSummary;
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&[f32]>> {
+        self.labels.label(pn)
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = CovSummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(label) => summary.add(label),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Scalar labels, like the targets of a regression. The summaries are t-digests, see `QuantileSummary`.
+#[derive(Debug)]
+pub struct ScalarLabels {
+    labels: Vec<f32>,
+    mask: Option<Vec<bool>>,
+}
+
+impl ScalarLabels {
+    /// Creates a new scalar label set.
+    pub fn new(labels: Vec<f32>, mask: Option<Vec<bool>>) -> ScalarLabels {
+        ScalarLabels { labels, mask }
+    }
+}
+
+impl LabelSet for ScalarLabels {
+    type Label = f32;
+    type LabelSummary = QuantileSummary;
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&f32>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        Ok(self.labels.get(pn))
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = QuantileSummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(label) => summary.add(label),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Labels that are summarized by a uniform sample of `(index, label)` pairs, see `ReservoirSummary`. The samples can
+/// be shown as representative examples of a node.
+#[derive(Debug)]
+pub struct ReservoirLabels<T> {
+    labels: Vec<(PointIndex, T)>,
+    mask: Option<Vec<bool>>,
+    size: usize,
+}
+
+impl<T> ReservoirLabels<T> {
+    /// Creates a new label set that keeps up to `DEFAULT_RESERVOIR_SIZE` samples per summary.
+    pub fn new(labels: Vec<T>, mask: Option<Vec<bool>>) -> ReservoirLabels<T> {
+        ReservoirLabels::with_size(labels, mask, DEFAULT_RESERVOIR_SIZE)
+    }
+
+    /// Creates a new label set that keeps up to `size` samples per summary.
+    pub fn with_size(labels: Vec<T>, mask: Option<Vec<bool>>, size: usize) -> ReservoirLabels<T> {
+        ReservoirLabels {
+            labels: labels.into_iter().enumerate().collect(),
+            mask,
+            size,
+        }
+    }
+}
+
+impl<T: Clone + Debug + Send + Sync + 'static> LabelSet for ReservoirLabels<T> {
+    type Label = (PointIndex, T);
+    type LabelSummary = ReservoirSummary<(PointIndex, T)>;
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&(PointIndex, T)>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        Ok(self.labels.get(pn))
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = ReservoirSummary::with_size(self.size);
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(label) => summary.add(label),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Labels that are paths in a taxonomy, like `"animals/mammals/dogs"`. The summaries count every level of the taxonomy,
+/// see `TaxonomySummary`.
+#[derive(Debug)]
+pub struct TaxonomyLabels {
+    labels: Vec<String>,
+    mask: Option<Vec<bool>>,
+}
+
+impl TaxonomyLabels {
+    /// Creates a new taxonomy label set.
+    pub fn new(labels: Vec<String>, mask: Option<Vec<bool>>) -> TaxonomyLabels {
+        TaxonomyLabels { labels, mask }
+    }
+
+    /// The indexes of the points labeled with the taxonomy node `path` or anything under it.
+    pub fn indexes_under(&self, path: &str) -> Vec<PointIndex> {
+        (0..self.labels.len())
+            .filter(|i| self.mask.as_ref().map(|m| m[*i]).unwrap_or(true))
+            .filter(|i| taxonomy_contains(path, &self.labels[*i]))
+            .collect()
+    }
+}
+
+impl LabelSet for TaxonomyLabels {
+    type Label = str;
+    type LabelSummary = TaxonomySummary;
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&str>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        Ok(self.labels.get(pn).map(|l| l.as_str()))
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = TaxonomySummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(label) => summary.add(label),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Per point metadata of any type, like document ids or JSON blobs, to attach with `PointCloud::attach_metadata`. The
+/// summaries are a few sampled values, see `ReservoirSummary`, as counting every distinct id would keep them all.
+#[derive(Debug)]
+pub struct VecMetadata<T> {
+    values: Vec<T>,
+    mask: Option<Vec<bool>>,
+}
+
+/// Metadata that are strings, like document ids.
+pub type StringMetadata = VecMetadata<String>;
+/// Metadata that are JSON blobs.
+pub type JsonMetadata = VecMetadata<serde_json::Value>;
+
+impl<T> VecMetadata<T> {
+    /// Creates a new metadata set.
+    pub fn new(values: Vec<T>, mask: Option<Vec<bool>>) -> VecMetadata<T> {
+        VecMetadata { values, mask }
+    }
+}
+
+impl<T: Clone + Debug + Send + Sync + 'static> LabelSet for VecMetadata<T> {
+    type Label = T;
+    type LabelSummary = ReservoirSummary<T>;
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&T>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        Ok(self.values.get(pn))
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = ReservoirSummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(value) => summary.add(value),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+/// Timestamps of the points, to attach with `PointCloud::attach_metadata`. The summaries are the time ranges.
+#[derive(Debug)]
+pub struct TimestampMetadata {
+    timestamps: Vec<i64>,
+    mask: Option<Vec<bool>>,
+}
+
+impl TimestampMetadata {
+    /// Creates a new timestamp set.
+    pub fn new(timestamps: Vec<i64>, mask: Option<Vec<bool>>) -> TimestampMetadata {
+        TimestampMetadata { timestamps, mask }
+    }
+}
+
+impl LabelSet for TimestampMetadata {
+    type Label = i64;
+    type LabelSummary = TimeRangeSummary;
+
+    fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+    fn label(&self, pn: PointIndex) -> PointCloudResult<Option<&i64>> {
+        if let Some(mask) = &self.mask {
+            if !mask[pn] {
+                return Ok(None);
+            }
+        }
+        Ok(self.timestamps.get(pn))
+    }
+    fn label_summary(
+        &self,
+        pns: &[PointIndex],
+    ) -> PointCloudResult<SummaryCounter<Self::LabelSummary>> {
+        let mut summary = TimeRangeSummary::default();
+        let mut nones = 0;
+        for i in pns {
+            match self.label(*i)? {
+                Some(timestamp) => summary.add(timestamp),
+                None => nones += 1,
+            }
+        }
+        Ok(SummaryCounter {
+            summary,
+            nones,
+            errors: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::DataRam;
+    use crate::{LabeledCloud, MetaCloud, PointCloud, L2};
+
+    #[test]
+    fn vec_labels_count_labels() {
+        let labels = VecLabels::new(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], 3, None);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels.label(1).unwrap(), Some(&[3.0, 4.0, 5.0][..]));
+        let empty = VecLabels {
+            labels: Vec::new(),
+            label_dim: 0,
+            mask: None,
+        };
+        assert_eq!(empty.len(), 0);
+    }
+
+    #[test]
+    fn metadata_stays_out_of_the_labels() {
+        let data = DataRam::<L2>::new(vec![0.0, 1.0, 2.0, 3.0], 1).unwrap();
+        let ids: Vec<String> = (0..4).map(|i| format!("doc-{}", i)).collect();
+        let cloud = data
+            .attach_labels(SmallIntLabels::new(vec![0, 0, 1, 1], None))
+            .unwrap()
+            .attach_metadata(StringMetadata::new(
+                ids,
+                Some(vec![true, true, true, false]),
+            ))
+            .unwrap();
+        assert_eq!(cloud.metadata(1).unwrap(), Some(&"doc-1".to_string()));
+        assert_eq!(cloud.metadata(3).unwrap(), None);
+        assert_eq!(cloud.label(2).unwrap(), Some(&1));
+
+        let labels = cloud.label_summary(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(labels.summary().posterior(), vec![(0, 0.5), (1, 0.5)]);
+        let metadata = cloud.metasummary(&[0, 1, 2, 3]).unwrap();
+        assert_eq!(metadata.summary().samples().len(), 3);
+        assert_eq!(metadata.nones(), 1);
+
+        let times = DataRam::<L2>::new(vec![0.0, 1.0, 2.0], 1)
+            .unwrap()
+            .attach_metadata(TimestampMetadata::new(vec![30, 10, 20], None))
+            .unwrap();
+        let range = times.metasummary(&[0, 1, 2]).unwrap();
+        assert_eq!(range.summary().range(), Some((10, 30)));
+        assert!(DataRam::<L2>::new(vec![0.0], 1)
+            .unwrap()
+            .attach_metadata(TimestampMetadata::new(vec![1, 2], None))
+            .is_err());
+    }
+}
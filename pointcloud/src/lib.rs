@@ -33,6 +33,12 @@ extern crate assert_approx_eq;
 
 mod distances;
 pub use distances::*;
+mod kernels;
+pub use kernels::simd_level;
+mod scalar;
+pub use scalar::Scalar;
+mod interop;
+pub use interop::AsPointRef;
 pub mod pc_errors;
 
 pub mod data_sources;
@@ -41,13 +47,22 @@ pub mod summaries;
 
 pub mod glued_data_cloud;
 pub mod loaders;
+pub mod metric_audit;
+pub mod synthetic;
+mod user_metric;
+pub use user_metric::{DenseDistance, MetricSlot, MetricTag, UserMetric};
+mod weighted_l2;
+pub use weighted_l2::{inverse_variances, WeightedL2};
 
 mod base_traits;
 #[doc(inline)]
 pub use base_traits::*;
+mod par_iter;
+pub use par_iter::{ParLabels, ParPoints};
 
 use data_sources::DataRam;
 use label_sources::SmallIntLabels;
+use pc_errors::PointCloudResult;
 
 /// A sensible default for an labeled cloud
 pub type DefaultLabeledCloud<M> = SimpleLabeledCloud<DataRam<M>, SmallIntLabels>;
@@ -77,6 +92,10 @@ pub enum PointRef<'a> {
     Dense(&'a [f32]),
     /// Sparse reference, values, then indexes
     Sparse(&'a [f32], &'a [u32]),
+    /// Bit packed reference, dimension `i` is bit `i % 64` of word `i / 64`
+    Binary(&'a [u64]),
+    /// A string, compared with metrics like `Levenshtein`
+    Text(&'a str),
 }
 
 ///
@@ -99,6 +118,30 @@ impl<'a> Iterator for DenseIter<'a> {
                     None
                 }
             }
+            PointRef::Binary(words) => {
+                if self.index < self.dim {
+                    let bit = (words[self.index / 64] >> (self.index % 64)) & 1;
+                    self.index += 1;
+                    Some(bit as f32)
+                } else {
+                    None
+                }
+            }
+            PointRef::Text(text) => {
+                // The sparse index tracks the byte offset of the next character
+                if self.index < self.dim {
+                    self.index += 1;
+                    match text[self.sparse_index..].chars().next() {
+                        Some(c) => {
+                            self.sparse_index += c.len_utf8();
+                            Some(c as u32 as f32)
+                        }
+                        None => Some(0.0),
+                    }
+                } else {
+                    None
+                }
+            }
             PointRef::Sparse(vals, inds) => {
                 if self.index < self.dim {
                     if inds[self.sparse_index] == self.index as u32 {
@@ -121,7 +164,9 @@ impl<'a> Iterator for DenseIter<'a> {
     fn size_hint(&self) -> (usize, Option<usize>) {
         match self.p_ref {
             PointRef::Dense(vals) => (vals.len(), Some(vals.len())),
-            PointRef::Sparse(_, _) => (self.dim, Some(self.dim)),
+            PointRef::Sparse(_, _) | PointRef::Binary(_) | PointRef::Text(_) => {
+                (self.dim, Some(self.dim))
+            }
         }
     }
 }
@@ -145,6 +190,10 @@ pub enum Point {
     Dense(Vec<f32>),
     /// Sparse contiguous point, values then indexes
     Sparse(Vec<f32>, Vec<u32>),
+    /// Bit packed point, dimension `i` is bit `i % 64` of word `i / 64`
+    Binary(Vec<u64>),
+    /// A string
+    Text(String),
 }
 
 impl<'a> From<&'a [f32]> for PointRef<'a> {
@@ -164,6 +213,8 @@ impl<'b, 'a: 'b> From<&'b PointRef<'a>> for PointRef<'a> {
         match arr {
             PointRef::Dense(v) => PointRef::Dense(&v[..]),
             PointRef::Sparse(v, i) => PointRef::Sparse(&v[..], &i[..]),
+            PointRef::Binary(w) => PointRef::Binary(&w[..]),
+            PointRef::Text(t) => PointRef::Text(t),
         }
     }
 }
@@ -173,10 +224,24 @@ impl<'b, 'a: 'b, 'c: 'b> From<&'c &'b PointRef<'a>> for PointRef<'a> {
         match arr {
             PointRef::Dense(v) => PointRef::Dense(&v[..]),
             PointRef::Sparse(v, i) => PointRef::Sparse(&v[..], &i[..]),
+            PointRef::Binary(w) => PointRef::Binary(&w[..]),
+            PointRef::Text(t) => PointRef::Text(t),
         }
     }
 }
 
+impl<'a> From<&'a [u64]> for PointRef<'a> {
+    fn from(words: &'a [u64]) -> PointRef<'a> {
+        PointRef::Binary(words)
+    }
+}
+
+impl<'a> From<&'a str> for PointRef<'a> {
+    fn from(text: &'a str) -> PointRef<'a> {
+        PointRef::Text(text)
+    }
+}
+
 impl<'a> From<(&'a [f32], &'a [u32])> for PointRef<'a> {
     fn from(arr: (&'a [f32], &'a [u32])) -> PointRef<'a> {
         PointRef::Sparse(arr.0, arr.1)
@@ -188,6 +253,50 @@ impl<'a> From<&'a Point> for PointRef<'a> {
         match arr {
             Point::Dense(v) => PointRef::Dense(&v[..]),
             Point::Sparse(v, i) => PointRef::Sparse(&v[..], &i[..]),
+            Point::Binary(w) => PointRef::Binary(&w[..]),
+            Point::Text(t) => PointRef::Text(&t[..]),
         }
     }
 }
+
+/// A batch of points borrowed from a point cloud all at once. See `PointCloud::points`.
+#[derive(Clone, Debug)]
+pub struct PointBatch<'a> {
+    points: Vec<PointRef<'a>>,
+}
+
+impl<'a> PointBatch<'a> {
+    /// Wraps a set of borrowed points.
+    pub fn new(points: Vec<PointRef<'a>>) -> PointBatch<'a> {
+        PointBatch { points }
+    }
+
+    /// The number of points in the batch
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// If there are no points in the batch
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The point at position `i` of the batch, this is not the point index.
+    pub fn get(&self, i: usize) -> Option<PointRef<'a>> {
+        self.points.get(i).copied()
+    }
+
+    /// Iterates over the points in the order they were requested.
+    pub fn iter<'b>(&'b self) -> impl Iterator<Item = PointRef<'a>> + 'b {
+        self.points.iter().copied()
+    }
+
+    /// The distances from each point in the batch to the passed in point.
+    pub fn distances_to_point<'b, M: Metric, T: Into<PointRef<'b>>>(
+        &self,
+        point: T,
+    ) -> PointCloudResult<Vec<f32>> {
+        let point: PointRef<'b> = point.into();
+        self.points.iter().map(|p| M::dist(p, &point)).collect()
+    }
+}
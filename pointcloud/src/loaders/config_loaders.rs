@@ -0,0 +1,506 @@
+//! The dataset config the yaml, json and toml loaders share.
+
+use glob::{glob_with, MatchOptions};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use yaml_rust::{Yaml, YamlLoader};
+
+use super::*;
+use crate::distances::L2;
+use crate::{DefaultCloud, DefaultLabeledCloud};
+
+/// Where a dataset lives and how to read it. The paths are globs, relative to the config file. Other keys in the file,
+/// like the tree's parameters, are ignored. A minimal json example:
+/// ```json
+/// {
+///     "data_path": "DATAMEMMAP",
+///     "labels_path": "LABELS_CSV",
+///     "data_dim": 784,
+///     "labels_index": 2
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataConfig {
+    /// The memmaps holding the points
+    pub data_path: String,
+    /// The number of floats in a point
+    pub data_dim: usize,
+    /// The number of points, for reference, it isn't checked
+    #[serde(default)]
+    pub count: Option<usize>,
+    /// The files holding the labels, CSVs or memmaps ending in `.dat`
+    #[serde(default)]
+    pub labels_path: Option<String>,
+    /// The number of floats in a label, for labels in memmaps
+    #[serde(default)]
+    pub labels_dim: Option<usize>,
+    /// The column of the label, for labels in CSVs
+    #[serde(default)]
+    pub labels_index: Option<usize>,
+    /// `ram`, `memmap` or `auto`, see `backend_from_yaml`
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Where to pack several data files into one memmap, see `backend_from_yaml`. Without it they're mapped in place
+    #[serde(default)]
+    pub memmap_dir: Option<String>,
+    /// The file the config was read from, the globs are relative to it
+    #[serde(skip)]
+    pub config_path: PathBuf,
+}
+
+fn config_error(
+    config_path: &Path,
+    key: Option<&str>,
+    file: Option<&Path>,
+    reason: String,
+) -> PointCloudError {
+    PointCloudError::ConfigError {
+        config_path: config_path.to_string_lossy().to_string(),
+        key: key.map(|k| k.to_string()),
+        file: file.map(|f| f.to_string_lossy().to_string()),
+        reason,
+    }
+}
+
+fn missing(config_path: &Path, key: &str) -> PointCloudError {
+    config_error(
+        config_path,
+        Some(key),
+        None,
+        "the key is missing".to_string(),
+    )
+}
+
+fn malformed(config_path: &Path, key: &str, reason: String) -> PointCloudError {
+    config_error(config_path, Some(key), None, reason)
+}
+
+fn unreadable(config_path: &Path, reason: String) -> PointCloudError {
+    config_error(config_path, None, None, reason)
+}
+
+impl DataConfig {
+    /// Reads the config from a yaml file.
+    pub fn from_yaml<P: AsRef<Path>>(path: P) -> PointCloudResult<DataConfig> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| unreadable(path, e.to_string()))?;
+        let docs =
+            YamlLoader::load_from_str(&contents).map_err(|e| unreadable(path, e.to_string()))?;
+        let doc = docs.first().ok_or_else(|| missing(path, "data_path"))?;
+        let string = |field: &str| doc[field].as_str().map(|s| s.to_string());
+        let number = |field: &str| -> PointCloudResult<Option<usize>> {
+            match &doc[field] {
+                Yaml::BadValue | Yaml::Null => Ok(None),
+                Yaml::Integer(i) if *i >= 0 => Ok(Some(*i as usize)),
+                other => Err(malformed(
+                    path,
+                    field,
+                    format!("expected a non-negative integer, found {:?}", other),
+                )),
+            }
+        };
+        Ok(DataConfig {
+            data_path: string("data_path").ok_or_else(|| missing(path, "data_path"))?,
+            data_dim: number("data_dim")?.ok_or_else(|| missing(path, "data_dim"))?,
+            count: number("count")?,
+            labels_path: string("labels_path"),
+            labels_dim: number("labels_dim")?,
+            labels_index: number("labels_index")?,
+            backend: string("backend"),
+            memmap_dir: string("memmap_dir"),
+            config_path: path.to_path_buf(),
+        })
+    }
+
+    /// Reads the config from a json file.
+    pub fn from_json<P: AsRef<Path>>(path: P) -> PointCloudResult<DataConfig> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| unreadable(path, e.to_string()))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&contents).map_err(|e| unreadable(path, e.to_string()))?;
+        DataConfig::from_value(path, &value)
+    }
+
+    /// Reads the config from a toml file.
+    #[cfg(feature = "toml")]
+    pub fn from_toml<P: AsRef<Path>>(path: P) -> PointCloudResult<DataConfig> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| unreadable(path, e.to_string()))?;
+        let value: toml::Value =
+            toml::from_str(&contents).map_err(|e| unreadable(path, e.to_string()))?;
+        let value = serde_json::to_value(value).map_err(|e| unreadable(path, e.to_string()))?;
+        DataConfig::from_value(path, &value)
+    }
+
+    /// Reads the keys one at a time, rather than deserializing the whole config, so that a missing or mistyped key
+    /// can be named in the error.
+    fn from_value(path: &Path, value: &serde_json::Value) -> PointCloudResult<DataConfig> {
+        use serde_json::Value;
+        let doc = value.as_object().ok_or_else(|| {
+            unreadable(path, format!("expected a table of keys, found {}", value))
+        })?;
+        let string = |field: &str| -> PointCloudResult<Option<String>> {
+            match doc.get(field) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s.clone())),
+                Some(other) => Err(malformed(
+                    path,
+                    field,
+                    format!("expected a string, found {}", other),
+                )),
+            }
+        };
+        let number = |field: &str| -> PointCloudResult<Option<usize>> {
+            match doc.get(field) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::Number(n)) if n.is_u64() => Ok(n.as_u64().map(|n| n as usize)),
+                Some(other) => Err(malformed(
+                    path,
+                    field,
+                    format!("expected a non-negative integer, found {}", other),
+                )),
+            }
+        };
+        Ok(DataConfig {
+            data_path: string("data_path")?.ok_or_else(|| missing(path, "data_path"))?,
+            data_dim: number("data_dim")?.ok_or_else(|| missing(path, "data_dim"))?,
+            count: number("count")?,
+            labels_path: string("labels_path")?,
+            labels_dim: number("labels_dim")?,
+            labels_index: number("labels_index")?,
+            backend: string("backend")?,
+            memmap_dir: string("memmap_dir")?,
+            config_path: path.to_path_buf(),
+        })
+    }
+
+    fn data_paths(&self) -> PointCloudResult<Vec<PathBuf>> {
+        get_file_list(&self.data_path, &self.config_path, "data_path")
+    }
+
+    /// Opens the points in ram.
+    pub fn ram<M: Metric>(&self) -> PointCloudResult<DefaultCloud<M>> {
+        let data_set = open_memmaps(self.data_dim, &self.data_paths()?)?;
+        Ok(convert_glued_memmap_to_ram(data_set))
+    }
+
+    /// Opens the points and integer labels in ram.
+    pub fn labeled_ram<M: Metric>(&self) -> PointCloudResult<DefaultLabeledCloud<M>> {
+        let label_set = self.labels()?;
+        let data_set = self.ram()?;
+        Ok(SimpleLabeledCloud::new(data_set, label_set))
+    }
+
+    /// Opens the points and vector labels, read from memmaps `labels_dim` wide, in ram.
+    pub fn vec_labeled_ram<M: Metric>(
+        &self,
+    ) -> PointCloudResult<SimpleLabeledCloud<DataRam<M>, VecLabels>> {
+        let labels_path = self
+            .labels_path
+            .as_ref()
+            .ok_or_else(|| missing(&self.config_path, "labels_path"))?;
+        let labels_dim = self
+            .labels_dim
+            .ok_or_else(|| missing(&self.config_path, "labels_dim"))?;
+        let labels_paths = get_file_list(labels_path, &self.config_path, "labels_path")?;
+        let label_set = convert_glued_memmap_to_ram(open_memmaps::<M>(labels_dim, &labels_paths)?)
+            .convert_to_labels();
+        Ok(SimpleLabeledCloud::new(self.ram()?, label_set))
+    }
+
+    /// Opens the points in ram or memory mapped, as `backend` asks, see `backend_from_yaml`.
+    pub fn backend<M: Metric>(&self) -> PointCloudResult<DataBackend<M>> {
+        let choice = match &self.backend {
+            None => BackendChoice::Auto,
+            Some(name) => BackendChoice::from_name(name).ok_or_else(|| {
+                malformed(
+                    &self.config_path,
+                    "backend",
+                    format!("expected ram, memmap or auto, found {}", name),
+                )
+            })?,
+        };
+        let data_paths = self.data_paths()?;
+        if data_paths.len() == 1 {
+            let data_set = DataMemmap::open_read_only(self.data_dim, &data_paths[0])?;
+            return Ok(DataBackend::from_memmap(data_set, choice));
+        }
+        let data_set = open_memmaps::<M>(self.data_dim, &data_paths)?;
+        if choice.use_ram(data_set.len() * self.data_dim * std::mem::size_of::<f32>()) {
+            return Ok(DataBackend::Ram(convert_glued_memmap_to_ram(data_set)));
+        }
+        match &self.memmap_dir {
+            Some(dir) => {
+                let dir = self
+                    .config_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(dir);
+                let packed = pack_points(data_set.data_sources(), &dir).map_err(|e| {
+                    config_error(
+                        &self.config_path,
+                        Some("memmap_dir"),
+                        Some(&dir),
+                        e.to_string(),
+                    )
+                })?;
+                Ok(DataBackend::Memmap(DataMemmap::open_read_only(
+                    self.data_dim,
+                    &packed,
+                )?))
+            }
+            None => Ok(DataBackend::GluedMemmap(data_set)),
+        }
+    }
+
+    /// Opens the integer labels, from CSVs with `labels_index` or one hot or binary memmaps with `labels_dim`.
+    pub fn labels(&self) -> PointCloudResult<SmallIntLabels> {
+        let labels_path = self
+            .labels_path
+            .as_ref()
+            .ok_or_else(|| missing(&self.config_path, "labels_path"))?;
+        let labels_paths = get_file_list(labels_path, &self.config_path, "labels_path")?;
+
+        let mut label_set = labels_paths.iter().map(|path| {
+            let label_error = |key: &str, reason: String| {
+                config_error(&self.config_path, Some(key), Some(path), reason)
+            };
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            match (extension, self.labels_index, self.labels_dim) {
+                ("csv", Some(index), _) | ("gz", Some(index), _) => open_int_csv(&path, index)
+                    .map_err(|e| label_error("labels_path", e.to_string())),
+                ("csv", None, _) | ("gz", None, _) => Err(label_error(
+                    "labels_index",
+                    "CSV labels need the column index of the label".to_string(),
+                )),
+                ("dat", _, Some(dim)) => {
+                    let labels: VecLabels = DataMemmap::<L2>::new(dim, path)
+                        .map_err(|e| label_error("labels_path", e.to_string()))?
+                        .convert_to_labels();
+                    match dim.cmp(&1) {
+                        Ordering::Greater => Ok(labels.one_hot_to_int()),
+                        Ordering::Equal => Ok(labels.binary_to_int()),
+                        Ordering::Less => Err(label_error(
+                            "labels_dim",
+                            "memmapped labels are one hot, or binary with a dimension of 1"
+                                .to_string(),
+                        )),
+                    }
+                }
+                ("dat", _, None) => Err(label_error(
+                    "labels_dim",
+                    "memmapped labels need their dimension".to_string(),
+                )),
+                _ => Err(label_error(
+                    "labels_path",
+                    "labels are read from .csv, .gz or .dat files".to_string(),
+                )),
+            }
+        });
+        let mut labels = label_set.next().unwrap()?;
+        for other in label_set {
+            labels.merge(&other?);
+        }
+        Ok(labels)
+    }
+}
+
+/// The files matching a glob, relative to the config's directory. Errors if the pattern is malformed, or matches
+/// nothing.
+fn get_file_list(files_reg: &str, config_path: &Path, key: &str) -> PointCloudResult<Vec<PathBuf>> {
+    let options = MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    let files_reg_path = Path::new(files_reg);
+    let pattern = if files_reg_path.is_absolute() {
+        files_reg_path.to_path_buf()
+    } else {
+        config_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(files_reg_path)
+    };
+    let glob_paths = glob_with(&pattern.to_string_lossy(), options)
+        .map_err(|e| malformed(config_path, key, e.to_string()))?;
+
+    let mut paths = Vec::new();
+    for entry in glob_paths {
+        let path = entry.map_err(|e| {
+            config_error(
+                config_path,
+                Some(key),
+                Some(e.path()),
+                e.error().to_string(),
+            )
+        })?;
+        paths.push(path)
+    }
+    if paths.is_empty() {
+        return Err(malformed(
+            config_path,
+            key,
+            format!("{} doesn't match any files", pattern.to_string_lossy()),
+        ));
+    }
+    Ok(paths)
+}
+
+/// Same as `labeled_ram_from_yaml`, but reads a json config, see `DataConfig`.
+pub fn labeled_ram_from_json<P: AsRef<Path>, M: Metric>(
+    path: P,
+) -> PointCloudResult<DefaultLabeledCloud<M>> {
+    DataConfig::from_json(path)?.labeled_ram()
+}
+
+/// Same as `labeled_ram_from_yaml`, but reads a toml config, see `DataConfig`. A minimal example below.
+/// ```toml
+/// data_path = "DATAMEMMAP"
+/// labels_path = "LABELS_CSV"
+/// data_dim = 784
+/// labels_index = 2
+/// ```
+#[cfg(feature = "toml")]
+pub fn labeled_ram_from_toml<P: AsRef<Path>, M: Metric>(
+    path: P,
+) -> PointCloudResult<DefaultLabeledCloud<M>> {
+    DataConfig::from_toml(path)?.labeled_ram()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn configs_agree_across_formats() {
+        let dir = TempDir::new("data_config").unwrap();
+        let labels = SmallIntLabels::new(vec![1, 0, 2], None);
+        let cloud = SimpleLabeledCloud::new(
+            DataRam::<L2>::new(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], 2).unwrap(),
+            labels,
+        );
+        let yaml = write_labeled_memmap(&cloud, dir.path()).unwrap();
+        let json = dir.path().join("config.json");
+        fs::write(
+            &json,
+            r#"{"data_path": "points.f32", "labels_path": "labels.dat", "data_dim": 2, "labels_dim": 3, "scale_base": 1.3}"#,
+        )
+        .unwrap();
+
+        let from_yaml = DataConfig::from_yaml(&yaml).unwrap();
+        let from_json = DataConfig::from_json(&json).unwrap();
+        assert_eq!(from_yaml.count, Some(3));
+        assert_eq!(
+            DataConfig {
+                count: None,
+                config_path: json.clone(),
+                ..from_yaml
+            },
+            from_json
+        );
+        let read = labeled_ram_from_json::<_, L2>(&json).unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read.label(2).unwrap(), Some(&2));
+
+        fs::write(&json, r#"{"data_path": "points.f32"}"#).unwrap();
+        match DataConfig::from_json(&json) {
+            Err(PointCloudError::ConfigError {
+                config_path, key, ..
+            }) => {
+                assert!(config_path.ends_with("config.json"));
+                assert_eq!(key.as_deref(), Some("data_dim"));
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        fs::write(&json, r#"{"data_path": "points.f32", "data_dim": "two"}"#).unwrap();
+        match DataConfig::from_json(&json) {
+            Err(PointCloudError::ConfigError { key, .. }) => {
+                assert_eq!(key.as_deref(), Some("data_dim"))
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+        fs::write(&json, "[1, 2]").unwrap();
+        match DataConfig::from_json(&json) {
+            Err(PointCloudError::ConfigError { key, .. }) => assert_eq!(key, None),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+
+        let config_key = |contents: &str| {
+            fs::write(&yaml, contents).unwrap();
+            match labeled_ram_from_yaml::<_, L2>(&yaml) {
+                Err(PointCloudError::ConfigError { key, file, .. }) => (key, file),
+                other => panic!("expected a config error, got {:?}", other),
+            }
+        };
+        assert_eq!(
+            config_key("---\ndata_dim: 2\n").0.as_deref(),
+            Some("data_path")
+        );
+        assert_eq!(
+            config_key("---\ndata_path: points.f32\ndata_dim: two\n")
+                .0
+                .as_deref(),
+            Some("data_dim")
+        );
+        assert_eq!(
+            config_key("---\ndata_path: missing.f32\ndata_dim: 2\nlabels_path: labels.dat\nlabels_dim: 3\n")
+                .0
+                .as_deref(),
+            Some("data_path")
+        );
+        let (key, file) =
+            config_key("---\ndata_path: points.f32\ndata_dim: 2\nlabels_path: labels.dat\n");
+        assert_eq!(key.as_deref(), Some("labels_dim"));
+        assert!(file.unwrap().ends_with("labels.dat"));
+        fs::write(dir.path().join("labels.csv"), "id,label\na,1\nb,x\n").unwrap();
+        let (key, file) = config_key(
+            "---\ndata_path: points.f32\ndata_dim: 2\nlabels_path: labels.csv\nlabels_index: 1\n",
+        );
+        assert_eq!(key.as_deref(), Some("labels_path"));
+        assert!(file.unwrap().ends_with("labels.csv"));
+    }
+
+    #[test]
+    fn several_files_are_mapped_without_ram() {
+        let dir = TempDir::new("glued_backend").unwrap();
+        for (i, data) in [vec![0.0f32, 1.0, 2.0, 3.0], vec![4.0, 5.0]]
+            .iter()
+            .enumerate()
+        {
+            let bytes: Vec<u8> = data.iter().flat_map(|x| x.to_ne_bytes().to_vec()).collect();
+            fs::write(dir.path().join(format!("part{}.f32", i)), bytes).unwrap();
+        }
+        let config = dir.path().join("config.json");
+        let config_with = |extra: &str| {
+            fs::write(
+                &config,
+                format!(
+                    r#"{{"data_path": "part*.f32", "data_dim": 2, "backend": "memmap"{}}}"#,
+                    extra
+                ),
+            )
+            .unwrap();
+            DataConfig::from_json(&config)
+                .unwrap()
+                .backend::<L2>()
+                .unwrap()
+        };
+        let points = |backend: &DataBackend<L2>| {
+            (0..3)
+                .map(|pi| backend.point(pi).unwrap().dense_iter(2).collect())
+                .collect::<Vec<Vec<f32>>>()
+        };
+        let expected = vec![vec![0.0, 1.0], vec![2.0, 3.0], vec![4.0, 5.0]];
+
+        let glued = config_with("");
+        assert!(matches!(glued, DataBackend::GluedMemmap(_)));
+        assert_eq!(points(&glued), expected);
+        glued.prefetch(&[0, 2]);
+
+        let packed = config_with(r#", "memmap_dir": "packed""#);
+        assert!(matches!(packed, DataBackend::Memmap(_)));
+        assert_eq!(points(&packed), expected);
+    }
+}
@@ -5,27 +5,35 @@ use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
+use crate::data_sources::DataRam;
 use crate::label_sources::*;
+use crate::{DefaultLabeledCloud, Metric, SimpleLabeledCloud};
 
 /// Opens a CSV and reads a single column from it as a integer label. Negative labels are treated as unlabeled and are masked.
 pub fn open_int_csv<P: AsRef<Path> + std::fmt::Debug>(
     path: &P,
     index: usize,
 ) -> PointCloudResult<SmallIntLabels> {
-    if !path.as_ref().exists() {
-        panic!("CSV file {:?} does not exist", path);
+    let file =
+        File::open(path).map_err(|e| csv_error(path, 0, format!("Unable to open: {}", e)))?;
+    if path
+        .as_ref()
+        .extension()
+        .map(|e| e == "gz")
+        .unwrap_or(false)
+    {
+        read_csv(index, path, Reader::from_reader(GzDecoder::new(file)))
+    } else {
+        read_csv(index, path, Reader::from_reader(file))
     }
+}
 
-    match File::open(&path) {
-        Ok(file) => {
-            if path.as_ref().extension().unwrap() == "gz" {
-                read_csv(index, path, Reader::from_reader(GzDecoder::new(file)))
-            } else {
-                read_csv(index, path, Reader::from_reader(file))
-            }
-        }
-        Err(e) => panic!("Unable to open csv file {:#?}", e),
-    }
+fn csv_error<P: AsRef<Path>>(path: &P, line_number: usize, key: String) -> PointCloudError {
+    PointCloudError::ParsingError(ParsingError::CSVReadError {
+        file_name: path.as_ref().to_string_lossy().to_string(),
+        line_number,
+        key,
+    })
 }
 
 fn read_csv<P: AsRef<Path> + std::fmt::Debug, R: Read>(
@@ -39,15 +47,19 @@ fn read_csv<P: AsRef<Path> + std::fmt::Debug, R: Read>(
     for result in rdr.records() {
         // The iterator yields Result<StringRecord, Error>, so we check the
         // error here.
-        let record = result.expect("Unable to read a record from the label CSV");
+        let record = result.map_err(|e| {
+            let line_number = e.position().map(|p| p.line() as usize).unwrap_or(0);
+            csv_error(path, line_number, e.to_string())
+        })?;
         match record.get(index) {
             Some(val) => {
                 let val = val.parse::<i64>().map_err(|_| {
-                    PointCloudError::ParsingError(ParsingError::CSVReadError {
-                        file_name: path.as_ref().to_string_lossy().to_string(),
-                        line_number: record.position().unwrap().line() as usize,
-                        key: format!("Unable to read u64 from {:?}", record),
-                    })
+                    let line_number = record.position().map(|p| p.line() as usize).unwrap_or(0);
+                    csv_error(
+                        path,
+                        line_number,
+                        format!("Unable to read u64 from {:?}", record),
+                    )
                 })?;
                 if 0 < val {
                     mask.push(true);
@@ -68,3 +80,191 @@ fn read_csv<P: AsRef<Path> + std::fmt::Debug, R: Read>(
         Ok(SmallIntLabels::new(labels, None))
     }
 }
+
+/// Picks a column of a CSV, either by the name in the header or by position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvColumn {
+    /// The column with this name in the header
+    Name(String),
+    /// The column at this position, starting from 0
+    Index(usize),
+}
+
+impl From<&str> for CsvColumn {
+    fn from(name: &str) -> CsvColumn {
+        CsvColumn::Name(name.to_string())
+    }
+}
+
+impl From<usize> for CsvColumn {
+    fn from(index: usize) -> CsvColumn {
+        CsvColumn::Index(index)
+    }
+}
+
+/// Opens a CSV with a header and reads the feature columns as a dense point cloud, with an optional integer label
+/// column. Like `open_int_csv`, negative labels are masked, as are all of the labels if there's no label column. Gzipped
+/// files are read if the extension is `gz`.
+pub fn open_csv_points<M: Metric, P: AsRef<Path>, C: Into<CsvColumn> + Clone>(
+    path: &P,
+    feature_cols: &[C],
+    label_col: Option<C>,
+) -> PointCloudResult<DefaultLabeledCloud<M>> {
+    let file = File::open(path)?;
+    if path
+        .as_ref()
+        .extension()
+        .map(|e| e == "gz")
+        .unwrap_or(false)
+    {
+        read_csv_points(
+            path,
+            feature_cols,
+            label_col,
+            Reader::from_reader(GzDecoder::new(file)),
+        )
+    } else {
+        read_csv_points(path, feature_cols, label_col, Reader::from_reader(file))
+    }
+}
+
+fn read_csv_points<M: Metric, P: AsRef<Path>, C: Into<CsvColumn> + Clone, R: Read>(
+    path: &P,
+    feature_cols: &[C],
+    label_col: Option<C>,
+    mut rdr: Reader<R>,
+) -> PointCloudResult<DefaultLabeledCloud<M>> {
+    let file_name = path.as_ref().to_string_lossy().to_string();
+    let parse_error = |line_number: usize, key: String| {
+        PointCloudError::ParsingError(ParsingError::CSVReadError {
+            file_name: file_name.clone(),
+            line_number,
+            key,
+        })
+    };
+    let headers = rdr
+        .headers()
+        .map_err(|e| parse_error(1, format!("Unable to read the header: {}", e)))?
+        .clone();
+    let position = |col: CsvColumn| match col {
+        CsvColumn::Index(i) if i < headers.len() => Ok(i),
+        CsvColumn::Name(ref name) => headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| parse_error(1, format!("No column named {}", name))),
+        col => Err(parse_error(1, format!("No column {:?}", col))),
+    };
+    let feature_positions: Vec<usize> = feature_cols
+        .iter()
+        .map(|c| position(c.clone().into()))
+        .collect::<PointCloudResult<Vec<usize>>>()?;
+    let label_position = label_col.map(|c| position(c.into())).transpose()?;
+
+    let mut data = Vec::new();
+    let mut labels = Vec::new();
+    let mut mask = Vec::new();
+    for result in rdr.records() {
+        let record = result.map_err(|e| parse_error(0, e.to_string()))?;
+        let line_number = record.position().map(|p| p.line() as usize).unwrap_or(0);
+        for i in &feature_positions {
+            let val = record
+                .get(*i)
+                .and_then(|v| v.trim().parse::<f32>().ok())
+                .ok_or_else(|| {
+                    parse_error(line_number, format!("Unable to read f32 from column {}", i))
+                })?;
+            data.push(val);
+        }
+        match label_position.and_then(|i| record.get(i)) {
+            Some(val) => {
+                let val = val.trim().parse::<i64>().map_err(|_| {
+                    parse_error(line_number, format!("Unable to read i64 from {:?}", record))
+                })?;
+                mask.push(0 < val);
+                labels.push(val);
+            }
+            None => {
+                labels.push(0);
+                mask.push(false);
+            }
+        }
+    }
+    let labels = if mask.iter().any(|f| !f) {
+        SmallIntLabels::new(labels, Some(mask))
+    } else {
+        SmallIntLabels::new(labels, None)
+    };
+    Ok(SimpleLabeledCloud::new(
+        DataRam::<M>::new(data, feature_positions.len())?,
+        labels,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabeledCloud, PointCloud, L2};
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn csv_points_by_name_and_index() {
+        let dir = TempDir::new("csv_points").unwrap();
+        let path = dir.path().join("points.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "id,x,y,label").unwrap();
+        writeln!(file, "a,1.0,2.0,3").unwrap();
+        writeln!(file, "b,4.0,5.0,-1").unwrap();
+        drop(file);
+
+        let by_name = open_csv_points::<L2, _, _>(&path, &["y", "x"], Some("label")).unwrap();
+        assert_eq!(by_name.len(), 2);
+        assert_eq!(by_name.dim(), 2);
+        assert_eq!(
+            by_name
+                .point(1)
+                .unwrap()
+                .dense_iter(2)
+                .collect::<Vec<f32>>(),
+            vec![5.0, 4.0]
+        );
+        assert_eq!(by_name.label(0).unwrap(), Some(&3));
+        assert_eq!(by_name.label(1).unwrap(), None);
+
+        let by_index = open_csv_points::<L2, _, _>(&path, &[1usize, 2], None).unwrap();
+        assert_eq!(
+            by_index
+                .point(0)
+                .unwrap()
+                .dense_iter(2)
+                .collect::<Vec<f32>>(),
+            vec![1.0, 2.0]
+        );
+        assert_eq!(by_index.label(0).unwrap(), None);
+
+        assert!(open_csv_points::<L2, _, _>(&path, &["z"], None).is_err());
+        assert!(open_csv_points::<L2, _, _>(&path, &["id"], None).is_err());
+    }
+
+    #[test]
+    fn int_csv_errors_name_the_file() {
+        let dir = TempDir::new("int_csv").unwrap();
+        let path = dir.path().join("labels.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "id,label").unwrap();
+        writeln!(file, "a,3").unwrap();
+        writeln!(file, "b,c,4").unwrap();
+        drop(file);
+
+        let file_name = |result: PointCloudResult<SmallIntLabels>| match result {
+            Err(PointCloudError::ParsingError(ParsingError::CSVReadError {
+                file_name, ..
+            })) => file_name,
+            other => panic!("expected a CSV error, got {:?}", other),
+        };
+        assert!(file_name(open_int_csv(&path, 1)).ends_with("labels.csv"));
+        assert!(
+            file_name(open_int_csv(&dir.path().join("missing.csv"), 1)).ends_with("missing.csv")
+        );
+    }
+}
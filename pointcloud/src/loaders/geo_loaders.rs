@@ -0,0 +1,110 @@
+//! Loaders for geographic points, as `[latitude, longitude]` pairs in degrees compared with `Haversine`.
+
+use std::path::Path;
+
+use super::csv_loaders::{open_csv_points, CsvColumn};
+use crate::base_traits::*;
+use crate::data_sources::DataRam;
+use crate::pc_errors::*;
+use crate::{DefaultCloud, DefaultLabeledCloud, Haversine};
+
+fn check_coordinates(index: usize, lat: f32, lon: f32) -> PointCloudResult<()> {
+    if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+        Ok(())
+    } else {
+        Err(PointCloudError::data_access(
+            index,
+            format!(
+                "({}, {}) is not a latitude and longitude in degrees",
+                lat, lon
+            ),
+        ))
+    }
+}
+
+/// Packs `(latitude, longitude)` pairs in degrees into a point cloud. Errors if a latitude isn't in [-90, 90] or a
+/// longitude isn't in [-180, 180].
+pub fn geo_points(coordinates: &[(f32, f32)]) -> PointCloudResult<DefaultCloud<Haversine>> {
+    let mut data = Vec::with_capacity(2 * coordinates.len());
+    for (i, (lat, lon)) in coordinates.iter().enumerate() {
+        check_coordinates(i, *lat, *lon)?;
+        data.push(*lat);
+        data.push(*lon);
+    }
+    DataRam::new(data, 2)
+}
+
+/// Opens a CSV with a header and reads the latitude and longitude columns, in degrees, with an optional integer label
+/// column. See `open_csv_points`. Errors if any of the coordinates are out of range.
+pub fn open_csv_geo<P: AsRef<Path>, C: Into<CsvColumn> + Clone>(
+    path: &P,
+    lat_col: C,
+    lon_col: C,
+    label_col: Option<C>,
+) -> PointCloudResult<DefaultLabeledCloud<Haversine>> {
+    let point_cloud = open_csv_points::<Haversine, _, _>(path, &[lat_col, lon_col], label_col)?;
+    for pi in point_cloud.reference_indexes() {
+        let mut coordinates = point_cloud.point(pi)?.dense_iter(2);
+        check_coordinates(pi, coordinates.next().unwrap(), coordinates.next().unwrap())?;
+    }
+    Ok(point_cloud)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabeledCloud, Metric};
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn great_circle_distances() {
+        let cities = geo_points(&[
+            (51.5074, -0.1278),
+            (48.8566, 2.3522),
+            (-33.8688, 151.2093),
+            (40.7128, -74.0060),
+        ])
+        .unwrap();
+        let from_london = cities.distances_to_point_index(0, &[0, 1, 2, 3]).unwrap();
+        assert_eq!(from_london[0], 0.0);
+        assert!((from_london[1] - 343.6).abs() < 1.0);
+        assert!((from_london[2] - 16_994.0).abs() < 10.0);
+        assert!((from_london[3] - 5_570.0).abs() < 10.0);
+        // Across the antimeridian, and between the poles
+        assert!((Haversine::dense(&[0.0, 179.5], &[0.0, -179.5]) - 111.2).abs() < 0.1);
+        let half_way_round = std::f64::consts::PI * Haversine::EARTH_RADIUS_KM;
+        assert!(
+            (Haversine::dense(&[90.0, 0.0], &[-90.0, 0.0]) as f64 - half_way_round).abs() < 0.01
+        );
+        // Points that aren't pairs read a missing coordinate as 0 and ignore any past the second
+        assert_eq!(Haversine::dense(&[0.0], &[0.0, 0.0, 5.0]), 0.0);
+        assert_eq!(
+            Haversine::dense(&[], &[90.0, 0.0]),
+            Haversine::norm(&[90.0])
+        );
+        assert_eq!(
+            Haversine::sparse(&[1, 2], &[179.5, 7.0], &[1], &[-179.5]),
+            Haversine::dense(&[0.0, 179.5], &[0.0, -179.5])
+        );
+        assert!(geo_points(&[(91.0, 0.0)]).is_err());
+        assert!(geo_points(&[(0.0, -181.0)]).is_err());
+
+        let dir = TempDir::new("geo_points").unwrap();
+        let path = dir.path().join("cities.csv");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "name,lat,lon,elevation,label").unwrap();
+        writeln!(file, "london,51.5074,-0.1278,245,1").unwrap();
+        writeln!(file, "paris,48.8566,2.3522,35,2").unwrap();
+        drop(file);
+        let from_csv = open_csv_geo(&path, "lat", "lon", Some("label")).unwrap();
+        assert_eq!(from_csv.len(), 2);
+        assert_eq!(from_csv.label(1).unwrap(), Some(&2));
+        assert_eq!(
+            from_csv.distances_to_point_index(0, &[1]).unwrap(),
+            vec![from_london[1]]
+        );
+        assert!(open_csv_geo(&path, "lat", "elevation", None).is_err());
+    }
+}
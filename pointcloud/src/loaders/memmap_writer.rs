@@ -0,0 +1,231 @@
+//! Writes a point cloud back to the memmap layout the yaml loaders read, so a dataset assembled in ram can be saved in
+//! the fastest format we open.
+
+use std::fs::{self, create_dir_all};
+use std::io::Write;
+
+use super::*;
+use crate::data_sources::replace_file;
+use crate::PointIndex;
+
+/// The name of the file `write_labeled_memmap` writes the labels to.
+pub const MEMMAP_LABELS_FILE: &str = "labels.dat";
+/// The name of the yaml config `write_memmap` and `write_labeled_memmap` write next to the memmaps.
+pub const MEMMAP_CONFIG_FILE: &str = "config.yml";
+
+/// A label that can be packed into a labels memmap, as `dim` floats per point.
+pub trait MemmapLabel {
+    /// The floats per point needed to write all of the labels.
+    fn memmap_dim<'a, I: Iterator<Item = (PointIndex, &'a Self)>>(
+        labels: I,
+    ) -> PointCloudResult<usize>
+    where
+        Self: 'a;
+    /// Appends `dim` floats for the label, or for a missing label.
+    fn write_label(label: Option<&Self>, dim: usize, out: &mut Vec<f32>);
+}
+
+/// Integer labels are written one hot, with a missing label as all zeros, so `labeled_ram_from_yaml` reads them back
+/// as `SmallIntLabels`. There are always at least 2 columns, as a single column is read as a binary label. Errors on
+/// negative labels.
+impl MemmapLabel for i64 {
+    fn memmap_dim<'a, I: Iterator<Item = (PointIndex, &'a Self)>>(
+        labels: I,
+    ) -> PointCloudResult<usize> {
+        let mut dim = 2;
+        for (pi, label) in labels {
+            if *label < 0 {
+                return Err(PointCloudError::data_access(
+                    pi,
+                    format!("the label {} can't be one hot encoded", label),
+                ));
+            }
+            dim = dim.max(*label as usize + 1);
+        }
+        Ok(dim)
+    }
+
+    fn write_label(label: Option<&Self>, dim: usize, out: &mut Vec<f32>) {
+        let start = out.len();
+        out.resize(start + dim, 0.0);
+        if let Some(label) = label {
+            out[start + *label as usize] = 1.0;
+        }
+    }
+}
+
+/// Vector labels are written as they are, so `vec_labeled_ram_from_yaml` reads them back as `VecLabels`. The layout
+/// has no mask, so a missing label is written as NaNs. Errors if the labels aren't all the same length.
+impl MemmapLabel for [f32] {
+    fn memmap_dim<'a, I: Iterator<Item = (PointIndex, &'a Self)>>(
+        labels: I,
+    ) -> PointCloudResult<usize> {
+        let mut dim = None;
+        for (pi, label) in labels {
+            match dim {
+                None => dim = Some(label.len()),
+                Some(d) if d != label.len() => {
+                    return Err(PointCloudError::data_access(
+                        pi,
+                        format!(
+                            "the label has {} entries, the others have {}",
+                            label.len(),
+                            d
+                        ),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(dim.unwrap_or(1))
+    }
+
+    fn write_label(label: Option<&Self>, dim: usize, out: &mut Vec<f32>) {
+        match label {
+            Some(label) => out.extend_from_slice(label),
+            None => out.resize(out.len() + dim, f32::NAN),
+        }
+    }
+}
+
+fn write_floats(path: &Path, floats: impl Iterator<Item = f32>) -> PointCloudResult<()> {
+    replace_file(path, |file| {
+        for x in floats {
+            file.write_all(&x.to_ne_bytes())?;
+        }
+        Ok(())
+    })
+}
+
+fn write_points<D: PointCloud>(point_cloud: &D, dir: &Path) -> PointCloudResult<Vec<PointIndex>> {
+    let indexes = point_cloud.reference_indexes();
+    let dim = point_cloud.dim();
+    replace_file(&dir.join(MEMMAP_POINTS_FILE), |file| {
+        for pi in &indexes {
+            for x in point_cloud.point(*pi)?.dense_iter(dim) {
+                file.write_all(&x.to_ne_bytes())?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(indexes)
+}
+
+/// Packs the points of the sources, one after the other, into `MEMMAP_POINTS_FILE` in `dir`, streaming them so they
+/// never all sit in ram. Returns the path of the points.
+pub(crate) fn pack_points<D: PointCloud>(sources: &[D], dir: &Path) -> PointCloudResult<PathBuf> {
+    create_dir_all(dir)?;
+    let path = dir.join(MEMMAP_POINTS_FILE);
+    replace_file(&path, |file| {
+        for source in sources {
+            let dim = source.dim();
+            for pi in 0..source.len() {
+                for x in source.point(pi)?.dense_iter(dim) {
+                    file.write_all(&x.to_ne_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(path)
+}
+
+/// Writes the points to `MEMMAP_POINTS_FILE` in `dir`, densely as native `f32`s, and a `MEMMAP_CONFIG_FILE` that
+/// `ram_from_yaml` and `backend_from_yaml` open. The points are written in the order of `reference_indexes`, so
+/// they're renumbered from 0. Returns the path of the config. Files already there are replaced by a rename, so a
+/// cloud still mapping the old points keeps them.
+pub fn write_memmap<D: PointCloud, P: AsRef<Path>>(
+    point_cloud: &D,
+    dir: P,
+) -> PointCloudResult<PathBuf> {
+    let dir = dir.as_ref();
+    create_dir_all(dir)?;
+    let indexes = write_points(point_cloud, dir)?;
+    let config = format!(
+        "---\ndata_path: {}\ncount: {}\ndata_dim: {}\n",
+        MEMMAP_POINTS_FILE,
+        indexes.len(),
+        point_cloud.dim()
+    );
+    let config_path = dir.join(MEMMAP_CONFIG_FILE);
+    fs::write(&config_path, config)?;
+    Ok(config_path)
+}
+
+/// Same as `write_memmap`, but also writes the labels to `MEMMAP_LABELS_FILE`, see `MemmapLabel` for the encoding.
+/// Integer labels are read back with `labeled_ram_from_yaml`, and vector labels with `vec_labeled_ram_from_yaml`.
+pub fn write_labeled_memmap<D, P>(point_cloud: &D, dir: P) -> PointCloudResult<PathBuf>
+where
+    D: LabeledCloud,
+    D::Label: MemmapLabel,
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    create_dir_all(dir)?;
+    let indexes = write_points(point_cloud, dir)?;
+    let labels = indexes
+        .iter()
+        .map(|pi| point_cloud.label(*pi))
+        .collect::<PointCloudResult<Vec<Option<&D::Label>>>>()?;
+    let labels_dim = D::Label::memmap_dim(
+        indexes
+            .iter()
+            .zip(&labels)
+            .filter_map(|(pi, l)| l.map(|l| (*pi, l))),
+    )?;
+    let mut packed = Vec::with_capacity(labels_dim * labels.len());
+    for label in &labels {
+        D::Label::write_label(*label, labels_dim, &mut packed);
+    }
+    write_floats(&dir.join(MEMMAP_LABELS_FILE), packed.into_iter())?;
+    let config = format!(
+        "---\ndata_path: {}\nlabels_path: {}\ncount: {}\ndata_dim: {}\nlabels_dim: {}\n",
+        MEMMAP_POINTS_FILE,
+        MEMMAP_LABELS_FILE,
+        indexes.len(),
+        point_cloud.dim(),
+        labels_dim
+    );
+    let config_path = dir.join(MEMMAP_CONFIG_FILE);
+    fs::write(&config_path, config)?;
+    Ok(config_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::L2;
+    use tempdir::TempDir;
+
+    #[test]
+    fn labeled_memmaps_round_trip() {
+        let data = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let labels = SmallIntLabels::new(vec![3, 0, 1, 2], Some(vec![true, true, false, true]));
+        let cloud = SimpleLabeledCloud::new(DataRam::<L2>::new(data.clone(), 2).unwrap(), labels);
+        let dir = TempDir::new("memmap_writer").unwrap();
+        let config = write_labeled_memmap(&cloud, dir.path()).unwrap();
+
+        let read = labeled_ram_from_yaml::<_, L2>(&config).unwrap();
+        assert_eq!(read.len(), 4);
+        assert_eq!(read.dim(), 2);
+        for pi in 0..4 {
+            let a: Vec<f32> = read.point(pi).unwrap().dense_iter(2).collect();
+            assert_eq!(a, data[2 * pi..2 * pi + 2].to_vec());
+            assert_eq!(read.label(pi).unwrap(), cloud.label(pi).unwrap());
+        }
+        let points = ram_from_yaml::<_, L2>(&config).unwrap();
+        assert_eq!(points.len(), 4);
+
+        let vectors = VecLabels::new(vec![0.5, -1.0, 2.0, 0.0, 1.0, 1.0, 3.0, 3.0], 2, None);
+        let cloud = SimpleLabeledCloud::new(DataRam::<L2>::new(data, 2).unwrap(), vectors);
+        let config = write_labeled_memmap(&cloud, dir.path().join("vectors")).unwrap();
+        let read = vec_labeled_ram_from_yaml::<_, L2>(&config).unwrap();
+        assert_eq!(read.label(0).unwrap(), Some(&[0.5, -1.0][..]));
+        assert_eq!(read.label(3).unwrap(), Some(&[3.0, 3.0][..]));
+
+        let negative = SmallIntLabels::new(vec![0, -1], None);
+        let cloud =
+            SimpleLabeledCloud::new(DataRam::<L2>::new(vec![0.0, 1.0], 1).unwrap(), negative);
+        assert!(write_labeled_memmap(&cloud, dir.path().join("negative")).is_err());
+    }
+}
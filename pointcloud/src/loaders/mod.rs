@@ -9,10 +9,16 @@ use crate::label_sources::*;
 use crate::pc_errors::*;
 use crate::Metric;
 
+mod config_loaders;
+pub use config_loaders::*;
 mod yaml_loaders;
 pub use yaml_loaders::*;
 mod csv_loaders;
 pub use csv_loaders::*;
+mod geo_loaders;
+pub use geo_loaders::*;
+mod memmap_writer;
+pub use memmap_writer::*;
 
 /// Opens a set of memmaps of both data and labels
 pub fn open_labeled_memmaps<M: Metric>(
@@ -22,10 +28,10 @@ pub fn open_labeled_memmaps<M: Metric>(
     labels_paths: &[PathBuf],
 ) -> PointCloudResult<HashGluedCloud<SimpleLabeledCloud<DataMemmap<M>, VecLabels>>> {
     if data_paths.len() != labels_paths.len() {
-        panic!(
-            "Mismatch of label and data paths Data: {:?}, Labels: {:?}",
-            data_paths, labels_paths
-        );
+        return Err(PointCloudError::LengthMismatch {
+            expected: data_paths.len(),
+            found: labels_paths.len(),
+        });
     }
     let collection: PointCloudResult<Vec<SimpleLabeledCloud<DataMemmap<M>, VecLabels>>> =
         data_paths
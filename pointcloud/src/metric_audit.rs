@@ -0,0 +1,180 @@
+//! Checks the metric axioms of a point cloud's metric on samples of its points.
+//!
+//! The cover tree prunes with the triangle inequality, so a `Metric` that isn't one, like a squared distance, a
+//! divergence, or a hand written kernel with a bug in it, silently loses neighbors instead of failing. `audit_metric`
+//! samples triples of points and counts how often the distances break symmetry, the triangle inequality, or
+//! non-negativity and identity, so a new metric can be checked on real data before a tree is built with it.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::base_traits::*;
+use crate::distances::Metric;
+use crate::pc_errors::*;
+use crate::PointIndex;
+
+/// The number of checks of each axiom that failed.
+#[derive(Debug, Clone, Default)]
+pub struct MetricAudit {
+    /// The number of sampled triples
+    pub triples: usize,
+    /// Pairs with `d(x,y)` different from `d(y,x)`, out of 3 per triple
+    pub symmetry_violations: usize,
+    /// Cases of `d(x,z) > d(x,y) + d(y,z)`, out of 3 per triple
+    pub triangle_violations: usize,
+    /// Negative distances, out of 6 per triple
+    pub negative_distances: usize,
+    /// Points with a nonzero distance to themselves, out of 3 per triple
+    pub self_distance_violations: usize,
+    /// The largest amount a side exceeded the sum of the other two, relative to that sum
+    pub max_triangle_excess: f32,
+}
+
+impl MetricAudit {
+    /// The fraction of the symmetry checks that failed.
+    pub fn symmetry_rate(&self) -> f32 {
+        rate(self.symmetry_violations, 3 * self.triples)
+    }
+
+    /// The fraction of the triangle inequality checks that failed.
+    pub fn triangle_rate(&self) -> f32 {
+        rate(self.triangle_violations, 3 * self.triples)
+    }
+
+    /// If no check failed.
+    pub fn is_metric(&self) -> bool {
+        self.symmetry_violations == 0
+            && self.triangle_violations == 0
+            && self.negative_distances == 0
+            && self.self_distance_violations == 0
+    }
+}
+
+fn rate(violations: usize, checks: usize) -> f32 {
+    if checks == 0 {
+        0.0
+    } else {
+        violations as f32 / checks as f32
+    }
+}
+
+/// Samples `triples` triples of distinct points and checks the cloud's metric on them. A distance is allowed to be
+/// off by `tolerance` times the size of the quantities it's compared to, to leave room for rounding.
+pub fn audit_metric<D: PointCloud, R: Rng>(
+    point_cloud: &D,
+    triples: usize,
+    tolerance: f32,
+    rng: &mut R,
+) -> PointCloudResult<MetricAudit> {
+    let indexes = point_cloud.reference_indexes();
+    let mut audit = MetricAudit::default();
+    if indexes.len() < 3 {
+        return Ok(audit);
+    }
+    let dist = |i: PointIndex, j: PointIndex| -> PointCloudResult<f32> {
+        D::Metric::dist(point_cloud.point(i)?, point_cloud.point(j)?)
+    };
+    for _ in 0..triples {
+        let triple: Vec<PointIndex> = indexes.choose_multiple(rng, 3).cloned().collect();
+        audit.triples += 1;
+        // d[i][j] is the distance from the ith to the jth point of the triple
+        let mut d = [[0.0f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                d[i][j] = dist(triple[i], triple[j])?;
+            }
+        }
+        for (i, row) in d.iter().enumerate() {
+            if row[i].abs() > tolerance {
+                audit.self_distance_violations += 1;
+            }
+            for j in (i + 1)..3 {
+                let (forward, backward) = (row[j], d[j][i]);
+                if (forward - backward).abs() > tolerance * forward.abs().max(backward.abs()) {
+                    audit.symmetry_violations += 1;
+                }
+                audit.negative_distances += (forward < 0.0) as usize + (backward < 0.0) as usize;
+            }
+        }
+        for (x, y, z) in &[(0, 1, 2), (1, 2, 0), (2, 0, 1)] {
+            let detour = d[*x][*y] + d[*y][*z];
+            let excess = d[*x][*z] - detour;
+            if excess > tolerance * detour {
+                audit.triangle_violations += 1;
+            }
+            if detour > 0.0 && excess / detour > audit.max_triangle_excess {
+                audit.max_triangle_excess = excess / detour;
+            }
+        }
+    }
+    Ok(audit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::DataRam;
+    use crate::distances::L2;
+
+    /// Squared L2, which breaks the triangle inequality.
+    #[derive(Debug, Clone)]
+    struct SquaredL2 {}
+
+    impl Metric for SquaredL2 {
+        fn dense(x: &[f32], y: &[f32]) -> f32 {
+            x.iter().zip(y).map(|(a, b)| (a - b) * (a - b)).sum()
+        }
+        fn sparse(_: &[u32], _: &[f32], _: &[u32], _: &[f32]) -> f32 {
+            unimplemented!()
+        }
+        fn norm(x: &[f32]) -> f32 {
+            x.iter().map(|a| a * a).sum()
+        }
+    }
+
+    /// Weighs the first point's coordinates more, so it isn't symmetric.
+    #[derive(Debug, Clone)]
+    struct Lopsided {}
+
+    impl Metric for Lopsided {
+        fn dense(x: &[f32], y: &[f32]) -> f32 {
+            x.iter()
+                .zip(y)
+                .map(|(a, b)| (a - b).abs() * (1.0 + a.abs()))
+                .sum()
+        }
+        fn sparse(_: &[u32], _: &[f32], _: &[u32], _: &[f32]) -> f32 {
+            unimplemented!()
+        }
+        fn norm(x: &[f32]) -> f32 {
+            x.iter().map(|a| a.abs()).sum()
+        }
+    }
+
+    fn data(count: usize, dim: usize) -> Vec<f32> {
+        (0..count * dim).map(|_| rand::random::<f32>()).collect()
+    }
+
+    #[test]
+    fn l2_is_a_metric() {
+        let cloud = DataRam::<L2>::new(data(100, 5), 5).unwrap();
+        let audit = audit_metric(&cloud, 500, 1e-5, &mut rand::thread_rng()).unwrap();
+        assert_eq!(audit.triples, 500);
+        assert!(audit.is_metric(), "{:?}", audit);
+        assert_eq!(audit.triangle_rate(), 0.0);
+    }
+
+    #[test]
+    fn violations_are_counted() {
+        let cloud = DataRam::<SquaredL2>::new(data(100, 5), 5).unwrap();
+        let audit = audit_metric(&cloud, 500, 1e-5, &mut rand::thread_rng()).unwrap();
+        assert!(audit.triangle_violations > 0);
+        assert!(audit.max_triangle_excess > 0.0);
+        assert_eq!(audit.symmetry_violations, 0);
+
+        let cloud = DataRam::<Lopsided>::new(data(100, 5), 5).unwrap();
+        let audit = audit_metric(&cloud, 100, 1e-5, &mut rand::thread_rng()).unwrap();
+        assert!(audit.symmetry_rate() > 0.9);
+        assert!(!audit.is_metric());
+    }
+}
@@ -0,0 +1,154 @@
+//! Parallel iterators over the points and labels of a cloud, see `PointCloud::par_points` and
+//! `LabeledCloud::par_labels`.
+
+use rayon::iter::plumbing::{Consumer, ProducerCallback, UnindexedConsumer};
+use rayon::prelude::*;
+
+use crate::base_traits::*;
+use crate::pc_errors::PointCloudResult;
+use crate::{PointIndex, PointRef};
+
+/// Every point of a cloud with its index, in the order of `reference_indexes`, made by `PointCloud::par_points`.
+#[derive(Debug)]
+pub struct ParPoints<'a, D: ?Sized> {
+    point_cloud: &'a D,
+    indexes: Vec<PointIndex>,
+}
+
+impl<'a, D: PointCloud + ?Sized> ParPoints<'a, D> {
+    pub(crate) fn new(point_cloud: &'a D) -> Self {
+        ParPoints {
+            point_cloud,
+            indexes: point_cloud.reference_indexes(),
+        }
+    }
+}
+
+impl<'a, D: PointCloud + ?Sized> ParallelIterator for ParPoints<'a, D> {
+    type Item = PointCloudResult<(PointIndex, PointRef<'a>)>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.drive(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.indexes.len())
+    }
+}
+
+impl<'a, D: PointCloud + ?Sized> IndexedParallelIterator for ParPoints<'a, D> {
+    fn len(&self) -> usize {
+        self.indexes.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let point_cloud = self.point_cloud;
+        self.indexes
+            .into_par_iter()
+            .map(move |pi| point_cloud.point(pi).map(|p| (pi, p)))
+            .drive(consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        let point_cloud = self.point_cloud;
+        self.indexes
+            .into_par_iter()
+            .map(move |pi| point_cloud.point(pi).map(|p| (pi, p)))
+            .with_producer(callback)
+    }
+}
+
+/// Every label of a cloud with its index, in the order of `reference_indexes`, made by `LabeledCloud::par_labels`.
+#[derive(Debug)]
+pub struct ParLabels<'a, D: ?Sized> {
+    point_cloud: &'a D,
+    indexes: Vec<PointIndex>,
+}
+
+impl<'a, D: LabeledCloud + ?Sized> ParLabels<'a, D> {
+    pub(crate) fn new(point_cloud: &'a D) -> Self {
+        ParLabels {
+            point_cloud,
+            indexes: point_cloud.reference_indexes(),
+        }
+    }
+}
+
+impl<'a, D> ParallelIterator for ParLabels<'a, D>
+where
+    D: LabeledCloud + ?Sized,
+    D::Label: Sync,
+{
+    type Item = PointCloudResult<(PointIndex, Option<&'a D::Label>)>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.drive(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.indexes.len())
+    }
+}
+
+impl<'a, D> IndexedParallelIterator for ParLabels<'a, D>
+where
+    D: LabeledCloud + ?Sized,
+    D::Label: Sync,
+{
+    fn len(&self) -> usize {
+        self.indexes.len()
+    }
+
+    fn drive<C: Consumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let point_cloud = self.point_cloud;
+        self.indexes
+            .into_par_iter()
+            .map(move |pi| point_cloud.label(pi).map(|l| (pi, l)))
+            .drive(consumer)
+    }
+
+    fn with_producer<CB: ProducerCallback<Self::Item>>(self, callback: CB) -> CB::Output {
+        let point_cloud = self.point_cloud;
+        self.indexes
+            .into_par_iter()
+            .map(move |pi| point_cloud.label(pi).map(|l| (pi, l)))
+            .with_producer(callback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn parallel_iterators_match_indexing() {
+        let data: Vec<f32> = (0..2000).map(|i| i as f32).collect();
+        let labels: Vec<i64> = (0..1000).map(|i| i % 3).collect();
+        let cloud = DefaultLabeledCloud::<L2>::new_simple(data, 2, labels);
+
+        let indexes: Vec<PointIndex> = cloud.par_indexes().collect();
+        assert_eq!(indexes, cloud.reference_indexes());
+        let sums = cloud
+            .par_points()
+            .map(|p| p.map(|(pi, p)| (pi, p.dense_iter(2).sum::<f32>())))
+            .collect::<PointCloudResult<Vec<(PointIndex, f32)>>>()
+            .unwrap();
+        assert_eq!(sums.len(), 1000);
+        for (pi, sum) in sums {
+            assert_eq!(sum, (4 * pi + 1) as f32);
+        }
+        let labels = cloud
+            .par_labels()
+            .collect::<PointCloudResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(labels[5], (5, Some(&2)));
+        assert_eq!(cloud.par_points().len(), 1000);
+    }
+}
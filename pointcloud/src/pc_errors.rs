@@ -41,6 +41,8 @@ pub enum PointCloudError {
     MetricError,
     /// You passes unsorted indexes into a function that required sorted indexes
     NotSorted,
+    /// The values of a vector aren't contiguous in memory, so it can't be borrowed as a point
+    NotContiguous,
     /// Most common error, the given point name isn't present in the training data
     UnknownName,
     /// IO error when opening files
@@ -52,6 +54,28 @@ pub enum PointCloudError {
         /// Exact nesting error
         message: &'static str,
     },
+    /// Two sources that need to be the same length are not
+    LengthMismatch {
+        /// The length we needed
+        expected: usize,
+        /// The length we got
+        found: usize,
+    },
+    /// A dataset config is missing a key, has a bad value, or leads to files we can't open
+    ConfigError {
+        /// The config file
+        config_path: String,
+        /// The key that's missing or wrong, none if the config itself couldn't be read
+        key: Option<String>,
+        /// The data or label file the key led to, if it got that far
+        file: Option<String>,
+        /// What went wrong
+        reason: String,
+    },
+    /// A glued cloud doesn't have a segment at this position
+    SegmentNotFound(usize),
+    /// The user metric with this name already has a distance or weights, they can't be replaced
+    MetricAlreadyRegistered(&'static str),
 }
 
 impl fmt::Display for PointCloudError {
@@ -74,6 +98,35 @@ impl fmt::Display for PointCloudError {
                 "The metric failed, you probably mixed sparse and dense data"
             ),
             PointCloudError::NotSorted => write!(f, "Passed data that wasn't sorted"),
+            PointCloudError::NotContiguous => {
+                write!(f, "Passed a vector that isn't contiguous in memory")
+            }
+            PointCloudError::LengthMismatch { expected, found } => write!(
+                f,
+                "Expected a source of length {}, but it has length {}",
+                expected, found
+            ),
+            PointCloudError::ConfigError {
+                ref config_path,
+                ref key,
+                ref file,
+                ref reason,
+            } => {
+                write!(f, "config {}", config_path)?;
+                if let Some(key) = key {
+                    write!(f, ", key '{}'", key)?;
+                }
+                if let Some(file) = file {
+                    write!(f, ", file {}", file)?;
+                }
+                write!(f, ": {}", reason)
+            }
+            PointCloudError::SegmentNotFound(segment) => {
+                write!(f, "There is no segment {} in the glued cloud", segment)
+            }
+            PointCloudError::MetricAlreadyRegistered(name) => {
+                write!(f, "The user metric {} is already registered", name)
+            }
         }
     }
 }
@@ -98,6 +151,11 @@ impl Error for PointCloudError {
                 "The metric failed, you probably mixed sparse and dense data"
             }
             PointCloudError::NotSorted => "Passed data that wasn't sorted",
+            PointCloudError::NotContiguous => "Passed a vector that isn't contiguous in memory",
+            PointCloudError::LengthMismatch { .. } => "The sources are of different lengths",
+            PointCloudError::ConfigError { .. } => "A dataset config couldn't be used",
+            PointCloudError::SegmentNotFound(..) => "There is no segment at that position",
+            PointCloudError::MetricAlreadyRegistered(..) => "The user metric is already registered",
         }
     }
 
@@ -110,6 +168,11 @@ impl Error for PointCloudError {
             PointCloudError::NodeNestingError { .. } => None,
             PointCloudError::MetricError { .. } => None,
             PointCloudError::NotSorted { .. } => None,
+            PointCloudError::NotContiguous => None,
+            PointCloudError::LengthMismatch { .. } => None,
+            PointCloudError::ConfigError { .. } => None,
+            PointCloudError::SegmentNotFound(..) => None,
+            PointCloudError::MetricAlreadyRegistered(..) => None,
         }
     }
 }
@@ -165,6 +228,13 @@ pub enum ParsingError {
         /// The column name that was messed up
         key: String,
     },
+    /// A column asked for isn't in the file
+    MissingColumnError {
+        /// The file the column was looked for in
+        file_name: String,
+        /// The missing column
+        column: String,
+    },
     /// Something else happened parsing a string
     RegularParsingError(&'static str),
 }
@@ -182,6 +252,7 @@ impl Error for ParsingError {
             ParsingError::MalformedYamlError { .. } => "there is a error reading a yaml entry",
             ParsingError::MissingYamlError { .. } => "not all message fields set",
             ParsingError::CSVReadError { .. } => "issue reading a CSV entry",
+            ParsingError::MissingColumnError { .. } => "a column is missing from the file",
             ParsingError::RegularParsingError(..) => "Error parsing a string",
         }
     }
@@ -191,6 +262,7 @@ impl Error for ParsingError {
             ParsingError::MalformedYamlError { .. } => None,
             ParsingError::MissingYamlError { .. } => None,
             ParsingError::CSVReadError { .. } => None,
+            ParsingError::MissingColumnError { .. } => None,
             ParsingError::RegularParsingError(..) => None,
         }
     }
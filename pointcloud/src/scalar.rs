@@ -0,0 +1,48 @@
+//! The element types points can be stored in.
+//!
+//! Only the dense distances are generic over the element type so far. `PointCloud`, `Point`, `PointRef`, the sparse
+//! and binary kernels, and the trees built on them still work in `f32`. Data that needs more precision, or that is
+//! quantized to save memory, can be kept in its own type with `ScalarRam` and compared with `Metric::dense_scalar`,
+//! which accumulates in `f64` whatever the element type, but it can't be indexed without rounding it to `f32` first.
+
+use std::fmt::Debug;
+
+/// An element type of a dense point.
+pub trait Scalar: 'static + Copy + Send + Sync + Debug + PartialOrd {
+    /// The name of the type, for error messages and metadata
+    const NAME: &'static str;
+    /// Widens the value, this is exact for every supported type
+    fn to_f64(self) -> f64;
+    /// The value as a `f32`, this rounds `f64` values
+    fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+}
+
+impl Scalar for f32 {
+    const NAME: &'static str = "f32";
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    #[inline]
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Scalar for f64 {
+    const NAME: &'static str = "f64";
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Scalar for i8 {
+    const NAME: &'static str = "i8";
+    #[inline]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
@@ -4,6 +4,7 @@ use hashbrown::HashMap;
 use std::default::Default;
 use std::iter::Iterator;
 
+use rand::prelude::*;
 use smallvec::SmallVec;
 
 use crate::base_traits::*;
@@ -62,6 +63,74 @@ impl Summary for CategorySummary {
     }
 }
 
+impl CategorySummary {
+    /// The fraction of the points with each label, most common first. Empty if there are no points.
+    pub fn posterior(&self) -> Vec<(i64, f32)> {
+        let total = self.count() as f32;
+        let mut posterior: Vec<(i64, f32)> = self
+            .items
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(label, count)| (*label, *count as f32 / total))
+            .collect();
+        posterior.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        posterior
+    }
+}
+
+/// A summary of sets of categories, like tags. Counts how many of the sets contain each category.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct MultiCatSummary {
+    /// The number of sets each category appears in
+    pub items: HashMap<i64, usize>,
+    /// The number of sets, including empty ones
+    pub count: usize,
+}
+
+impl MultiCatSummary {
+    /// The fraction of the sets that contain each category, most common first. The fractions don't add up to 1 as a
+    /// set can have several categories, or none.
+    pub fn frequencies(&self) -> Vec<(i64, f32)> {
+        let total = self.count as f32;
+        let mut frequencies: Vec<(i64, f32)> = self
+            .items
+            .iter()
+            .map(|(label, count)| (*label, *count as f32 / total))
+            .collect();
+        frequencies.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        frequencies
+    }
+}
+
+impl Summary for MultiCatSummary {
+    type Label = [i64];
+
+    fn add(&mut self, val: &[i64]) {
+        for category in val {
+            *self.items.entry(*category).or_insert(0) += 1;
+        }
+        self.count += 1;
+    }
+    fn combine(&mut self, other: &MultiCatSummary) {
+        for (category, count) in other.items.iter() {
+            *self.items.entry(*category).or_insert(0) += count;
+        }
+        self.count += other.count;
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
 /// Summary of vectors
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct VecSummary {
@@ -94,18 +163,186 @@ impl Summary for VecSummary {
             }
         } else {
             self.moment1.extend(val);
-            self.moment2.extend(val.iter().map(|x| x * x))
+            self.moment2.extend(val.iter().map(|x| x * x));
+            self.count = 1;
         }
     }
     fn combine(&mut self, other: &VecSummary) {
-        self.moment1
+        if other.moment1.is_empty() {
+            return;
+        }
+        if self.moment1.is_empty() {
+            self.moment1.extend(&other.moment1);
+            self.moment2.extend(&other.moment2);
+        } else if self.moment1.len() == other.moment1.len() {
+            self.moment1
+                .iter_mut()
+                .zip(&other.moment1)
+                .for_each(|(x, y)| *x += y);
+            self.moment2
+                .iter_mut()
+                .zip(&other.moment2)
+                .for_each(|(x, y)| *x += y);
+        } else {
+            panic!(
+                "Combining a vec of len {:?} and of len {:?}",
+                self.moment1.len(),
+                other.moment1.len()
+            );
+        }
+        self.count += other.count;
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Summary of vectors that keeps the full covariance matrix, where `VecSummary` only has the per-dimension moments.
+/// The mean and comoments are updated with Welford's method, so they stay accurate for labels far from the origin.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct CovSummary {
+    /// The mean of the labels
+    pub mean: Vec<f64>,
+    /// The sum of the outer products of the labels minus the mean, a `dim` by `dim` row major matrix
+    pub comoment: Vec<f64>,
+    /// The count of the number of labels included
+    pub count: usize,
+}
+
+impl CovSummary {
+    /// The dimension of the labels, 0 if the summary is empty.
+    pub fn dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    /// The sample covariance matrix, row major. `None` until there are 2 labels.
+    pub fn covariance(&self) -> Option<Vec<f64>> {
+        if self.count < 2 {
+            return None;
+        }
+        let n = (self.count - 1) as f64;
+        Some(self.comoment.iter().map(|c| c / n).collect())
+    }
+
+    /// Whitens a label: subtracts the mean and multiplies by the inverse of the Cholesky factor of the covariance, so
+    /// the residuals of this node's labels come out with the identity covariance. The `ridge` is added to the diagonal
+    /// first, which keeps nodes with few labels or degenerate directions invertible. `None` if the covariance is
+    /// not positive definite even then.
+    pub fn whiten(&self, val: &[f32], ridge: f64) -> Option<Vec<f64>> {
+        assert_eq!(self.dim(), val.len(), "Whitening a vec of the wrong dim");
+        let dim = self.dim();
+        let mut factor = self.covariance()?;
+        for i in 0..dim {
+            factor[i * dim + i] += ridge;
+        }
+        // In place Cholesky, the lower triangle becomes L with LL^T = covariance
+        for j in 0..dim {
+            let mut diag = factor[j * dim + j];
+            for k in 0..j {
+                diag -= factor[j * dim + k] * factor[j * dim + k];
+            }
+            // A pivot that is all rounding error means the covariance is singular
+            if diag <= 1e-10 * factor[j * dim + j] || !diag.is_finite() {
+                return None;
+            }
+            let diag = diag.sqrt();
+            factor[j * dim + j] = diag;
+            for i in (j + 1)..dim {
+                let mut x = factor[i * dim + j];
+                for k in 0..j {
+                    x -= factor[i * dim + k] * factor[j * dim + k];
+                }
+                factor[i * dim + j] = x / diag;
+            }
+        }
+        // Forward substitution for L y = val - mean
+        let mut whitened: Vec<f64> = Vec::with_capacity(dim);
+        for i in 0..dim {
+            let mut y = val[i] as f64 - self.mean[i];
+            for (k, w) in whitened.iter().enumerate() {
+                y -= factor[i * dim + k] * w;
+            }
+            whitened.push(y / factor[i * dim + i]);
+        }
+        Some(whitened)
+    }
+
+    /// The Mahalanobis distance of the label from the mean, the norm of the whitened label. See `whiten`.
+    pub fn mahalanobis(&self, val: &[f32], ridge: f64) -> Option<f64> {
+        self.whiten(val, ridge)
+            .map(|w| w.iter().map(|x| x * x).sum::<f64>().sqrt())
+    }
+}
+
+impl Summary for CovSummary {
+    type Label = [f32];
+
+    fn add(&mut self, val: &[f32]) {
+        if self.mean.is_empty() {
+            self.mean = vec![0.0; val.len()];
+            self.comoment = vec![0.0; val.len() * val.len()];
+        } else if self.mean.len() != val.len() {
+            panic!(
+                "Combining a vec of len {:?} and of len {:?}",
+                self.mean.len(),
+                val.len()
+            );
+        }
+        self.count += 1;
+        let n = self.count as f64;
+        let delta: Vec<f64> = val
+            .iter()
+            .zip(&self.mean)
+            .map(|(x, m)| *x as f64 - m)
+            .collect();
+        self.mean
             .iter_mut()
-            .zip(&other.moment1)
-            .for_each(|(x, y)| *x += y);
-        self.moment2
+            .zip(&delta)
+            .for_each(|(m, d)| *m += d / n);
+        let weight = (n - 1.0) / n;
+        let dim = delta.len();
+        for i in 0..dim {
+            for j in 0..dim {
+                self.comoment[i * dim + j] += delta[i] * delta[j] * weight;
+            }
+        }
+    }
+    fn combine(&mut self, other: &CovSummary) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        if self.mean.len() != other.mean.len() {
+            panic!(
+                "Combining a vec of len {:?} and of len {:?}",
+                self.mean.len(),
+                other.mean.len()
+            );
+        }
+        let (n_a, n_b) = (self.count as f64, other.count as f64);
+        let n = n_a + n_b;
+        let delta: Vec<f64> = other
+            .mean
+            .iter()
+            .zip(&self.mean)
+            .map(|(b, a)| b - a)
+            .collect();
+        self.mean
             .iter_mut()
-            .zip(&other.moment2)
-            .for_each(|(x, y)| *x += y);
+            .zip(&delta)
+            .for_each(|(m, d)| *m += d * n_b / n);
+        let weight = n_a * n_b / n;
+        let dim = delta.len();
+        for i in 0..dim {
+            for j in 0..dim {
+                self.comoment[i * dim + j] +=
+                    other.comoment[i * dim + j] + delta[i] * delta[j] * weight;
+            }
+        }
         self.count += other.count;
     }
 
@@ -174,6 +411,314 @@ impl Summary for IntSummary {
     }
 }
 
+/// The default compression of a `QuantileSummary`.
+pub const DEFAULT_QUANTILE_COMPRESSION: f64 = 100.0;
+
+/// A t-digest of scalar labels, for estimating their quantiles. The values are kept as weighted centroids, small near
+/// the tails and large in the middle, so the extreme quantiles stay accurate while the digest holds a few times
+/// `compression` centroids no matter how many values it summarizes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuantileSummary {
+    /// Bigger keeps more centroids and gives more accurate quantiles
+    pub compression: f64,
+    /// The merged centroids, as `(mean, weight)` sorted by mean
+    centroids: Vec<(f64, f64)>,
+    /// Values and centroids that haven't been merged in yet
+    unmerged: Vec<(f64, f64)>,
+    count: usize,
+    min: f32,
+    max: f32,
+}
+
+impl Default for QuantileSummary {
+    fn default() -> Self {
+        QuantileSummary::with_compression(DEFAULT_QUANTILE_COMPRESSION)
+    }
+}
+
+impl QuantileSummary {
+    /// An empty digest with the given compression.
+    pub fn with_compression(compression: f64) -> Self {
+        QuantileSummary {
+            compression,
+            centroids: Vec::new(),
+            unmerged: Vec::new(),
+            count: 0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Merges the centroids, sorted or not, into as few as the compression allows.
+    fn compress(compression: f64, mut centroids: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+        centroids.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let total: f64 = centroids.iter().map(|c| c.1).sum();
+        let mut merged = Vec::with_capacity(compression as usize);
+        let mut centroids = centroids.into_iter();
+        let mut current = match centroids.next() {
+            Some(c) => c,
+            None => return merged,
+        };
+        let mut cumulative = 0.0;
+        for (mean, weight) in centroids {
+            let combined = current.1 + weight;
+            let q = (cumulative + combined / 2.0) / total;
+            let limit = 4.0 * total * q * (1.0 - q) / compression;
+            if combined <= limit.max(1.0) {
+                current.0 += (mean - current.0) * weight / combined;
+                current.1 = combined;
+            } else {
+                cumulative += current.1;
+                merged.push(current);
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        merged
+    }
+
+    fn flush(&mut self) {
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.unmerged);
+        self.centroids = QuantileSummary::compress(self.compression, all);
+    }
+
+    fn merged_centroids(&self) -> Vec<(f64, f64)> {
+        if self.unmerged.is_empty() {
+            self.centroids.clone()
+        } else {
+            let mut all = self.centroids.clone();
+            all.extend_from_slice(&self.unmerged);
+            QuantileSummary::compress(self.compression, all)
+        }
+    }
+
+    /// The number of centroids after merging.
+    pub fn centroid_count(&self) -> usize {
+        self.merged_centroids().len()
+    }
+
+    /// The smallest value, if there are any.
+    pub fn min(&self) -> Option<f32> {
+        if self.count > 0 {
+            Some(self.min)
+        } else {
+            None
+        }
+    }
+
+    /// The largest value, if there are any.
+    pub fn max(&self) -> Option<f32> {
+        if self.count > 0 {
+            Some(self.max)
+        } else {
+            None
+        }
+    }
+
+    /// Estimates the `q`th quantile, for `q` between 0 and 1. None if the digest is empty.
+    pub fn quantile(&self, q: f64) -> Option<f32> {
+        if self.count == 0 {
+            return None;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let centroids = self.merged_centroids();
+        let total: f64 = centroids.iter().map(|c| c.1).sum();
+        let target = q * total;
+        let (min, max) = (self.min as f64, self.max as f64);
+        // Each centroid's mean sits at the middle of its weight, interpolate between those
+        let mut previous = (0.0, min);
+        let mut cumulative = 0.0;
+        for (mean, weight) in &centroids {
+            let position = cumulative + weight / 2.0;
+            if target < position {
+                let (previous_position, previous_mean) = previous;
+                let t = (target - previous_position) / (position - previous_position);
+                return Some((previous_mean + t * (mean - previous_mean)) as f32);
+            }
+            previous = (position, *mean);
+            cumulative += weight;
+        }
+        let (previous_position, previous_mean) = previous;
+        let t = if total > previous_position {
+            (target - previous_position) / (total - previous_position)
+        } else {
+            1.0
+        };
+        Some((previous_mean + t * (max - previous_mean)) as f32)
+    }
+
+    /// Estimates the median. None if the digest is empty.
+    pub fn median(&self) -> Option<f32> {
+        self.quantile(0.5)
+    }
+}
+
+impl Summary for QuantileSummary {
+    type Label = f32;
+
+    fn add(&mut self, val: &f32) {
+        self.unmerged.push((*val as f64, 1.0));
+        self.count += 1;
+        self.min = self.min.min(*val);
+        self.max = self.max.max(*val);
+        if self.unmerged.len() as f64 > 4.0 * self.compression {
+            self.flush();
+        }
+    }
+
+    fn combine(&mut self, other: &QuantileSummary) {
+        self.unmerged.extend_from_slice(&other.centroids);
+        self.unmerged.extend_from_slice(&other.unmerged);
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.flush();
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// The earliest and latest of a set of timestamps, in whatever unit they're given.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimeRangeSummary {
+    /// The earliest timestamp
+    pub min: i64,
+    /// The latest timestamp
+    pub max: i64,
+    /// The count of the number of timestamps included
+    pub count: usize,
+}
+
+impl Default for TimeRangeSummary {
+    fn default() -> Self {
+        TimeRangeSummary {
+            min: i64::MAX,
+            max: i64::MIN,
+            count: 0,
+        }
+    }
+}
+
+impl TimeRangeSummary {
+    /// The `(min, max)` of the timestamps, `None` if there are none.
+    pub fn range(&self) -> Option<(i64, i64)> {
+        if self.count > 0 {
+            Some((self.min, self.max))
+        } else {
+            None
+        }
+    }
+}
+
+impl Summary for TimeRangeSummary {
+    type Label = i64;
+
+    fn add(&mut self, val: &i64) {
+        self.min = self.min.min(*val);
+        self.max = self.max.max(*val);
+        self.count += 1;
+    }
+    fn combine(&mut self, other: &TimeRangeSummary) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// The default number of samples a `ReservoirSummary` keeps.
+pub const DEFAULT_RESERVOIR_SIZE: usize = 16;
+
+/// A uniform random sample of up to `size` labels, for showing a few representative examples of a node without keeping
+/// all of its indexes around. Use `ReservoirLabels` to sample the point indexes along with the labels.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReservoirSummary<T> {
+    /// The most samples this keeps
+    pub size: usize,
+    samples: Vec<T>,
+    count: usize,
+}
+
+impl<T> Default for ReservoirSummary<T> {
+    fn default() -> Self {
+        ReservoirSummary::with_size(DEFAULT_RESERVOIR_SIZE)
+    }
+}
+
+impl<T> ReservoirSummary<T> {
+    /// An empty reservoir that keeps up to `size` samples.
+    pub fn with_size(size: usize) -> Self {
+        ReservoirSummary {
+            size,
+            samples: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// The sampled labels, in no particular order.
+    pub fn samples(&self) -> &[T] {
+        &self.samples
+    }
+}
+
+impl<T: Clone + Debug + Send + Sync + 'static> Summary for ReservoirSummary<T> {
+    type Label = T;
+
+    fn add(&mut self, val: &T) {
+        self.count += 1;
+        if self.samples.len() < self.size {
+            self.samples.push(val.clone());
+        } else {
+            let i = thread_rng().gen_range(0, self.count);
+            if i < self.size {
+                self.samples[i] = val.clone();
+            }
+        }
+    }
+    fn combine(&mut self, other: &ReservoirSummary<T>) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            let size = self.size;
+            *self = other.clone();
+            self.size = size;
+            self.samples.truncate(size);
+            return;
+        }
+        // Draws without replacement from the union, picking a side in proportion to how many labels it still stands
+        // for. This keeps the sample uniform over everything both summaries have seen.
+        let mut rng = thread_rng();
+        let mut ours = std::mem::take(&mut self.samples);
+        let mut theirs = other.samples.clone();
+        let mut ours_left = self.count;
+        let mut theirs_left = other.count;
+        while self.samples.len() < self.size && !(ours.is_empty() && theirs.is_empty()) {
+            let from_ours = rng.gen_range(0, ours_left + theirs_left) < ours_left;
+            let source = if (from_ours && !ours.is_empty()) || theirs.is_empty() {
+                ours_left = ours_left.saturating_sub(1);
+                &mut ours
+            } else {
+                theirs_left = theirs_left.saturating_sub(1);
+                &mut theirs
+            };
+            let i = rng.gen_range(0, source.len());
+            self.samples.push(source.swap_remove(i));
+        }
+        self.count += other.count;
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
 /// A summary for a small number of categories.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StringSummary {
@@ -205,3 +750,446 @@ impl Summary for StringSummary {
         self.items.values().sum()
     }
 }
+
+/// The separator between the levels of a taxonomy label, `"animals/mammals/dogs"`.
+pub const TAXONOMY_SEPARATOR: char = '/';
+
+/// The label and each of its ancestors in the taxonomy, most general first. `"animals/mammals/dogs"` gives
+/// `"animals"`, `"animals/mammals"` and `"animals/mammals/dogs"`.
+pub fn taxonomy_prefixes(label: &str) -> impl Iterator<Item = &str> {
+    let label = label.trim_matches(TAXONOMY_SEPARATOR);
+    label
+        .match_indices(TAXONOMY_SEPARATOR)
+        .map(move |(i, _)| &label[..i])
+        .chain(std::iter::once(label).filter(|l| !l.is_empty()))
+}
+
+/// If the label is the taxonomy node `path` or falls somewhere under it.
+pub fn taxonomy_contains(path: &str, label: &str) -> bool {
+    let path = path.trim_matches(TAXONOMY_SEPARATOR);
+    let label = label.trim_matches(TAXONOMY_SEPARATOR);
+    path.is_empty()
+        || (label.starts_with(path)
+            && (label.len() == path.len() || label[path.len()..].starts_with(TAXONOMY_SEPARATOR)))
+}
+
+/// Counts of labels that are paths in a taxonomy, at every level. Each label counts towards itself and all of its
+/// ancestors, so the count of `"animals"` includes every `"animals/..."` label.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaxonomySummary {
+    /// The number of labels at or under each taxonomy node
+    pub items: HashMap<String, usize>,
+    /// The number of labels added
+    pub count: usize,
+}
+
+impl Default for TaxonomySummary {
+    fn default() -> Self {
+        TaxonomySummary {
+            items: HashMap::new(),
+            count: 0,
+        }
+    }
+}
+
+impl TaxonomySummary {
+    /// The number of labels at or under the taxonomy node. The empty path is the root and counts everything.
+    pub fn count_under(&self, path: &str) -> usize {
+        let path = path.trim_matches(TAXONOMY_SEPARATOR);
+        if path.is_empty() {
+            self.count
+        } else {
+            self.items.get(path).cloned().unwrap_or(0)
+        }
+    }
+
+    /// The counts of the nodes directly under the given taxonomy node, largest first.
+    pub fn children(&self, path: &str) -> Vec<(&str, usize)> {
+        let path = path.trim_matches(TAXONOMY_SEPARATOR);
+        let depth = taxonomy_prefixes(path).count();
+        self.nodes_where(|node| {
+            taxonomy_contains(path, node) && taxonomy_prefixes(node).count() == depth + 1
+        })
+    }
+
+    /// The counts of the nodes at the given depth of the taxonomy, largest first. The top level is depth 1.
+    pub fn level(&self, depth: usize) -> Vec<(&str, usize)> {
+        self.nodes_where(|node| taxonomy_prefixes(node).count() == depth)
+    }
+
+    fn nodes_where<F: Fn(&str) -> bool>(&self, f: F) -> Vec<(&str, usize)> {
+        let mut nodes: Vec<(&str, usize)> = self
+            .items
+            .iter()
+            .filter(|(node, _)| f(node))
+            .map(|(node, count)| (node.as_str(), *count))
+            .collect();
+        nodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        nodes
+    }
+}
+
+impl Summary for TaxonomySummary {
+    type Label = str;
+    fn add(&mut self, val: &str) {
+        for prefix in taxonomy_prefixes(val) {
+            *self.items.entry(prefix.to_string()).or_insert(0) += 1;
+        }
+        self.count += 1;
+    }
+
+    fn combine(&mut self, other: &TaxonomySummary) {
+        for (node, count) in other.items.iter() {
+            *self.items.entry(node.to_string()).or_insert(0) += count;
+        }
+        self.count += other.count;
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    /// Splits `0..len` into three random, possibly empty, pieces and checks that combining their summaries in any
+    /// order and grouping gives the summary of the whole range, and that the default summary is an identity.
+    fn check_combine<S, A, E>(len: usize, add: A, eq: E)
+    where
+        S: Summary + Clone,
+        A: Fn(&mut S, usize),
+        E: Fn(&S, &S) -> bool,
+    {
+        let mut rng = thread_rng();
+        let summarize = |range: Range<usize>| {
+            let mut summary = S::default();
+            range.for_each(|i| add(&mut summary, i));
+            summary
+        };
+        let combine = |x: &S, y: &S| {
+            let mut z = x.clone();
+            z.combine(y);
+            z
+        };
+        let whole = summarize(0..len);
+        for _ in 0..100 {
+            let a_end = rng.gen_range(0, len + 1);
+            let b_end = rng.gen_range(a_end, len + 1);
+            let a = summarize(0..a_end);
+            let b = summarize(a_end..b_end);
+            let c = summarize(b_end..len);
+            let left = combine(&combine(&a, &b), &c);
+            let right = combine(&a, &combine(&b, &c));
+            let swapped = combine(&combine(&c, &a), &b);
+            assert!(eq(&left, &whole), "{:?} != {:?}", left, whole);
+            assert!(eq(&right, &whole), "{:?} != {:?}", right, whole);
+            assert!(eq(&swapped, &whole), "{:?} != {:?}", swapped, whole);
+            assert!(eq(&combine(&S::default(), &a), &a));
+            assert!(eq(&combine(&a, &S::default()), &a));
+            assert_eq!(left.count(), len);
+        }
+    }
+
+    fn close(x: f64, y: f64) -> bool {
+        (x - y).abs() <= 1e-4 * (1.0 + x.abs().max(y.abs()))
+    }
+
+    #[test]
+    fn quantile_summary_estimates_quantiles() {
+        let mut rng = thread_rng();
+        let mut values: Vec<f32> = (0..10000).map(|_| rng.gen::<f32>()).collect();
+        let mut left = QuantileSummary::default();
+        let mut right = QuantileSummary::default();
+        assert_eq!(left.median(), None);
+        for (i, v) in values.iter().enumerate() {
+            if i % 3 == 0 {
+                left.add(v);
+            } else {
+                right.add(v);
+            }
+        }
+        left.combine(&right);
+        assert_eq!(left.count(), 10000);
+        assert!(left.centroid_count() < 1000);
+
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for q in &[0.01, 0.25, 0.5, 0.9, 0.99] {
+            let exact = values[(q * 9999.0) as usize];
+            let estimate = left.quantile(*q).unwrap();
+            assert!(
+                (exact - estimate).abs() < 0.01,
+                "q {}: {} vs {}",
+                q,
+                exact,
+                estimate
+            );
+        }
+        assert_eq!(left.quantile(0.0), left.min());
+        assert_eq!(left.quantile(1.0), left.max());
+
+        let mut single = QuantileSummary::default();
+        single.add(&3.0);
+        assert_eq!(single.median(), Some(3.0));
+    }
+
+    #[test]
+    fn time_range_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<i64> = (0..20).map(|_| rng.gen_range(-1000, 1000)).collect();
+        check_combine(
+            data.len(),
+            |s: &mut TimeRangeSummary, i| s.add(&data[i]),
+            |x, y| x.count == y.count && x.range() == y.range(),
+        );
+    }
+
+    #[test]
+    fn reservoir_combine() {
+        check_combine(
+            100,
+            |s: &mut ReservoirSummary<usize>, i| s.add(&i),
+            |x, y| x.count == y.count && x.samples.len() == y.samples.len(),
+        );
+    }
+
+    #[test]
+    fn reservoir_samples_uniformly() {
+        let mut small = ReservoirSummary::with_size(3);
+        small.add(&7);
+        assert_eq!(small.samples(), &[7]);
+
+        // Splits 0..1000 unevenly and checks that the combined samples don't favor either side
+        let mut total = 0;
+        let mut sampled = 0;
+        for _ in 0..200 {
+            let mut left = ReservoirSummary::default();
+            let mut right = ReservoirSummary::default();
+            (0..100).for_each(|i| left.add(&i));
+            (100..1000).for_each(|i| right.add(&i));
+            left.combine(&right);
+            assert_eq!(left.count(), 1000);
+            assert_eq!(left.samples().len(), DEFAULT_RESERVOIR_SIZE);
+            let mut samples = left.samples().to_vec();
+            samples.sort_unstable();
+            samples.dedup();
+            assert_eq!(samples.len(), DEFAULT_RESERVOIR_SIZE);
+            total += samples.iter().sum::<usize>();
+            sampled += samples.len();
+        }
+        let mean = total as f64 / sampled as f64;
+        assert!((mean - 499.5).abs() < 25.0, "mean {}", mean);
+    }
+
+    #[test]
+    fn category_posterior() {
+        let mut summary = CategorySummary::default();
+        assert!(summary.posterior().is_empty());
+        for label in &[3, 1, 3, 3] {
+            summary.add(label);
+        }
+        assert_eq!(summary.posterior(), vec![(3, 0.75), (1, 0.25)]);
+    }
+
+    #[test]
+    fn vec_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<Vec<f32>> = (0..20)
+            .map(|_| (0..3).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let moments_close = |x: &[f32], y: &[f32]| {
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| close(*a as f64, *b as f64))
+        };
+        check_combine(
+            data.len(),
+            |s: &mut VecSummary, i| s.add(&data[i]),
+            |x, y| {
+                x.count == y.count
+                    && moments_close(&x.moment1, &y.moment1)
+                    && moments_close(&x.moment2, &y.moment2)
+            },
+        );
+    }
+
+    #[test]
+    fn cov_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<Vec<f32>> = (0..20)
+            .map(|_| (0..3).map(|_| rng.gen::<f32>()).collect())
+            .collect();
+        let all_close = |x: &[f64], y: &[f64]| {
+            x.len() == y.len() && x.iter().zip(y).all(|(a, b)| close(*a, *b))
+        };
+        check_combine(
+            data.len(),
+            |s: &mut CovSummary, i| s.add(&data[i]),
+            |x, y| {
+                x.count == y.count
+                    && all_close(&x.mean, &y.mean)
+                    && all_close(&x.comoment, &y.comoment)
+            },
+        );
+    }
+
+    #[test]
+    fn cov_summary_whitens() {
+        let mut summary = CovSummary::default();
+        assert_eq!(summary.covariance(), None);
+        // The second coordinate is correlated with the first
+        for (x, y) in &[(1.0, 2.0), (2.0, 3.0), (3.0, 7.0), (4.0, 8.0)] {
+            summary.add(&[*x, *y]);
+        }
+        assert_eq!(summary.mean, vec![2.5, 5.0]);
+        let cov = summary.covariance().unwrap();
+        let expected = [5.0 / 3.0, 11.0 / 3.0, 11.0 / 3.0, 26.0 / 3.0];
+        assert!(cov.iter().zip(&expected).all(|(a, b)| close(*a, *b)));
+
+        // Whitening every label gives residuals with the identity covariance
+        let mut whitened = CovSummary::default();
+        for (x, y) in &[(1.0, 2.0), (2.0, 3.0), (3.0, 7.0), (4.0, 8.0)] {
+            let w = summary.whiten(&[*x, *y], 0.0).unwrap();
+            whitened.add(&[w[0] as f32, w[1] as f32]);
+        }
+        let identity = [1.0, 0.0, 0.0, 1.0];
+        let cov = whitened.covariance().unwrap();
+        assert!(cov.iter().zip(&identity).all(|(a, b)| (a - b).abs() < 1e-4));
+        assert!(close(summary.mahalanobis(&[2.5, 5.0], 0.0).unwrap(), 0.0));
+
+        let mut degenerate = CovSummary::default();
+        degenerate.add(&[1.0, 1.0]);
+        degenerate.add(&[2.0, 2.0]);
+        assert_eq!(degenerate.whiten(&[1.0, 1.0], 0.0), None);
+        assert!(degenerate.whiten(&[1.0, 1.0], 0.1).is_some());
+    }
+
+    #[test]
+    fn vec_combine_into_empty() {
+        let mut other = VecSummary::default();
+        other.add(&[1.0, 2.0]);
+        other.add(&[3.0, 4.0]);
+        let mut summary = VecSummary::default();
+        summary.combine(&other);
+        assert_eq!(summary.count, 2);
+        assert_eq!(summary.moment1, vec![4.0, 6.0]);
+        assert_eq!(summary.moment2, vec![10.0, 20.0]);
+    }
+
+    #[test]
+    fn float_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<f64> = (0..20).map(|_| rng.gen::<f64>()).collect();
+        check_combine(
+            data.len(),
+            |s: &mut FloatSummary, i| s.add(&data[i]),
+            |x, y| x.count == y.count && close(x.moment1, y.moment1) && close(x.moment2, y.moment2),
+        );
+    }
+
+    #[test]
+    fn int_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<i64> = (0..20).map(|_| rng.gen_range(-100, 100)).collect();
+        check_combine(
+            data.len(),
+            |s: &mut IntSummary, i| s.add(&data[i]),
+            |x, y| x.count == y.count && x.moment1 == y.moment1 && x.moment2 == y.moment2,
+        );
+    }
+
+    #[test]
+    fn category_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<i64> = (0..20).map(|_| rng.gen_range(0, 6)).collect();
+        let sorted = |s: &CategorySummary| {
+            let mut items = s.items.to_vec();
+            items.sort();
+            items
+        };
+        check_combine(
+            data.len(),
+            |s: &mut CategorySummary, i| s.add(&data[i]),
+            |x, y| sorted(x) == sorted(y),
+        );
+    }
+
+    #[test]
+    fn multi_category_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<Vec<i64>> = (0..20)
+            .map(|_| {
+                let mut set: Vec<i64> = (0..rng.gen_range(0, 4))
+                    .map(|_| rng.gen_range(0, 6))
+                    .collect();
+                set.sort_unstable();
+                set.dedup();
+                set
+            })
+            .collect();
+        check_combine(
+            data.len(),
+            |s: &mut MultiCatSummary, i| s.add(&data[i]),
+            |x, y| x.count == y.count && x.items == y.items,
+        );
+
+        let mut summary = MultiCatSummary::default();
+        summary.add(&[1, 2]);
+        summary.add(&[2]);
+        summary.add(&[]);
+        summary.add(&[2, 5]);
+        assert_eq!(summary.count(), 4);
+        assert_eq!(summary.frequencies(), vec![(2, 0.75), (1, 0.25), (5, 0.25)]);
+    }
+
+    #[test]
+    fn taxonomy_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<String> = (0..20)
+            .map(|_| format!("a{}/b{}", rng.gen_range(0, 3), rng.gen_range(0, 3)))
+            .collect();
+        check_combine(
+            data.len(),
+            |s: &mut TaxonomySummary, i| s.add(&data[i]),
+            |x, y| x.count == y.count && x.items == y.items,
+        );
+    }
+
+    #[test]
+    fn taxonomy_levels() {
+        let mut summary = TaxonomySummary::default();
+        for label in &[
+            "animals/mammals/dogs",
+            "animals/mammals/cats",
+            "animals/mammals/dogs",
+            "animals/birds",
+            "plants",
+        ] {
+            summary.add(label);
+        }
+        assert_eq!(summary.count_under(""), 5);
+        assert_eq!(summary.count_under("animals"), 4);
+        assert_eq!(summary.count_under("animals/mammals/"), 3);
+        assert_eq!(summary.count_under("animals/fish"), 0);
+        assert_eq!(summary.level(1), vec![("animals", 4), ("plants", 1)]);
+        assert_eq!(
+            summary.children("animals/mammals"),
+            vec![("animals/mammals/dogs", 2), ("animals/mammals/cats", 1)]
+        );
+        assert!(taxonomy_contains("animals/mammals", "animals/mammals/dogs"));
+        assert!(!taxonomy_contains("animals/mam", "animals/mammals/dogs"));
+    }
+
+    #[test]
+    fn string_combine() {
+        let mut rng = thread_rng();
+        let data: Vec<String> = (0..20)
+            .map(|_| format!("label {}", rng.gen_range(0, 6)))
+            .collect();
+        check_combine(
+            data.len(),
+            |s: &mut StringSummary, i| s.add(&data[i]),
+            |x, y| x.items == y.items,
+        );
+    }
+}
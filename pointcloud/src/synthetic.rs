@@ -0,0 +1,232 @@
+//! Seeded generators for labeled datasets with a known structure, for tests and benchmarks.
+//!
+//! Each generator is a set of parameters with a `data` method that gives the row major points and their labels, and a
+//! `cloud` method that glues them into a `DefaultLabeledCloud`. The same parameters and seed always give the same data.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+
+use crate::data_sources::DataRam;
+use crate::distances::Metric;
+use crate::label_sources::SmallIntLabels;
+use crate::pc_errors::PointCloudResult;
+use crate::{DefaultLabeledCloud, SimpleLabeledCloud};
+
+/// A standard normal sample, with the Box-Muller transform.
+fn normal<R: Rng>(rng: &mut R) -> f32 {
+    let u: f32 = 1.0 - rng.gen::<f32>();
+    let v: f32 = rng.gen();
+    (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+fn labeled_cloud<M: Metric>(
+    (data, labels): (Vec<f32>, Vec<i64>),
+    dim: usize,
+) -> PointCloudResult<DefaultLabeledCloud<M>> {
+    Ok(SimpleLabeledCloud::new(
+        DataRam::<M>::new(data, dim)?,
+        SmallIntLabels::new(labels, None),
+    ))
+}
+
+/// Isotropic gaussian clusters with centers drawn uniformly from a cube. Points are labeled with their cluster.
+#[derive(Debug, Clone)]
+pub struct GaussianMixture {
+    /// The number of points
+    pub count: usize,
+    /// The dimension of the points
+    pub dim: usize,
+    /// The number of clusters, the points are split evenly between them
+    pub clusters: usize,
+    /// The standard deviation of each cluster
+    pub cluster_std: f32,
+    /// The centers are drawn from `[-spread, spread]^dim`
+    pub spread: f32,
+    /// Seed for the random number generator
+    pub seed: u64,
+}
+
+impl Default for GaussianMixture {
+    fn default() -> Self {
+        GaussianMixture {
+            count: 1000,
+            dim: 5,
+            clusters: 4,
+            cluster_std: 1.0,
+            spread: 10.0,
+            seed: 0,
+        }
+    }
+}
+
+impl GaussianMixture {
+    /// The points, row major, and their labels.
+    pub fn data(&self) -> (Vec<f32>, Vec<i64>) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let centers: Vec<f32> = (0..self.clusters * self.dim)
+            .map(|_| rng.gen_range(-self.spread, self.spread))
+            .collect();
+        let mut data = Vec::with_capacity(self.count * self.dim);
+        let mut labels = Vec::with_capacity(self.count);
+        for i in 0..self.count {
+            let cluster = i % self.clusters;
+            let center = &centers[cluster * self.dim..(cluster + 1) * self.dim];
+            data.extend(
+                center
+                    .iter()
+                    .map(|c| c + self.cluster_std * normal(&mut rng)),
+            );
+            labels.push(cluster as i64);
+        }
+        (data, labels)
+    }
+
+    /// The points and labels, glued together.
+    pub fn cloud<M: Metric>(&self) -> PointCloudResult<DefaultLabeledCloud<M>> {
+        labeled_cloud(self.data(), self.dim)
+    }
+}
+
+/// Points drawn uniformly from `[0, 1]^dim`. Points are labeled by which of `classes` equal slabs along the first
+/// coordinate they fall into.
+#[derive(Debug, Clone)]
+pub struct Uniform {
+    /// The number of points
+    pub count: usize,
+    /// The dimension of the points
+    pub dim: usize,
+    /// The number of labels
+    pub classes: usize,
+    /// Seed for the random number generator
+    pub seed: u64,
+}
+
+impl Default for Uniform {
+    fn default() -> Self {
+        Uniform {
+            count: 1000,
+            dim: 5,
+            classes: 2,
+            seed: 0,
+        }
+    }
+}
+
+impl Uniform {
+    /// The points, row major, and their labels.
+    pub fn data(&self) -> (Vec<f32>, Vec<i64>) {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let data: Vec<f32> = (0..self.count * self.dim).map(|_| rng.gen()).collect();
+        let labels = data
+            .chunks(self.dim)
+            .map(|p| ((p[0] * self.classes as f32) as i64).min(self.classes as i64 - 1))
+            .collect();
+        (data, labels)
+    }
+
+    /// The points and labels, glued together.
+    pub fn cloud<M: Metric>(&self) -> PointCloudResult<DefaultLabeledCloud<M>> {
+        labeled_cloud(self.data(), self.dim)
+    }
+}
+
+/// A swiss roll, a 2 dimensional sheet rolled up in the first 3 coordinates. The remaining coordinates are gaussian
+/// noise. Points are labeled by which of `classes` equal bands along the roll they lie on, so neighbors on the sheet
+/// share labels while the layers of the roll, which are close in the ambient space, don't.
+#[derive(Debug, Clone)]
+pub struct SwissRoll {
+    /// The number of points
+    pub count: usize,
+    /// The dimension of the points, at least 3
+    pub dim: usize,
+    /// The number of labels
+    pub classes: usize,
+    /// The standard deviation of the noise added to every coordinate
+    pub noise: f32,
+    /// Seed for the random number generator
+    pub seed: u64,
+}
+
+impl Default for SwissRoll {
+    fn default() -> Self {
+        SwissRoll {
+            count: 1000,
+            dim: 3,
+            classes: 4,
+            noise: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+impl SwissRoll {
+    /// The points, row major, and their labels.
+    pub fn data(&self) -> (Vec<f32>, Vec<i64>) {
+        assert!(self.dim >= 3, "A swiss roll needs at least 3 dimensions");
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut data = Vec::with_capacity(self.count * self.dim);
+        let mut labels = Vec::with_capacity(self.count);
+        for _ in 0..self.count {
+            let position: f32 = rng.gen();
+            let t = 1.5 * PI * (1.0 + 2.0 * position);
+            let height = 21.0 * rng.gen::<f32>();
+            data.push(t * t.cos() + self.noise * normal(&mut rng));
+            data.push(height + self.noise * normal(&mut rng));
+            data.push(t * t.sin() + self.noise * normal(&mut rng));
+            data.extend((3..self.dim).map(|_| self.noise * normal(&mut rng)));
+            labels.push(((position * self.classes as f32) as i64).min(self.classes as i64 - 1));
+        }
+        (data, labels)
+    }
+
+    /// The points and labels, glued together.
+    pub fn cloud<M: Metric>(&self) -> PointCloudResult<DefaultLabeledCloud<M>> {
+        labeled_cloud(self.data(), self.dim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabeledCloud, PointCloud, L2};
+
+    #[test]
+    fn generators_are_seeded() {
+        let mixture = GaussianMixture {
+            count: 200,
+            clusters: 3,
+            cluster_std: 0.1,
+            ..Default::default()
+        };
+        let (data, labels) = mixture.data();
+        assert_eq!(data.len(), 200 * 5);
+        assert_eq!((data.clone(), labels.clone()), mixture.data());
+        assert_ne!(
+            data,
+            GaussianMixture {
+                seed: 1,
+                ..mixture.clone()
+            }
+            .data()
+            .0
+        );
+        // Points of the same cluster are much closer than the spread of the centers
+        let cloud = mixture.cloud::<L2>().unwrap();
+        assert_eq!(cloud.len(), 200);
+        assert!(cloud.distances_to_point_index(0, &[3]).unwrap()[0] < 1.0);
+        assert_eq!(cloud.label(3).unwrap(), Some(&0));
+
+        let (data, labels) = Uniform::default().data();
+        assert!(data.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!(labels.iter().all(|l| *l == 0 || *l == 1));
+
+        let roll = SwissRoll {
+            dim: 4,
+            ..Default::default()
+        };
+        let (data, labels) = roll.data();
+        assert_eq!(data.len(), 1000 * 4);
+        assert_eq!(labels.iter().max(), Some(&3));
+    }
+}
@@ -0,0 +1,229 @@
+//! Metrics supplied at runtime, as a closure or a boxed trait object.
+//!
+//! `Metric` is implemented on types, with no state, so a distance that only exists at runtime, like a learned metric
+//! whose weights are loaded from a file, can't implement it directly. `UserMetric<T>` is a `Metric` that calls the
+//! distance registered for the tag type `T`. Register it once before building or loading a tree, then use
+//! `UserMetric<T>` wherever a metric type is expected.
+
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use once_cell::sync::OnceCell;
+
+use crate::distances::Metric;
+use crate::pc_errors::*;
+
+/// A dense distance that can be registered for a `UserMetric`.
+pub type DenseDistance = Arc<dyn Fn(&[f32], &[f32]) -> f32 + Send + Sync>;
+
+/// Where the distance or weights of a tag are set, once. Each tag owns one in a static, so reading it is a single
+/// atomic load, with no lock or lookup on the distance's path.
+pub struct MetricSlot {
+    distance: OnceCell<DenseDistance>,
+    pub(crate) weights: OnceCell<Vec<f32>>,
+}
+
+impl MetricSlot {
+    /// An empty slot, for the static of a `MetricTag`.
+    pub const fn new() -> MetricSlot {
+        MetricSlot {
+            distance: OnceCell::new(),
+            weights: OnceCell::new(),
+        }
+    }
+}
+
+impl Default for MetricSlot {
+    fn default() -> MetricSlot {
+        MetricSlot::new()
+    }
+}
+
+impl Debug for MetricSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MetricSlot")
+            .field("distance", &self.distance.get().is_some())
+            .field("weights", &self.weights)
+            .finish()
+    }
+}
+
+/// Names a user metric. Implement this on an empty type for each distance you register:
+/// ```rust
+/// # use pointcloud::{MetricSlot, MetricTag};
+/// #[derive(Debug, Clone)]
+/// struct Learned {}
+/// impl MetricTag for Learned {
+///     fn name() -> &'static str {
+///         "Learned"
+///     }
+///     fn slot() -> &'static MetricSlot {
+///         static SLOT: MetricSlot = MetricSlot::new();
+///         &SLOT
+///     }
+/// }
+/// ```
+pub trait MetricTag: 'static + Send + Sync + Debug + Clone {
+    /// The name trees built with the metric are saved under
+    fn name() -> &'static str;
+    /// The slot the metric is registered in, a static owned by this tag
+    fn slot() -> &'static MetricSlot;
+}
+
+thread_local! {
+    static ZEROS: RefCell<Vec<f32>> = RefCell::new(Vec::new());
+}
+
+/// A metric backed by the distance registered for `T`. Computing a distance before one is registered panics.
+///
+/// Only the dense distance is supplied, sparse points are densified and the norm is the distance to the origin. Check
+/// the distance with `audit_metric` before building a tree with it, the tree relies on the triangle inequality.
+#[derive(Debug, Clone)]
+pub struct UserMetric<T: MetricTag> {
+    tag: PhantomData<T>,
+}
+
+impl<T: MetricTag> UserMetric<T> {
+    /// Registers the distance for `T`. A tag's distance can only be registered once, trees built with it would
+    /// silently change if it were replaced, so registering it again is an error.
+    pub fn register<F>(distance: F) -> PointCloudResult<()>
+    where
+        F: Fn(&[f32], &[f32]) -> f32 + Send + Sync + 'static,
+    {
+        Self::register_shared(Arc::new(distance))
+    }
+
+    /// Registers a distance that is already shared, like a trait object.
+    pub fn register_shared(distance: DenseDistance) -> PointCloudResult<()> {
+        T::slot()
+            .distance
+            .set(distance)
+            .map_err(|_| PointCloudError::MetricAlreadyRegistered(T::name()))
+    }
+
+    /// If a distance is registered for `T`.
+    pub fn is_registered() -> bool {
+        T::slot().distance.get().is_some()
+    }
+
+    fn distance() -> &'static DenseDistance {
+        T::slot().distance.get().unwrap_or_else(|| {
+            panic!(
+                "No distance is registered for the user metric {}",
+                T::name()
+            )
+        })
+    }
+
+    fn densify(ind: &[u32], val: &[f32], dim: usize) -> Vec<f32> {
+        let mut x = vec![0.0; dim];
+        for (i, v) in ind.iter().zip(val) {
+            x[*i as usize] = *v;
+        }
+        x
+    }
+}
+
+impl<T: MetricTag> Metric for UserMetric<T> {
+    fn name() -> &'static str {
+        T::name()
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        Self::distance()(x, y)
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let dim = x_ind
+            .last()
+            .max(y_ind.last())
+            .map(|i| *i as usize + 1)
+            .unwrap_or(0);
+        Self::dense(
+            &Self::densify(x_ind, x_val, dim),
+            &Self::densify(y_ind, y_val, dim),
+        )
+    }
+
+    /// The distance to the origin, measured against a zero buffer each thread keeps and only grows.
+    fn norm(x: &[f32]) -> f32 {
+        let distance = Self::distance();
+        ZEROS.with(|zeros| {
+            let mut zeros = zeros.borrow_mut();
+            if zeros.len() < x.len() {
+                zeros.resize(x.len(), 0.0);
+            }
+            distance(x, &zeros[..x.len()])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_traits::PointCloud;
+    use crate::data_sources::DataRam;
+    use crate::metric_audit::audit_metric;
+
+    #[derive(Debug, Clone)]
+    struct Weighted {}
+    impl MetricTag for Weighted {
+        fn name() -> &'static str {
+            "Weighted"
+        }
+        fn slot() -> &'static MetricSlot {
+            static SLOT: MetricSlot = MetricSlot::new();
+            &SLOT
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Unregistered {}
+    impl MetricTag for Unregistered {
+        fn name() -> &'static str {
+            "Unregistered"
+        }
+        fn slot() -> &'static MetricSlot {
+            static SLOT: MetricSlot = MetricSlot::new();
+            &SLOT
+        }
+    }
+
+    #[test]
+    fn user_metric_uses_the_registered_distance() {
+        let weights = vec![1.0f32, 4.0];
+        UserMetric::<Weighted>::register(move |x, y| {
+            x.iter()
+                .zip(y)
+                .zip(&weights)
+                .map(|((a, b), w)| w * (a - b) * (a - b))
+                .sum::<f32>()
+                .sqrt()
+        })
+        .unwrap();
+        assert!(UserMetric::<Weighted>::is_registered());
+        match UserMetric::<Weighted>::register(|x, y| crate::L2::dense(x, y)) {
+            Err(PointCloudError::MetricAlreadyRegistered(name)) => assert_eq!(name, "Weighted"),
+            _ => panic!("the distance was registered twice"),
+        }
+        assert!(!UserMetric::<Unregistered>::is_registered());
+        assert_eq!(UserMetric::<Weighted>::name(), "Weighted");
+
+        let cloud =
+            DataRam::<UserMetric<Weighted>>::new(vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0], 2).unwrap();
+        assert_eq!(
+            cloud.distances_to_point_index(0, &[1, 2]).unwrap(),
+            vec![1.0, 2.0]
+        );
+        assert_eq!(
+            UserMetric::<Weighted>::sparse(&[1], &[1.0], &[0], &[1.0]),
+            5.0f32.sqrt()
+        );
+        assert_eq!(UserMetric::<Weighted>::norm(&[0.0, 1.0]), 2.0);
+
+        let audit = audit_metric(&cloud, 20, 1e-5, &mut rand::thread_rng()).unwrap();
+        assert!(audit.is_metric());
+    }
+}
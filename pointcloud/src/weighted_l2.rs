@@ -0,0 +1,237 @@
+//! L2 with a weight on each dimension.
+//!
+//! With the weights set to the diagonal of a precision matrix this is the Mahalanobis distance of that matrix, and
+//! fitting them to the inverse variance of each dimension puts features measured on very different scales on the
+//! same footing without rescaling the data. Like `UserMetric`, the weights are registered at runtime in the slot of a
+//! tag type, so that `WeightedL2<T>` can be used wherever a metric type is expected.
+
+use std::iter::repeat;
+use std::marker::PhantomData;
+
+use crate::base_traits::PointCloud;
+use crate::distances::Metric;
+use crate::pc_errors::*;
+use crate::{MetricTag, Scalar};
+
+/// The square root of the weighted sum of squared differences, with the weights registered for `T`. Dimensions past
+/// the end of the weights have a weight of 1. Computing a distance before weights are registered panics.
+#[derive(Debug, Clone)]
+pub struct WeightedL2<T: MetricTag> {
+    tag: PhantomData<T>,
+}
+
+impl<T: MetricTag> WeightedL2<T> {
+    /// Registers the weights for `T`. Every weight has to be positive and finite, or the distance isn't a metric. The
+    /// weights can only be registered once, trees built with them would silently change if they were replaced.
+    pub fn set_weights(weights: Vec<f32>) -> PointCloudResult<()> {
+        if let Some(i) = weights.iter().position(|w| !(w.is_finite() && *w > 0.0)) {
+            return Err(PointCloudError::data_access(
+                i,
+                format!("the weight {} is not positive and finite", weights[i]),
+            ));
+        }
+        T::slot()
+            .weights
+            .set(weights)
+            .map_err(|_| PointCloudError::MetricAlreadyRegistered(T::name()))
+    }
+
+    /// The weights registered for `T`.
+    pub fn weights() -> Option<&'static [f32]> {
+        T::slot().weights.get().map(|w| &w[..])
+    }
+
+    /// Registers the inverse variance of each dimension of the point cloud as the weights. See `inverse_variances`.
+    pub fn fit<D: PointCloud>(point_cloud: &D) -> PointCloudResult<()> {
+        Self::set_weights(inverse_variances(point_cloud)?)
+    }
+
+    fn registered_weights() -> &'static [f32] {
+        Self::weights().unwrap_or_else(|| {
+            panic!(
+                "No weights are registered for the weighted metric {}",
+                T::name()
+            )
+        })
+    }
+
+    /// The weight of each dimension, continuing with 1 past the registered weights.
+    fn weight_iter() -> impl Iterator<Item = f32> {
+        Self::registered_weights()
+            .iter()
+            .cloned()
+            .chain(repeat(1.0))
+    }
+
+    #[inline]
+    fn weight(w: &[f32], i: u32) -> f32 {
+        w.get(i as usize).cloned().unwrap_or(1.0)
+    }
+}
+
+/// The inverse of the variance of each dimension over all points of the cloud. Dimensions that don't vary get a
+/// weight of 1.
+pub fn inverse_variances<D: PointCloud>(point_cloud: &D) -> PointCloudResult<Vec<f32>> {
+    let dim = point_cloud.dim();
+    let mut count = 0.0f64;
+    let mut means = vec![0.0f64; dim];
+    let mut squares = vec![0.0f64; dim];
+    // Welford's update, in f64 so that large datasets don't lose the variance to rounding
+    for pi in point_cloud.reference_indexes() {
+        count += 1.0;
+        let point = point_cloud.point(pi)?;
+        for ((x, mean), square) in point.dense_iter(dim).zip(&mut means).zip(&mut squares) {
+            let delta = x as f64 - *mean;
+            *mean += delta / count;
+            *square += delta * (x as f64 - *mean);
+        }
+    }
+    Ok(squares
+        .iter()
+        .map(|s| {
+            let variance = s / count.max(1.0);
+            if variance > 0.0 {
+                (1.0 / variance) as f32
+            } else {
+                1.0
+            }
+        })
+        .collect())
+}
+
+impl<T: MetricTag> Metric for WeightedL2<T> {
+    /// The name of the tag, trees built with different weights are saved under different names.
+    fn name() -> &'static str {
+        T::name()
+    }
+
+    fn dense(x: &[f32], y: &[f32]) -> f32 {
+        x.iter()
+            .zip(y)
+            .zip(Self::weight_iter())
+            .map(|((a, b), w)| w * (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    fn sparse(x_ind: &[u32], x_val: &[f32], y_ind: &[u32], y_val: &[f32]) -> f32 {
+        let w = Self::registered_weights();
+        let mut total = 0.0;
+        let (mut i, mut j) = (0, 0);
+        while i < x_ind.len() || j < y_ind.len() {
+            match (x_ind.get(i), y_ind.get(j)) {
+                (Some(a), Some(b)) if a == b => {
+                    let d = x_val[i] - y_val[j];
+                    total += Self::weight(w, *a) * d * d;
+                    i += 1;
+                    j += 1;
+                }
+                (Some(a), b) if b.map_or(true, |b| a < b) => {
+                    total += Self::weight(w, *a) * x_val[i] * x_val[i];
+                    i += 1;
+                }
+                (_, b) => {
+                    total += Self::weight(w, *b.unwrap()) * y_val[j] * y_val[j];
+                    j += 1;
+                }
+            }
+        }
+        total.sqrt()
+    }
+
+    fn norm(x: &[f32]) -> f32 {
+        x.iter()
+            .zip(Self::weight_iter())
+            .map(|(a, w)| w * a * a)
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    fn dense_scalar<S: Scalar>(x: &[S], y: &[S]) -> f64 {
+        x.iter()
+            .zip(y)
+            .zip(Self::weight_iter())
+            .map(|((a, b), w)| w as f64 * (a.to_f64() - b.to_f64()).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sources::DataRam;
+    use crate::metric_audit::audit_metric;
+    use crate::MetricSlot;
+
+    #[derive(Debug, Clone)]
+    struct Scaled {}
+    impl MetricTag for Scaled {
+        fn name() -> &'static str {
+            "Scaled"
+        }
+        fn slot() -> &'static MetricSlot {
+            static SLOT: MetricSlot = MetricSlot::new();
+            &SLOT
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Fitted {}
+    impl MetricTag for Fitted {
+        fn name() -> &'static str {
+            "Fitted"
+        }
+        fn slot() -> &'static MetricSlot {
+            static SLOT: MetricSlot = MetricSlot::new();
+            &SLOT
+        }
+    }
+
+    #[test]
+    fn weights_scale_each_dimension() {
+        assert!(WeightedL2::<Scaled>::weights().is_none());
+        assert!(WeightedL2::<Scaled>::set_weights(vec![1.0, 0.0]).is_err());
+        assert!(WeightedL2::<Scaled>::set_weights(vec![1.0, f32::NAN]).is_err());
+        WeightedL2::<Scaled>::set_weights(vec![1.0, 4.0, 9.0]).unwrap();
+
+        let x = [1.0, 1.0, 0.0];
+        let y = [0.0, 0.0, 1.0];
+        assert_eq!(WeightedL2::<Scaled>::dense(&x, &y), 14.0f32.sqrt());
+        assert_eq!(WeightedL2::<Scaled>::norm(&x), 5.0f32.sqrt());
+        assert_eq!(
+            WeightedL2::<Scaled>::sparse(&[0, 1], &[1.0, 1.0], &[2], &[1.0]),
+            14.0f32.sqrt()
+        );
+        assert_eq!(
+            WeightedL2::<Scaled>::sparse(&[1], &[3.0], &[1, 2], &[1.0, 1.0]),
+            25.0f32.sqrt()
+        );
+        assert!((WeightedL2::<Scaled>::dense_scalar(&x, &y) - 14.0f64.sqrt()).abs() < 1e-12);
+        // Indexes past the weights are unweighted rather than out of bounds
+        assert_eq!(
+            WeightedL2::<Scaled>::sparse(&[0, 5], &[1.0, 2.0], &[], &[]),
+            5.0f32.sqrt()
+        );
+        assert_eq!(
+            WeightedL2::<Scaled>::dense(&[0.0; 4], &[1.0; 4]),
+            15.0f32.sqrt()
+        );
+        assert!(WeightedL2::<Scaled>::set_weights(vec![1.0, 1.0, 1.0]).is_err());
+
+        // The second feature is on a scale 100 times larger than the first
+        let data: Vec<f32> = (0..50)
+            .flat_map(|i| vec![(i % 7) as f32, ((i * 3) % 11) as f32 * 100.0])
+            .collect();
+        let cloud = DataRam::<WeightedL2<Fitted>>::new(data, 2).unwrap();
+        WeightedL2::<Fitted>::fit(&cloud).unwrap();
+        let weights = WeightedL2::<Fitted>::weights().unwrap();
+        let plain =
+            inverse_variances(&DataRam::<crate::L2>::new(vec![0.0, 5.0, 2.0, 5.0], 2).unwrap())
+                .unwrap();
+        assert_eq!(plain, vec![1.0, 1.0]);
+        assert!(weights[1] < weights[0] / 1000.0);
+        let audit = audit_metric(&cloud, 30, 1e-4, &mut rand::thread_rng()).unwrap();
+        assert!(audit.is_metric());
+    }
+}
@@ -17,6 +17,8 @@
 * under the License.
 */
 
+use goko::errors::GokoError;
+use pyo3::exceptions::ValueError;
 use pyo3::prelude::*;
 
 pub mod layer;
@@ -26,6 +28,11 @@ pub mod tree;
 
 use tree::CoverTree;
 
+/// Raises a goko (or pointcloud) error in python as a `ValueError` carrying its message.
+pub(crate) fn goko_err<E: Into<GokoError>>(e: E) -> PyErr {
+    PyErr::new::<ValueError, _>(e.into().to_string())
+}
+
 #[pymodule]
 fn pygoko(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<CoverTree>()?;
@@ -4,6 +4,7 @@ use ndarray::{Array1, Array2};
 use numpy::{IntoPyArray, PyArray1, PyArray2};
 use pyo3::PyIterProtocol;
 
+use goko::errors::GokoError;
 use goko::plugins::distributions::*;
 use goko::*;
 use pointcloud::*;
@@ -11,6 +12,8 @@ use std::sync::Arc;
 
 use pyo3::types::PyDict;
 
+use crate::goko_err;
+
 #[pyclass(unsendable)]
 pub struct IterLayerNode {
     pub parameters: Arc<CoverTreeParameters<DefaultLabeledCloud<L1>>>,
@@ -59,16 +62,16 @@ impl PyNode {
         self.address
     }
 
-    pub fn is_leaf(&self) -> bool {
+    pub fn is_leaf(&self) -> PyResult<bool> {
         self.tree
             .get_node_and(self.address, |n| n.is_leaf())
-            .unwrap()
+            .map_err(goko_err)
     }
 
-    pub fn coverage_count(&self) -> usize {
+    pub fn coverage_count(&self) -> PyResult<usize> {
         self.tree
             .get_node_and(self.address, |n| n.coverage_count())
-            .unwrap()
+            .map_err(goko_err)
     }
 
     pub fn children(&self) -> Vec<PyNode> {
@@ -85,6 +88,8 @@ impl PyNode {
     pub fn children_probs(&self) -> Option<(Vec<((i32, usize), f64)>, f64)> {
         self.tree
             .get_node_plugin_and(self.address, |p: &Dirichlet| p.prob_vector())
+            .ok()
+            .flatten()
             .flatten()
     }
 
@@ -97,35 +102,40 @@ impl PyNode {
                     py_nodes
                 })
             })
+            .ok()
             .flatten()
             .unwrap_or(vec![])
     }
 
-    pub fn fractal_dim(&self) -> f32 {
-        self.tree.node_fractal_dim(self.address)
+    pub fn fractal_dim(&self) -> PyResult<f32> {
+        self.tree.node_fractal_dim(self.address).map_err(goko_err)
     }
 
-    pub fn weighted_fractal_dim(&self) -> f32 {
-        self.tree.node_weighted_fractal_dim(self.address)
+    pub fn weighted_fractal_dim(&self) -> PyResult<f32> {
+        self.tree
+            .node_weighted_fractal_dim(self.address)
+            .map_err(goko_err)
     }
 
     pub fn singletons(&self) -> PyResult<Py<PyArray2<f32>>> {
         let dim = self.parameters.point_cloud.dim();
-        let len = self.coverage_count() as usize;
+        let len = self.coverage_count()?;
         let mut ret_matrix = Vec::with_capacity(len * dim);
-        self.tree.get_node_and(self.address, |n| {
-            n.singletons().iter().for_each(|pi| {
-                if let Ok(p) = self.parameters.point_cloud.point(*pi) {
-                    ret_matrix.extend(p.dense_iter(dim));
-                }
-            });
-
-            if n.is_leaf() {
-                if let Ok(p) = self.parameters.point_cloud.point(*n.center_index()) {
-                    ret_matrix.extend(p.dense_iter(dim));
+        self.tree
+            .get_node_and(self.address, |n| {
+                n.singletons().iter().for_each(|pi| {
+                    if let Ok(p) = self.parameters.point_cloud.point(*pi) {
+                        ret_matrix.extend(p.dense_iter(dim));
+                    }
+                });
+
+                if n.is_leaf() {
+                    if let Ok(p) = self.parameters.point_cloud.point(*n.center_index()) {
+                        ret_matrix.extend(p.dense_iter(dim));
+                    }
                 }
-            }
-        });
+            })
+            .map_err(goko_err)?;
 
         let ret_matrix = Array2::from_shape_vec((len, dim), ret_matrix).unwrap();
         let gil = GILGuard::acquire();
@@ -136,7 +146,7 @@ impl PyNode {
     pub fn singletons_indexes(&self) -> Vec<usize> {
         self.tree
             .get_node_and(self.address, |n| Vec::from(n.singletons()))
-            .unwrap_or(vec![])
+            .unwrap_or_default()
     }
 
     pub fn cover_mean(&self) -> PyResult<Option<Py<PyArray1<f32>>>> {
@@ -146,6 +156,7 @@ impl PyNode {
         let mean = self
             .tree
             .get_node_plugin_and::<DiagGaussian, _, _>(self.address, |p| p.mean())
+            .map_err(goko_err)?
             .map(|m| {
                 Array1::from_shape_vec((dim,), m)
                     .unwrap()
@@ -161,7 +172,8 @@ impl PyNode {
         let var = self
             .tree
             .get_node_plugin_and::<DiagGaussian, _, _>(self.address, |p| p.var())
-            .unwrap();
+            .map_err(goko_err)?
+            .ok_or_else(|| goko_err(GokoError::PluginNotInstalled("GokoDiagGaussian")))?;
         let py_mean = Array1::from_shape_vec((dim,), var).unwrap();
         let gil = GILGuard::acquire();
         let py = gil.python();
@@ -175,14 +187,19 @@ impl PyNode {
             .tree
             .get_node_plugin_and::<SvdGaussian, _, _>(self.address, |p| {
                 p.singular_vals.clone().into_pyarray(py).to_owned()
-            }))
+            })
+            .map_err(goko_err)?)
     }
 
     pub fn label_summary(&self) -> PyResult<Option<PyObject>> {
         let gil = GILGuard::acquire();
         let py = gil.python();
         let dict = PyDict::new(py);
-        match self.tree.get_node_label_summary(self.address) {
+        match self
+            .tree
+            .get_node_label_summary(self.address)
+            .map_err(goko_err)?
+        {
             Some(s) => {
                 dict.set_item("errors", s.errors)?;
                 dict.set_item("nones", s.nones)?;
@@ -6,6 +6,8 @@ use goko::*;
 use pointcloud::*;
 use pyo3::types::PyDict;
 
+use crate::goko_err;
+
 /*
 pub #[derive(Debug)]
 struct PyBucketProbs {
@@ -31,12 +33,13 @@ pub struct PyBayesCategoricalTracker {
 
 #[pymethods]
 impl PyBayesCategoricalTracker {
-    pub fn push(&mut self, point: &PyArray1<f32>) {
+    pub fn push(&mut self, point: &PyArray1<f32>) -> PyResult<()> {
         let results = self
             .tree
             .path(point.readonly().as_slice().unwrap())
-            .unwrap();
+            .map_err(goko_err)?;
         self.hkl.add_path(results);
+        Ok(())
     }
 
     pub fn print(&self) {
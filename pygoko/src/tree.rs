@@ -26,12 +26,14 @@ use rand::prelude::*;
 use std::path::Path;
 use std::sync::Arc;
 
+use goko::errors::GokoError;
 use goko::plugins::distributions::*;
 use goko::query_interface::BulkInterface;
 use goko::*;
 use pointcloud::loaders::labeled_ram_from_yaml;
 use pointcloud::*;
 
+use crate::goko_err;
 use crate::layer::*;
 use crate::node::*;
 use crate::plugins::*;
@@ -128,19 +130,30 @@ impl CoverTree {
         let builder = self.builder.take();
         self.writer = Some(builder.unwrap().build(point_cloud).unwrap());
         let writer = self.writer.as_mut().unwrap();
-        writer.generate_summaries();
-        writer.add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::singletons());
-        writer.add_plugin::<GokoDirichlet>(DirichletTree {});
+        writer.generate_summaries().map_err(goko_err)?;
+        writer
+            .add_plugin::<GokoDiagGaussian>(GokoDiagGaussian::singletons())
+            .map_err(goko_err)?;
+        writer
+            .add_plugin::<GokoDirichlet>(DirichletTree {})
+            .map_err(goko_err)?;
         Ok(())
     }
 
-    pub fn attach_svds(&mut self, min_point_count: usize, max_point_count: usize, tau: f32) {
+    pub fn attach_svds(
+        &mut self,
+        min_point_count: usize,
+        max_point_count: usize,
+        tau: f32,
+    ) -> PyResult<()> {
         let writer = self.writer.as_mut().unwrap();
-        writer.add_plugin::<GokoSvdGaussian>(GokoSvdGaussian::new(
-            min_point_count,
-            max_point_count,
-            tau,
-        ));
+        writer
+            .add_plugin::<GokoSvdGaussian>(GokoSvdGaussian::new(
+                min_point_count,
+                max_point_count,
+                tau,
+            ))
+            .map_err(goko_err)
     }
 
     pub fn data_point(&self, point_index: usize) -> PyResult<Option<Py<PyArray1<f32>>>> {
@@ -198,7 +211,7 @@ impl CoverTree {
     pub fn node(&self, address: (i32, usize)) -> PyResult<PyNode> {
         let reader = self.writer.as_ref().unwrap().reader();
         // Check node exists
-        reader.get_node_and(address, |_| true).unwrap();
+        reader.get_node_and(address, |_| true).map_err(goko_err)?;
         Ok(PyNode {
             parameters: Arc::clone(reader.parameters()),
             address,
@@ -211,32 +224,51 @@ impl CoverTree {
         self.node(reader.root_address())
     }
 
-    pub fn knn(&self, point: &PyArray1<f32>, k: usize) -> Vec<(f32, usize)> {
+    pub fn knn(&self, point: &PyArray1<f32>, k: usize) -> PyResult<Vec<(f32, usize)>> {
         let reader = self.writer.as_ref().unwrap().reader();
-        reader.knn(point.readonly().as_slice().unwrap(), k).unwrap()
+        reader
+            .knn(point.readonly().as_slice().unwrap(), k)
+            .map_err(goko_err)
     }
 
-    pub fn routing_knn(&self, point: &PyArray1<f32>, k: usize) -> Vec<(f32, usize)> {
+    pub fn routing_knn(&self, point: &PyArray1<f32>, k: usize) -> PyResult<Vec<(f32, usize)>> {
         let reader = self.writer.as_ref().unwrap().reader();
         reader
             .routing_knn(point.readonly().as_slice().unwrap(), k)
-            .unwrap()
+            .map_err(goko_err)
+    }
+
+    pub fn knn_by_index(&self, point_index: usize, k: usize) -> PyResult<Vec<(f32, usize)>> {
+        let reader = self.writer.as_ref().unwrap().reader();
+        reader.knn_by_index(point_index, k).map_err(goko_err)
+    }
+
+    pub fn range_by_index(&self, point_index: usize, radius: f32) -> PyResult<Vec<(f32, usize)>> {
+        let reader = self.writer.as_ref().unwrap().reader();
+        reader.range_by_index(point_index, radius).map_err(goko_err)
     }
 
-    pub fn known_path(&self, point_index: usize) -> Vec<(f32, (i32, usize))> {
+    pub fn path_by_index(&self, point_index: usize) -> PyResult<Vec<(f32, (i32, usize))>> {
         let reader = self.writer.as_ref().unwrap().reader();
-        reader.known_path(point_index).unwrap()
+        reader.path_by_index(point_index).map_err(goko_err)
     }
 
-    pub fn index_depths(&self, point_indexes: Vec<usize>, tau: Option<f32>) -> Vec<(usize,usize)> {
+    pub fn known_path(&self, point_index: usize) -> PyResult<Vec<(f32, (i32, usize))>> {
+        let reader = self.writer.as_ref().unwrap().reader();
+        reader.known_path(point_index).map_err(goko_err)
+    }
+
+    pub fn index_depths(&self, point_indexes: Vec<usize>, tau: Option<f32>) -> PyResult<Vec<(usize,usize)>> {
         let reader = self.writer.as_ref().unwrap().reader();
         let bulk = BulkInterface::new(reader);
         let tau = tau.unwrap_or(0.00001);
-        bulk.known_path_and(&point_indexes, |reader,path| 
+        bulk.known_path_and(&point_indexes, |reader,path| -> GokoResult<(usize,usize)> {
             if let Ok(path) = path {
                 let mut homogenity_depth = path.len();
                 for (i, (_d, a)) in path.iter().enumerate() {
-                    let summ = reader.get_node_label_summary(*a).unwrap();
+                    let summ = reader
+                        .get_node_label_summary(*a)?
+                        .ok_or(GokoError::PluginNotInstalled("LabelSummaryPlugin"))?;
                     if summ.summary.items.len() == 1 {
                         homogenity_depth = i;
                         break;
@@ -248,23 +280,28 @@ impl CoverTree {
                         break;
                     }
                 }
-                (path.len(), homogenity_depth)
+                Ok((path.len(), homogenity_depth))
             } else {
-                (0, 0)
+                Ok((0, 0))
             }
-        )
+        })
+        .into_iter()
+        .collect::<GokoResult<Vec<(usize,usize)>>>()
+        .map_err(goko_err)
     }
 
-    pub fn point_depths(&self, points: &PyArray2<f32>, tau: Option<f32>) -> Vec<(usize,usize)> {
+    pub fn point_depths(&self, points: &PyArray2<f32>, tau: Option<f32>) -> PyResult<Vec<(usize,usize)>> {
         let reader = self.writer.as_ref().unwrap().reader();
         let bulk = BulkInterface::new(reader);
         let tau = tau.unwrap_or(0.00001);
         
-        bulk.array_map_with_reader(points.readonly().as_array(), |reader,point| 
+        bulk.array_map_with_reader(points.readonly().as_array(), |reader,point| -> GokoResult<(usize,usize)> {
             if let Ok(path) = reader.path(point) {
                 let mut homogenity_depth = path.len();
                 for (i, (_d, a)) in path.iter().enumerate() {
-                    let summ = reader.get_node_label_summary(*a).unwrap();
+                    let summ = reader
+                        .get_node_label_summary(*a)?
+                        .ok_or(GokoError::PluginNotInstalled("LabelSummaryPlugin"))?;
                     if summ.summary.items.len() == 1 {
                         homogenity_depth = i;
                         break;
@@ -276,16 +313,43 @@ impl CoverTree {
                         break;
                     }
                 }
-                (path.len(), homogenity_depth)
+                Ok((path.len(), homogenity_depth))
             } else {
-                (0, 0)
+                Ok((0, 0))
             }
-        )
+        })
+        .into_iter()
+        .collect::<GokoResult<Vec<(usize,usize)>>>()
+        .map_err(goko_err)
     }
 
-    pub fn path(&self, point: &PyArray1<f32>) -> Vec<(f32, (i32, usize))> {
+    pub fn path(&self, point: &PyArray1<f32>) -> PyResult<Vec<(f32, (i32, usize))>> {
         let reader = self.writer.as_ref().unwrap().reader();
-        reader.path(point.readonly().as_slice().unwrap()).unwrap()
+        reader
+            .path(point.readonly().as_slice().unwrap())
+            .map_err(goko_err)
+    }
+
+    pub fn multiresolution_export(
+        &self,
+        min_scale_index: i32,
+    ) -> PyResult<(
+        Vec<(i32, usize)>,
+        Py<PyArray2<f32>>,
+        Py<PyArray1<usize>>,
+        Vec<(usize, usize)>,
+    )> {
+        let reader = self.writer.as_ref().unwrap().reader();
+        let export =
+            export::multiresolution_export(&reader, min_scale_index).map_err(goko_err)?;
+        let gil = GILGuard::acquire();
+        let py = gil.python();
+        Ok((
+            export.addresses,
+            export.centers.into_pyarray(py).to_owned(),
+            Array1::from(export.weights).into_pyarray(py).to_owned(),
+            export.edges,
+        ))
     }
 
     pub fn sample(&self) -> PyResult<(Py<PyArray1<f32>>, Option<PyObject>)> {
@@ -295,7 +359,8 @@ impl CoverTree {
 
         while let Some(pat) = reader
             .get_node_plugin_and::<Dirichlet, _, _>(parent_addr, |p| p.sample(&mut rng))
-            .unwrap()
+            .map_err(goko_err)?
+            .ok_or_else(|| goko_err(GokoError::PluginNotInstalled("GokoDirichlet")))?
         {
             parent_addr = pat;
         }
@@ -303,15 +368,19 @@ impl CoverTree {
         let py = gil.python();
         let vec = reader
             .get_node_plugin_and::<DiagGaussian, _, _>(parent_addr, |p| p.sample(&mut rng))
+            .map_err(goko_err)?
             .map(|m| {
                 Array1::from_shape_vec((m.len(),), m)
                     .unwrap()
                     .into_pyarray(py)
                     .to_owned()
             })
-            .unwrap();
+            .ok_or_else(|| goko_err(GokoError::PluginNotInstalled("GokoDiagGaussian")))?;
         let dict = PyDict::new(py);
-        let summ = match reader.get_node_label_summary(parent_addr) {
+        let summ = match reader
+            .get_node_label_summary(parent_addr)
+            .map_err(goko_err)?
+        {
             Some(s) => {
                 dict.set_item("errors", s.errors)?;
                 dict.set_item("nones", s.nones)?;
@@ -348,7 +417,7 @@ impl CoverTree {
         sequence_len: usize,
         num_sequences: usize,
         sample_rate: usize,
-    ) -> PyKLDivergenceBaseline {
+    ) -> PyResult<PyKLDivergenceBaseline> {
         let reader = self.writer.as_ref().unwrap().reader();
         let mut trainer = DirichletBaseline::default();
         trainer.set_prior_weight(prior_weight);
@@ -356,7 +425,7 @@ impl CoverTree {
         trainer.set_sequence_len(sequence_len);
         trainer.set_num_sequences(num_sequences);
         trainer.set_sample_rate(sample_rate);
-        let baseline = trainer.train(reader).unwrap();
-        PyKLDivergenceBaseline { baseline }
+        let baseline = trainer.train(reader).map_err(goko_err)?;
+        Ok(PyKLDivergenceBaseline { baseline })
     }
 }